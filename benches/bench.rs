@@ -1,7 +1,7 @@
 use std::mem::MaybeUninit;
 
 use criterion::{criterion_group, criterion_main, Criterion};
-use libyaml_safer::{Document, Emitter, Parser};
+use libyaml_safer::{Document, Emitter, MappingStyle, Parser, ScalarStyle, Scanner};
 use unsafe_libyaml::*;
 
 static VERY_LARGE_YAML: &[u8] = include_bytes!("very_large.yml");
@@ -118,5 +118,92 @@ pub fn parser(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, parser);
+pub fn scanner(c: &mut Criterion) {
+    c.bench_function("libyaml-safer scan large", |b| {
+        b.iter(|| {
+            let mut input = VERY_LARGE_YAML;
+            let mut scanner = Scanner::new();
+            scanner.set_input(&mut input);
+            scanner.scan_all().unwrap()
+        })
+    });
+
+    c.bench_function("libyaml-safer scan large small buffer", |b| {
+        b.iter(|| {
+            let mut input = VERY_LARGE_YAML;
+            let mut scanner = Scanner::new();
+            scanner.set_buffer_capacity(1024);
+            scanner.set_input(&mut input);
+            scanner.scan_all().unwrap()
+        })
+    });
+
+    let plain_scalars = (0..100_000)
+        .map(|i| format!("key {i}: some plain value with words {i}\n"))
+        .collect::<String>();
+    c.bench_function("libyaml-safer scan plain scalars", |b| {
+        b.iter(|| {
+            let mut input = plain_scalars.as_bytes();
+            let mut scanner = Scanner::new();
+            scanner.set_input(&mut input);
+            scanner.scan_all().unwrap()
+        })
+    });
+
+    let long_tokens = {
+        let name = "x".repeat(1_000);
+        let text = "y".repeat(1_000_000);
+        format!("- &{name} !{name} {text}\n- *{name}\n- '{text}'\n- \"{text}\"\n- |\n  {text}\n")
+    };
+    c.bench_function("libyaml-safer scan long tokens", |b| {
+        b.iter(|| {
+            let mut input = long_tokens.as_bytes();
+            let mut scanner = Scanner::new();
+            scanner.set_input(&mut input);
+            scanner.scan_all().unwrap()
+        })
+    });
+}
+
+pub fn document(c: &mut Criterion) {
+    let mut document = Document::new(None, &[], true, true);
+    let mapping = document.add_mapping(None, MappingStyle::Block);
+    let keys: Vec<String> = (0..10_000).map(|i| format!("key{i}")).collect();
+    for key in &keys {
+        let key = document.add_scalar(None, key, ScalarStyle::Any);
+        document.yaml_document_append_mapping_pair(mapping, key, key);
+    }
+
+    c.bench_function("libyaml-safer mapping lookup", |b| {
+        b.iter(|| {
+            keys.iter()
+                .step_by(10)
+                .filter_map(|key| document.get_mapping_value(mapping, key))
+                .count()
+        })
+    });
+
+    let mut indexed = document.clone();
+    indexed.build_mapping_index(mapping);
+    c.bench_function("libyaml-safer mapping lookup indexed", |b| {
+        b.iter(|| {
+            keys.iter()
+                .step_by(10)
+                .filter_map(|key| indexed.get_mapping_value(mapping, key))
+                .count()
+        })
+    });
+
+    let absent: Vec<String> = (0..1_000).map(|i| format!("absent{i}")).collect();
+    c.bench_function("libyaml-safer mapping lookup indexed absent", |b| {
+        b.iter(|| {
+            absent
+                .iter()
+                .filter_map(|key| indexed.get_mapping_value(mapping, key))
+                .count()
+        })
+    });
+}
+
+criterion_group!(benches, parser, scanner, document);
 criterion_main!(benches);
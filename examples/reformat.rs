@@ -0,0 +1,82 @@
+//! Reformat a YAML stream read from stdin, with the indentation and line
+//! width given as arguments.
+//!
+//! ```text
+//! cargo run --example reformat -- --indent 4 --width 60 < input.yaml
+//! ```
+
+#![warn(clippy::pedantic)]
+
+use libyaml_safer::{Document, Emitter, Parser};
+use std::env;
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+/// The settings of the emitter.
+pub(crate) struct Options {
+    pub indent: i32,
+    pub width: i32,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            indent: 2,
+            width: 80,
+        }
+    }
+}
+
+impl Options {
+    fn from_args(mut args: impl Iterator<Item = String>) -> Result<Options, Box<dyn Error>> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let value = match arg.as_str() {
+                "--indent" => &mut options.indent,
+                "--width" => &mut options.width,
+                _ => return Err(format!("unknown argument `{arg}`").into()),
+            };
+            let Some(number) = args.next() else {
+                return Err(format!("missing value for `{arg}`").into());
+            };
+            *value = number.parse()?;
+        }
+        Ok(options)
+    }
+}
+
+/// Load every document of `input`, and dump them to `output`.
+pub(crate) fn reformat(
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+    options: &Options,
+) -> Result<(), Box<dyn Error>> {
+    let mut input = io::BufReader::new(input);
+    let mut parser = Parser::new();
+    parser.set_input(&mut input);
+    let mut emitter = Emitter::new();
+    emitter.set_indent(options.indent);
+    emitter.set_width(options.width);
+    emitter.set_output(output);
+    emitter.open()?;
+    loop {
+        let mut document = Document::load(&mut parser)?;
+        if document.get_root_node().is_none() {
+            break;
+        }
+        document.dump(&mut emitter)?;
+    }
+    emitter.close()?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let result = Options::from_args(env::args().skip(1))
+        .and_then(|options| reformat(&mut io::stdin(), &mut io::stdout(), &options));
+    if let Err(err) = result {
+        let _ = writeln!(io::stderr(), "reformat: {err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
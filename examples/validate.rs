@@ -0,0 +1,101 @@
+//! Check that YAML files are well-formed, and print their errors with the
+//! line they point at. The exit code is non-zero if any file is invalid.
+//!
+//! ```text
+//! cargo run --example validate -- first.yaml second.yaml
+//! ```
+//!
+//! Without arguments, stdin is checked. The parser stops at the first error
+//! of a file, so there is at most one error per file.
+
+#![warn(clippy::pedantic)]
+#![allow(clippy::cast_possible_truncation)]
+
+use libyaml_safer::{Error, Parser};
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+/// Parse the events of `input`, and print its error to `output`, if any.
+/// Returns whether `input` is valid.
+pub(crate) fn validate(name: &str, input: &str, output: &mut dyn Write) -> io::Result<bool> {
+    let mut parser = Parser::new();
+    let mut bytes = input.as_bytes();
+    parser.set_input_string(&mut bytes);
+    for event in parser {
+        if let Err(err) = event {
+            render_error(name, input, &err, output)?;
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Print `err` with the line of `input` that it points at, and a caret under
+/// its column.
+fn render_error(name: &str, input: &str, err: &Error, output: &mut dyn Write) -> io::Result<()> {
+    let Some(mark) = err.problem_mark() else {
+        return writeln!(output, "{name}: {err}");
+    };
+    writeln!(output, "{name}:{mark}: {}", err.problem())?;
+    let line = input
+        .lines()
+        .nth(mark.line as usize)
+        .unwrap_or_default()
+        .trim_end_matches('\r');
+    let indent: String = line
+        .chars()
+        .take(mark.column as usize)
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+    writeln!(output, "    {line}")?;
+    writeln!(output, "    {indent}^")?;
+    if let Some(context) = err.context() {
+        write!(output, "  {context}")?;
+        if let Some(context_mark) = err.context_mark() {
+            write!(output, " at {context_mark}")?;
+        }
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut inputs = Vec::new();
+    let files: Vec<String> = env::args().skip(1).collect();
+    if files.is_empty() {
+        let mut input = String::new();
+        if let Err(err) = io::stdin().read_to_string(&mut input) {
+            let _ = writeln!(io::stderr(), "validate: <stdin>: {err}");
+            return ExitCode::FAILURE;
+        }
+        inputs.push(("<stdin>".to_owned(), input));
+    }
+    for file in files {
+        match fs::read_to_string(&file) {
+            Ok(input) => inputs.push((file, input)),
+            Err(err) => {
+                let _ = writeln!(io::stderr(), "validate: {file}: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut valid = true;
+    let mut stdout = io::stdout();
+    for (name, input) in &inputs {
+        match validate(name, input, &mut stdout) {
+            Ok(is_valid) => valid &= is_valid,
+            Err(err) => {
+                let _ = writeln!(io::stderr(), "validate: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if valid {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,204 @@
+//! Convert a YAML stream read from stdin to JSON, one line per document.
+//!
+//! ```text
+//! cargo run --example yaml2json < input.yaml
+//! ```
+//!
+//! Plain scalars are resolved with the YAML 1.2 core schema: `null`, `true`,
+//! `42`, and `1.5` become JSON literals, and all other scalars become strings.
+//! Aliases are replaced by a copy of their anchored node.
+
+#![warn(clippy::pedantic)]
+
+use libyaml_safer::{EventData, Parser, ScalarStyle};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+/// A collection being converted.
+struct Frame {
+    json: String,
+    mapping: bool,
+    anchor: Option<String>,
+    /// The number of converted nodes, counting both keys and values.
+    nodes: usize,
+}
+
+impl Frame {
+    fn new(mapping: bool, anchor: Option<String>) -> Frame {
+        Frame {
+            json: String::from(if mapping { "{" } else { "[" }),
+            mapping,
+            anchor,
+            nodes: 0,
+        }
+    }
+}
+
+/// Convert every document of `input` to a line of JSON in `output`.
+pub(crate) fn yaml_to_json(
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> Result<(), Box<dyn Error>> {
+    let mut input = io::BufReader::new(input);
+    let mut parser = Parser::new();
+    parser.set_input(&mut input);
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut anchors: HashMap<String, String> = HashMap::new();
+    for event in parser {
+        let event = event?;
+        let json = match event.data {
+            EventData::DocumentStart { .. } => {
+                anchors.clear();
+                continue;
+            }
+            EventData::SequenceStart { anchor, .. } => {
+                stack.push(Frame::new(false, anchor));
+                continue;
+            }
+            EventData::MappingStart { anchor, .. } => {
+                stack.push(Frame::new(true, anchor));
+                continue;
+            }
+            EventData::SequenceEnd | EventData::MappingEnd => {
+                let mut frame = stack.pop().ok_or("unbalanced collection end")?;
+                frame.json.push(if frame.mapping { '}' } else { ']' });
+                if let Some(anchor) = frame.anchor {
+                    anchors.insert(anchor, frame.json.clone());
+                }
+                frame.json
+            }
+            EventData::Scalar {
+                anchor,
+                tag,
+                value,
+                style,
+                ..
+            } => {
+                let json = resolve_scalar(&value, style, tag.as_deref());
+                if let Some(anchor) = anchor {
+                    anchors.insert(anchor, json.clone());
+                }
+                json
+            }
+            EventData::Alias { anchor } => anchors
+                .get(&anchor)
+                .cloned()
+                .ok_or_else(|| format!("undefined alias `{anchor}`"))?,
+            EventData::StreamStart { .. }
+            | EventData::StreamEnd
+            | EventData::DocumentEnd { .. } => continue,
+        };
+        match stack.last_mut() {
+            None => writeln!(output, "{json}")?,
+            Some(frame) => add_node(frame, &json)?,
+        }
+    }
+    Ok(())
+}
+
+/// Add the JSON of a key or a value to the collection of `frame`.
+fn add_node(frame: &mut Frame, json: &str) -> Result<(), Box<dyn Error>> {
+    let is_key = frame.mapping && frame.nodes % 2 == 0;
+    if frame.nodes != 0 {
+        frame
+            .json
+            .push(if frame.mapping && !is_key { ':' } else { ',' });
+    }
+    if is_key && !json.starts_with('"') {
+        // JSON keys are strings, so literals are quoted, and collections
+        // are not supported.
+        if json.starts_with(['[', '{']) {
+            return Err("collections as mapping keys cannot be converted to JSON".into());
+        }
+        push_string(&mut frame.json, json);
+    } else {
+        frame.json.push_str(json);
+    }
+    frame.nodes += 1;
+    Ok(())
+}
+
+/// The JSON of a scalar, resolved with the core schema if it is plain and
+/// not tagged, or if its tag is one of the core schema.
+fn resolve_scalar(value: &str, style: ScalarStyle, tag: Option<&str>) -> String {
+    let resolve = match tag {
+        None => style == ScalarStyle::Plain,
+        Some(tag) => matches!(
+            tag,
+            "tag:yaml.org,2002:null"
+                | "tag:yaml.org,2002:bool"
+                | "tag:yaml.org,2002:int"
+                | "tag:yaml.org,2002:float"
+        ),
+    };
+    if resolve {
+        match value {
+            "" | "~" | "null" | "Null" | "NULL" => return "null".to_owned(),
+            "true" | "True" | "TRUE" => return "true".to_owned(),
+            "false" | "False" | "FALSE" => return "false".to_owned(),
+            _ => {}
+        }
+        if let Some(number) = resolve_number(value) {
+            return number;
+        }
+    }
+    let mut json = String::new();
+    push_string(&mut json, value);
+    json
+}
+
+fn resolve_number(value: &str) -> Option<String> {
+    let integer = if let Some(hex) = value.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(octal) = value.strip_prefix("0o") {
+        i64::from_str_radix(octal, 8).ok()
+    } else if value
+        .bytes()
+        .all(|byte| byte.is_ascii_digit() || byte == b'-' || byte == b'+')
+    {
+        value.parse().ok()
+    } else {
+        None
+    };
+    if let Some(integer) = integer {
+        return Some(integer.to_string());
+    }
+    // Only the decimal forms of the core schema, as Rust also accepts words
+    // like `inf`, and JSON has no infinities.
+    let decimal = value.bytes().any(|byte| byte.is_ascii_digit())
+        && value
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E'));
+    let float: f64 = value.parse().ok().filter(|_| decimal)?;
+    float.is_finite().then(|| format!("{float:?}"))
+}
+
+/// Push `value` as a JSON string.
+fn push_string(json: &mut String, value: &str) {
+    json.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            '\u{0}'..='\u{1f}' => {
+                let _ = write!(json, "\\u{:04x}", ch as u32);
+            }
+            _ => json.push(ch),
+        }
+    }
+    json.push('"');
+}
+
+fn main() -> ExitCode {
+    if let Err(err) = yaml_to_json(&mut io::stdin(), &mut io::stdout()) {
+        let _ = writeln!(io::stderr(), "yaml2json: {err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,446 @@
+#ifndef LIBYAML_SAFER_H
+#define LIBYAML_SAFER_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// The stream encodings, as in libyaml.
+typedef enum yaml_encoding_t {
+  YAML_ANY_ENCODING = 0,
+  YAML_UTF8_ENCODING = 1,
+  YAML_UTF16LE_ENCODING = 2,
+  YAML_UTF16BE_ENCODING = 3,
+} yaml_encoding_t;
+
+// The error types, as in libyaml.
+typedef enum yaml_error_type_t {
+  YAML_NO_ERROR = 0,
+  YAML_MEMORY_ERROR = 1,
+  YAML_READER_ERROR = 2,
+  YAML_SCANNER_ERROR = 3,
+  YAML_PARSER_ERROR = 4,
+  YAML_COMPOSER_ERROR = 5,
+  YAML_WRITER_ERROR = 6,
+  YAML_EMITTER_ERROR = 7,
+} yaml_error_type_t;
+
+// The event types, as in libyaml.
+typedef enum yaml_event_type_t {
+  YAML_NO_EVENT = 0,
+  YAML_STREAM_START_EVENT = 1,
+  YAML_STREAM_END_EVENT = 2,
+  YAML_DOCUMENT_START_EVENT = 3,
+  YAML_DOCUMENT_END_EVENT = 4,
+  YAML_ALIAS_EVENT = 5,
+  YAML_SCALAR_EVENT = 6,
+  YAML_SEQUENCE_START_EVENT = 7,
+  YAML_SEQUENCE_END_EVENT = 8,
+  YAML_MAPPING_START_EVENT = 9,
+  YAML_MAPPING_END_EVENT = 10,
+} yaml_event_type_t;
+
+// The mapping styles, as in libyaml.
+typedef enum yaml_mapping_style_t {
+  YAML_ANY_MAPPING_STYLE = 0,
+  YAML_BLOCK_MAPPING_STYLE = 1,
+  YAML_FLOW_MAPPING_STYLE = 2,
+} yaml_mapping_style_t;
+
+// The scalar styles, as in libyaml.
+typedef enum yaml_scalar_style_t {
+  YAML_ANY_SCALAR_STYLE = 0,
+  YAML_PLAIN_SCALAR_STYLE = 1,
+  YAML_SINGLE_QUOTED_SCALAR_STYLE = 2,
+  YAML_DOUBLE_QUOTED_SCALAR_STYLE = 3,
+  YAML_LITERAL_SCALAR_STYLE = 4,
+  YAML_FOLDED_SCALAR_STYLE = 5,
+} yaml_scalar_style_t;
+
+// The sequence styles, as in libyaml.
+typedef enum yaml_sequence_style_t {
+  YAML_ANY_SEQUENCE_STYLE = 0,
+  YAML_BLOCK_SEQUENCE_STYLE = 1,
+  YAML_FLOW_SEQUENCE_STYLE = 2,
+} yaml_sequence_style_t;
+
+// An emitter, created by [`yaml_emitter_initialize()`].
+typedef struct yaml_emitter_t yaml_emitter_t;
+
+// An event, created by [`yaml_parser_parse()`] or by one of the
+// `yaml_*_event_initialize()` functions.
+typedef struct yaml_event_t yaml_event_t;
+
+// A parser, created by [`yaml_parser_initialize()`].
+typedef struct yaml_parser_t yaml_parser_t;
+
+// A position in the input, as in libyaml.
+typedef struct yaml_mark_t {
+  // The position index, in bytes for UTF-8 input.
+  size_t index;
+  // The position line, starting from 0.
+  size_t line;
+  // The position column, starting from 0.
+  size_t column;
+} yaml_mark_t;
+
+// The version directive data, as in libyaml.
+typedef struct yaml_version_directive_t {
+  // The major version number.
+  int major;
+  // The minor version number.
+  int minor;
+} yaml_version_directive_t;
+
+// The character type of strings, which are UTF-8.
+typedef uint8_t yaml_char_t;
+
+// The tag directive data, as in libyaml.
+typedef struct yaml_tag_directive_t {
+  // The tag handle, NUL-terminated.
+  const yaml_char_t *handle;
+  // The tag prefix, NUL-terminated.
+  const yaml_char_t *prefix;
+} yaml_tag_directive_t;
+
+// Create a parser in `*parser`, to be freed with [`yaml_parser_delete()`].
+//
+// # Safety
+//
+// `parser` must be valid for writes.
+int yaml_parser_initialize(struct yaml_parser_t **parser);
+
+// Free a parser, and the input it holds.
+//
+// # Safety
+//
+// `parser` must be null or a parser from [`yaml_parser_initialize()`] that
+// was not freed yet.
+void yaml_parser_delete(struct yaml_parser_t *parser);
+
+// Set a string input of `size` bytes. Unlike in libyaml, the input is copied,
+// so it does not need to outlive the parser.
+//
+// # Safety
+//
+// `parser` must be a parser from [`yaml_parser_initialize()`], and `input`
+// must be valid for reads of `size` bytes.
+void yaml_parser_set_input_string(struct yaml_parser_t *parser, const uint8_t *input, size_t size);
+
+// Set the encoding of the input, a `yaml_encoding_t`. Returns 0 if the
+// parser is null or the encoding is unknown.
+//
+// # Safety
+//
+// `parser` must be a parser from [`yaml_parser_initialize()`].
+int yaml_parser_set_encoding(struct yaml_parser_t *parser, int encoding);
+
+// Parse the next event into `*event`, to be freed with
+// [`yaml_event_delete()`] or passed to [`yaml_emitter_emit()`].
+//
+// # Safety
+//
+// `parser` must be a parser from [`yaml_parser_initialize()`], and `event`
+// must be valid for writes.
+int yaml_parser_parse(struct yaml_parser_t *parser, struct yaml_event_t **event);
+
+// The type of the last error of a parser.
+//
+// # Safety
+//
+// `parser` must be a parser from [`yaml_parser_initialize()`].
+enum yaml_error_type_t yaml_parser_error(const struct yaml_parser_t *parser);
+
+// The problem of the last error of a parser, NUL-terminated, or null if there
+// was no error. The string lives as long as the parser.
+//
+// # Safety
+//
+// `parser` must be a parser from [`yaml_parser_initialize()`].
+const char *yaml_parser_error_problem(const struct yaml_parser_t *parser);
+
+// The position of the problem of the last error of a parser, or a zero mark.
+//
+// # Safety
+//
+// `parser` must be a parser from [`yaml_parser_initialize()`].
+struct yaml_mark_t yaml_parser_error_problem_mark(const struct yaml_parser_t *parser);
+
+// The context of the last error of a parser, NUL-terminated, or null if
+// there is none. The string lives as long as the parser.
+//
+// # Safety
+//
+// `parser` must be a parser from [`yaml_parser_initialize()`].
+const char *yaml_parser_error_context(const struct yaml_parser_t *parser);
+
+// The position of the context of the last error of a parser, or a zero
+// mark.
+//
+// # Safety
+//
+// `parser` must be a parser from [`yaml_parser_initialize()`].
+struct yaml_mark_t yaml_parser_error_context_mark(const struct yaml_parser_t *parser);
+
+// Create a STREAM-START event. `encoding` is a `yaml_encoding_t`; an
+// unknown encoding is an error.
+//
+// # Safety
+//
+// `event` must be valid for writes.
+int yaml_stream_start_event_initialize(struct yaml_event_t **event, int encoding);
+
+// Create a STREAM-END event.
+//
+// # Safety
+//
+// `event` must be valid for writes.
+int yaml_stream_end_event_initialize(struct yaml_event_t **event);
+
+// Create a DOCUMENT-START event, with the tag directives from
+// `tag_directives_start` up to `tag_directives_end`.
+//
+// # Safety
+//
+// `event` must be valid for writes, `version_directive` must be null or
+// valid for reads, and the tag directives must be a valid range of
+// directives with NUL-terminated strings, or both null.
+int yaml_document_start_event_initialize(struct yaml_event_t **event,
+                                         const struct yaml_version_directive_t *version_directive,
+                                         const struct yaml_tag_directive_t *tag_directives_start,
+                                         const struct yaml_tag_directive_t *tag_directives_end,
+                                         int implicit);
+
+// Create a DOCUMENT-END event.
+//
+// # Safety
+//
+// `event` must be valid for writes.
+int yaml_document_end_event_initialize(struct yaml_event_t **event, int implicit);
+
+// Create an ALIAS event.
+//
+// # Safety
+//
+// `event` must be valid for writes, and `anchor` must be a NUL-terminated
+// string.
+int yaml_alias_event_initialize(struct yaml_event_t **event, const yaml_char_t *anchor);
+
+// Create a SCALAR event. `length` is the length of `value` in bytes, or -1 if
+// `value` is NUL-terminated. `style` is a `yaml_scalar_style_t`; an unknown
+// style is an error.
+//
+// # Safety
+//
+// `event` must be valid for writes, `anchor` and `tag` must be null or
+// NUL-terminated strings, and `value` must be valid for reads of `length`
+// bytes, or NUL-terminated.
+int yaml_scalar_event_initialize(struct yaml_event_t **event,
+                                 const yaml_char_t *anchor,
+                                 const yaml_char_t *tag,
+                                 const yaml_char_t *value,
+                                 int length,
+                                 int plain_implicit,
+                                 int quoted_implicit,
+                                 int style);
+
+// Create a SEQUENCE-START event. `style` is a `yaml_sequence_style_t`; an
+// unknown style is an error.
+//
+// # Safety
+//
+// `event` must be valid for writes, and `anchor` and `tag` must be null or
+// NUL-terminated strings.
+int yaml_sequence_start_event_initialize(struct yaml_event_t **event,
+                                         const yaml_char_t *anchor,
+                                         const yaml_char_t *tag,
+                                         int implicit,
+                                         int style);
+
+// Create a SEQUENCE-END event.
+//
+// # Safety
+//
+// `event` must be valid for writes.
+int yaml_sequence_end_event_initialize(struct yaml_event_t **event);
+
+// Create a MAPPING-START event. `style` is a `yaml_mapping_style_t`; an
+// unknown style is an error.
+//
+// # Safety
+//
+// `event` must be valid for writes, and `anchor` and `tag` must be null or
+// NUL-terminated strings.
+int yaml_mapping_start_event_initialize(struct yaml_event_t **event,
+                                        const yaml_char_t *anchor,
+                                        const yaml_char_t *tag,
+                                        int implicit,
+                                        int style);
+
+// Create a MAPPING-END event.
+//
+// # Safety
+//
+// `event` must be valid for writes.
+int yaml_mapping_end_event_initialize(struct yaml_event_t **event);
+
+// Free an event.
+//
+// # Safety
+//
+// `event` must be null or an event that was not freed or emitted yet.
+void yaml_event_delete(struct yaml_event_t *event);
+
+// The type of an event.
+//
+// # Safety
+//
+// `event` must be null or a valid event.
+enum yaml_event_type_t yaml_event_get_type(const struct yaml_event_t *event);
+
+// The start of an event in the input.
+//
+// # Safety
+//
+// `event` must be null or a valid event.
+struct yaml_mark_t yaml_event_get_start_mark(const struct yaml_event_t *event);
+
+// The end of an event in the input.
+//
+// # Safety
+//
+// `event` must be null or a valid event.
+struct yaml_mark_t yaml_event_get_end_mark(const struct yaml_event_t *event);
+
+// Get the anchor of an event, which is not NUL-terminated, in `*anchor` and
+// `*length`. Returns 0 if the event has no anchor.
+//
+// The anchor lives as long as the event.
+//
+// # Safety
+//
+// `event` must be a valid event, and `anchor` and `length` must be valid for
+// writes.
+int yaml_event_get_anchor(const struct yaml_event_t *event,
+                          const yaml_char_t **anchor,
+                          size_t *length);
+
+// Get the tag of an event, which is not NUL-terminated, in `*tag` and
+// `*length`. Returns 0 if the event has no tag.
+//
+// The tag lives as long as the event.
+//
+// # Safety
+//
+// `event` must be a valid event, and `tag` and `length` must be valid for
+// writes.
+int yaml_event_get_tag(const struct yaml_event_t *event, const yaml_char_t **tag, size_t *length);
+
+// Get the value of a SCALAR event, which is not NUL-terminated, in `*value`
+// and `*length`. Returns 0 if the event is not a SCALAR event.
+//
+// The value lives as long as the event.
+//
+// # Safety
+//
+// `event` must be a valid event, and `value` and `length` must be valid for
+// writes.
+int yaml_event_get_scalar_value(const struct yaml_event_t *event,
+                                const yaml_char_t **value,
+                                size_t *length);
+
+// Create an emitter in `*emitter`, to be freed with
+// [`yaml_emitter_delete()`].
+//
+// # Safety
+//
+// `emitter` must be valid for writes.
+int yaml_emitter_initialize(struct yaml_emitter_t **emitter);
+
+// Free an emitter.
+//
+// # Safety
+//
+// `emitter` must be null or an emitter from [`yaml_emitter_initialize()`]
+// that was not freed yet.
+void yaml_emitter_delete(struct yaml_emitter_t *emitter);
+
+// Write the output to the buffer `output` of `size` bytes, and the number of
+// bytes written to `*size_written`. Writing beyond `size` bytes is a writer
+// error.
+//
+// This resets the other settings of the emitter, so it should be called
+// first.
+//
+// # Safety
+//
+// `emitter` must be an emitter from [`yaml_emitter_initialize()`], `output`
+// must be valid for writes of `size` bytes and `size_written` valid for
+// writes, as long as the emitter is in use.
+void yaml_emitter_set_output_string(struct yaml_emitter_t *emitter,
+                                    uint8_t *output,
+                                    size_t size,
+                                    size_t *size_written);
+
+// Emit all scalars in the canonical format of the YAML specification.
+//
+// # Safety
+//
+// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+void yaml_emitter_set_canonical(struct yaml_emitter_t *emitter, int canonical);
+
+// Set the indentation increment.
+//
+// # Safety
+//
+// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+void yaml_emitter_set_indent(struct yaml_emitter_t *emitter, int indent);
+
+// Set the preferred line width, or -1 for unlimited.
+//
+// # Safety
+//
+// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+void yaml_emitter_set_width(struct yaml_emitter_t *emitter, int width);
+
+// Allow unescaped non-ASCII characters.
+//
+// # Safety
+//
+// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+void yaml_emitter_set_unicode(struct yaml_emitter_t *emitter, int unicode);
+
+// Emit an event. The emitter takes the event, which must not be used
+// afterwards, even if this fails.
+//
+// # Safety
+//
+// `emitter` must be an emitter from [`yaml_emitter_initialize()`] with an
+// output, and `event` must be an event that was not freed or emitted yet.
+int yaml_emitter_emit(struct yaml_emitter_t *emitter, struct yaml_event_t *event);
+
+// Write the buffered output.
+//
+// # Safety
+//
+// `emitter` must be an emitter from [`yaml_emitter_initialize()`] with an
+// output.
+int yaml_emitter_flush(struct yaml_emitter_t *emitter);
+
+// The type of the last error of an emitter.
+//
+// # Safety
+//
+// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+enum yaml_error_type_t yaml_emitter_error(const struct yaml_emitter_t *emitter);
+
+// The problem of the last error of an emitter, NUL-terminated, or null if
+// there was no error. The string lives as long as the emitter.
+//
+// # Safety
+//
+// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+const char *yaml_emitter_error_problem(const struct yaml_emitter_t *emitter);
+
+#endif  /* LIBYAML_SAFER_H */
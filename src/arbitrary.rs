@@ -0,0 +1,509 @@
+//! Implementations of [`arbitrary::Arbitrary`] for structure-aware fuzzing,
+//! with the `arbitrary` feature.
+//!
+//! Random fields rarely make valid event streams or documents, so the
+//! implementations generate valid structures: balanced event streams, with
+//! aliases only of anchors defined before, and documents whose node indices
+//! are in range.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{
+    Document, Encoding, Event, EventData, MappingStyle, Node, NodeData, ScalarSource, ScalarStyle,
+    SequenceStyle, TagDirective, VersionDirective,
+};
+
+/// The maximum nesting of generated collections.
+const MAX_DEPTH: usize = 4;
+
+/// The maximum number of items or pairs of a generated collection.
+const MAX_LEN: u32 = 4;
+
+/// Pieces of generated scalar values, which include the characters that need
+/// quoting or escaping.
+const VALUE_PIECES: &[&str] = &[
+    "a",
+    "key",
+    "value",
+    "1",
+    " ",
+    "  ",
+    ": ",
+    " #",
+    "-",
+    "- ",
+    "? ",
+    "'",
+    "\"",
+    "\\",
+    "\n",
+    "\n\n",
+    "\t",
+    "~",
+    "null",
+    "[",
+    "]",
+    "{",
+    "}",
+    ",",
+    "!",
+    "&",
+    "*",
+    "%",
+    "@",
+    "`",
+    "\u{e9}",
+    "\u{1f600}",
+    "\u{85}",
+    "\u{2028}",
+    "\u{7f}",
+    "\u{feff}",
+    "\r",
+];
+
+/// Tags of generated nodes. The non-specific tag `!` is left out, as it is
+/// resolved when documents are loaded.
+const TAGS: &[&str] = &[
+    "tag:yaml.org,2002:str",
+    "tag:yaml.org,2002:int",
+    "tag:example.com,2000:app/x",
+    "!local",
+    "!a%21b",
+];
+
+fn value(u: &mut Unstructured<'_>) -> Result<String> {
+    let mut value = String::new();
+    for _ in 0..u.int_in_range(0..=6)? {
+        value.push_str(u.choose(VALUE_PIECES)?);
+    }
+    Ok(value)
+}
+
+fn tag(u: &mut Unstructured<'_>) -> Result<Option<String>> {
+    Ok(if u.ratio(1, 4)? {
+        Some((*u.choose(TAGS)?).to_string())
+    } else {
+        None
+    })
+}
+
+impl<'a> Arbitrary<'a> for Encoding {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for ScalarStyle {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            ScalarStyle::Any,
+            ScalarStyle::Plain,
+            ScalarStyle::SingleQuoted,
+            ScalarStyle::DoubleQuoted,
+            ScalarStyle::Literal,
+            ScalarStyle::Folded,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for SequenceStyle {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            SequenceStyle::Any,
+            SequenceStyle::Block,
+            SequenceStyle::Flow,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for MappingStyle {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[MappingStyle::Any, MappingStyle::Block, MappingStyle::Flow])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for VersionDirective {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(VersionDirective {
+            major: 1,
+            minor: u.int_in_range(1..=2)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for TagDirective {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let (handle, prefix) = *u.choose(&[
+            ("!e!", "tag:example.com,2000:app/"),
+            ("!y!", "tag:yaml.org,2002:"),
+            ("!l!", "!local-"),
+        ])?;
+        Ok(TagDirective {
+            handle: handle.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+/// A single event, which is only valid in a stream at the right place.
+/// Use [`ArbitraryEventStream`] for whole streams.
+impl<'a> Arbitrary<'a> for EventData {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=9)? {
+            0 => EventData::StreamStart {
+                encoding: u.arbitrary()?,
+            },
+            1 => EventData::StreamEnd,
+            2 => EventData::DocumentStart {
+                version_directive: u.arbitrary()?,
+                tag_directives: Vec::new(),
+                implicit: u.arbitrary()?,
+            },
+            3 => EventData::DocumentEnd {
+                implicit: u.arbitrary()?,
+            },
+            4 => EventData::Alias {
+                anchor: "a".to_string(),
+            },
+            5 => {
+                let tag = tag(u)?;
+                let implicit = tag.is_none();
+                EventData::Scalar {
+                    anchor: None,
+                    tag,
+                    value: value(u)?,
+                    plain_implicit: implicit,
+                    quoted_implicit: implicit,
+                    style: u.arbitrary()?,
+                    source: ScalarSource::default(),
+                }
+            }
+            6 => {
+                let tag = tag(u)?;
+                EventData::SequenceStart {
+                    anchor: None,
+                    implicit: tag.is_none(),
+                    tag,
+                    style: u.arbitrary()?,
+                }
+            }
+            7 => EventData::SequenceEnd,
+            8 => {
+                let tag = tag(u)?;
+                EventData::MappingStart {
+                    anchor: None,
+                    implicit: tag.is_none(),
+                    tag,
+                    style: u.arbitrary()?,
+                }
+            }
+            _ => EventData::MappingEnd,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Event {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Event::new(u.arbitrary()?))
+    }
+}
+
+/// A complete, balanced stream of events that passes
+/// [`validate_event_stream()`](crate::validate_event_stream).
+///
+/// Scalars and collections have either a tag or the implicit flags set, and
+/// aliases only refer to anchors of nodes that are complete.
+#[derive(Clone, Debug)]
+pub struct ArbitraryEventStream(pub Vec<Event>);
+
+impl<'a> Arbitrary<'a> for ArbitraryEventStream {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut generator = StreamGenerator {
+            events: vec![Event::stream_start(Encoding::Utf8)],
+            anchors: Vec::new(),
+        };
+        for _ in 0..u.int_in_range(0..=3)? {
+            generator.document(u)?;
+        }
+        generator.events.push(Event::stream_end());
+        Ok(ArbitraryEventStream(generator.events))
+    }
+}
+
+struct StreamGenerator {
+    events: Vec<Event>,
+    /// The anchors of the complete nodes of the current document.
+    anchors: Vec<String>,
+}
+
+impl StreamGenerator {
+    fn document(&mut self, u: &mut Unstructured<'_>) -> Result<()> {
+        let tag_directives: Vec<TagDirective> = if u.ratio(1, 4)? {
+            vec![u.arbitrary()?]
+        } else {
+            Vec::new()
+        };
+        let version_directive: Option<VersionDirective> = u.arbitrary()?;
+        self.events.push(Event::document_start(
+            version_directive,
+            &tag_directives,
+            u.arbitrary()?,
+        ));
+        self.anchors.clear();
+        self.node(u, 0)?;
+        self.events.push(Event::document_end(u.arbitrary()?));
+        Ok(())
+    }
+
+    fn anchor(&self, u: &mut Unstructured<'_>) -> Result<Option<String>> {
+        Ok(if u.ratio(1, 5)? {
+            Some(format!("a{}", self.events.len()))
+        } else {
+            None
+        })
+    }
+
+    fn node(&mut self, u: &mut Unstructured<'_>, depth: usize) -> Result<()> {
+        let kind = if depth >= MAX_DEPTH {
+            0
+        } else {
+            u.int_in_range(0..=3)?
+        };
+        if kind == 3 && !self.anchors.is_empty() {
+            let anchor = u.choose(&self.anchors)?.clone();
+            self.events.push(Event::alias(&anchor));
+            return Ok(());
+        }
+        let anchor = self.anchor(u)?;
+        let tag = tag(u)?;
+        let implicit = tag.is_none();
+        match kind {
+            1 => {
+                self.events.push(Event::sequence_start(
+                    anchor.as_deref(),
+                    tag.as_deref(),
+                    implicit,
+                    u.arbitrary()?,
+                ));
+                for _ in 0..u.int_in_range(0..=MAX_LEN)? {
+                    self.node(u, depth + 1)?;
+                }
+                self.events.push(Event::sequence_end());
+            }
+            2 => {
+                self.events.push(Event::mapping_start(
+                    anchor.as_deref(),
+                    tag.as_deref(),
+                    implicit,
+                    u.arbitrary()?,
+                ));
+                for _ in 0..u.int_in_range(0..=MAX_LEN)? {
+                    self.node(u, depth + 1)?;
+                    self.node(u, depth + 1)?;
+                }
+                self.events.push(Event::mapping_end());
+            }
+            _ => {
+                self.events.push(Event::scalar(
+                    anchor.as_deref(),
+                    tag.as_deref(),
+                    &value(u)?,
+                    implicit,
+                    implicit,
+                    u.arbitrary()?,
+                ));
+            }
+        }
+        self.anchors.extend(anchor);
+        Ok(())
+    }
+}
+
+/// A scalar node, or an empty collection, as the items and pairs of a node
+/// are only meaningful in a document.
+impl<'a> Arbitrary<'a> for NodeData {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=2)? {
+            0 => NodeData::Scalar {
+                value: value(u)?,
+                style: u.arbitrary()?,
+            },
+            1 => NodeData::Sequence {
+                items: Vec::new(),
+                style: u.arbitrary()?,
+            },
+            _ => NodeData::Mapping {
+                pairs: Vec::new(),
+                style: u.arbitrary()?,
+            },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Node {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let data: NodeData = u.arbitrary()?;
+        let tag = tag(u)?.or_else(|| data_default_tag(&data).map(str::to_string));
+        Ok(Node {
+            data,
+            tag,
+            ..Node::default()
+        })
+    }
+}
+
+fn data_default_tag(data: &NodeData) -> Option<&'static str> {
+    match data {
+        NodeData::NoNode => None,
+        NodeData::Scalar { .. } => Some(crate::DEFAULT_SCALAR_TAG),
+        NodeData::Sequence { .. } => Some(crate::DEFAULT_SEQUENCE_TAG),
+        NodeData::Mapping { .. } => Some(crate::DEFAULT_MAPPING_TAG),
+    }
+}
+
+/// A document with a tree of nodes, where scalars may be shared by several
+/// collections, so that they are dumped with aliases.
+impl<'a> Arbitrary<'a> for Document {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let tag_directives: Vec<TagDirective> = if u.ratio(1, 4)? {
+            vec![u.arbitrary()?]
+        } else {
+            Vec::new()
+        };
+        let mut document = Document::new(
+            u.arbitrary()?,
+            &tag_directives,
+            u.arbitrary()?,
+            u.arbitrary()?,
+        );
+        document_node(&mut document, u, 0)?;
+        Ok(document)
+    }
+}
+
+/// Add a node and its content to `document`, and return its index.
+fn document_node(document: &mut Document, u: &mut Unstructured<'_>, depth: usize) -> Result<i32> {
+    let kind = if depth >= MAX_DEPTH {
+        0
+    } else {
+        u.int_in_range(0..=3)?
+    };
+    let tag = tag(u)?;
+    let tag = tag.as_deref();
+    Ok(match kind {
+        1 => {
+            let sequence = document.add_sequence(tag, u.arbitrary()?);
+            for _ in 0..u.int_in_range(0..=MAX_LEN)? {
+                let item = document_node(document, u, depth + 1)?;
+                document.append_sequence_item(sequence, item);
+            }
+            sequence
+        }
+        2 => {
+            let mapping = document.add_mapping(tag, u.arbitrary()?);
+            for _ in 0..u.int_in_range(0..=MAX_LEN)? {
+                let key = document_node(document, u, depth + 1)?;
+                let value = document_node(document, u, depth + 1)?;
+                document.yaml_document_append_mapping_pair(mapping, key, value);
+            }
+            mapping
+        }
+        3 if document.nodes.len() > 1 => {
+            // Share a scalar that is already in the document, other than the
+            // root.
+            let scalars: Vec<i32> = (2..=document.nodes.len() as i32)
+                .filter(|index| {
+                    matches!(
+                        document.nodes[*index as usize - 1].data,
+                        NodeData::Scalar { .. }
+                    )
+                })
+                .collect();
+            match u.choose(&scalars) {
+                Ok(index) => *index,
+                Err(_) => document.add_scalar(tag, &value(u)?, u.arbitrary()?),
+            }
+        }
+        _ => document.add_scalar(tag, &value(u)?, u.arbitrary()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArbitraryEventStream;
+    use crate::{emit_events, parse_events, validate_event_stream, Document, Event, EventData};
+    use arbitrary::{Arbitrary, Unstructured};
+
+    /// Random bytes from a fixed seed, for reproducible runs.
+    fn random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 32) as u8
+            })
+            .collect()
+    }
+
+    /// The content of events: scalar values, tags, anchors, and structure,
+    /// without the presentation details that the emitter may change.
+    fn content(events: &[Event]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match &event.data {
+                EventData::StreamStart { .. } | EventData::StreamEnd => None,
+                EventData::DocumentStart { .. } => Some("+DOC".to_string()),
+                EventData::DocumentEnd { .. } => Some("-DOC".to_string()),
+                EventData::Alias { anchor } => Some(format!("*{anchor}")),
+                EventData::Scalar {
+                    anchor, tag, value, ..
+                } => Some(format!("={anchor:?} {tag:?} {value:?}")),
+                EventData::SequenceStart { anchor, tag, .. } => {
+                    Some(format!("+SEQ {anchor:?} {tag:?}"))
+                }
+                EventData::SequenceEnd => Some("-SEQ".to_string()),
+                EventData::MappingStart { anchor, tag, .. } => {
+                    Some(format!("+MAP {anchor:?} {tag:?}"))
+                }
+                EventData::MappingEnd => Some("-MAP".to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn event_streams_round_trip() {
+        for seed in 0..500 {
+            let bytes = random_bytes(seed, 512);
+            let mut u = Unstructured::new(&bytes);
+            let ArbitraryEventStream(events) = ArbitraryEventStream::arbitrary(&mut u).unwrap();
+            validate_event_stream(&events).unwrap();
+            let expected = content(&events);
+            let output = emit_events(events).unwrap();
+            let parsed =
+                parse_events(&output).unwrap_or_else(|err| panic!("{seed}: {err}\n{output}"));
+            assert_eq!(content(&parsed), expected, "{seed}:\n{output}");
+        }
+    }
+
+    #[test]
+    fn documents_round_trip() {
+        for seed in 0..500 {
+            let bytes = random_bytes(seed, 512);
+            let mut u = Unstructured::new(&bytes);
+            let document = Document::arbitrary(&mut u).unwrap();
+            let mut output = Vec::new();
+            let mut emitter = crate::Emitter::new();
+            emitter.set_output(&mut output);
+            document.clone().dump(&mut emitter).unwrap();
+            emitter.close().unwrap();
+            drop(emitter);
+            let output = String::from_utf8(output).unwrap();
+            let loaded = crate::parse_documents(&output)
+                .unwrap_or_else(|err| panic!("{seed}: {err}\n{output}"));
+            assert_eq!(loaded.len(), 1, "{seed}:\n{output}");
+            assert!(loaded[0].deep_eq(&document), "{seed}:\n{output}");
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::pin::Pin;
+use std::task::Poll;
+
+use tokio::io::{AsyncBufRead, AsyncWrite, AsyncWriteExt as _};
+
+use crate::emitter::DetachedEmitter;
+use crate::parser::ParserCheckpoint;
+use crate::{Emitter, Event, EventData, Parser, ParserState, Result};
+
+type Configure = Box<dyn Fn(&mut Parser) + Send>;
+
+/// A parser reading from a Tokio [`AsyncBufRead`], with the `tokio` feature.
+///
+/// The input is parsed as it arrives, and each event is returned as soon as
+/// the input read so far decides it. Between events, the parser only keeps a
+/// snapshot of its state and the input that is not parsed yet, so it is
+/// [`Send`] if the reader is.
+///
+/// When an event needs more input than has arrived, it is parsed again from
+/// the end of the previous event once more input is available. To keep the
+/// total work linear, the parser waits for the unparsed input to double
+/// before parsing it again, unless the reader has nothing more to read for
+/// now.
+pub struct AsyncParser<R> {
+    reader: R,
+    /// The input that is not parsed yet, from the offset of `checkpoint`.
+    input: Vec<u8>,
+    /// The length of `input` when it was last parsed.
+    parsed_length: usize,
+    /// The length of `input` at which it is parsed again.
+    wanted_length: usize,
+    /// The state of the parser after the last produced event, if any.
+    checkpoint: Option<ParserCheckpoint>,
+    /// The offset of the start of `input` in the whole input.
+    offset: usize,
+    eof: bool,
+    done: bool,
+    events: VecDeque<Result<Event>>,
+    configure: Option<Configure>,
+}
+
+/// The input of an [`AsyncParser`] that has arrived so far. Reading past it
+/// before the end of the input fails with [`std::io::ErrorKind::WouldBlock`].
+struct ArrivedInput<'a> {
+    input: &'a [u8],
+    eof: bool,
+    starved: bool,
+}
+
+impl std::io::Read for ArrivedInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for ArrivedInput<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.input.is_empty() && !self.eof {
+            self.starved = true;
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        Ok(self.input)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.input = &self.input[amt..];
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncParser<R> {
+    /// Create a parser for `reader`.
+    pub fn from_reader(reader: R) -> AsyncParser<R> {
+        AsyncParser {
+            reader,
+            input: Vec::new(),
+            parsed_length: 0,
+            wanted_length: 1,
+            checkpoint: None,
+            offset: 0,
+            eof: false,
+            done: false,
+            events: VecDeque::new(),
+            configure: None,
+        }
+    }
+
+    /// Set a function to configure the [`Parser`], for settings like
+    /// [`Parser::set_encoding()`].
+    ///
+    /// The function is called again every time the input is parsed, so a
+    /// trace hook set by it may see the steps of an event more than once.
+    #[must_use]
+    pub fn with_config(mut self, configure: impl Fn(&mut Parser) + Send + 'static) -> Self {
+        self.configure = Some(Box::new(configure));
+        self
+    }
+
+    /// Produce the next event, or `None` after the STREAM-END event or an
+    /// error.
+    pub async fn next_event(&mut self) -> Option<Result<Event>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(event);
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(err) = self.read().await {
+                self.done = true;
+                return Some(Err(err.into()));
+            }
+            self.parse();
+        }
+    }
+
+    /// Read until the input is long enough to be parsed again, the reader
+    /// has nothing more to read for now, or the input ends.
+    async fn read(&mut self) -> std::io::Result<()> {
+        while !self.eof && self.input.len() < self.wanted_length {
+            let reader = &mut self.reader;
+            let input = &mut self.input;
+            let parsed_length = self.parsed_length;
+            let read = std::future::poll_fn(|cx| match Pin::new(&mut *reader).poll_fill_buf(cx) {
+                Poll::Ready(Ok(chunk)) => {
+                    let n = chunk.len();
+                    input.extend_from_slice(chunk);
+                    Pin::new(&mut *reader).consume(n);
+                    Poll::Ready(Ok(Some(n)))
+                }
+                Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                Poll::Pending if input.len() > parsed_length => Poll::Ready(Ok(None)),
+                Poll::Pending => Poll::Pending,
+            })
+            .await?;
+            match read {
+                Some(0) => self.eof = true,
+                Some(_) => {}
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the events that the input read so far decides.
+    fn parse(&mut self) {
+        let mut input = ArrivedInput {
+            input: &self.input,
+            eof: self.eof,
+            starved: false,
+        };
+        let (checkpoint, err) = {
+            let mut parser = Parser::new();
+            if let Some(configure) = &self.configure {
+                configure(&mut parser);
+            }
+            match self.checkpoint.take() {
+                Some(checkpoint) => parser.restore(checkpoint, &mut input),
+                None => parser.set_input(&mut input),
+            }
+            loop {
+                // Until the first event, the input is parsed from the start,
+                // to detect its encoding again.
+                let checkpoint =
+                    (parser.state != ParserState::StreamStart).then(|| parser.checkpoint());
+                match parser.parse() {
+                    Ok(event) => {
+                        let end = matches!(event.data, EventData::StreamEnd);
+                        self.events.push_back(Ok(event));
+                        if end {
+                            self.done = true;
+                            return;
+                        }
+                    }
+                    Err(err) => break (checkpoint, err),
+                }
+            }
+        };
+        if !input.starved {
+            self.events.push_back(Err(err));
+            self.done = true;
+            return;
+        }
+        // Keep the input of the event that needs more, and parse it again
+        // once the input has doubled.
+        if let Some(checkpoint) = checkpoint {
+            let consumed = checkpoint.input_offset() - self.offset;
+            self.input.drain(..consumed);
+            self.offset += consumed;
+            self.checkpoint = Some(checkpoint);
+        }
+        self.parsed_length = self.input.len();
+        self.wanted_length = self.input.len() + self.input.len().max(1);
+    }
+}
+
+/// An emitter writing to a Tokio [`AsyncWrite`], with the `tokio` feature.
+///
+/// The output of each event is collected by an [`Emitter`], and written to
+/// the writer before [`AsyncEmitter::emit()`] returns. Between events, only
+/// the state of the emitter is kept, so the `AsyncEmitter` is [`Send`] if the
+/// writer is.
+pub struct AsyncEmitter<W> {
+    emitter: Option<DetachedEmitter>,
+    writer: W,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncEmitter<W> {
+    /// Create an emitter for `writer`.
+    pub fn from_writer(writer: W) -> AsyncEmitter<W> {
+        AsyncEmitter {
+            emitter: Some(Emitter::new().detach()),
+            writer,
+        }
+    }
+
+    /// Set a function to configure the [`Emitter`] before the first event,
+    /// for settings like [`Emitter::set_indent()`]. The output of the emitter
+    /// must not be set.
+    #[must_use]
+    pub fn with_config(mut self, configure: impl FnOnce(&mut Emitter)) -> Self {
+        let mut emitter = Emitter::new();
+        configure(&mut emitter);
+        self.emitter = Some(emitter.detach());
+        self
+    }
+
+    /// Emit an event, and write its output.
+    ///
+    /// The emitter may need the following events to decide how to write an
+    /// event, so its output can be written with a later event.
+    pub async fn emit(&mut self, event: Event) -> Result<()> {
+        let output = self.with_emitter(|emitter| {
+            emitter.emit(event)?;
+            emitter.flush()
+        })?;
+        self.write_output(&output).await
+    }
+
+    /// Write all output, and flush the writer.
+    pub async fn flush(&mut self) -> Result<()> {
+        let output = self.with_emitter(Emitter::flush)?;
+        self.write_output(&output).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// The writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Run `f` with the emitter, and return the output it flushed.
+    fn with_emitter(
+        &mut self,
+        f: impl FnOnce(&mut Emitter<'static>) -> Result<()>,
+    ) -> Result<Vec<u8>> {
+        let mut emitter = self
+            .emitter
+            .take()
+            .expect("emitter should be detached")
+            .attach();
+        let result = f(&mut emitter);
+        let output = emitter.take_output();
+        self.emitter = Some(emitter.detach());
+        result.map(|()| output)
+    }
+
+    async fn write_output(&mut self, output: &[u8]) -> Result<()> {
+        if !output.is_empty() {
+            self.writer.write_all(output).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _, BufReader};
+
+    use std::task::Poll;
+
+    use super::{AsyncEmitter, AsyncParser};
+    use crate::{Encoding, Event, EventData, Result};
+
+    const INPUT: &str = "%YAML 1.2\n---\na: [b, 'c']\n? |\n  literal\n: &x {d: e}\n--- !t x\n...\n";
+
+    fn block_on<T>(future: impl core::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn send() {
+        assert_send::<AsyncParser<BufReader<tokio::io::DuplexStream>>>();
+        assert_send::<AsyncEmitter<tokio::io::DuplexStream>>();
+    }
+
+    #[test]
+    fn parse_in_chunks() {
+        let expected = crate::parse_events(INPUT).unwrap();
+        for chunk_size in [1, 2, 5, 64] {
+            let events = block_on(async {
+                let (mut client, server) = tokio::io::duplex(8);
+                let writer = tokio::spawn(async move {
+                    for chunk in INPUT.as_bytes().chunks(chunk_size) {
+                        client.write_all(chunk).await.unwrap();
+                    }
+                });
+                let mut parser = AsyncParser::from_reader(BufReader::new(server));
+                let mut events = Vec::new();
+                while let Some(event) = parser.next_event().await {
+                    events.push(event.unwrap());
+                }
+                writer.await.unwrap();
+                events
+            });
+            assert_eq!(events, expected, "{chunk_size}");
+        }
+
+        // UTF-16 input, whose offsets differ from the marks.
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(
+            "a: é\nb: [𝄞, c]\n"
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes),
+        );
+        let events = block_on(async {
+            let (mut client, server) = tokio::io::duplex(3);
+            let writer = tokio::spawn(async move {
+                client.write_all(&utf16).await.unwrap();
+            });
+            let mut parser = AsyncParser::from_reader(BufReader::new(server));
+            let mut events = Vec::new();
+            while let Some(event) = parser.next_event().await {
+                events.push(event.unwrap().data);
+            }
+            writer.await.unwrap();
+            events
+        });
+        assert_eq!(
+            events[0],
+            EventData::StreamStart {
+                encoding: Encoding::Utf16Le
+            }
+        );
+        let values: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                EventData::Scalar { value, .. } => Some(value.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values, ["a", "é", "b", "𝄞", "c"]);
+
+        let error = block_on(async {
+            let mut parser = AsyncParser::from_reader("a: [b".as_bytes());
+            let mut results: Vec<Result<Event>> = Vec::new();
+            while let Some(event) = parser.next_event().await {
+                results.push(event);
+            }
+            results.pop().unwrap().unwrap_err()
+        });
+        assert_eq!(error.problem(), "found unexpected end of stream");
+    }
+
+    #[test]
+    fn parse_before_end_of_input() {
+        /// Poll `future` once, as a writer that is still open would not wake
+        /// it again.
+        async fn poll_once<F: core::future::Future>(future: F) -> Option<F::Output> {
+            let mut future = core::pin::pin!(future);
+            match core::future::poll_fn(|cx| Poll::Ready(future.as_mut().poll(cx))).await {
+                Poll::Ready(output) => Some(output),
+                Poll::Pending => None,
+            }
+        }
+
+        block_on(async {
+            let (mut client, server) = tokio::io::duplex(64);
+            let mut parser = AsyncParser::from_reader(BufReader::new(server));
+            client.write_all(b"- a\n- b").await.unwrap();
+            for expected in ["StreamStart", "DocumentStart", "SequenceStart", "a"] {
+                let event = poll_once(parser.next_event()).await;
+                let found = match event.unwrap().unwrap().unwrap().data {
+                    EventData::Scalar { value, .. } => value,
+                    data => format!("{data:?}"),
+                };
+                assert!(found.starts_with(expected), "{found}");
+            }
+            // The plain scalar `b` may continue, so it waits for more input.
+            assert!(poll_once(parser.next_event()).await.is_none());
+            client.write_all(b"c\n").await.unwrap();
+            drop(client);
+            let event = parser.next_event().await.unwrap().unwrap();
+            assert!(matches!(event.data, EventData::Scalar { value, .. } if value == "bc"));
+        });
+    }
+
+    #[test]
+    fn emit_in_chunks() {
+        let events = crate::parse_events(INPUT).unwrap();
+        let expected = crate::emit_events(events.clone()).unwrap();
+        let output = block_on(async {
+            let (client, mut server) = tokio::io::duplex(8);
+            let reader = tokio::spawn(async move {
+                let mut output = String::new();
+                server.read_to_string(&mut output).await.unwrap();
+                output
+            });
+            let mut emitter = AsyncEmitter::from_writer(client);
+            for event in events {
+                emitter.emit(event).await.unwrap();
+            }
+            emitter.flush().await.unwrap();
+            drop(emitter.into_inner());
+            reader.await.unwrap()
+        });
+        assert_eq!(output, expected);
+
+        let output = block_on(async {
+            let mut emitter = AsyncEmitter::from_writer(Vec::new()).with_config(|emitter| {
+                emitter.set_indent(4);
+            });
+            for event in crate::parse_events("a:\n  b: c\n").unwrap() {
+                emitter.emit(event).await.unwrap();
+            }
+            emitter.flush().await.unwrap();
+            emitter.into_inner()
+        });
+        assert_eq!(output, b"a:\n    b: c\n");
+    }
+}
@@ -0,0 +1,117 @@
+use core::ops::Index;
+
+/// The working buffer of a scanner, with the decoded characters that have not
+/// been consumed yet.
+///
+/// Characters are consumed from the front by advancing an index, and the
+/// consumed prefix is dropped by [`CharBuffer::compact()`] before the reader
+/// appends more characters at the back. Unlike a `VecDeque`, the characters
+/// are always contiguous, so lookahead is plain indexing.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CharBuffer {
+    chars: Vec<char>,
+    /// The number of consumed characters at the start of `chars`.
+    start: usize,
+}
+
+impl CharBuffer {
+    pub(crate) fn with_capacity(capacity: usize) -> CharBuffer {
+        CharBuffer {
+            chars: Vec::with_capacity(capacity),
+            start: 0,
+        }
+    }
+
+    /// The number of characters that have not been consumed.
+    pub(crate) fn len(&self) -> usize {
+        self.chars.len() - self.start
+    }
+
+    /// The characters that have not been consumed.
+    pub(crate) fn as_slice(&self) -> &[char] {
+        &self.chars[self.start..]
+    }
+
+    pub(crate) fn iter(&self) -> core::slice::Iter<'_, char> {
+        self.as_slice().iter()
+    }
+
+    /// The character at `offset` from the front.
+    pub(crate) fn get(&self, offset: usize) -> Option<&char> {
+        self.chars.get(self.start + offset)
+    }
+
+    pub(crate) fn front(&self) -> Option<&char> {
+        self.get(0)
+    }
+
+    pub(crate) fn pop_front(&mut self) -> Option<char> {
+        let ch = self.front().copied()?;
+        self.start += 1;
+        Some(ch)
+    }
+
+    /// Consume `count` characters, and return them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if fewer than `count` characters are buffered.
+    pub(crate) fn consume(&mut self, count: usize) -> &[char] {
+        let consumed = &self.chars[self.start..self.start + count];
+        self.start += count;
+        consumed
+    }
+
+    pub(crate) fn push_back(&mut self, ch: char) {
+        self.chars.push(ch);
+    }
+
+    /// Drop the consumed characters, moving the others to the start, so that
+    /// the buffer does not grow beyond what is not consumed yet.
+    pub(crate) fn compact(&mut self) {
+        if self.start != 0 {
+            self.chars.drain(..self.start);
+            self.start = 0;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.chars.clear();
+        self.start = 0;
+    }
+}
+
+impl Index<usize> for CharBuffer {
+    type Output = char;
+
+    fn index(&self, offset: usize) -> &char {
+        &self.as_slice()[offset]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CharBuffer;
+
+    #[test]
+    fn consume_and_compact() {
+        let mut buffer = CharBuffer::with_capacity(4);
+        for ch in "abcdé".chars() {
+            buffer.push_back(ch);
+        }
+        assert_eq!(buffer.pop_front(), Some('a'));
+        assert_eq!(buffer.consume(2), ['b', 'c']);
+        assert_eq!(
+            (buffer.len(), buffer[0], buffer.get(1)),
+            (2, 'd', Some(&'é'))
+        );
+        buffer.compact();
+        buffer.push_back('f');
+        assert_eq!(buffer.as_slice(), ['d', 'é', 'f']);
+        assert_eq!(buffer.chars.len(), 3);
+        assert_eq!(buffer.get(3), None);
+        buffer.clear();
+        assert!(buffer.as_slice().is_empty());
+        assert_eq!(buffer.pop_front(), None);
+    }
+}
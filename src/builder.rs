@@ -0,0 +1,654 @@
+use crate::{
+    Document, Error, MappingStyle, NodeData, Result, ScalarStyle, SequenceStyle, TagDirective,
+    TagOrigin, VersionDirective,
+};
+
+/// A builder for constructing a [`Document`] in code, without going through
+/// the low-level [`Document::add_scalar()`] and related methods.
+///
+/// The children of a collection are added in a closure, which receives a
+/// [`SequenceBuilder`] or a [`MappingBuilder`] for the collection. The tag,
+/// anchor and style of the node that was just added can be set through the
+/// returned [`NodeBuilder`], or for the root node, through the methods of the
+/// `DocumentBuilder` itself.
+///
+/// Mistakes, like defining an anchor twice or referring to an undefined
+/// anchor, do not panic: the first one is reported by
+/// [`build()`](DocumentBuilder::build).
+///
+/// ```
+/// # use libyaml_safer::{DocumentBuilder, Emitter};
+/// let document = DocumentBuilder::new()
+///     .mapping(|m| {
+///         m.entry_str("name", "demo");
+///         m.entry_seq("items", |s| {
+///             s.scalar("a");
+///             s.scalar("b");
+///         });
+///     })
+///     .build()
+///     .unwrap();
+///
+/// let mut output = Vec::new();
+/// let mut emitter = Emitter::new();
+/// emitter.set_output(&mut output);
+/// document.dump(&mut emitter).unwrap();
+/// emitter.close().unwrap();
+/// assert_eq!(output, b"name: demo\nitems:\n- a\n- b\n");
+/// ```
+#[derive(Debug)]
+pub struct DocumentBuilder {
+    state: State,
+}
+
+/// Adds the items of a sequence node. See [`DocumentBuilder`].
+#[derive(Debug)]
+pub struct SequenceBuilder<'a> {
+    state: &'a mut State,
+    target: Target,
+}
+
+/// Where a [`SequenceBuilder`] puts the nodes it adds.
+#[derive(Debug)]
+enum Target {
+    /// The items of a sequence node.
+    Sequence(i32),
+    /// A key or a value of [`MappingBuilder::entry()`].
+    Nodes(Vec<i32>),
+}
+
+/// Adds the pairs of a mapping node. See [`DocumentBuilder`].
+#[derive(Debug)]
+pub struct MappingBuilder<'a> {
+    state: &'a mut State,
+    mapping: i32,
+}
+
+/// Sets the properties of a node that was just added with a
+/// [`SequenceBuilder`] or a [`MappingBuilder`].
+#[derive(Debug)]
+pub struct NodeBuilder<'a> {
+    state: &'a mut State,
+    node: i32,
+}
+
+#[derive(Debug)]
+struct State {
+    document: Document,
+    root: i32,
+    error: Option<Error>,
+}
+
+impl State {
+    fn fail(&mut self, problem: &'static str, detail: Option<String>) {
+        if self.error.is_none() {
+            self.error = Some(Error::builder(problem, detail));
+        }
+    }
+
+    fn scalar(&mut self, value: &str) -> i32 {
+        self.document.add_scalar(None, value, ScalarStyle::Any)
+    }
+
+    fn sequence(&mut self, f: impl FnOnce(&mut SequenceBuilder)) -> i32 {
+        let sequence = self.document.add_sequence(None, SequenceStyle::Any);
+        f(&mut SequenceBuilder {
+            state: self,
+            target: Target::Sequence(sequence),
+        });
+        sequence
+    }
+
+    fn mapping(&mut self, f: impl FnOnce(&mut MappingBuilder)) -> i32 {
+        let mapping = self.document.add_mapping(None, MappingStyle::Any);
+        f(&mut MappingBuilder {
+            state: self,
+            mapping,
+        });
+        mapping
+    }
+
+    fn alias(&mut self, anchor: &str) -> Option<i32> {
+        let node = self.document.anchor(anchor);
+        if node.is_none() {
+            self.fail("found undefined alias", Some(format!("alias *{anchor}")));
+        }
+        node
+    }
+
+    fn set_tag(&mut self, node: i32, tag: &str) {
+        if let Some(node) = self.document.get_node_mut(node) {
+            node.tag = Some(String::from(tag));
+            node.tag_origin = TagOrigin::Explicit;
+        }
+    }
+
+    fn set_anchor(&mut self, node: i32, anchor: &str) {
+        if self.document.anchor(anchor).is_some() {
+            self.fail("found duplicate anchor", Some(format!("anchor &{anchor}")));
+        } else {
+            self.document.anchors.push((String::from(anchor), node));
+        }
+    }
+
+    fn set_scalar_style(&mut self, node: i32, scalar_style: ScalarStyle) {
+        match self.document.get_node_mut(node).map(|node| &mut node.data) {
+            Some(NodeData::Scalar { style, .. }) => *style = scalar_style,
+            _ => self.fail("found a scalar style for a node that is not a scalar", None),
+        }
+    }
+
+    fn set_sequence_style(&mut self, node: i32, sequence_style: SequenceStyle) {
+        match self.document.get_node_mut(node).map(|node| &mut node.data) {
+            Some(NodeData::Sequence { style, .. }) => *style = sequence_style,
+            _ => self.fail(
+                "found a sequence style for a node that is not a sequence",
+                None,
+            ),
+        }
+    }
+
+    fn set_mapping_style(&mut self, node: i32, mapping_style: MappingStyle) {
+        match self.document.get_node_mut(node).map(|node| &mut node.data) {
+            Some(NodeData::Mapping { style, .. }) => *style = mapping_style,
+            _ => self.fail(
+                "found a mapping style for a node that is not a mapping",
+                None,
+            ),
+        }
+    }
+
+    fn set_root(&mut self, add: impl FnOnce(&mut Self) -> i32) {
+        if self.root == 0 {
+            self.root = add(self);
+        } else {
+            self.fail("found a second root node", None);
+        }
+    }
+}
+
+impl Default for DocumentBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocumentBuilder {
+    /// Create a builder for a document with implicit start and end
+    /// indicators.
+    pub fn new() -> Self {
+        Self {
+            state: State {
+                document: Document::new(None, &[], true, true),
+                root: 0,
+                error: None,
+            },
+        }
+    }
+
+    /// Set the `%YAML` directive of the document.
+    #[must_use]
+    pub fn version_directive(mut self, major: i32, minor: i32) -> Self {
+        self.state.document.version_directive = Some(VersionDirective { major, minor });
+        self
+    }
+
+    /// Add a `%TAG` directive to the document.
+    #[must_use]
+    pub fn tag_directive(mut self, handle: &str, prefix: &str) -> Self {
+        self.state.document.tag_directives.push(TagDirective {
+            handle: String::from(handle),
+            prefix: String::from(prefix),
+        });
+        self
+    }
+
+    /// Set whether the document start and end indicators are implicit.
+    #[must_use]
+    pub fn implicit(mut self, start_implicit: bool, end_implicit: bool) -> Self {
+        self.state.document.start_implicit = start_implicit;
+        self.state.document.end_implicit = end_implicit;
+        self
+    }
+
+    /// Use a scalar as the root node.
+    #[must_use]
+    pub fn scalar(mut self, value: &str) -> Self {
+        self.state.set_root(|state| state.scalar(value));
+        self
+    }
+
+    /// Use a sequence as the root node, with the items added by `f`.
+    #[must_use]
+    pub fn sequence(mut self, f: impl FnOnce(&mut SequenceBuilder)) -> Self {
+        self.state.set_root(|state| state.sequence(f));
+        self
+    }
+
+    /// Use a mapping as the root node, with the pairs added by `f`.
+    #[must_use]
+    pub fn mapping(mut self, f: impl FnOnce(&mut MappingBuilder)) -> Self {
+        self.state.set_root(|state| state.mapping(f));
+        self
+    }
+
+    /// Set the tag of the root node. See [`NodeBuilder::tag()`].
+    #[must_use]
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.root().tag(tag);
+        self
+    }
+
+    /// Attach an anchor to the root node.
+    #[must_use]
+    pub fn anchor(mut self, anchor: &str) -> Self {
+        self.root().anchor(anchor);
+        self
+    }
+
+    /// Set the style of the root node, which must be a scalar.
+    #[must_use]
+    pub fn scalar_style(mut self, style: ScalarStyle) -> Self {
+        self.root().scalar_style(style);
+        self
+    }
+
+    /// Set the style of the root node, which must be a sequence.
+    #[must_use]
+    pub fn sequence_style(mut self, style: SequenceStyle) -> Self {
+        self.root().sequence_style(style);
+        self
+    }
+
+    /// Set the style of the root node, which must be a mapping.
+    #[must_use]
+    pub fn mapping_style(mut self, style: MappingStyle) -> Self {
+        self.root().mapping_style(style);
+        self
+    }
+
+    /// Finish the document.
+    ///
+    /// Fails with an [`ErrorKind::Builder`](crate::ErrorKind::Builder) error
+    /// if the document has no root node, or for the first mistake made while
+    /// building it.
+    pub fn build(self) -> Result<Document> {
+        let State {
+            document,
+            root,
+            error,
+        } = self.state;
+        match error {
+            Some(error) => Err(error),
+            None if root == 0 => Err(Error::builder("found no root node", None)),
+            None => Ok(document),
+        }
+    }
+
+    fn root(&mut self) -> NodeBuilder<'_> {
+        if self.state.root == 0 {
+            self.state.fail("found no root node", None);
+        }
+        NodeBuilder {
+            node: self.state.root,
+            state: &mut self.state,
+        }
+    }
+}
+
+impl SequenceBuilder<'_> {
+    /// Add a plain scalar item.
+    pub fn scalar(&mut self, value: &str) -> NodeBuilder<'_> {
+        let item = self.state.scalar(value);
+        self.push(item)
+    }
+
+    /// Add a sequence item, with its items added by `f`.
+    pub fn sequence(&mut self, f: impl FnOnce(&mut SequenceBuilder)) -> NodeBuilder<'_> {
+        let item = self.state.document.add_sequence(None, SequenceStyle::Any);
+        self.append(item);
+        f(&mut SequenceBuilder {
+            state: self.state,
+            target: Target::Sequence(item),
+        });
+        self.node(item)
+    }
+
+    /// Add a mapping item, with its pairs added by `f`.
+    pub fn mapping(&mut self, f: impl FnOnce(&mut MappingBuilder)) -> NodeBuilder<'_> {
+        let item = self.state.document.add_mapping(None, MappingStyle::Any);
+        self.append(item);
+        f(&mut MappingBuilder {
+            state: self.state,
+            mapping: item,
+        });
+        self.node(item)
+    }
+
+    /// Add an alias to the node with the anchor `anchor`, which must have
+    /// been attached already.
+    pub fn alias(&mut self, anchor: &str) {
+        if let Some(item) = self.state.alias(anchor) {
+            self.append(item);
+        }
+    }
+
+    fn push(&mut self, item: i32) -> NodeBuilder<'_> {
+        self.append(item);
+        self.node(item)
+    }
+
+    fn append(&mut self, item: i32) {
+        match self.target {
+            Target::Sequence(sequence) => {
+                self.state.document.append_sequence_item(sequence, item);
+            }
+            Target::Nodes(ref mut nodes) => nodes.push(item),
+        }
+    }
+
+    fn node(&mut self, node: i32) -> NodeBuilder<'_> {
+        NodeBuilder {
+            state: self.state,
+            node,
+        }
+    }
+}
+
+impl MappingBuilder<'_> {
+    /// Add a pair of plain scalars.
+    ///
+    /// Returns a builder for the value.
+    pub fn entry_str(&mut self, key: &str, value: &str) -> NodeBuilder<'_> {
+        let key = self.state.scalar(key);
+        let value = self.state.scalar(value);
+        self.push(key, value)
+    }
+
+    /// Add a pair with a plain scalar key and a sequence value, with its items
+    /// added by `f`.
+    ///
+    /// Returns a builder for the value.
+    pub fn entry_seq(
+        &mut self,
+        key: &str,
+        f: impl FnOnce(&mut SequenceBuilder),
+    ) -> NodeBuilder<'_> {
+        let key = self.state.scalar(key);
+        let value = self.state.document.add_sequence(None, SequenceStyle::Any);
+        self.state
+            .document
+            .yaml_document_append_mapping_pair(self.mapping, key, value);
+        f(&mut SequenceBuilder {
+            state: self.state,
+            target: Target::Sequence(value),
+        });
+        self.node(value)
+    }
+
+    /// Add a pair with a plain scalar key and a mapping value, with its pairs
+    /// added by `f`.
+    ///
+    /// Returns a builder for the value.
+    pub fn entry_map(&mut self, key: &str, f: impl FnOnce(&mut MappingBuilder)) -> NodeBuilder<'_> {
+        let key = self.state.scalar(key);
+        let value = self.state.document.add_mapping(None, MappingStyle::Any);
+        self.state
+            .document
+            .yaml_document_append_mapping_pair(self.mapping, key, value);
+        f(&mut MappingBuilder {
+            state: self.state,
+            mapping: value,
+        });
+        self.node(value)
+    }
+
+    /// Add a pair with a plain scalar key and an alias to the node with the
+    /// anchor `anchor`, which must have been attached already.
+    pub fn entry_alias(&mut self, key: &str, anchor: &str) {
+        if let Some(value) = self.state.alias(anchor) {
+            let key = self.state.scalar(key);
+            self.push(key, value);
+        }
+    }
+
+    /// Add a pair with a key and a value of any kind, each added by a closure
+    /// that must add exactly one item to the given sequence builder.
+    ///
+    /// This is the way to add complex keys, or to set the properties of keys.
+    pub fn entry(
+        &mut self,
+        key: impl FnOnce(&mut SequenceBuilder),
+        value: impl FnOnce(&mut SequenceBuilder),
+    ) {
+        let key = self.single(key);
+        let value = self.single(value);
+        if let (Some(key), Some(value)) = (key, value) {
+            self.push(key, value);
+        }
+    }
+
+    fn single(&mut self, f: impl FnOnce(&mut SequenceBuilder)) -> Option<i32> {
+        let mut builder = SequenceBuilder {
+            state: self.state,
+            target: Target::Nodes(Vec::new()),
+        };
+        f(&mut builder);
+        match builder.target {
+            Target::Nodes(nodes) if nodes.len() == 1 => Some(nodes[0]),
+            _ => {
+                self.state
+                    .fail("found a pair without exactly one key and value", None);
+                None
+            }
+        }
+    }
+
+    fn push(&mut self, key: i32, value: i32) -> NodeBuilder<'_> {
+        self.state
+            .document
+            .yaml_document_append_mapping_pair(self.mapping, key, value);
+        self.node(value)
+    }
+
+    fn node(&mut self, node: i32) -> NodeBuilder<'_> {
+        NodeBuilder {
+            state: self.state,
+            node,
+        }
+    }
+}
+
+impl NodeBuilder<'_> {
+    /// Set the tag of the node, as a full tag like
+    /// [`DEFAULT_SCALAR_TAG`](crate::DEFAULT_SCALAR_TAG) or a local tag like
+    /// `!point`.
+    ///
+    /// The tag is always written by the emitter.
+    pub fn tag(&mut self, tag: &str) -> &mut Self {
+        self.state.set_tag(self.node, tag);
+        self
+    }
+
+    /// Attach an anchor to the node, so that later aliases can refer to it.
+    pub fn anchor(&mut self, anchor: &str) -> &mut Self {
+        self.state.set_anchor(self.node, anchor);
+        self
+    }
+
+    /// Set the style of the node, which must be a scalar.
+    pub fn scalar_style(&mut self, style: ScalarStyle) -> &mut Self {
+        self.state.set_scalar_style(self.node, style);
+        self
+    }
+
+    /// Set the style of the node, which must be a sequence.
+    pub fn sequence_style(&mut self, style: SequenceStyle) -> &mut Self {
+        self.state.set_sequence_style(self.node, style);
+        self
+    }
+
+    /// Set the style of the node, which must be a mapping.
+    pub fn mapping_style(&mut self, style: MappingStyle) -> &mut Self {
+        self.state.set_mapping_style(self.node, style);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Document, DocumentBuilder, Emitter, ErrorKind, MappingStyle, Parser, ScalarStyle,
+        SequenceStyle,
+    };
+
+    fn load(input: &str) -> Document {
+        let mut parser = Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        Document::load(&mut parser).unwrap()
+    }
+
+    fn dump(document: Document) -> String {
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        document.dump(&mut emitter).unwrap();
+        emitter.close().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn build() {
+        let document = DocumentBuilder::new()
+            .mapping(|m| {
+                m.entry_map("defaults", |m| {
+                    m.entry_str("adapter", "postgres");
+                    m.entry_str("port", "5432").tag("tag:yaml.org,2002:int");
+                })
+                .anchor("defaults");
+                m.entry_seq("hosts", |s| {
+                    s.scalar("alpha");
+                    s.scalar("two\nlines").scalar_style(ScalarStyle::Literal);
+                    s.sequence(|s| {
+                        s.scalar("x");
+                        s.scalar("y");
+                    })
+                    .sequence_style(SequenceStyle::Flow);
+                })
+                .tag("!hosts");
+                m.entry_map("test", |m| {
+                    m.entry_alias("base", "defaults");
+                    m.entry_str("database", "test")
+                        .scalar_style(ScalarStyle::DoubleQuoted);
+                });
+                m.entry(
+                    |k| {
+                        k.sequence(|s| {
+                            s.scalar("complex");
+                        })
+                        .sequence_style(SequenceStyle::Flow);
+                    },
+                    |v| {
+                        v.mapping(|_| {}).mapping_style(MappingStyle::Flow);
+                    },
+                );
+            })
+            .build()
+            .unwrap();
+
+        let expected = load(
+            "defaults: &a {adapter: postgres, port: !!int 5432}\n\
+             hosts: !hosts [alpha, \"two\\nlines\", [x, y]]\n\
+             test: {base: *a, database: test}\n\
+             [complex]: {}\n",
+        );
+        assert!(document.deep_eq(&expected));
+        assert_eq!(
+            dump(document),
+            "\
+defaults: &defaults
+  adapter: postgres
+  port: !!int 5432
+hosts: !hosts
+- alpha
+- |-
+  two
+  lines
+- [x, y]
+test:
+  base: *defaults
+  database: \"test\"
+? [complex]
+: {}
+"
+        );
+    }
+
+    #[test]
+    fn root_properties() {
+        let document = DocumentBuilder::new()
+            .version_directive(1, 1)
+            .tag_directive("!e!", "tag:example.com,2000:")
+            .scalar("value")
+            .tag("tag:example.com,2000:thing")
+            .scalar_style(ScalarStyle::SingleQuoted)
+            .build()
+            .unwrap();
+        assert_eq!(
+            dump(document),
+            "%YAML 1.1\n%TAG !e! tag:example.com,2000:\n--- !e!thing 'value'\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |builder: DocumentBuilder| {
+            let error = builder.build().unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::Builder);
+            error.to_string()
+        };
+
+        assert_eq!(
+            error(DocumentBuilder::new()),
+            "Builder error: found no root node"
+        );
+        assert_eq!(
+            error(DocumentBuilder::new().scalar("a").scalar("b")),
+            "Builder error: found a second root node"
+        );
+        assert_eq!(
+            error(DocumentBuilder::new().sequence(|s| {
+                s.scalar("a").anchor("x");
+                s.scalar("b").anchor("x");
+            })),
+            "Builder error: found duplicate anchor with anchor &x"
+        );
+        assert_eq!(
+            error(DocumentBuilder::new().sequence(|s| {
+                s.alias("x");
+                s.scalar("a").anchor("x");
+            })),
+            "Builder error: found undefined alias with alias *x"
+        );
+        assert_eq!(
+            error(
+                DocumentBuilder::new()
+                    .mapping(|_| {})
+                    .scalar_style(ScalarStyle::Plain)
+            ),
+            "Builder error: found a scalar style for a node that is not a scalar"
+        );
+        assert_eq!(
+            error(DocumentBuilder::new().mapping(|m| {
+                m.entry(
+                    |_| {},
+                    |v| {
+                        v.scalar("a");
+                    },
+                );
+            })),
+            "Builder error: found a pair without exactly one key and value"
+        );
+    }
+}
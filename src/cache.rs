@@ -0,0 +1,519 @@
+use std::io::{self, Read, Write};
+
+use crate::document::MappingIndexes;
+use crate::{
+    Chomping, Document, MappingStyle, Mark, Node, NodeData, NodePair, Presentation, ScalarStyle,
+    SequenceStyle, TagDirective, TagOrigin, VersionDirective,
+};
+
+const MAGIC: [u8; 4] = *b"YAMC";
+const FORMAT_VERSION: u8 = 4;
+
+const NODE_SCALAR: u8 = 1;
+const NODE_SEQUENCE: u8 = 2;
+const NODE_MAPPING: u8 = 3;
+
+impl Document {
+    /// Write the document to a compact binary cache.
+    ///
+    /// The cache preserves everything in the document, including marks, so
+    /// that a document read back with [`Document::read_cache()`] compares
+    /// equal to the original. The format is versioned but is specific to this
+    /// crate; it is not meant for exchange with other tools.
+    pub fn write_cache(&self, w: impl Write) -> io::Result<()> {
+        let mut w = w;
+        w.write_all(&MAGIC)?;
+        w.write_all(&[FORMAT_VERSION])?;
+
+        if let Some(version_directive) = self.version_directive {
+            write_u8(&mut w, 1)?;
+            write_i32(&mut w, version_directive.major)?;
+            write_i32(&mut w, version_directive.minor)?;
+        } else {
+            write_u8(&mut w, 0)?;
+        }
+        write_len(&mut w, self.tag_directives.len())?;
+        for tag_directive in &self.tag_directives {
+            write_str(&mut w, &tag_directive.handle)?;
+            write_str(&mut w, &tag_directive.prefix)?;
+        }
+        write_u8(&mut w, u8::from(self.start_implicit))?;
+        write_u8(&mut w, u8::from(self.end_implicit))?;
+        write_mark(&mut w, self.start_mark)?;
+        write_mark(&mut w, self.end_mark)?;
+
+        write_len(&mut w, self.nodes.len())?;
+        for node in &self.nodes {
+            if let Some(tag) = &node.tag {
+                write_u8(&mut w, 1)?;
+                write_str(&mut w, tag)?;
+            } else {
+                write_u8(&mut w, 0)?;
+            }
+            write_u8(&mut w, node.tag_origin as u8)?;
+            write_mark(&mut w, node.start_mark)?;
+            write_mark(&mut w, node.end_mark)?;
+            match &node.data {
+                NodeData::NoNode => {
+                    return Err(invalid_input("cannot cache an empty node"));
+                }
+                NodeData::Scalar { value, style } => {
+                    write_u8(&mut w, NODE_SCALAR)?;
+                    write_u8(&mut w, *style as u8)?;
+                    write_str(&mut w, value)?;
+                }
+                NodeData::Sequence { items, style } => {
+                    write_u8(&mut w, NODE_SEQUENCE)?;
+                    write_u8(&mut w, *style as u8)?;
+                    write_len(&mut w, items.len())?;
+                    for (i, item) in items.iter().enumerate() {
+                        write_i32(&mut w, *item)?;
+                        write_mark(&mut w, node.item_marks.get(i).copied().unwrap_or_default())?;
+                    }
+                }
+                NodeData::Mapping { pairs, style } => {
+                    write_u8(&mut w, NODE_MAPPING)?;
+                    write_u8(&mut w, *style as u8)?;
+                    write_len(&mut w, pairs.len())?;
+                    for pair in pairs {
+                        write_i32(&mut w, pair.key)?;
+                        write_i32(&mut w, pair.value)?;
+                        write_mark(&mut w, pair.key_mark)?;
+                    }
+                }
+            }
+            if let Some(presentation) = node.presentation {
+                write_u8(&mut w, 1)?;
+                write_u8(&mut w, presentation.chomping as u8)?;
+                if let Some(indent_indicator) = presentation.indent_indicator {
+                    write_u8(&mut w, 1)?;
+                    write_u8(&mut w, indent_indicator)?;
+                } else {
+                    write_u8(&mut w, 0)?;
+                }
+                write_u8(&mut w, u8::from(presentation.chomping_first))?;
+                write_u8(&mut w, u8::from(presentation.empty_inline))?;
+            } else {
+                write_u8(&mut w, 0)?;
+            }
+        }
+
+        write_len(&mut w, self.anchors.len())?;
+        for (anchor, index) in &self.anchors {
+            write_str(&mut w, anchor)?;
+            write_i32(&mut w, *index)?;
+        }
+        write_len(&mut w, self.alias_uses.len())?;
+        for (alias, mark, index) in &self.alias_uses {
+            write_str(&mut w, alias)?;
+            write_mark(&mut w, *mark)?;
+            write_i32(&mut w, *index)?;
+        }
+        Ok(())
+    }
+
+    /// Read a document from a binary cache written by
+    /// [`Document::write_cache()`].
+    ///
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] if the cache
+    /// was written by an incompatible version of this crate, or if it is
+    /// corrupt (for example, if it refers to nodes that do not exist).
+    pub fn read_cache(r: impl Read) -> io::Result<Document> {
+        let mut r = r;
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("not a document cache"));
+        }
+        if read_u8(&mut r)? != FORMAT_VERSION {
+            return Err(invalid_data("unsupported document cache version"));
+        }
+
+        let version_directive = if read_bool(&mut r)? {
+            let major = read_i32(&mut r)?;
+            let minor = read_i32(&mut r)?;
+            Some(VersionDirective { major, minor })
+        } else {
+            None
+        };
+        let count = read_len(&mut r)?;
+        let mut tag_directives = Vec::with_capacity(count.min(64));
+        for _ in 0..count {
+            let handle = read_string(&mut r)?;
+            let prefix = read_string(&mut r)?;
+            tag_directives.push(TagDirective { handle, prefix });
+        }
+        let start_implicit = read_bool(&mut r)?;
+        let end_implicit = read_bool(&mut r)?;
+        let start_mark = read_mark(&mut r)?;
+        let end_mark = read_mark(&mut r)?;
+
+        let count = read_len(&mut r)?;
+        let Ok(max_index) = i32::try_from(count) else {
+            return Err(invalid_data("too many nodes in document cache"));
+        };
+        let read_index = |r: &mut dyn Read| -> io::Result<i32> {
+            let index = read_i32(r)?;
+            if index < 1 || index > max_index {
+                return Err(invalid_data("node index out of range in document cache"));
+            }
+            Ok(index)
+        };
+        let mut nodes = Vec::with_capacity(count.min(1024));
+        for _ in 0..count {
+            let tag = if read_bool(&mut r)? {
+                Some(read_string(&mut r)?)
+            } else {
+                None
+            };
+            let tag_origin = tag_origin(read_u8(&mut r)?)?;
+            let node_start_mark = read_mark(&mut r)?;
+            let node_end_mark = read_mark(&mut r)?;
+            let mut item_marks = Vec::new();
+            let data = match read_u8(&mut r)? {
+                NODE_SCALAR => {
+                    let style = scalar_style(read_u8(&mut r)?)?;
+                    let value = read_string(&mut r)?;
+                    NodeData::Scalar { value, style }
+                }
+                NODE_SEQUENCE => {
+                    let style = sequence_style(read_u8(&mut r)?)?;
+                    let len = read_len(&mut r)?;
+                    let mut items = Vec::with_capacity(len.min(1024));
+                    for _ in 0..len {
+                        items.push(read_index(&mut r)?);
+                        item_marks.push(read_mark(&mut r)?);
+                    }
+                    NodeData::Sequence { items, style }
+                }
+                NODE_MAPPING => {
+                    let style = mapping_style(read_u8(&mut r)?)?;
+                    let len = read_len(&mut r)?;
+                    let mut pairs = Vec::with_capacity(len.min(1024));
+                    for _ in 0..len {
+                        let key = read_index(&mut r)?;
+                        let value = read_index(&mut r)?;
+                        let key_mark = read_mark(&mut r)?;
+                        pairs.push(NodePair {
+                            key,
+                            value,
+                            key_mark,
+                        });
+                    }
+                    NodeData::Mapping { pairs, style }
+                }
+                _ => return Err(invalid_data("invalid node type in document cache")),
+            };
+            let presentation = if read_bool(&mut r)? {
+                let chomping = chomping(read_u8(&mut r)?)?;
+                let indent_indicator = if read_bool(&mut r)? {
+                    Some(read_u8(&mut r)?)
+                } else {
+                    None
+                };
+                Some(Presentation {
+                    chomping,
+                    indent_indicator,
+                    chomping_first: read_bool(&mut r)?,
+                    empty_inline: read_bool(&mut r)?,
+                })
+            } else {
+                None
+            };
+            nodes.push(Node {
+                data,
+                tag,
+                tag_origin,
+                start_mark: node_start_mark,
+                end_mark: node_end_mark,
+                item_marks,
+                presentation,
+            });
+        }
+
+        let count = read_len(&mut r)?;
+        let mut anchors = Vec::with_capacity(count.min(64));
+        for _ in 0..count {
+            let anchor = read_string(&mut r)?;
+            let index = read_index(&mut r)?;
+            anchors.push((anchor, index));
+        }
+        let count = read_len(&mut r)?;
+        let mut alias_uses = Vec::with_capacity(count.min(64));
+        for _ in 0..count {
+            let alias = read_string(&mut r)?;
+            let mark = read_mark(&mut r)?;
+            let index = read_index(&mut r)?;
+            alias_uses.push((alias, mark, index));
+        }
+
+        Ok(Document {
+            nodes,
+            version_directive,
+            tag_directives,
+            start_implicit,
+            end_implicit,
+            start_mark,
+            end_mark,
+            anchors,
+            alias_uses,
+            mapping_indexes: MappingIndexes::default(),
+        })
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn write_u8(w: &mut dyn Write, value: u8) -> io::Result<()> {
+    w.write_all(&[value])
+}
+
+fn write_varint(w: &mut dyn Write, mut value: u64) -> io::Result<()> {
+    let mut buffer = [0; 10];
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer[len] = byte;
+            len += 1;
+            break;
+        }
+        buffer[len] = byte | 0x80;
+        len += 1;
+    }
+    w.write_all(&buffer[..len])
+}
+
+fn write_i32(w: &mut dyn Write, value: i32) -> io::Result<()> {
+    // Zigzag encoding, so that small negative numbers stay small.
+    let zigzag = ((value << 1) ^ (value >> 31)) as u32;
+    write_varint(w, u64::from(zigzag))
+}
+
+fn write_len(w: &mut dyn Write, len: usize) -> io::Result<()> {
+    write_varint(w, len as u64)
+}
+
+fn write_str(w: &mut dyn Write, value: &str) -> io::Result<()> {
+    write_len(w, value.len())?;
+    w.write_all(value.as_bytes())
+}
+
+fn write_mark(w: &mut dyn Write, mark: Mark) -> io::Result<()> {
+    write_varint(w, mark.index)?;
+    write_varint(w, mark.line)?;
+    write_varint(w, mark.column)
+}
+
+fn read_u8(r: &mut dyn Read) -> io::Result<u8> {
+    let mut buffer = [0; 1];
+    r.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn read_bool(r: &mut dyn Read) -> io::Result<bool> {
+    match read_u8(r)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(invalid_data("invalid flag in document cache")),
+    }
+}
+
+fn read_varint(r: &mut dyn Read) -> io::Result<u64> {
+    let mut value = 0_u64;
+    let mut shift = 0;
+    loop {
+        let byte = read_u8(r)?;
+        if shift == 63 && byte > 1 || shift > 63 {
+            return Err(invalid_data("integer overflow in document cache"));
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+fn read_i32(r: &mut dyn Read) -> io::Result<i32> {
+    let Ok(zigzag) = u32::try_from(read_varint(r)?) else {
+        return Err(invalid_data("integer overflow in document cache"));
+    };
+    Ok((zigzag >> 1) as i32 ^ -((zigzag & 1) as i32))
+}
+
+fn read_len(r: &mut dyn Read) -> io::Result<usize> {
+    usize::try_from(read_varint(r)?).map_err(|_| invalid_data("length overflow in document cache"))
+}
+
+fn read_string(r: &mut dyn Read) -> io::Result<String> {
+    let len = read_len(r)?;
+    // Do not trust the length for preallocation; a corrupt cache could
+    // otherwise make us allocate arbitrary amounts of memory.
+    let mut bytes = Vec::with_capacity(len.min(4096));
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("invalid UTF-8 in document cache"))
+}
+
+fn read_mark(r: &mut dyn Read) -> io::Result<Mark> {
+    Ok(Mark {
+        index: read_varint(r)?,
+        line: read_varint(r)?,
+        column: read_varint(r)?,
+    })
+}
+
+fn tag_origin(value: u8) -> io::Result<TagOrigin> {
+    Ok(match value {
+        0 => TagOrigin::Resolved,
+        1 => TagOrigin::NonSpecific,
+        2 => TagOrigin::Explicit,
+        _ => return Err(invalid_data("invalid tag origin in document cache")),
+    })
+}
+
+fn scalar_style(value: u8) -> io::Result<ScalarStyle> {
+    Ok(match value {
+        0 => ScalarStyle::Any,
+        1 => ScalarStyle::Plain,
+        2 => ScalarStyle::SingleQuoted,
+        3 => ScalarStyle::DoubleQuoted,
+        4 => ScalarStyle::Literal,
+        5 => ScalarStyle::Folded,
+        _ => return Err(invalid_data("invalid scalar style in document cache")),
+    })
+}
+
+fn chomping(value: u8) -> io::Result<Chomping> {
+    Ok(match value {
+        0 => Chomping::Clip,
+        1 => Chomping::Strip,
+        2 => Chomping::Keep,
+        _ => return Err(invalid_data("invalid chomping in document cache")),
+    })
+}
+
+fn sequence_style(value: u8) -> io::Result<SequenceStyle> {
+    Ok(match value {
+        0 => SequenceStyle::Any,
+        1 => SequenceStyle::Block,
+        2 => SequenceStyle::Flow,
+        _ => return Err(invalid_data("invalid sequence style in document cache")),
+    })
+}
+
+fn mapping_style(value: u8) -> io::Result<MappingStyle> {
+    Ok(match value {
+        0 => MappingStyle::Any,
+        1 => MappingStyle::Block,
+        2 => MappingStyle::Flow,
+        _ => return Err(invalid_data("invalid mapping style in document cache")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{Document, NodeData, Parser};
+
+    const COMPLEX: &str = "%YAML 1.1
+%TAG !e! tag:example.com,2000:
+---
+plain: value
+quoted: 'single'
+double: \"double\\nline\"
+literal: |
+  some
+  text
+folded: >-
+  more
+  text
+tagged: !e!thing {a: 1, b: [x, y]}
+anchored: &anchor
+  - one
+  - &inner two
+alias: *anchor
+inner: *inner
+? [complex, key]
+: -42
+...
+";
+
+    fn load(input: &str) -> Document {
+        let mut parser = Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        Document::load(&mut parser).unwrap()
+    }
+
+    fn write(document: &Document) -> Vec<u8> {
+        let mut output = Vec::new();
+        document.write_cache(&mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn round_trip() {
+        let document = load(COMPLEX);
+        let cache = write(&document);
+        let cached = Document::read_cache(cache.as_slice()).unwrap();
+        assert!(cached.deep_eq(&document));
+        // Marks, styles and directives are preserved as well.
+        assert_eq!(cached, document);
+
+        let empty = load("");
+        let cached = Document::read_cache(write(&empty).as_slice()).unwrap();
+        assert_eq!(cached, empty);
+
+        let mut parser = Parser::new();
+        parser.set_capture_presentation(true);
+        let mut input = "a: |+2\n    kept\n\nb: >-\n  x\nc: []\nd: {}\n".as_bytes();
+        parser.set_input_string(&mut input);
+        let document = Document::load(&mut parser).unwrap();
+        assert!(document
+            .nodes
+            .iter()
+            .all(|node| node.presentation.is_some()));
+        let cached = Document::read_cache(write(&document).as_slice()).unwrap();
+        assert_eq!(cached, document);
+    }
+
+    #[test]
+    fn corrupt_input() {
+        let cache = write(&load(COMPLEX));
+        let read = |bytes: &[u8]| Document::read_cache(bytes).unwrap_err().kind();
+
+        assert_eq!(read(b"nope"), io::ErrorKind::InvalidData);
+        let mut wrong_version = cache.clone();
+        wrong_version[4] = 99;
+        assert_eq!(read(&wrong_version), io::ErrorKind::InvalidData);
+        for len in [0, 5, cache.len() / 2, cache.len() - 1] {
+            assert_eq!(read(&cache[..len]), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn out_of_range_index() {
+        for index in [0, 4, -1] {
+            let mut document = load("[a, b]");
+            let NodeData::Sequence { items, .. } = &mut document.nodes[0].data else {
+                panic!("expected a sequence");
+            };
+            items[1] = index;
+            let cache = write(&document);
+            let err = Document::read_cache(cache.as_slice()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
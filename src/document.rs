@@ -1,18 +1,27 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::schema::{core_schema_tag, format_float};
 use crate::{
-    AliasData, Anchors, Emitter, Error, Event, EventData, MappingStyle, Mark, Parser, Result,
-    ScalarStyle, SequenceStyle, TagDirective, VersionDirective, DEFAULT_MAPPING_TAG,
-    DEFAULT_SCALAR_TAG, DEFAULT_SEQUENCE_TAG,
+    AliasData, Anchors, Emitter, Error, Event, EventData, MappingStyle, Mark, NodePath, Parser,
+    PathSegment, PathSelector, Presentation, Result, ScalarSource, ScalarStyle, SequenceStyle,
+    Span, TagDirective, VersionDirective, BOOL_TAG, DEFAULT_MAPPING_TAG, DEFAULT_SCALAR_TAG,
+    DEFAULT_SEQUENCE_TAG, FLOAT_TAG, INT_TAG, NULL_TAG, STR_TAG,
 };
 
 /// The document structure.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub struct Document {
     /// The document nodes.
     pub nodes: Vec<Node>,
     /// The version directive.
     pub version_directive: Option<VersionDirective>,
-    /// The list of tag directives.
+    /// The list of tag directives, in the order in which they are written.
+    ///
+    /// Handles must be unique, or dumping the document fails before writing
+    /// it. [`Document::add_tag_directive()`] and
+    /// [`Document::set_tag_directive()`] keep them unique.
     pub tag_directives: Vec<TagDirective>,
     /// Is the document start indicator implicit?
     pub start_implicit: bool,
@@ -22,24 +31,96 @@ pub struct Document {
     pub start_mark: Mark,
     /// The end of the document.
     pub end_mark: Mark,
+    /// The anchors defined in the document, with the nodes they are attached
+    /// to, in the order in which they appear.
+    ///
+    /// When the document is dumped, these names are used for nodes that need
+    /// an anchor, and for the root node.
+    pub anchors: Vec<(String, i32)>,
+    /// The aliases of a loaded document. See [`Document::alias_uses()`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) alias_uses: Vec<(String, Mark, i32)>,
+    /// Indexes of the keys of large mappings.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) mapping_indexes: MappingIndexes,
 }
 
-/// The node structure.
+/// Indexes of the scalar keys of mappings, built with
+/// [`Document::build_mapping_index()`], from key to the position of the first
+/// pair with that key.
+///
+/// The indexes only speed up lookups, so they are ignored when comparing
+/// documents.
 #[derive(Clone, Default, Debug)]
+pub(crate) struct MappingIndexes(HashMap<i32, HashMap<String, usize>>);
+
+impl PartialEq for MappingIndexes {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// The node structure.
+#[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub struct Node {
     /// The node type.
     pub data: NodeData,
-    /// The node tag.
+    /// The node tag, with any `%` escapes decoded.
     pub tag: Option<String>,
+    /// How the tag of the node was determined.
+    pub tag_origin: TagOrigin,
     /// The beginning of the node.
     pub start_mark: Mark,
     /// The end of the node.
     pub end_mark: Mark,
+    /// For a sequence node, the positions of its items, in the same order
+    /// as the items: the `-` indicator of each item of a block sequence, or
+    /// the start of each item of a flow sequence.
+    ///
+    /// Empty for other nodes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub item_marks: Vec<Mark>,
+    /// How the node was written in the input, if the parser was set to
+    /// capture it with [`Parser::set_capture_presentation()`]. Dumping the
+    /// document keeps these details where it can.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub presentation: Option<Presentation>,
+}
+
+impl Node {
+    /// The range of the input of the node.
+    pub fn span(&self) -> Span {
+        Span::new(self.start_mark, self.end_mark)
+    }
+
+    /// Is the node a null: a scalar with the null tag, or a plain scalar
+    /// resolved to `null` by the core schema?
+    fn is_null(&self) -> bool {
+        let NodeData::Scalar { value, style } = &self.data else {
+            return false;
+        };
+        self.tag.as_deref() == Some(NULL_TAG)
+            || (self.tag_origin == TagOrigin::Resolved
+                && matches!(style, ScalarStyle::Plain | ScalarStyle::Any)
+                && matches!(value.as_str(), "" | "~" | "null" | "Null" | "NULL"))
+    }
+
+    /// The tag given to untagged nodes of this kind.
+    fn default_tag(&self) -> Option<&'static str> {
+        match self.data {
+            NodeData::NoNode => None,
+            NodeData::Scalar { .. } => Some(DEFAULT_SCALAR_TAG),
+            NodeData::Sequence { .. } => Some(DEFAULT_SEQUENCE_TAG),
+            NodeData::Mapping { .. } => Some(DEFAULT_MAPPING_TAG),
+        }
+    }
 }
 
 /// Node types.
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NodeData {
     /// An empty node.
     #[default]
@@ -67,17 +148,185 @@ pub enum NodeData {
     },
 }
 
+/// How the tag of a [`Node`] was determined.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum TagOrigin {
+    /// The node had no tag, and was given the default tag for its kind.
+    ///
+    /// This is also used for nodes created with the [`Document`] API.
+    #[default]
+    Resolved = 0,
+    /// The node had the non-specific tag `!`, and was given the default tag
+    /// for its kind.
+    NonSpecific = 1,
+    /// The tag was given explicitly, as in `!!str value`.
+    Explicit = 2,
+}
+
 /// An element of a sequence node.
 pub type NodeItem = i32;
 
 /// An element of a mapping node.
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub struct NodePair {
     /// The key of the element.
     pub key: i32,
     /// The value of the element.
     pub value: i32,
+    /// The position of the key: the `?` indicator of an explicit key, or the
+    /// start of the key node.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub key_mark: Mark,
+}
+
+/// What [`Document::load_filtered()`] does with a node.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NodeAction {
+    /// Compose the node and all of its content.
+    Compose,
+    /// Compose a sequence or a mapping without its content, and call the
+    /// filter for each of its items or values. Scalars and aliases are
+    /// composed.
+    Descend,
+    /// Consume the events of the node and its content without composing
+    /// them.
+    Skip,
+    /// Stop loading, and return the nodes composed so far. The parser is left
+    /// in the middle of the document, so it must not be used to load another
+    /// document.
+    Abort,
+}
+
+/// A collection that [`Document::load_filtered()`] descended into.
+struct FilterLevel {
+    /// Is it a sequence, rather than a mapping?
+    sequence: bool,
+    /// Are all of its nodes skipped, without calling the filter?
+    skip_content: bool,
+    /// The number of items of a sequence seen so far.
+    items: usize,
+    /// The number of document nodes before the key of the current pair of a
+    /// mapping, once the key has started.
+    key_start: Option<usize>,
+    /// Has the key of the current pair of a mapping been composed?
+    in_value: bool,
+}
+
+impl FilterLevel {
+    fn new(sequence: bool, skip_content: bool) -> FilterLevel {
+        FilterLevel {
+            sequence,
+            skip_content,
+            items: 0,
+            key_start: None,
+            in_value: false,
+        }
+    }
+}
+
+/// How [`Document::load_filtered()`] handles the next event.
+#[derive(Copy, Clone)]
+enum FilterMode {
+    /// Call the filter for the next node.
+    Filter,
+    /// Compose the events of the current node, with this many collections of
+    /// it still open.
+    Compose(usize),
+    /// Skip the events of the current node, with this many collections of it
+    /// still open.
+    Skip(usize),
+}
+
+/// The transformations made by [`Document::normalize()`].
+///
+/// All of them are enabled by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct NormalizeOptions {
+    /// Set the styles of all nodes to `Any`.
+    pub styles: bool,
+    /// Rename the anchors to `id001`, `id002`, and so on, in the order in
+    /// which their nodes are first reached from the root node, and drop the
+    /// anchors of unreachable nodes. The aliases of
+    /// [`Document::alias_uses()`] are renamed as well.
+    pub anchors: bool,
+    /// Give untagged nodes the default tag for their kind, and mark default
+    /// tags as [`TagOrigin::Resolved`], so that `!!str a` and `a` are the
+    /// same.
+    pub tags: bool,
+    /// Set the marks of the document, all nodes, and all aliases to zero.
+    pub marks: bool,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        Self {
+            styles: true,
+            anchors: true,
+            tags: true,
+            marks: true,
+        }
+    }
+}
+
+/// How [`Document::merge()`] combines two mappings.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MapMerge {
+    /// Merge the pairs of both mappings, merging the values of equal scalar
+    /// keys in turn.
+    #[default]
+    DeepMerge,
+    /// Replace the mapping with the other one.
+    Replace,
+}
+
+/// How [`Document::merge()`] combines two sequences.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SeqMerge {
+    /// Replace the sequence with the other one.
+    #[default]
+    Replace,
+    /// Append the items of the other sequence.
+    Concat,
+}
+
+/// The rules of [`Document::merge()`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MergeStrategy {
+    /// How to combine two mappings.
+    pub map_conflict: MapMerge,
+    /// How to combine two sequences.
+    pub seq_conflict: SeqMerge,
+    /// Do null values replace values? If not, the values are kept, so that a
+    /// key left empty in the other document does not remove a default.
+    pub null_overrides: bool,
+}
+
+impl Default for MergeStrategy {
+    fn default() -> Self {
+        Self {
+            map_conflict: MapMerge::DeepMerge,
+            seq_conflict: SeqMerge::Replace,
+            null_overrides: true,
+        }
+    }
+}
+
+/// Where [`Document::merge()`] stores the result of merging two nodes.
+#[derive(Copy, Clone)]
+enum MergeSlot {
+    /// The root node.
+    Root,
+    /// The value of a pair of a mapping, at a position.
+    Value(i32, usize),
 }
 
 impl Document {
@@ -99,21 +348,64 @@ impl Document {
             end_implicit,
             start_mark: Mark::default(),
             end_mark: Mark::default(),
+            anchors: Vec::new(),
+            alias_uses: Vec::new(),
+            mapping_indexes: MappingIndexes::default(),
+        }
+    }
+
+    /// Add a tag directive after the existing ones.
+    ///
+    /// Returns a composer error if the document already has a tag directive
+    /// with the same handle, and leaves the document unchanged.
+    pub fn add_tag_directive(&mut self, tag_directive: TagDirective) -> Result<()> {
+        if self
+            .tag_directives
+            .iter()
+            .any(|existing| existing.handle == tag_directive.handle)
+        {
+            return Err(Error::composer(
+                "",
+                Mark::default(),
+                "found duplicate %TAG directive",
+                Mark::default(),
+            ));
+        }
+        self.tag_directives.push(tag_directive);
+        Ok(())
+    }
+
+    /// Set the tag directive for a handle, replacing the existing one in
+    /// place, or adding it after the existing ones.
+    ///
+    /// Returns the replaced tag directive, if any.
+    pub fn set_tag_directive(&mut self, tag_directive: TagDirective) -> Option<TagDirective> {
+        if let Some(existing) = self
+            .tag_directives
+            .iter_mut()
+            .find(|existing| existing.handle == tag_directive.handle)
+        {
+            return Some(core::mem::replace(existing, tag_directive));
         }
+        self.tag_directives.push(tag_directive);
+        None
     }
 
     /// Get a node of a YAML document.
     ///
-    /// Returns the node object or `None` if `index` is out of range.
+    /// Returns the node object or `None` if `index` is out of range. The
+    /// indexes built with [`Document::build_mapping_index()`] are removed,
+    /// since the node may be modified.
     pub fn get_node_mut(&mut self, index: i32) -> Option<&mut Node> {
-        self.nodes.get_mut(index as usize - 1)
+        self.clear_mapping_indexes();
+        self.nodes.get_mut((index as usize).checked_sub(1)?)
     }
 
     /// Get a node of a YAML document.
     ///
     /// Returns the node object or `None` if `index` is out of range.
     pub fn get_node(&self, index: i32) -> Option<&Node> {
-        self.nodes.get(index as usize - 1)
+        self.nodes.get((index as usize).checked_sub(1)?)
     }
 
     /// Get the root of a YAML document node.
@@ -122,180 +414,1053 @@ impl Document {
     ///
     /// An empty document produced by the parser signifies the end of a YAML stream.
     ///
-    /// Returns the node object or `None` if the document is empty.
+    /// Returns the node object or `None` if the document is empty. The
+    /// indexes built with [`Document::build_mapping_index()`] are removed,
+    /// since the node may be modified.
     pub fn get_root_node(&mut self) -> Option<&mut Node> {
+        self.clear_mapping_indexes();
         self.nodes.get_mut(0)
     }
 
-    /// Create a SCALAR node and attach it to the document.
-    ///
-    /// The `style` argument may be ignored by the emitter.
-    ///
-    /// Returns the node id or 0 on error.
-    #[must_use]
-    pub fn add_scalar(&mut self, tag: Option<&str>, value: &str, style: ScalarStyle) -> i32 {
-        let mark = Mark {
-            index: 0_u64,
-            line: 0_u64,
-            column: 0_u64,
-        };
-        let tag = tag.unwrap_or(DEFAULT_SCALAR_TAG);
-        let tag_copy = String::from(tag);
-        let value_copy = String::from(value);
-        let node = Node {
-            data: NodeData::Scalar {
-                value: value_copy,
-                style,
-            },
-            tag: Some(tag_copy),
-            start_mark: mark,
-            end_mark: mark,
-        };
-        self.nodes.push(node);
-        self.nodes.len() as i32
-    }
-
-    /// Create a SEQUENCE node and attach it to the document.
-    ///
-    /// The `style` argument may be ignored by the emitter.
-    ///
-    /// Returns the node id, which is a nonzero integer.
-    #[must_use]
-    pub fn add_sequence(&mut self, tag: Option<&str>, style: SequenceStyle) -> i32 {
-        let mark = Mark {
-            index: 0_u64,
-            line: 0_u64,
-            column: 0_u64,
-        };
-
-        let items = Vec::with_capacity(16);
-        let tag = tag.unwrap_or(DEFAULT_SEQUENCE_TAG);
-        let tag_copy = String::from(tag);
-        let node = Node {
-            data: NodeData::Sequence { items, style },
-            tag: Some(tag_copy),
-            start_mark: mark,
-            end_mark: mark,
-        };
-        self.nodes.push(node);
-        self.nodes.len() as i32
-    }
-
-    /// Create a MAPPING node and attach it to the document.
-    ///
-    /// The `style` argument may be ignored by the emitter.
+    /// Compare the content of two documents.
     ///
-    /// Returns the node id, which is a nonzero integer.
-    #[must_use]
-    pub fn add_mapping(&mut self, tag: Option<&str>, style: MappingStyle) -> i32 {
-        let mark = Mark {
-            index: 0_u64,
-            line: 0_u64,
-            column: 0_u64,
-        };
-        let pairs = Vec::with_capacity(16);
-        let tag = tag.unwrap_or(DEFAULT_MAPPING_TAG);
-        let tag_copy = String::from(tag);
+    /// Unlike `==`, this compares the node graphs reachable from the root
+    /// nodes: the tags and values of nodes and the structure of collections,
+    /// including which collections are shared through aliases. Marks, styles,
+    /// tag origins, directives, anchor names, the numbering of nodes, and
+    /// whether scalars are shared are ignored.
+    pub fn deep_eq(&self, other: &Document) -> bool {
+        if self.nodes.is_empty() || other.nodes.is_empty() {
+            return self.nodes.is_empty() && other.nodes.is_empty();
+        }
 
-        let node = Node {
-            data: NodeData::Mapping { pairs, style },
-            tag: Some(tag_copy),
-            start_mark: mark,
-            end_mark: mark,
-        };
+        // Node ids in `self` matched with node ids in `other`, and vice versa.
+        let mut matched = vec![0; self.nodes.len()];
+        let mut matched_other = vec![0; other.nodes.len()];
+        let mut stack = vec![(1, 1)];
+        while let Some((index, other_index)) = stack.pop() {
+            let (Some(node), Some(other_node)) =
+                (self.get_node(index), other.get_node(other_index))
+            else {
+                return false;
+            };
+            // Scalars are values, so whether they are shared does not matter.
+            if let (
+                NodeData::Scalar { value, .. },
+                NodeData::Scalar {
+                    value: other_value, ..
+                },
+            ) = (&node.data, &other_node.data)
+            {
+                if node.tag != other_node.tag || value != other_value {
+                    return false;
+                }
+                continue;
+            }
+            let seen = &mut matched[index as usize - 1];
+            let other_seen = &mut matched_other[other_index as usize - 1];
+            if *seen != 0 || *other_seen != 0 {
+                if *seen != other_index || *other_seen != index {
+                    return false;
+                }
+                continue;
+            }
+            *seen = other_index;
+            *other_seen = index;
 
-        self.nodes.push(node);
-        self.nodes.len() as i32
+            if node.tag != other_node.tag {
+                return false;
+            }
+            match (&node.data, &other_node.data) {
+                (
+                    NodeData::Sequence { items, .. },
+                    NodeData::Sequence {
+                        items: other_items, ..
+                    },
+                ) => {
+                    if items.len() != other_items.len() {
+                        return false;
+                    }
+                    stack.extend(items.iter().copied().zip(other_items.iter().copied()));
+                }
+                (
+                    NodeData::Mapping { pairs, .. },
+                    NodeData::Mapping {
+                        pairs: other_pairs, ..
+                    },
+                ) => {
+                    if pairs.len() != other_pairs.len() {
+                        return false;
+                    }
+                    for (pair, other_pair) in pairs.iter().zip(other_pairs) {
+                        stack.push((pair.key, other_pair.key));
+                        stack.push((pair.value, other_pair.value));
+                    }
+                }
+                _ => return false,
+            }
+        }
+        true
     }
 
-    /// Add an item to a SEQUENCE node.
-    pub fn append_sequence_item(&mut self, sequence: i32, item: i32) {
-        assert!(sequence > 0 && sequence as usize - 1 < self.nodes.len());
-        assert!(matches!(
-            &self.nodes[sequence as usize - 1].data,
-            NodeData::Sequence { .. }
-        ));
-        assert!(item > 0 && item as usize - 1 < self.nodes.len());
-        if let NodeData::Sequence { ref mut items, .. } =
-            &mut self.nodes[sequence as usize - 1].data
-        {
-            items.push(item);
+    /// Remove the presentation details of the document, as selected by
+    /// `options`, so that documents with the same content compare equal with
+    /// `==` if their nodes were created in the same order.
+    pub fn normalize(&mut self, options: &NormalizeOptions) {
+        if options.anchors {
+            let mut anchors = Vec::new();
+            self.walk(|index, seen| {
+                if !seen && self.anchors.iter().any(|(_, node)| *node == index) {
+                    anchors.push((Emitter::generate_anchor(anchors.len() as i32 + 1), index));
+                }
+            });
+            for (alias, _, index) in &mut self.alias_uses {
+                if let Some((anchor, _)) = anchors.iter().find(|(_, node)| node == index) {
+                    alias.clone_from(anchor);
+                }
+            }
+            self.anchors = anchors;
+        }
+        if options.marks {
+            self.start_mark = Mark::default();
+            self.end_mark = Mark::default();
+            for (_, mark, _) in &mut self.alias_uses {
+                *mark = Mark::default();
+            }
+        }
+        for node in &mut self.nodes {
+            if options.styles {
+                match &mut node.data {
+                    NodeData::Scalar { style, .. } => *style = ScalarStyle::Any,
+                    NodeData::Sequence { style, .. } => *style = SequenceStyle::Any,
+                    NodeData::Mapping { style, .. } => *style = MappingStyle::Any,
+                    NodeData::NoNode => {}
+                }
+            }
+            if options.tags {
+                if let Some(default_tag) = node.default_tag() {
+                    let tag = node.tag.get_or_insert_with(|| String::from(default_tag));
+                    if tag == default_tag {
+                        node.tag_origin = TagOrigin::Resolved;
+                    }
+                }
+            }
+            if options.marks {
+                node.start_mark = Mark::default();
+                node.end_mark = Mark::default();
+                node.item_marks.fill(Mark::default());
+                if let NodeData::Mapping { pairs, .. } = &mut node.data {
+                    for pair in pairs {
+                        pair.key_mark = Mark::default();
+                    }
+                }
+            }
         }
     }
 
-    /// Add a pair of a key and a value to a MAPPING node.
-    pub fn yaml_document_append_mapping_pair(&mut self, mapping: i32, key: i32, value: i32) {
-        assert!(mapping > 0 && mapping as usize - 1 < self.nodes.len());
-        assert!(matches!(
-            &self.nodes[mapping as usize - 1].data,
-            NodeData::Mapping { .. }
-        ));
-        assert!(key > 0 && key as usize - 1 < self.nodes.len());
-        assert!(value > 0 && value as usize - 1 < self.nodes.len());
-        let pair = NodePair { key, value };
-        if let NodeData::Mapping { ref mut pairs, .. } = &mut self.nodes[mapping as usize - 1].data
-        {
-            pairs.push(pair);
+    /// Move all marks of the document by `base`, as when the document was
+    /// loaded from a slice of a stream that starts at `base`.
+    ///
+    /// The index and line of `base` are added to every mark, and the column
+    /// of `base` is added to the marks on the first line of the slice.
+    pub fn offset_marks(&mut self, base: Mark) {
+        self.start_mark = self.start_mark.offset_by(base);
+        self.end_mark = self.end_mark.offset_by(base);
+        for (_, mark, _) in &mut self.alias_uses {
+            *mark = mark.offset_by(base);
+        }
+        for node in &mut self.nodes {
+            node.start_mark = node.start_mark.offset_by(base);
+            node.end_mark = node.end_mark.offset_by(base);
+            for mark in &mut node.item_marks {
+                *mark = mark.offset_by(base);
+            }
+            if let NodeData::Mapping { pairs, .. } = &mut node.data {
+                for pair in pairs {
+                    pair.key_mark = pair.key_mark.offset_by(base);
+                }
+            }
         }
     }
 
-    /// Parse the input stream and produce the next YAML document.
+    /// Merge the document `other` into this document, as when layering a
+    /// configuration file over the defaults.
     ///
-    /// Call this function subsequently to produce a sequence of documents
-    /// constituting the input stream.
+    /// The nodes of `other` reachable from its root node are copied into this
+    /// document, keeping their marks, and its root node is merged with this
+    /// root node:
     ///
-    /// If the produced document has no root node, it means that the document
-    /// end has been reached.
+    /// - Two mappings are merged as selected by
+    ///   [`map_conflict`](MergeStrategy::map_conflict). When merged deeply,
+    ///   the values of keys with the same scalar value are merged in turn,
+    ///   the other pairs are appended, and the mapping keeps the tag and the
+    ///   style of this document.
+    /// - Two sequences are merged as selected by
+    ///   [`seq_conflict`](MergeStrategy::seq_conflict).
+    /// - Otherwise, the node of `other` replaces the node of this document,
+    ///   with its tag and style, unless it is a null and
+    ///   [`null_overrides`](MergeStrategy::null_overrides) is not set.
     ///
-    /// An application must not alternate the calls of [`Document::load()`] with
-    /// the calls of [`Parser::parse()`]. Doing this will break the parser.
-    pub fn load(parser: &mut Parser) -> Result<Document> {
-        let mut document = Document::new(None, &[], false, false);
-        document.nodes.reserve(16);
-
-        if !parser.scanner.stream_start_produced {
-            match parser.parse() {
-                Ok(Event {
-                    data: EventData::StreamStart { .. },
-                    ..
-                }) => (),
-                Ok(_) => panic!("expected stream start"),
-                Err(err) => {
-                    parser.delete_aliases();
-                    return Err(err);
-                }
-            }
+    /// The anchors of `other` are kept, and renamed with a numeric suffix if
+    /// this document already has an anchor with the same name. Replaced
+    /// nodes stay in the document, but are no longer reachable from the root
+    /// node. A collection reachable through several aliases is merged once
+    /// for each of them.
+    pub fn merge(&mut self, other: &Document, strategy: MergeStrategy) {
+        if other.nodes.is_empty() {
+            return;
         }
-        if parser.scanner.stream_end_produced {
-            return Ok(document);
+        let root = self.copy_nodes(other);
+        if root == 1 {
+            // This document was empty.
+            return;
         }
-        let err: Error;
-        match parser.parse() {
-            Ok(event) => {
-                if let EventData::StreamEnd = &event.data {
-                    return Ok(document);
+
+        let mut stack = vec![(MergeSlot::Root, 1, root)];
+        while let Some((slot, index, other_index)) = stack.pop() {
+            let node = &self.nodes[index as usize - 1];
+            let other_node = &self.nodes[other_index as usize - 1];
+            let winner = match (&node.data, &other_node.data) {
+                (
+                    NodeData::Mapping { pairs, .. },
+                    NodeData::Mapping {
+                        pairs: other_pairs, ..
+                    },
+                ) if strategy.map_conflict == MapMerge::DeepMerge => {
+                    let mut appended = Vec::new();
+                    for other_pair in other_pairs {
+                        let key = self.scalar_value(other_pair.key);
+                        let position = key.and_then(|key| {
+                            pairs
+                                .iter()
+                                .position(|pair| self.scalar_value(pair.key) == Some(key))
+                        });
+                        if let Some(position) = position {
+                            let value = pairs[position].value;
+                            stack.push((
+                                MergeSlot::Value(index, position),
+                                value,
+                                other_pair.value,
+                            ));
+                        } else {
+                            appended.push(*other_pair);
+                        }
+                    }
+                    if let NodeData::Mapping { pairs, .. } =
+                        &mut self.nodes[index as usize - 1].data
+                    {
+                        pairs.extend(appended);
+                    }
+                    index
                 }
-                parser.aliases.reserve(16);
-                match document.load_document(parser, event) {
-                    Ok(()) => {
-                        parser.delete_aliases();
-                        return Ok(document);
+                (
+                    NodeData::Sequence { .. },
+                    NodeData::Sequence {
+                        items: other_items, ..
+                    },
+                ) if strategy.seq_conflict == SeqMerge::Concat => {
+                    let other_items = other_items.clone();
+                    let other_marks = other_node.item_marks.clone();
+                    let node = &mut self.nodes[index as usize - 1];
+                    if let NodeData::Sequence { items, .. } = &mut node.data {
+                        items.extend(other_items);
+                    }
+                    node.item_marks.extend(other_marks);
+                    index
+                }
+                _ if !strategy.null_overrides && other_node.is_null() => index,
+                _ => other_index,
+            };
+            match slot {
+                MergeSlot::Root => {
+                    if winner != 1 {
+                        self.swap_nodes(1, winner);
+                    }
+                }
+                MergeSlot::Value(mapping, position) => {
+                    if let NodeData::Mapping { pairs, .. } =
+                        &mut self.nodes[mapping as usize - 1].data
+                    {
+                        pairs[position].value = winner;
                     }
-                    Err(e) => err = e,
                 }
             }
-            Err(e) => err = e,
         }
-        parser.delete_aliases();
-        Err(err)
+        self.clear_mapping_indexes();
     }
 
-    fn load_document(&mut self, parser: &mut Parser, event: Event) -> Result<()> {
-        let mut ctx = vec![];
-        if let EventData::DocumentStart {
-            version_directive,
-            tag_directives,
-            implicit,
+    /// Append copies of the nodes of `other` reachable from its root node,
+    /// with their anchors and aliases, and return the index of the copy of
+    /// its root node.
+    fn copy_nodes(&mut self, other: &Document) -> i32 {
+        let mut reachable = Vec::new();
+        other.walk(|index, seen| {
+            if !seen {
+                reachable.push(index);
+            }
+        });
+        let mut remap = vec![0; other.nodes.len()];
+        for (new_index, index) in (self.nodes.len() as i32 + 1..).zip(&reachable) {
+            remap[*index as usize - 1] = new_index;
+        }
+        let remap_index = |index: &mut i32| *index = remap[*index as usize - 1];
+        for index in &reachable {
+            let mut node = other.nodes[*index as usize - 1].clone();
+            match &mut node.data {
+                NodeData::Sequence { items, .. } => items.iter_mut().for_each(remap_index),
+                NodeData::Mapping { pairs, .. } => {
+                    for pair in pairs {
+                        remap_index(&mut pair.key);
+                        remap_index(&mut pair.value);
+                    }
+                }
+                _ => {}
+            }
+            self.nodes.push(node);
+        }
+
+        let mut renamed = HashMap::new();
+        for (anchor, index) in &other.anchors {
+            let new_index = remap[*index as usize - 1];
+            if new_index == 0 {
+                continue;
+            }
+            let mut name = anchor.clone();
+            let mut suffix = 1;
+            while self.anchor(&name).is_some() {
+                suffix += 1;
+                name = format!("{anchor}{suffix}");
+            }
+            renamed.insert(anchor.as_str(), name.clone());
+            self.anchors.push((name, new_index));
+        }
+        for (alias, mark, index) in &other.alias_uses {
+            let new_index = remap[*index as usize - 1];
+            if new_index != 0 {
+                let name = renamed.get(alias.as_str()).unwrap_or(alias);
+                self.alias_uses.push((name.clone(), *mark, new_index));
+            }
+        }
+        remap[0]
+    }
+
+    /// Exchange the ids of two nodes, updating all references to them.
+    fn swap_nodes(&mut self, a: i32, b: i32) {
+        self.nodes.swap(a as usize - 1, b as usize - 1);
+        let swap = |index: &mut i32| {
+            if *index == a {
+                *index = b;
+            } else if *index == b {
+                *index = a;
+            }
+        };
+        for node in &mut self.nodes {
+            match &mut node.data {
+                NodeData::Sequence { items, .. } => items.iter_mut().for_each(swap),
+                NodeData::Mapping { pairs, .. } => {
+                    for pair in pairs {
+                        swap(&mut pair.key);
+                        swap(&mut pair.value);
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (_, index) in &mut self.anchors {
+            swap(index);
+        }
+        for (_, _, index) in &mut self.alias_uses {
+            swap(index);
+        }
+    }
+
+    /// Compute a hash of the content of the document, which is equal for
+    /// documents that are equal with [`Document::deep_eq()`].
+    ///
+    /// The hash does not depend on presentation details, nor on the order in
+    /// which the nodes were created, and is stable across versions of this
+    /// crate. It is the 64-bit FNV-1a hash of the following bytes, for each
+    /// node reachable from the root node, in document order, with keys before
+    /// values:
+    ///
+    /// - For a scalar, `S`, the tag, and the value.
+    /// - For a sequence or a mapping reached for the first time, `Q` or `M`,
+    ///   the tag, and the number of items or pairs. The nodes of the
+    ///   collection follow.
+    /// - For a sequence or a mapping reached again through an alias, `A` and
+    ///   the number of collections reached before it for the first time.
+    /// - For an empty node, `N`.
+    ///
+    /// Numbers are written as 8 bytes in little endian order. Strings are
+    /// written as their length in bytes, followed by their UTF-8 encoding.
+    /// Untagged nodes use the default tag for their kind.
+    pub fn content_hash(&self) -> u64 {
+        struct Fnv(u64);
+
+        impl Fnv {
+            fn bytes(&mut self, bytes: &[u8]) {
+                for byte in bytes {
+                    self.0 ^= u64::from(*byte);
+                    self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+                }
+            }
+
+            fn number(&mut self, number: usize) {
+                self.bytes(&(number as u64).to_le_bytes());
+            }
+
+            fn string(&mut self, string: &str) {
+                self.number(string.len());
+                self.bytes(string.as_bytes());
+            }
+        }
+
+        let mut hash = Fnv(0xcbf2_9ce4_8422_2325);
+        // The position of each collection in the order of first visits.
+        let mut collections = vec![0; self.nodes.len()];
+        let mut count = 0;
+        self.walk(|index, seen| {
+            let node = &self.nodes[index as usize - 1];
+            let tag = node.tag.as_deref().or(node.default_tag()).unwrap_or("");
+            match &node.data {
+                NodeData::NoNode => hash.bytes(b"N"),
+                NodeData::Scalar { value, .. } => {
+                    hash.bytes(b"S");
+                    hash.string(tag);
+                    hash.string(value);
+                }
+                _ if seen => {
+                    hash.bytes(b"A");
+                    hash.number(collections[index as usize - 1]);
+                }
+                NodeData::Sequence { items, .. } => {
+                    hash.bytes(b"Q");
+                    hash.string(tag);
+                    hash.number(items.len());
+                }
+                NodeData::Mapping { pairs, .. } => {
+                    hash.bytes(b"M");
+                    hash.string(tag);
+                    hash.number(pairs.len());
+                }
+            }
+            if !seen {
+                collections[index as usize - 1] = count;
+                count += 1;
+            }
+        });
+        hash.0
+    }
+
+    /// Visit the nodes reachable from the root node in document order, with
+    /// keys before values, with the index of each node and whether it was
+    /// visited before. The children of a node are only visited once.
+    fn walk(&self, mut visit: impl FnMut(i32, bool)) {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = vec![1];
+        while let Some(index) = stack.pop() {
+            if index <= 0 || index as usize > self.nodes.len() {
+                continue;
+            }
+            let seen = core::mem::replace(&mut visited[index as usize - 1], true);
+            visit(index, seen);
+            if seen {
+                continue;
+            }
+            match &self.nodes[index as usize - 1].data {
+                NodeData::Sequence { items, .. } => stack.extend(items.iter().rev()),
+                NodeData::Mapping { pairs, .. } => {
+                    stack.extend(pairs.iter().rev().flat_map(|pair| [pair.value, pair.key]));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Get the node that the anchor `name` is attached to.
+    pub fn anchor(&self, name: &str) -> Option<i32> {
+        self.anchors
+            .iter()
+            .find(|(anchor, _)| anchor == name)
+            .map(|(_, index)| *index)
+    }
+
+    /// The aliases of a loaded document, in the order in which they appear,
+    /// with the name of each alias, the position of its `*` indicator, and
+    /// the node it refers to.
+    ///
+    /// The position of the anchor of the node is its
+    /// [`start_mark`](Node::start_mark).
+    pub fn alias_uses(&self) -> &[(String, Mark, i32)] {
+        &self.alias_uses
+    }
+
+    /// Shrink the capacity of the vectors and strings of the document, and of
+    /// all its nodes, to fit their contents.
+    ///
+    /// Loading leaves spare capacity in most of them, which adds up for
+    /// documents that are kept for long. See also
+    /// [`Parser::set_shrink_documents()`].
+    pub fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        for node in &mut self.nodes {
+            if let Some(tag) = &mut node.tag {
+                tag.shrink_to_fit();
+            }
+            match &mut node.data {
+                NodeData::NoNode => {}
+                NodeData::Scalar { value, .. } => value.shrink_to_fit(),
+                NodeData::Sequence { items, .. } => items.shrink_to_fit(),
+                NodeData::Mapping { pairs, .. } => pairs.shrink_to_fit(),
+            }
+            node.item_marks.shrink_to_fit();
+        }
+        self.tag_directives.shrink_to_fit();
+        for tag_directive in &mut self.tag_directives {
+            tag_directive.handle.shrink_to_fit();
+            tag_directive.prefix.shrink_to_fit();
+        }
+        self.anchors.shrink_to_fit();
+        for (anchor, _) in &mut self.anchors {
+            anchor.shrink_to_fit();
+        }
+        self.alias_uses.shrink_to_fit();
+        for (alias, _, _) in &mut self.alias_uses {
+            alias.shrink_to_fit();
+        }
+        self.mapping_indexes.0.shrink_to_fit();
+        for index in self.mapping_indexes.0.values_mut() {
+            index.shrink_to_fit();
+        }
+    }
+
+    /// An estimate of the heap memory used by the document, in bytes, from
+    /// the capacities of its vectors, strings, and mapping indexes.
+    ///
+    /// The overhead of the allocator and of the hash tables is not counted.
+    pub fn approx_memory_usage(&self) -> usize {
+        fn vec_usage<T>(vec: &Vec<T>) -> usize {
+            vec.capacity() * core::mem::size_of::<T>()
+        }
+
+        let mut usage = vec_usage(&self.nodes);
+        for node in &self.nodes {
+            usage += node.tag.as_ref().map_or(0, String::capacity);
+            usage += match &node.data {
+                NodeData::NoNode => 0,
+                NodeData::Scalar { value, .. } => value.capacity(),
+                NodeData::Sequence { items, .. } => vec_usage(items),
+                NodeData::Mapping { pairs, .. } => vec_usage(pairs),
+            };
+            usage += vec_usage(&node.item_marks);
+        }
+        usage += vec_usage(&self.tag_directives);
+        for tag_directive in &self.tag_directives {
+            usage += tag_directive.handle.capacity() + tag_directive.prefix.capacity();
+        }
+        usage += vec_usage(&self.anchors);
+        for (anchor, _) in &self.anchors {
+            usage += anchor.capacity();
+        }
+        usage += vec_usage(&self.alias_uses);
+        for (alias, _, _) in &self.alias_uses {
+            usage += alias.capacity();
+        }
+        let indexes = &self.mapping_indexes.0;
+        usage += indexes.capacity() * core::mem::size_of::<(i32, HashMap<String, usize>)>();
+        for index in indexes.values() {
+            usage += index.capacity() * core::mem::size_of::<(String, usize)>();
+            usage += index.keys().map(String::capacity).sum::<usize>();
+        }
+        usage
+    }
+
+    /// Find the nodes with the tag `tag`, in document order.
+    ///
+    /// Note that the loader resolves the tags of untagged nodes to the
+    /// default tags, such as [`DEFAULT_SCALAR_TAG`](crate::DEFAULT_SCALAR_TAG).
+    pub fn find_nodes_by_tag(&self, tag: &str) -> Vec<i32> {
+        self.find_nodes(|node| node.tag.as_deref() == Some(tag))
+    }
+
+    /// Find the scalar nodes with the value `value`, in document order.
+    pub fn find_scalars(&self, value: &str) -> Vec<i32> {
+        self.find_nodes(
+            |node| matches!(&node.data, NodeData::Scalar { value: v, .. } if v == value),
+        )
+    }
+
+    fn find_nodes(&self, mut predicate: impl FnMut(&Node) -> bool) -> Vec<i32> {
+        (1..)
+            .zip(&self.nodes)
+            .filter(|(_, node)| predicate(node))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Get the path from the root node to the node `node`.
+    ///
+    /// Nodes are searched in document order, so for a node with aliases, this
+    /// is the path of the node where its anchor is defined. Nodes inside
+    /// mapping keys have no path.
+    ///
+    /// ```
+    /// use libyaml_safer::{Document, Parser};
+    ///
+    /// let mut input = "spec:\n  containers:\n  - image: a\n  - image: b\n".as_bytes();
+    /// let mut parser = Parser::new();
+    /// parser.set_input_string(&mut input);
+    /// let document = Document::load(&mut parser)?;
+    /// let node = document.find_scalars("b")[0];
+    /// let path = document.path_of(node).unwrap();
+    /// assert_eq!(path.to_string(), ".spec.containers[1].image");
+    /// # Ok::<(), libyaml_safer::Error>(())
+    /// ```
+    pub fn path_of(&self, node: i32) -> Option<NodePath> {
+        self.get_node(node)?;
+        let mut path = NodePath::new();
+        if node == 1 {
+            return Some(path);
+        }
+        let mut visited = vec![false; self.nodes.len()];
+        visited[0] = true;
+        // The collections on the path, with the position of their next child.
+        let mut stack = vec![(1, 0)];
+        while let Some((index, position)) = stack.last_mut() {
+            let child = match &self.nodes[*index as usize - 1].data {
+                NodeData::Sequence { items, .. } => items
+                    .get(*position)
+                    .map(|item| (*item, PathSegment::Index(*position))),
+                NodeData::Mapping { pairs, .. } => pairs.get(*position).map(|pair| {
+                    let segment = match self.scalar_value(pair.key) {
+                        Some(key) => PathSegment::Key(key.to_string()),
+                        None => PathSegment::ComplexKey,
+                    };
+                    (pair.value, segment)
+                }),
+                _ => None,
+            };
+            let Some((child, segment)) = child else {
+                stack.pop();
+                path.pop();
+                continue;
+            };
+            *position += 1;
+            path.push(segment);
+            if child == node {
+                return Some(path);
+            }
+            if self.get_node(child).is_some()
+                && !core::mem::replace(&mut visited[child as usize - 1], true)
+            {
+                stack.push((child, 0));
+            } else {
+                path.pop();
+            }
+        }
+        None
+    }
+
+    /// Select the nodes at the path `path`, in document order.
+    ///
+    /// The path is written like the [`Display`](core::fmt::Display) output
+    /// of [`NodePath`], as in `.spec.containers[2].image`, with `*` in place
+    /// of a key or an index to select all values of a mapping or all items of
+    /// a sequence, as in `.spec.containers[*].image` or `.spec.*`. Keys only
+    /// match scalar keys. A path that cannot be parsed selects no nodes.
+    pub fn select(&self, path: &str) -> Vec<i32> {
+        let Some(selectors) = PathSelector::parse(path) else {
+            return Vec::new();
+        };
+        let mut selected = if self.nodes.is_empty() {
+            Vec::new()
+        } else {
+            vec![1]
+        };
+        for selector in selectors {
+            let mut next = Vec::new();
+            for index in selected {
+                match (&self.nodes[index as usize - 1].data, &selector) {
+                    (NodeData::Sequence { items, .. }, PathSelector::Index(position)) => {
+                        next.extend(items.get(*position));
+                    }
+                    (NodeData::Sequence { items, .. }, PathSelector::Wildcard) => {
+                        next.extend(items);
+                    }
+                    (NodeData::Mapping { pairs, .. }, PathSelector::Key(key)) => next.extend(
+                        pairs
+                            .iter()
+                            .filter(|pair| self.scalar_value(pair.key) == Some(key))
+                            .map(|pair| pair.value),
+                    ),
+                    (NodeData::Mapping { pairs, .. }, PathSelector::Wildcard) => {
+                        next.extend(pairs.iter().map(|pair| pair.value));
+                    }
+                    _ => {}
+                }
+            }
+            next.retain(|index| self.get_node(*index).is_some());
+            selected = next;
+        }
+        selected
+    }
+
+    /// Get the value of the first pair in a MAPPING node with the scalar key
+    /// `key`.
+    ///
+    /// Returns `None` if `mapping` is not a mapping, or if it has no such key.
+    /// This is a linear search, unless an index has been built for the mapping
+    /// with [`Document::build_mapping_index()`].
+    pub fn get_mapping_value(&self, mapping: i32, key: &str) -> Option<i32> {
+        let Some(Node {
+            data: NodeData::Mapping { pairs, .. },
+            ..
+        }) = self.get_node(mapping)
+        else {
+            return None;
+        };
+        if let Some(index) = self.mapping_indexes.0.get(&mapping) {
+            return index
+                .get(key)
+                .and_then(|position| pairs.get(*position))
+                .map(|pair| pair.value);
+        }
+        pairs
+            .iter()
+            .find(|pair| self.scalar_value(pair.key) == Some(key))
+            .map(|pair| pair.value)
+    }
+
+    /// Build an index of the scalar keys of a MAPPING node, to speed up
+    /// [`Document::get_mapping_value()`] for large mappings.
+    ///
+    /// The index is kept up to date when pairs are added with
+    /// [`Document::yaml_document_append_mapping_pair()`], and removed when a
+    /// node is borrowed with [`Document::get_node_mut()`] or
+    /// [`Document::get_root_node()`], or when documents are merged. If the
+    /// [`nodes`](Document::nodes) of the document are modified directly, the
+    /// index must be built again, or removed with
+    /// [`Document::clear_mapping_indexes()`].
+    pub fn build_mapping_index(&mut self, mapping: i32) {
+        assert!(mapping > 0 && mapping as usize - 1 < self.nodes.len());
+        let NodeData::Mapping { pairs, .. } = &self.nodes[mapping as usize - 1].data else {
+            panic!("node is not a mapping");
+        };
+        let mut index = HashMap::with_capacity(pairs.len());
+        for (position, pair) in pairs.iter().enumerate() {
+            if let Some(key) = self.scalar_value(pair.key) {
+                index.entry(String::from(key)).or_insert(position);
+            }
+        }
+        self.mapping_indexes.0.insert(mapping, index);
+    }
+
+    /// Remove all indexes built with [`Document::build_mapping_index()`].
+    pub fn clear_mapping_indexes(&mut self) {
+        self.mapping_indexes.0.clear();
+    }
+
+    fn scalar_value(&self, index: i32) -> Option<&str> {
+        match &self.get_node(index)?.data {
+            NodeData::Scalar { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Create a SCALAR node and attach it to the document.
+    ///
+    /// The `style` argument may be ignored by the emitter.
+    ///
+    /// Returns the node id or 0 on error.
+    #[must_use]
+    pub fn add_scalar(&mut self, tag: Option<&str>, value: &str, style: ScalarStyle) -> i32 {
+        let mark = Mark {
+            index: 0_u64,
+            line: 0_u64,
+            column: 0_u64,
+        };
+        let tag = tag.unwrap_or(DEFAULT_SCALAR_TAG);
+        let tag_copy = String::from(tag);
+        let value_copy = String::from(value);
+        let node = Node {
+            data: NodeData::Scalar {
+                value: value_copy,
+                style,
+            },
+            tag: Some(tag_copy),
+            tag_origin: TagOrigin::Resolved,
+            start_mark: mark,
+            end_mark: mark,
+            item_marks: Vec::new(),
+            presentation: None,
+        };
+        self.nodes.push(node);
+        self.nodes.len() as i32
+    }
+
+    /// Create a SCALAR node for an integer, with the `int` tag, and attach it
+    /// to the document.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_int(&mut self, value: i64) -> i32 {
+        self.add_scalar(Some(INT_TAG), &value.to_string(), ScalarStyle::Plain)
+    }
+
+    /// Create a SCALAR node for a float, with the `float` tag, and attach it
+    /// to the document.
+    ///
+    /// The value is written as by [`Event::scalar_float()`].
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_float(&mut self, value: f64) -> i32 {
+        self.add_scalar(Some(FLOAT_TAG), &format_float(value), ScalarStyle::Plain)
+    }
+
+    /// Create a SCALAR node for a boolean, with the `bool` tag, and attach it
+    /// to the document.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_bool(&mut self, value: bool) -> i32 {
+        let value = if value { "true" } else { "false" };
+        self.add_scalar(Some(BOOL_TAG), value, ScalarStyle::Plain)
+    }
+
+    /// Create a SCALAR node for `null`, with the `null` tag, and attach it to
+    /// the document.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_null(&mut self) -> i32 {
+        self.add_scalar(Some(NULL_TAG), "null", ScalarStyle::Plain)
+    }
+
+    /// Create a SEQUENCE node and attach it to the document.
+    ///
+    /// The `style` argument may be ignored by the emitter.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_sequence(&mut self, tag: Option<&str>, style: SequenceStyle) -> i32 {
+        let mark = Mark {
+            index: 0_u64,
+            line: 0_u64,
+            column: 0_u64,
+        };
+
+        let items = Vec::with_capacity(16);
+        let tag = tag.unwrap_or(DEFAULT_SEQUENCE_TAG);
+        let tag_copy = String::from(tag);
+        let node = Node {
+            data: NodeData::Sequence { items, style },
+            tag: Some(tag_copy),
+            tag_origin: TagOrigin::Resolved,
+            start_mark: mark,
+            end_mark: mark,
+            item_marks: Vec::new(),
+            presentation: None,
+        };
+        self.nodes.push(node);
+        self.nodes.len() as i32
+    }
+
+    /// Create a MAPPING node and attach it to the document.
+    ///
+    /// The `style` argument may be ignored by the emitter.
+    ///
+    /// Returns the node id, which is a nonzero integer.
+    #[must_use]
+    pub fn add_mapping(&mut self, tag: Option<&str>, style: MappingStyle) -> i32 {
+        let mark = Mark {
+            index: 0_u64,
+            line: 0_u64,
+            column: 0_u64,
+        };
+        let pairs = Vec::with_capacity(16);
+        let tag = tag.unwrap_or(DEFAULT_MAPPING_TAG);
+        let tag_copy = String::from(tag);
+
+        let node = Node {
+            data: NodeData::Mapping { pairs, style },
+            tag: Some(tag_copy),
+            tag_origin: TagOrigin::Resolved,
+            start_mark: mark,
+            end_mark: mark,
+            item_marks: Vec::new(),
+            presentation: None,
+        };
+
+        self.nodes.push(node);
+        self.nodes.len() as i32
+    }
+
+    /// Add an item to a SEQUENCE node.
+    pub fn append_sequence_item(&mut self, sequence: i32, item: i32) {
+        assert!(sequence > 0 && sequence as usize - 1 < self.nodes.len());
+        assert!(matches!(
+            &self.nodes[sequence as usize - 1].data,
+            NodeData::Sequence { .. }
+        ));
+        assert!(item > 0 && item as usize - 1 < self.nodes.len());
+        if let NodeData::Sequence { ref mut items, .. } =
+            &mut self.nodes[sequence as usize - 1].data
+        {
+            items.push(item);
+            self.nodes[sequence as usize - 1]
+                .item_marks
+                .push(Mark::default());
+        }
+    }
+
+    /// Add a pair of a key and a value to a MAPPING node.
+    pub fn yaml_document_append_mapping_pair(&mut self, mapping: i32, key: i32, value: i32) {
+        assert!(mapping > 0 && mapping as usize - 1 < self.nodes.len());
+        assert!(matches!(
+            &self.nodes[mapping as usize - 1].data,
+            NodeData::Mapping { .. }
+        ));
+        assert!(key > 0 && key as usize - 1 < self.nodes.len());
+        assert!(value > 0 && value as usize - 1 < self.nodes.len());
+        if let Some(index) = self.mapping_indexes.0.get_mut(&mapping) {
+            if let NodeData::Scalar {
+                value: key_value, ..
+            } = &self.nodes[key as usize - 1].data
+            {
+                if let NodeData::Mapping { pairs, .. } = &self.nodes[mapping as usize - 1].data {
+                    index.entry(key_value.clone()).or_insert(pairs.len());
+                }
+            }
+        }
+        let pair = NodePair {
+            key,
+            value,
+            key_mark: Mark::default(),
+        };
+        if let NodeData::Mapping { ref mut pairs, .. } = &mut self.nodes[mapping as usize - 1].data
+        {
+            pairs.push(pair);
+        }
+    }
+
+    /// Parse the input stream and produce the next YAML document.
+    ///
+    /// Call this function subsequently to produce a sequence of documents
+    /// constituting the input stream.
+    ///
+    /// If the produced document has no root node, it means that the document
+    /// end has been reached.
+    ///
+    /// An application must not alternate the calls of [`Document::load()`] with
+    /// the calls of [`Parser::parse()`]. Doing this will break the parser.
+    pub fn load(parser: &mut Parser) -> Result<Document> {
+        Self::load_with(parser, Self::load_document)
+    }
+
+    /// Parse the next YAML document, composing only the nodes selected by
+    /// `filter`, to load parts of a document that is too large to be composed
+    /// whole.
+    ///
+    /// The filter is called with the path and the first event of the root
+    /// node, of every item of a sequence, and of every value of a mapping that
+    /// it returned [`NodeAction::Descend`] for. It is not called for mapping
+    /// keys, which are composed with their value. The resulting document holds
+    /// the composed nodes, attached to the collections that were descended
+    /// into, which only hold the items and pairs that were kept. If the root
+    /// node is skipped, it is replaced by an empty node of the same kind, so
+    /// that the document is not mistaken for the end of the stream.
+    ///
+    /// An alias of an anchor defined in a skipped node is an error, as the
+    /// node it refers to is not available. Aliases of a collection that was
+    /// descended into refer to the partial collection.
+    ///
+    /// ```
+    /// use libyaml_safer::{Document, NodeAction, Parser, PathSegment};
+    ///
+    /// let mut input = "a: [1, 2]\nb: {c: 3, d: 4}\n".as_bytes();
+    /// let mut parser = Parser::new();
+    /// parser.set_input_string(&mut input);
+    /// let document = Document::load_filtered(&mut parser, |path, _event| {
+    ///     match path.segments() {
+    ///         [] | [PathSegment::Key(_)] => NodeAction::Descend,
+    ///         [PathSegment::Key(b), PathSegment::Key(d)] if b == "b" && d == "d" => {
+    ///             NodeAction::Compose
+    ///         }
+    ///         _ => NodeAction::Skip,
+    ///     }
+    /// })?;
+    /// // The root mapping, `a` and an empty sequence, `b` and a mapping, `d`,
+    /// // and `4`.
+    /// assert_eq!(document.nodes.len(), 7);
+    /// # Ok::<(), libyaml_safer::Error>(())
+    /// ```
+    pub fn load_filtered(
+        parser: &mut Parser,
+        mut filter: impl FnMut(&NodePath, &Event) -> NodeAction,
+    ) -> Result<Document> {
+        Self::load_with(parser, |document, parser, event| {
+            document.load_filtered_document(parser, event, &mut filter)
+        })
+    }
+
+    fn load_with(
+        parser: &mut Parser,
+        load_document: impl FnOnce(&mut Document, &mut Parser, Event) -> Result<()>,
+    ) -> Result<Document> {
+        let mut document = Document::new(None, &[], false, false);
+        document.nodes.reserve(16);
+
+        if !parser.scanner.stream_start_produced {
+            match parser.parse() {
+                Ok(Event {
+                    data: EventData::StreamStart { .. },
+                    ..
+                }) => (),
+                Ok(_) => panic!("expected stream start"),
+                Err(err) => {
+                    parser.delete_aliases();
+                    return Err(err);
+                }
+            }
+        }
+        if parser.scanner.stream_end_produced {
+            return Ok(document);
+        }
+        let err: Error;
+        match parser.parse() {
+            Ok(event) => {
+                if let EventData::StreamEnd = &event.data {
+                    return Ok(document);
+                }
+                parser.aliases.reserve(16);
+                match load_document(&mut document, parser, event) {
+                    Ok(()) => {
+                        document.anchors = parser
+                            .aliases
+                            .drain(..)
+                            .map(|alias_data| (alias_data.anchor, alias_data.index))
+                            .collect();
+                        if parser.shrink_documents {
+                            document.shrink_to_fit();
+                        }
+                        return Ok(document);
+                    }
+                    Err(e) => err = e,
+                }
+            }
+            Err(e) => err = e,
+        }
+        parser.delete_aliases();
+        Err(err)
+    }
+
+    fn load_document(&mut self, parser: &mut Parser, event: Event) -> Result<()> {
+        let mut ctx = vec![];
+        if let EventData::DocumentStart {
+            version_directive,
+            tag_directives,
+            implicit,
         } = event.data
         {
             self.version_directive = version_directive;
@@ -382,7 +1547,9 @@ impl Document {
         Ok(())
     }
 
-    fn load_node_add(&mut self, ctx: &[i32], index: i32) -> Result<()> {
+    /// Add the node `index` to the current collection. `mark` is the mark of
+    /// the '-' or '?' indicator of the node, or the start of the node.
+    fn load_node_add(&mut self, ctx: &[i32], index: i32, mark: Mark) -> Result<()> {
         let Some(parent_index) = ctx.last() else {
             return Ok(());
         };
@@ -391,6 +1558,7 @@ impl Document {
         match parent.data {
             NodeData::Sequence { ref mut items, .. } => {
                 items.push(index);
+                parent.item_marks.push(mark);
             }
             NodeData::Mapping { ref mut pairs, .. } => match pairs.last_mut() {
                 // If the last pair does not have a value, set `index` as the value.
@@ -401,6 +1569,7 @@ impl Document {
                 _ => pairs.push(NodePair {
                     key: index,
                     value: 0,
+                    key_mark: mark,
                 }),
             },
             _ => {
@@ -417,43 +1586,45 @@ impl Document {
 
         for alias_data in &parser.aliases {
             if alias_data.anchor == *anchor {
-                return self.load_node_add(ctx, alias_data.index);
+                self.alias_uses
+                    .push((anchor.clone(), event.start_mark, alias_data.index));
+                let mark = parser.indicator_mark.unwrap_or(event.start_mark);
+                return self.load_node_add(ctx, alias_data.index, mark);
             }
         }
 
-        Err(Error::composer(
-            "",
-            Mark::default(),
-            "found undefined alias",
-            event.start_mark,
-        ))
+        Err(undefined_alias(parser, anchor, event.start_mark))
     }
 
     fn load_scalar(&mut self, parser: &mut Parser, event: Event, ctx: &[i32]) -> Result<()> {
         let EventData::Scalar {
-            mut tag,
+            tag,
             value,
             style,
             anchor,
+            source,
             ..
         } = event.data
         else {
             unreachable!()
         };
 
-        if tag.is_none() || tag.as_deref() == Some("!") {
-            tag = Some(String::from(DEFAULT_SCALAR_TAG));
-        }
+        let (tag, tag_origin) = resolve_tag(tag, DEFAULT_SCALAR_TAG);
+        let capture = parser.scanner.capture_presentation;
         let node = Node {
             data: NodeData::Scalar { value, style },
             tag,
+            tag_origin,
             start_mark: event.start_mark,
             end_mark: event.end_mark,
+            item_marks: Vec::new(),
+            presentation: capture.then(|| source.presentation.unwrap_or_default()),
         };
         self.nodes.push(node);
         let index: i32 = self.nodes.len() as i32;
         self.register_anchor(parser, index, anchor)?;
-        self.load_node_add(ctx, index)
+        let mark = parser.indicator_mark.unwrap_or(event.start_mark);
+        self.load_node_add(ctx, index, mark)
     }
 
     fn load_sequence(
@@ -463,10 +1634,7 @@ impl Document {
         ctx: &mut Vec<i32>,
     ) -> Result<()> {
         let EventData::SequenceStart {
-            anchor,
-            mut tag,
-            style,
-            ..
+            anchor, tag, style, ..
         } = event.data
         else {
             unreachable!()
@@ -474,9 +1642,7 @@ impl Document {
 
         let mut items = Vec::with_capacity(16);
 
-        if tag.is_none() || tag.as_deref() == Some("!") {
-            tag = Some(String::from(DEFAULT_SEQUENCE_TAG));
-        }
+        let (tag, tag_origin) = resolve_tag(tag, DEFAULT_SEQUENCE_TAG);
 
         let node = Node {
             data: NodeData::Sequence {
@@ -484,14 +1650,21 @@ impl Document {
                 style,
             },
             tag,
+            tag_origin,
             start_mark: event.start_mark,
             end_mark: event.end_mark,
+            item_marks: Vec::new(),
+            presentation: parser
+                .scanner
+                .capture_presentation
+                .then(Presentation::default),
         };
 
         self.nodes.push(node);
         let index: i32 = self.nodes.len() as i32;
         self.register_anchor(parser, index, anchor)?;
-        self.load_node_add(ctx, index)?;
+        let mark = parser.indicator_mark.unwrap_or(event.start_mark);
+        self.load_node_add(ctx, index, mark)?;
         ctx.push(index);
         Ok(())
     }
@@ -500,11 +1673,14 @@ impl Document {
         let Some(index) = ctx.last().copied() else {
             panic!("sequence_end without a current sequence")
         };
-        assert!(matches!(
-            self.nodes[index as usize - 1].data,
-            NodeData::Sequence { .. }
-        ));
-        self.nodes[index as usize - 1].end_mark = event.end_mark;
+        let node = &mut self.nodes[index as usize - 1];
+        let NodeData::Sequence { items, style } = &node.data else {
+            panic!("sequence_end without a current sequence")
+        };
+        if let Some(presentation) = &mut node.presentation {
+            presentation.empty_inline = items.is_empty() && *style == SequenceStyle::Flow;
+        }
+        node.end_mark = event.end_mark;
         ctx.pop();
         Ok(())
     }
@@ -516,10 +1692,7 @@ impl Document {
         ctx: &mut Vec<i32>,
     ) -> Result<()> {
         let EventData::MappingStart {
-            anchor,
-            mut tag,
-            style,
-            ..
+            anchor, tag, style, ..
         } = event.data
         else {
             unreachable!()
@@ -527,22 +1700,27 @@ impl Document {
 
         let mut pairs = Vec::with_capacity(16);
 
-        if tag.is_none() || tag.as_deref() == Some("!") {
-            tag = Some(String::from(DEFAULT_MAPPING_TAG));
-        }
+        let (tag, tag_origin) = resolve_tag(tag, DEFAULT_MAPPING_TAG);
         let node = Node {
             data: NodeData::Mapping {
                 pairs: core::mem::take(&mut pairs),
                 style,
             },
             tag,
+            tag_origin,
             start_mark: event.start_mark,
             end_mark: event.end_mark,
+            item_marks: Vec::new(),
+            presentation: parser
+                .scanner
+                .capture_presentation
+                .then(Presentation::default),
         };
         self.nodes.push(node);
         let index: i32 = self.nodes.len() as i32;
         self.register_anchor(parser, index, anchor)?;
-        self.load_node_add(ctx, index)?;
+        let mark = parser.indicator_mark.unwrap_or(event.start_mark);
+        self.load_node_add(ctx, index, mark)?;
         ctx.push(index);
         Ok(())
     }
@@ -551,15 +1729,266 @@ impl Document {
         let Some(index) = ctx.last().copied() else {
             panic!("mapping_end without a current mapping")
         };
-        assert!(matches!(
-            self.nodes[index as usize - 1].data,
-            NodeData::Mapping { .. }
-        ));
-        self.nodes[index as usize - 1].end_mark = event.end_mark;
+        let node = &mut self.nodes[index as usize - 1];
+        let NodeData::Mapping { pairs, style } = &node.data else {
+            panic!("mapping_end without a current mapping")
+        };
+        if let Some(presentation) = &mut node.presentation {
+            presentation.empty_inline = pairs.is_empty() && *style == MappingStyle::Flow;
+        }
+        node.end_mark = event.end_mark;
         ctx.pop();
         Ok(())
     }
 
+    fn load_filtered_document(
+        &mut self,
+        parser: &mut Parser,
+        event: Event,
+        filter: &mut impl FnMut(&NodePath, &Event) -> NodeAction,
+    ) -> Result<()> {
+        let EventData::DocumentStart {
+            version_directive,
+            tag_directives,
+            implicit,
+        } = event.data
+        else {
+            panic!("Expected YAML_DOCUMENT_START_EVENT")
+        };
+        self.version_directive = version_directive;
+        self.tag_directives = tag_directives;
+        self.start_implicit = implicit;
+        self.start_mark = event.start_mark;
+
+        let mut ctx = Vec::with_capacity(16);
+        let mut levels: Vec<FilterLevel> = Vec::new();
+        let mut path = NodePath::new();
+        let mut mode = FilterMode::Filter;
+        let mut skipped_anchors = HashSet::new();
+        loop {
+            let mut event = parser.parse()?;
+            if let FilterMode::Filter = mode {
+                match event.data {
+                    EventData::DocumentEnd { implicit } => {
+                        self.end_implicit = implicit;
+                        self.end_mark = event.end_mark;
+                        return Ok(());
+                    }
+                    EventData::SequenceEnd | EventData::MappingEnd => {
+                        // The end of a collection that was descended into.
+                        self.compose_filtered_event(parser, event, &mut ctx, &skipped_anchors)?;
+                        levels.pop();
+                        self.filtered_node_done(
+                            parser,
+                            &ctx,
+                            &mut levels,
+                            &mut path,
+                            &mut skipped_anchors,
+                            true,
+                        );
+                        continue;
+                    }
+                    _ => {}
+                }
+                let action = match levels.last_mut() {
+                    Some(level) if level.skip_content => NodeAction::Skip,
+                    Some(level) if !level.sequence && !level.in_value => {
+                        level.key_start = Some(self.nodes.len());
+                        path.push(self.key_segment(parser, &event));
+                        NodeAction::Compose
+                    }
+                    level => {
+                        if let Some(level) = level.filter(|level| level.sequence) {
+                            path.push(PathSegment::Index(level.items));
+                        }
+                        filter(&path, &event)
+                    }
+                };
+                let sequence = matches!(event.data, EventData::SequenceStart { .. });
+                let collection = sequence || matches!(event.data, EventData::MappingStart { .. });
+                let root = levels.is_empty();
+                match action {
+                    NodeAction::Abort => {
+                        self.end_mark = event.start_mark;
+                        return Ok(());
+                    }
+                    NodeAction::Skip if root && !matches!(event.data, EventData::Alias { .. }) => {
+                        // Keep an empty node in place of the root.
+                        match &mut event.data {
+                            EventData::Scalar { anchor, value, .. } => {
+                                skipped_anchors.extend(anchor.take());
+                                value.clear();
+                            }
+                            EventData::SequenceStart { anchor, .. }
+                            | EventData::MappingStart { anchor, .. } => {
+                                skipped_anchors.extend(anchor.take());
+                            }
+                            _ => unreachable!(),
+                        }
+                        self.compose_filtered_event(parser, event, &mut ctx, &skipped_anchors)?;
+                        if collection {
+                            levels.push(FilterLevel::new(sequence, true));
+                        }
+                        continue;
+                    }
+                    NodeAction::Descend if collection => {
+                        self.compose_filtered_event(parser, event, &mut ctx, &skipped_anchors)?;
+                        levels.push(FilterLevel::new(sequence, false));
+                        continue;
+                    }
+                    NodeAction::Skip => mode = FilterMode::Skip(0),
+                    _ => mode = FilterMode::Compose(0),
+                }
+            }
+
+            let (depth, kept) = match mode {
+                FilterMode::Compose(depth) => (depth, true),
+                FilterMode::Skip(depth) => (depth, false),
+                FilterMode::Filter => unreachable!(),
+            };
+            let depth = match event.data {
+                EventData::SequenceStart { .. } | EventData::MappingStart { .. } => depth + 1,
+                EventData::SequenceEnd | EventData::MappingEnd => depth - 1,
+                _ => depth,
+            };
+            if kept {
+                self.compose_filtered_event(parser, event, &mut ctx, &skipped_anchors)?;
+                mode = FilterMode::Compose(depth);
+            } else {
+                Self::skip_filtered_event(parser, &event, &mut skipped_anchors)?;
+                mode = FilterMode::Skip(depth);
+            }
+            if depth == 0 {
+                mode = FilterMode::Filter;
+                self.filtered_node_done(
+                    parser,
+                    &ctx,
+                    &mut levels,
+                    &mut path,
+                    &mut skipped_anchors,
+                    kept,
+                );
+            }
+        }
+    }
+
+    /// The path segment for the value of a pair whose key starts with `event`.
+    fn key_segment(&self, parser: &Parser, event: &Event) -> PathSegment {
+        match &event.data {
+            EventData::Scalar { value, .. } => PathSegment::Key(value.clone()),
+            EventData::Alias { anchor } => parser
+                .aliases
+                .iter()
+                .find(|alias_data| alias_data.anchor == *anchor)
+                .and_then(|alias_data| self.scalar_value(alias_data.index))
+                .map_or(PathSegment::ComplexKey, |value| {
+                    PathSegment::Key(value.to_string())
+                }),
+            _ => PathSegment::ComplexKey,
+        }
+    }
+
+    /// Compose an event of a node kept by [`Document::load_filtered()`].
+    fn compose_filtered_event(
+        &mut self,
+        parser: &mut Parser,
+        event: Event,
+        ctx: &mut Vec<i32>,
+        skipped_anchors: &HashSet<String>,
+    ) -> Result<()> {
+        match &event.data {
+            EventData::Alias { anchor } if skipped_anchors.contains(anchor) => {
+                Err(Error::composer(
+                    "",
+                    Mark::default(),
+                    "found alias of a skipped node",
+                    event.start_mark,
+                ))
+            }
+            EventData::Alias { .. } => self.load_alias(parser, event, ctx),
+            EventData::Scalar { .. } => self.load_scalar(parser, event, ctx),
+            EventData::SequenceStart { .. } => self.load_sequence(parser, event, ctx),
+            EventData::SequenceEnd => self.load_sequence_end(event, ctx),
+            EventData::MappingStart { .. } => self.load_mapping(parser, event, ctx),
+            EventData::MappingEnd => self.load_mapping_end(event, ctx),
+            _ => unreachable!("unexpected event in a node"),
+        }
+    }
+
+    /// Check an event of a node skipped by [`Document::load_filtered()`], and
+    /// remember the anchors it defines.
+    fn skip_filtered_event(
+        parser: &Parser,
+        event: &Event,
+        skipped_anchors: &mut HashSet<String>,
+    ) -> Result<()> {
+        match &event.data {
+            EventData::Alias { anchor }
+                if !skipped_anchors.contains(anchor)
+                    && !parser
+                        .aliases
+                        .iter()
+                        .any(|alias_data| alias_data.anchor == *anchor) =>
+            {
+                return Err(undefined_alias(parser, anchor, event.start_mark));
+            }
+            EventData::Scalar { anchor, .. }
+            | EventData::SequenceStart { anchor, .. }
+            | EventData::MappingStart { anchor, .. } => {
+                skipped_anchors.extend(anchor.iter().cloned());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Account for a complete node in the innermost collection that
+    /// [`Document::load_filtered()`] descended into.
+    fn filtered_node_done(
+        &mut self,
+        parser: &mut Parser,
+        ctx: &[i32],
+        levels: &mut [FilterLevel],
+        path: &mut NodePath,
+        skipped_anchors: &mut HashSet<String>,
+        kept: bool,
+    ) {
+        let Some(level) = levels.last_mut() else {
+            return;
+        };
+        if level.skip_content {
+            return;
+        }
+        if level.sequence {
+            path.pop();
+            level.items += 1;
+            return;
+        }
+        if !level.in_value {
+            level.in_value = true;
+            return;
+        }
+        path.pop();
+        level.in_value = false;
+        let key_start = level.key_start.take().unwrap();
+        if !kept {
+            // Drop the key of the skipped value, with the anchors defined in
+            // it.
+            let mapping = &mut self.nodes[*ctx.last().unwrap() as usize - 1];
+            if let NodeData::Mapping { pairs, .. } = &mut mapping.data {
+                pairs.pop();
+            }
+            parser.aliases.retain(|alias_data| {
+                let dropped = alias_data.index as usize > key_start;
+                if dropped {
+                    skipped_anchors.insert(alias_data.anchor.clone());
+                }
+                !dropped
+            });
+            self.nodes.truncate(key_start);
+        }
+    }
+
     /// Emit a YAML document.
     ///
     /// The document object may be generated using the [`Document::load()`]
@@ -585,8 +2014,11 @@ impl Document {
                 implicit: self.start_implicit,
             });
             emitter.emit(event)?;
-            self.anchor_node(emitter, 1);
-            self.dump_node(emitter, 1)?;
+            if let Some(min_len) = emitter.deduplicate_scalars {
+                self.deduplicate_scalars(min_len);
+            }
+            self.anchor_nodes(emitter);
+            self.dump_nodes(emitter)?;
             let event = Event::document_end(self.end_implicit);
             emitter.emit(event)?;
         }
@@ -595,38 +2027,144 @@ impl Document {
         Ok(())
     }
 
-    fn anchor_node(&self, emitter: &mut Emitter, index: i32) {
-        let node = &self.nodes[index as usize - 1];
-        emitter.anchors[index as usize - 1].references += 1;
-        if emitter.anchors[index as usize - 1].references == 1 {
-            match &node.data {
-                NodeData::Sequence { items, .. } => {
-                    for item in items {
-                        emitter.anchor_node_sub(*item);
+    /// Emit the document as YAML with `indent` spaces at the start of every
+    /// line, to paste it under a mapping key of a larger document.
+    ///
+    /// The document is written without directives and without the `---` and
+    /// `...` markers, which are only markers at the start of a line. See
+    /// [`Emitter::set_base_indent()`] for where the output can be pasted.
+    ///
+    /// ```
+    /// use libyaml_safer::parse_documents;
+    ///
+    /// let documents = parse_documents("host: example.com\nports: [80, 443]\n")?;
+    /// let nested = documents[0].to_string_indented(2)?;
+    /// assert_eq!(nested, "  host: example.com\n  ports: [80, 443]\n");
+    /// let config = format!("server:\n{nested}");
+    /// assert_eq!(parse_documents(&config)?.len(), 1);
+    /// # Ok::<(), libyaml_safer::Error>(())
+    /// ```
+    pub fn to_string_indented(&self, indent: usize) -> Result<String> {
+        let mut document = self.clone();
+        document.version_directive = None;
+        document.tag_directives.clear();
+        document.start_implicit = true;
+        document.end_implicit = true;
+        let mut output = String::new();
+        let mut emitter = Emitter::new();
+        emitter.set_base_indent(indent);
+        emitter.set_output_fmt(&mut output);
+        document.dump(&mut emitter)?;
+        emitter.close()?;
+        drop(emitter);
+        Ok(output)
+    }
+
+    /// Count the references to the nodes reachable from the root node, and
+    /// number the anchors of those referenced more than once.
+    fn anchor_nodes(&self, emitter: &mut Emitter) {
+        self.walk(|index, _| {
+            let anchors = &mut emitter.anchors[index as usize - 1];
+            anchors.references += 1;
+            if anchors.references == 2 {
+                emitter.last_anchor_id += 1;
+                if self.anchor_name(index).is_none() {
+                    // Do not generate a name that is already taken by one of
+                    // the anchors of the document.
+                    while self
+                        .anchor(&Emitter::generate_anchor(emitter.last_anchor_id))
+                        .is_some()
+                    {
+                        emitter.last_anchor_id += 1;
                     }
                 }
+                emitter.anchors[index as usize - 1].anchor = emitter.last_anchor_id;
+            }
+        });
+    }
+
+    /// Make collections refer to the first of identical scalar nodes of at
+    /// least `min_len` bytes, so that the others are dumped as aliases.
+    fn deduplicate_scalars(&mut self, min_len: usize) {
+        let mut replacements: Vec<i32> = (1..=self.nodes.len() as i32).collect();
+        let mut first = HashMap::new();
+        let mut shared = false;
+        for (index, node) in (1..).zip(&self.nodes) {
+            let NodeData::Scalar { value, style } = &node.data else {
+                continue;
+            };
+            // Nodes with an anchor of their own are left alone, so that the
+            // anchor is kept.
+            if value.len() < min_len || self.anchor_name(index).is_some() {
+                continue;
+            }
+            let key = (node.tag.as_deref(), node.tag_origin, *style, value.as_str());
+            let first_index = *first.entry(key).or_insert(index);
+            if first_index != index {
+                replacements[index as usize - 1] = first_index;
+                shared = true;
+            }
+        }
+        if !shared {
+            return;
+        }
+
+        let replace = |index: &mut i32| *index = replacements[*index as usize - 1];
+        for node in &mut self.nodes {
+            match &mut node.data {
+                NodeData::Sequence { items, .. } => items.iter_mut().for_each(replace),
                 NodeData::Mapping { pairs, .. } => {
                     for pair in pairs {
-                        emitter.anchor_node_sub(pair.key);
-                        emitter.anchor_node_sub(pair.value);
+                        replace(&mut pair.key);
+                        replace(&mut pair.value);
                     }
                 }
                 _ => {}
             }
-        } else if emitter.anchors[index as usize - 1].references == 2 {
-            emitter.last_anchor_id += 1;
-            emitter.anchors[index as usize - 1].anchor = emitter.last_anchor_id;
         }
     }
 
-    fn dump_node(&mut self, emitter: &mut Emitter, index: i32) -> Result<()> {
+    fn anchor_name(&self, index: i32) -> Option<&str> {
+        self.anchors
+            .iter()
+            .find(|(_, anchor_index)| *anchor_index == index)
+            .map(|(anchor, _)| anchor.as_str())
+    }
+
+    /// Emit the events of the nodes reachable from the root node.
+    ///
+    /// Nodes are visited with an explicit stack rather than recursively, so
+    /// that deeply nested documents do not overflow the thread stack.
+    fn dump_nodes(&mut self, emitter: &mut Emitter) -> Result<()> {
+        let mut stack = vec![DumpStep::Node(1)];
+        while let Some(step) = stack.pop() {
+            match step {
+                DumpStep::Node(index) => self.dump_node(emitter, index, &mut stack)?,
+                DumpStep::End(event) => emitter.emit(event)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit the first event of a node, and push the rest on `stack`.
+    fn dump_node(
+        &mut self,
+        emitter: &mut Emitter,
+        index: i32,
+        stack: &mut Vec<DumpStep>,
+    ) -> Result<()> {
         assert!(index > 0);
-        let node = &mut self.nodes[index as usize - 1];
         let anchor_id: i32 = emitter.anchors[index as usize - 1].anchor;
         let mut anchor: Option<String> = None;
-        if anchor_id != 0 {
-            anchor = Some(Emitter::generate_anchor(anchor_id));
+        // Unused anchors are dropped, except on the root node, where they
+        // often name the document.
+        if anchor_id != 0 || index == 1 {
+            anchor = self
+                .anchor_name(index)
+                .map(String::from)
+                .or_else(|| (anchor_id != 0).then(|| Emitter::generate_anchor(anchor_id)));
         }
+        let node = &mut self.nodes[index as usize - 1];
         if emitter.anchors[index as usize - 1].serialized {
             return Self::dump_alias(emitter, anchor.unwrap());
         }
@@ -635,8 +2173,8 @@ impl Document {
         let node = core::mem::take(node);
         match node.data {
             NodeData::Scalar { .. } => Self::dump_scalar(emitter, node, anchor),
-            NodeData::Sequence { .. } => self.dump_sequence(emitter, node, anchor),
-            NodeData::Mapping { .. } => self.dump_mapping(emitter, node, anchor),
+            NodeData::Sequence { .. } => Self::dump_sequence(emitter, node, anchor, stack),
+            NodeData::Mapping { .. } => Self::dump_mapping(emitter, node, anchor, stack),
             _ => unreachable!("document node is neither a scalar, sequence, or a mapping"),
         }
     }
@@ -647,73 +2185,1402 @@ impl Document {
     }
 
     fn dump_scalar(emitter: &mut Emitter, node: Node, anchor: Option<String>) -> Result<()> {
-        let plain_implicit = node.tag.as_deref() == Some(DEFAULT_SCALAR_TAG);
-        let quoted_implicit = node.tag.as_deref() == Some(DEFAULT_SCALAR_TAG); // TODO: Why compare twice?! (even the C code does this)
-
+        let (tag, implicit) = dump_tag(node.tag, node.tag_origin, DEFAULT_SCALAR_TAG);
         let NodeData::Scalar { value, style } = node.data else {
             unreachable!()
         };
+        // A node with the tag that the core schema resolves its value to, like
+        // those of `Document::add_int()`, is written plain without the tag.
+        let resolved = node.tag_origin == TagOrigin::Resolved
+            && tag.as_deref() == Some(core_schema_tag(&value))
+            && tag.as_deref() != Some(STR_TAG);
+        let plain_implicit = implicit || resolved;
+        let quoted_implicit = implicit;
         let event = Event::new(EventData::Scalar {
             anchor,
-            tag: node.tag,
+            tag,
             value,
             plain_implicit,
             quoted_implicit,
             style,
+            source: ScalarSource {
+                lines: 0,
+                presentation: node.presentation,
+            },
         });
         emitter.emit(event)
     }
 
     fn dump_sequence(
-        &mut self,
         emitter: &mut Emitter,
         node: Node,
         anchor: Option<String>,
+        stack: &mut Vec<DumpStep>,
     ) -> Result<()> {
-        let implicit = node.tag.as_deref() == Some(DEFAULT_SEQUENCE_TAG);
+        let (tag, implicit) = dump_tag(node.tag, node.tag_origin, DEFAULT_SEQUENCE_TAG);
 
-        let NodeData::Sequence { items, style } = node.data else {
+        let NodeData::Sequence { items, mut style } = node.data else {
             unreachable!()
         };
+        if items.is_empty() && node.presentation.is_some_and(|p| p.empty_inline) {
+            style = SequenceStyle::Flow;
+        }
         let event = Event::new(EventData::SequenceStart {
             anchor,
-            tag: node.tag,
+            tag,
             implicit,
             style,
         });
 
         emitter.emit(event)?;
-        for item in items {
-            self.dump_node(emitter, item)?;
-        }
-        let event = Event::sequence_end();
-        emitter.emit(event)
+        stack.push(DumpStep::End(Event::sequence_end()));
+        stack.extend(items.into_iter().rev().map(DumpStep::Node));
+        Ok(())
     }
 
     fn dump_mapping(
-        &mut self,
         emitter: &mut Emitter,
         node: Node,
         anchor: Option<String>,
+        stack: &mut Vec<DumpStep>,
     ) -> Result<()> {
-        let implicit = node.tag.as_deref() == Some(DEFAULT_MAPPING_TAG);
+        let (tag, implicit) = dump_tag(node.tag, node.tag_origin, DEFAULT_MAPPING_TAG);
 
-        let NodeData::Mapping { pairs, style } = node.data else {
+        let NodeData::Mapping { pairs, mut style } = node.data else {
             unreachable!()
         };
+        if pairs.is_empty() && node.presentation.is_some_and(|p| p.empty_inline) {
+            style = MappingStyle::Flow;
+        }
         let event = Event::new(EventData::MappingStart {
             anchor,
-            tag: node.tag,
+            tag,
             implicit,
             style,
         });
 
         emitter.emit(event)?;
-        for pair in pairs {
-            self.dump_node(emitter, pair.key)?;
-            self.dump_node(emitter, pair.value)?;
+        stack.push(DumpStep::End(Event::mapping_end()));
+        stack.extend(
+            pairs
+                .into_iter()
+                .rev()
+                .flat_map(|pair| [DumpStep::Node(pair.value), DumpStep::Node(pair.key)]),
+        );
+        Ok(())
+    }
+}
+
+/// A step of [`Document::dump()`].
+enum DumpStep {
+    /// Emit a node.
+    Node(i32),
+    /// Emit the end of a collection.
+    End(Event),
+}
+
+/// The error for an alias of an undefined anchor, which suggests the most
+/// similar name of an anchor defined before it, if there is one.
+fn undefined_alias(parser: &Parser, anchor: &str, mark: Mark) -> Error {
+    let max_distance = anchor.chars().count() / 3;
+    let similar = parser
+        .aliases
+        .iter()
+        .map(|alias_data| (edit_distance(anchor, &alias_data.anchor), alias_data))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance);
+    match similar {
+        Some((_, alias_data)) => Error::composer(
+            "did you mean",
+            alias_data.mark,
+            "found undefined alias",
+            mark,
+        )
+        .with_suggestion(alloc::format!("&{}", alias_data.anchor)),
+        None => Error::composer("", Mark::default(), "found undefined alias", mark),
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // The distances from the prefix of `a` read so far to each prefix of `b`.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != *b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
         }
-        let event = Event::mapping_end();
-        emitter.emit(event)
+    }
+    row[b.len()]
+}
+
+/// Determine the tag of a loaded node, given the tag of its event.
+fn resolve_tag(tag: Option<String>, default_tag: &str) -> (Option<String>, TagOrigin) {
+    match tag.as_deref() {
+        None => (Some(String::from(default_tag)), TagOrigin::Resolved),
+        Some("!") => (Some(String::from(default_tag)), TagOrigin::NonSpecific),
+        Some(_) => (tag, TagOrigin::Explicit),
+    }
+}
+
+/// Determine the tag to emit for a node, and whether it may be omitted.
+fn dump_tag(
+    tag: Option<String>,
+    tag_origin: TagOrigin,
+    default_tag: &str,
+) -> (Option<String>, bool) {
+    match tag_origin {
+        TagOrigin::Resolved => {
+            let implicit = tag.as_deref() == Some(default_tag);
+            (tag, implicit)
+        }
+        TagOrigin::NonSpecific => (Some(String::from("!")), false),
+        TagOrigin::Explicit => (tag, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Chomping, Document, Emitter, ErrorKind, Event, MapMerge, MappingStyle, Mark, MergeStrategy,
+        Node, NodeAction, NodeData, NodePath, NormalizeOptions, Parser, PathSegment, Result,
+        ScalarStyle, SeqMerge, SequenceStyle, TagDirective, TagOrigin, VersionDirective,
+        DEFAULT_MAPPING_TAG, DEFAULT_SCALAR_TAG,
+    };
+    use core::fmt::Write as _;
+
+    fn load(input: &str) -> Document {
+        let mut parser = Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        Document::load(&mut parser).unwrap()
+    }
+
+    #[test]
+    fn deep_eq() {
+        let document = load("a: &x [1, 2]\nb: *x\n");
+        assert!(document.deep_eq(&document));
+        assert!(document.deep_eq(&load("\"a\": &y\n  - 1\n  - '2'\nb: *y\n")));
+        assert!(load("").deep_eq(&load("")));
+
+        assert!(!document.deep_eq(&load("a: [1, 2]\nb: [1, 2]\n")));
+        assert!(!document.deep_eq(&load("a: &x [1, 3]\nb: *x\n")));
+        assert!(!document.deep_eq(&load("a: &x [1, 2]\n")));
+        assert!(!document.deep_eq(&load("a: &x !t [1, 2]\nb: *x\n")));
+        assert!(!document.deep_eq(&load("")));
+
+        // Node numbering does not matter, so cyclic documents can be compared.
+        let cyclic = load("&a [x, *a]");
+        assert!(cyclic.deep_eq(&load("&b [x, *b]")));
+        assert!(!cyclic.deep_eq(&load("&b [x, [x, *b]]")));
+    }
+
+    #[test]
+    fn normalize() {
+        let mut document = load("a: &x !!seq [1, ! 'two']\nb: *x\nc: &y d\n");
+        document.normalize(&NormalizeOptions::default());
+        let mut other = load("{\"a\": &b [ !!str 1 ,\n \"two\" ], b: *b, c: &c !!str d}");
+        other.normalize(&NormalizeOptions::default());
+        assert_eq!(document, other);
+        assert_eq!(
+            document.anchors,
+            [(String::from("id001"), 3), (String::from("id002"), 8)]
+        );
+        assert!(document
+            .nodes
+            .iter()
+            .all(|node| node.start_mark == Mark::default()
+                && node.end_mark == Mark::default()
+                && node.tag_origin == TagOrigin::Resolved));
+        assert!(matches!(
+            document.nodes[4].data,
+            NodeData::Scalar {
+                style: ScalarStyle::Any,
+                ..
+            }
+        ));
+
+        let options = NormalizeOptions {
+            styles: false,
+            anchors: false,
+            ..NormalizeOptions::default()
+        };
+        let mut document = load("a: &x 'b'\n");
+        document.normalize(&options);
+        assert_eq!(document.anchors, [(String::from("x"), 3)]);
+        assert!(matches!(
+            document.nodes[2].data,
+            NodeData::Scalar {
+                style: ScalarStyle::SingleQuoted,
+                ..
+            }
+        ));
+
+        // Unreachable nodes lose their anchors.
+        let mut document = Document::new(None, &[], true, true);
+        let root = document.add_scalar(None, "a", ScalarStyle::Any);
+        let unreachable = document.add_scalar(None, "b", ScalarStyle::Any);
+        document.anchors.push((String::from("x"), unreachable));
+        document.anchors.push((String::from("y"), root));
+        document.normalize(&NormalizeOptions::default());
+        assert_eq!(document.anchors, [(String::from("id001"), root)]);
+    }
+
+    #[test]
+    fn content_hash() {
+        let document = load("a: &x [1, two]\nb: *x\n");
+        let hash = document.content_hash();
+        for same in [
+            "a: &x [1, two]\nb: *x\n",
+            "{'a': &anchor [\"1\", !!str two], b: *anchor}",
+            "---\n\n\na:   &x\n  - |-\n    1\n  - >-\n    two\n\nb: *x\n...\n",
+        ] {
+            assert_eq!(load(same).content_hash(), hash, "{same:?}");
+        }
+
+        // The order in which the nodes were created does not matter.
+        let mut built = Document::new(None, &[], true, true);
+        let root = built.add_mapping(None, MappingStyle::Any);
+        let sequence = built.add_sequence(None, SequenceStyle::Block);
+        let two = built.add_scalar(None, "two", ScalarStyle::Any);
+        let one = built.add_scalar(Some(DEFAULT_SCALAR_TAG), "1", ScalarStyle::Any);
+        built.append_sequence_item(sequence, one);
+        built.append_sequence_item(sequence, two);
+        let b = built.add_scalar(None, "b", ScalarStyle::Any);
+        let a = built.add_scalar(None, "a", ScalarStyle::Any);
+        built.yaml_document_append_mapping_pair(root, a, sequence);
+        built.yaml_document_append_mapping_pair(root, b, sequence);
+        assert_eq!(built.content_hash(), hash);
+
+        for different in [
+            "a: [1, two]\nb: [1, two]\n",
+            "a: &x [1, three]\nb: *x\n",
+            "b: &x [1, two]\na: *x\n",
+            "a: &x [1, !!int two]\nb: *x\n",
+            "a: &x {1: two}\nb: *x\n",
+            "a: &x [1, two]\n",
+            "a: &x [1, two]\nb: *x\nc: d\n",
+            "[a, [1, two], b, [1, two]]\n",
+            "",
+        ] {
+            assert_ne!(load(different).content_hash(), hash, "{different:?}");
+        }
+
+        assert_eq!(load("").content_hash(), 0xcbf2_9ce4_8422_2325);
+        // The algorithm is part of the API, so the values must not change.
+        assert_eq!(load("a").content_hash(), 0x1f6f_4a3d_0ebe_ecfd);
+    }
+
+    fn dump(document: Document) -> String {
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        document.dump(&mut emitter).unwrap();
+        emitter.close().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn deep_nesting() {
+        const DEPTH: usize = 50_000;
+        // A small stack makes an overflow deterministic if any traversal is
+        // recursive.
+        let thread = std::thread::Builder::new().stack_size(256 * 1024);
+        thread
+            .spawn(|| {
+                let input = "[".repeat(DEPTH) + &"]".repeat(DEPTH);
+                let document = load(&input);
+                assert_eq!(document.nodes.len(), DEPTH);
+                assert!(document.deep_eq(&document.clone()));
+                assert_eq!(document.path_of(DEPTH as i32).unwrap().len(), DEPTH - 1);
+                let mut normalized = document.clone();
+                normalized.normalize(&NormalizeOptions::default());
+                assert_eq!(normalized.content_hash(), document.content_hash());
+
+                let mut output = Vec::new();
+                let mut emitter = Emitter::new();
+                // Without wrapping, the output stays on one line instead of
+                // being indented for each level.
+                emitter.set_width(-1);
+                emitter.set_output(&mut output);
+                document.dump(&mut emitter).unwrap();
+                emitter.close().unwrap();
+                drop(emitter);
+                assert_eq!(String::from_utf8(output).unwrap(), input + "\n");
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    const ANCHORS: &str = "\
+defaults: &defaults
+  adapter: &adapter postgres
+  host: localhost
+development:
+  database: dev
+  base: *defaults
+test: &test !env
+  database: test
+  adapter: *adapter
+";
+
+    #[test]
+    fn anchors() {
+        let document = load(ANCHORS);
+        let names: Vec<_> = document.anchors.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["defaults", "adapter", "test"]);
+
+        // The nodes start at their anchors.
+        for name in ["defaults", "adapter", "test"] {
+            let node = document.get_node(document.anchor(name).unwrap()).unwrap();
+            let position = ANCHORS.find(&alloc::format!("&{name}")).unwrap();
+            assert_eq!(node.start_mark.index, position as u64, "{name}");
+        }
+        let node = |name| document.get_node(document.anchor(name).unwrap()).unwrap();
+        assert_eq!(node("defaults").end_mark.line, 2);
+        assert_eq!(node("adapter").end_mark.index, 48);
+        assert!(
+            matches!(&node("adapter").data, NodeData::Scalar { value, .. } if value == "postgres")
+        );
+        assert_eq!(node("test").tag.as_deref(), Some("!env"));
+        assert_eq!(document.anchor("missing"), None);
+
+        assert_eq!(
+            document.find_nodes_by_tag("!env"),
+            [document.anchor("test").unwrap()]
+        );
+        assert_eq!(document.find_nodes_by_tag(DEFAULT_MAPPING_TAG).len(), 3);
+        assert_eq!(
+            document.find_scalars("postgres"),
+            [document.anchor("adapter").unwrap()]
+        );
+        let databases = document.find_scalars("database");
+        assert_eq!(databases.len(), 2);
+        assert_eq!(document.get_node(databases[1]).unwrap().start_mark.line, 7);
+        assert!(document.find_scalars("missing").is_empty());
+    }
+
+    #[test]
+    fn alias_uses() {
+        let document = load(ANCHORS);
+        let uses: Vec<_> = document
+            .alias_uses()
+            .iter()
+            .map(|(alias, mark, index)| (alias.as_str(), mark.index, *index))
+            .collect();
+        let position = |alias| ANCHORS.find(alias).unwrap() as u64;
+        assert_eq!(
+            uses,
+            [
+                (
+                    "defaults",
+                    position("*defaults"),
+                    document.anchor("defaults").unwrap()
+                ),
+                (
+                    "adapter",
+                    position("*adapter"),
+                    document.anchor("adapter").unwrap()
+                ),
+            ]
+        );
+        assert!(load("a: b").alias_uses().is_empty());
+
+        let mut normalized = document.clone();
+        normalized.normalize(&NormalizeOptions::default());
+        let uses: Vec<_> = normalized
+            .alias_uses()
+            .iter()
+            .map(|(alias, mark, _)| (alias.as_str(), *mark))
+            .collect();
+        assert_eq!(
+            uses,
+            [("id001", Mark::default()), ("id002", Mark::default())]
+        );
+    }
+
+    #[test]
+    fn undefined_alias_suggestion() {
+        let load_err = |input: &str| {
+            let mut parser = Parser::new();
+            let mut input = input.as_bytes();
+            parser.set_input_string(&mut input);
+            Document::load(&mut parser).unwrap_err()
+        };
+
+        let input = "defaults: &defaults {a: 1}\ndev: *defualts\n";
+        let err = load_err(input);
+        assert_eq!(err.problem(), "found undefined alias");
+        assert_eq!(err.problem_mark().unwrap().index, 32);
+        assert_eq!(err.suggestion(), Some("&defaults"));
+        assert_eq!(err.context_mark().unwrap().index, 10);
+        assert_eq!(
+            err.to_string(),
+            "Composer error: line 1 column 5: found undefined alias; did you mean \
+             `&defaults` (line 0 column 10)"
+        );
+
+        // The closest of the anchors defined before the alias.
+        let err = load_err("- &defaulted 1\n- &default 2\n- *defaults\n- &defaults 3\n");
+        assert_eq!(err.suggestion(), Some("&default"));
+        assert_eq!(err.context_mark().unwrap().line, 1);
+
+        // No suggestion for names that are too different.
+        for input in ["- &a 1\n- *b\n", "- &defaults 1\n- *test\n", "- *a\n"] {
+            let err = load_err(input);
+            assert_eq!(err.problem(), "found undefined alias", "{input}");
+            assert_eq!(err.suggestion(), None, "{input}");
+            assert_eq!(err.context_mark(), None, "{input}");
+        }
+    }
+
+    #[test]
+    fn dump_anchors() {
+        // Aliased nodes keep their names, and unused anchors are dropped.
+        let output = dump(load(ANCHORS));
+        assert_eq!(
+            output,
+            "\
+defaults: &defaults
+  adapter: &adapter postgres
+  host: localhost
+development:
+  database: dev
+  base: *defaults
+test: !env
+  database: test
+  adapter: *adapter
+"
+        );
+
+        // Generated names do not clash with preserved ones.
+        let mut document = load("- &id001 a\n- *id001\n- b\n");
+        let b = document.find_scalars("b")[0];
+        document.nodes[0].data = NodeData::Sequence {
+            items: vec![2, 2, b, b],
+            style: crate::SequenceStyle::Block,
+        };
+        assert_eq!(
+            dump(document),
+            "- &id001 a\n- *id001\n- &id002 b\n- *id002\n"
+        );
+    }
+
+    #[test]
+    fn dump_root_anchor() {
+        // The root node keeps its anchor even if it is not aliased.
+        for input in [
+            "--- &base
+a: b
+",
+            "--- &base [a, b]
+",
+            "--- &base a
+",
+        ] {
+            assert_eq!(dump(load(input)), input);
+        }
+        assert_eq!(
+            dump(load(
+                "--- &base
+a: *base
+"
+            )),
+            "--- &base
+a: *base
+"
+        );
+    }
+
+    #[test]
+    fn tag_directives() {
+        let directive = |handle: &str, prefix: &str| TagDirective {
+            handle: handle.to_string(),
+            prefix: prefix.to_string(),
+        };
+        let mut document = load("%TAG !a! tag:a.com,2000:\n--- !a!x y\n");
+        let err = document
+            .add_tag_directive(directive("!a!", "tag:b.com,2000:"))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Composer);
+        assert_eq!(err.problem(), "found duplicate %TAG directive");
+        assert_eq!(
+            document.tag_directives,
+            [directive("!a!", "tag:a.com,2000:")]
+        );
+        document
+            .add_tag_directive(directive("!b!", "tag:b.com,2000:"))
+            .unwrap();
+        assert_eq!(
+            document.set_tag_directive(directive("!a!", "tag:c.com,2000:")),
+            Some(directive("!a!", "tag:a.com,2000:"))
+        );
+        assert_eq!(
+            document.set_tag_directive(directive("!c!", "tag:a.com,2000:")),
+            None
+        );
+        assert_eq!(
+            dump(document),
+            "%TAG !a! tag:c.com,2000:\n%TAG !b! tag:b.com,2000:\n%TAG !c! tag:a.com,2000:\n--- !c!x y\n"
+        );
+
+        // Duplicates added directly fail before anything of the document is
+        // written.
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        load("first").dump(&mut emitter).unwrap();
+        let mut document = load("second");
+        document.tag_directives = vec![
+            directive("!a!", "tag:a.com,2000:"),
+            directive("!a!", "tag:b.com,2000:"),
+        ];
+        let err = document.dump(&mut emitter).unwrap_err();
+        assert_eq!(err.problem(), "duplicate %TAG directive");
+        emitter.flush().unwrap();
+        drop(emitter);
+        assert_eq!(String::from_utf8(output).unwrap(), "first\n");
+    }
+
+    #[test]
+    fn dump_scalar_styles() {
+        let input = "\
+plain: value
+single: 'value'
+double: \"value\"
+literal: |
+  one
+  two
+folded: >
+  one two
+'key': [plain, 'single', \"double\"]
+\"k\": {'a': \"b\"}
+multiline: 'one
+
+  two'
+empty: ''
+";
+        assert_eq!(dump(load(input)), input);
+
+        // Styles that cannot represent the value in their context are
+        // replaced.
+        let mut document = load("[a, b]");
+        for (node, style) in document.nodes[1..]
+            .iter_mut()
+            .zip([ScalarStyle::Literal, ScalarStyle::Plain])
+        {
+            node.data = NodeData::Scalar {
+                value: "- x".to_string(),
+                style,
+            };
+        }
+        assert_eq!(dump(document), "[\"- x\", '- x']\n");
+
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        emitter.set_respect_scalar_styles(false);
+        load(input).dump(&mut emitter).unwrap();
+        emitter.close().unwrap();
+        drop(emitter);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+plain: value
+single: value
+double: value
+literal: 'one
+
+  two
+
+  '
+folded: 'one two
+
+  '
+key: [plain, single, double]
+k: {a: b}
+multiline: 'one
+
+  two'
+empty: ''
+"
+        );
+    }
+
+    #[test]
+    fn presentation_round_trip() {
+        fn load_presentation(input: &str) -> Document {
+            let mut parser = Parser::new();
+            parser.set_capture_presentation(true);
+            let mut input = input.as_bytes();
+            parser.set_input_string(&mut input);
+            Document::load(&mut parser).unwrap()
+        }
+
+        let input = "\
+keep: |+2
+    indented
+
+strip: >-
+  folded text
+clip: |
+  plain
+first: |2-
+   spaced
+empty_seq: []
+empty_map: {}
+items:
+- |+
+  kept
+- >1
+  x
+";
+        assert_eq!(dump(load_presentation(input)), input);
+
+        let document = load_presentation("a: |+2\n    x\n");
+        let presentation = document.nodes[2].presentation.unwrap();
+        assert_eq!(presentation.chomping, Chomping::Keep);
+        assert_eq!(presentation.indent_indicator, Some(2));
+        assert!(presentation.chomping_first);
+        assert!(load("a: |+2\n    x\n").nodes[2].presentation.is_none());
+
+        // Without the indicators, the emitter picks its own.
+        assert_eq!(
+            dump(load(input)),
+            "\
+keep: |2+
+    indented
+
+strip: >-
+  folded text
+clip: |
+  plain
+first: |2-
+   spaced
+empty_seq: []
+empty_map: {}
+items:
+- |
+  kept
+- >2
+   x
+"
+        );
+    }
+
+    #[test]
+    fn memory_usage() {
+        fn generate(count: usize) -> String {
+            let mut input = String::from("%TAG !e! tag:example.com,2000:\n---\n");
+            for i in 0..count {
+                writeln!(input, "key{i}: &a{i} !e!x [{i}, {{b: *a{i}}}]").unwrap();
+            }
+            input
+        }
+        fn load_shrunk(input: &str) -> Document {
+            let mut parser = Parser::new();
+            parser.set_shrink_documents(true);
+            let mut input = input.as_bytes();
+            parser.set_input_string(&mut input);
+            Document::load(&mut parser).unwrap()
+        }
+
+        let input = generate(20);
+        let mut document = load(&input);
+        document.build_mapping_index(1);
+        let usage = document.approx_memory_usage();
+        document.shrink_to_fit();
+        let shrunk = document.approx_memory_usage();
+        assert!(shrunk < usage, "{shrunk} < {usage}");
+        assert_eq!(document, load(&input));
+
+        let mut loaded = load_shrunk(&input);
+        assert!(loaded.approx_memory_usage() < usage);
+        assert_eq!(loaded, document);
+        loaded.shrink_to_fit();
+        assert_eq!(loaded, document);
+
+        let mut previous = 0;
+        for count in [0, 1, 2, 5, 10, 50, 100] {
+            let usage = load_shrunk(&generate(count)).approx_memory_usage();
+            assert!(previous < usage, "{count}: {previous} < {usage}");
+            previous = usage;
+        }
+    }
+
+    #[test]
+    fn tag_origin() {
+        for (input, origin, tag) in [
+            ("foo", TagOrigin::Resolved, None),
+            ("! foo", TagOrigin::NonSpecific, Some("!")),
+            ("!!str foo", TagOrigin::Explicit, Some("!!str")),
+            ("'foo'", TagOrigin::Resolved, None),
+            ("! 'foo'", TagOrigin::NonSpecific, Some("!")),
+            ("!!str 'foo'", TagOrigin::Explicit, Some("!!str")),
+            ("!!int 1", TagOrigin::Explicit, Some("!!int")),
+            ("[a]", TagOrigin::Resolved, None),
+            ("! [a]", TagOrigin::NonSpecific, Some("!")),
+            ("!!seq [a]", TagOrigin::Explicit, Some("!!seq")),
+            ("{a: b}", TagOrigin::Resolved, None),
+            ("! {a: b}", TagOrigin::NonSpecific, Some("!")),
+            ("!!map {a: b}", TagOrigin::Explicit, Some("!!map")),
+            ("!e {a: b}", TagOrigin::Explicit, Some("!e")),
+            ("[]", TagOrigin::Resolved, None),
+            ("! []", TagOrigin::NonSpecific, Some("!")),
+            ("!!seq []", TagOrigin::Explicit, Some("!!seq")),
+            ("!e []", TagOrigin::Explicit, Some("!e")),
+            ("{}", TagOrigin::Resolved, None),
+            ("! {}", TagOrigin::NonSpecific, Some("!")),
+            ("!!map {}", TagOrigin::Explicit, Some("!!map")),
+            ("!e {}", TagOrigin::Explicit, Some("!e")),
+        ] {
+            let document = load(input);
+            let root = document.get_node(1).unwrap();
+            assert_eq!(root.tag_origin, origin, "{input}");
+            if origin != TagOrigin::Explicit {
+                assert!(root
+                    .tag
+                    .as_deref()
+                    .unwrap()
+                    .starts_with("tag:yaml.org,2002:"));
+            }
+
+            let output = dump(document);
+            assert_eq!(output.trim_end(), input, "{input}");
+            match tag {
+                Some(tag) => assert!(output.starts_with(&alloc::format!("{tag} ")), "{output}"),
+                None => assert!(!output.contains('!'), "{output}"),
+            }
+        }
+    }
+
+    #[test]
+    fn mapping_index() {
+        fn scalar(document: &Document, index: Option<i32>) -> Option<&str> {
+            match &document.get_node(index?)?.data {
+                NodeData::Scalar { value, .. } => Some(value),
+                _ => None,
+            }
+        }
+
+        let mut document = Document::new(None, &[], true, true);
+        let mapping = document.add_mapping(None, MappingStyle::Block);
+        for i in 0..10_000 {
+            // Every hundredth key repeats an earlier one, and the first pair
+            // wins.
+            let key = if i % 100 == 99 { i - 99 } else { i };
+            let key = document.add_scalar(None, &alloc::format!("key{key}"), ScalarStyle::Any);
+            let value = document.add_scalar(None, &i.to_string(), ScalarStyle::Any);
+            document.yaml_document_append_mapping_pair(mapping, key, value);
+        }
+        let complex_key = document.add_sequence(None, SequenceStyle::Flow);
+        let value = document.add_scalar(None, "complex", ScalarStyle::Any);
+        document.yaml_document_append_mapping_pair(mapping, complex_key, value);
+
+        let keys: Vec<_> = (0..10_100).map(|i| alloc::format!("key{i}")).collect();
+        let linear: Vec<_> = keys
+            .iter()
+            .map(|key| document.get_mapping_value(mapping, key))
+            .collect();
+        assert_eq!(linear.iter().filter(|value| value.is_some()).count(), 9_900);
+        assert_eq!(scalar(&document, linear[0]), Some("0"));
+        assert_eq!(scalar(&document, linear[100]), Some("100"));
+
+        let mut indexed = document.clone();
+        indexed.build_mapping_index(mapping);
+        assert_eq!(indexed, document);
+        for (key, expected) in keys.iter().zip(&linear) {
+            assert_eq!(indexed.get_mapping_value(mapping, key), *expected, "{key}");
+        }
+
+        // Absent keys are answered by the index alone.
+        for key in ["key99", "key10100", "", "complex"] {
+            assert_eq!(indexed.get_mapping_value(mapping, key), None, "{key}");
+        }
+
+        // The index is updated when pairs are added.
+        for mut document in [document, indexed] {
+            let key = document.add_scalar(None, "new", ScalarStyle::Any);
+            document.yaml_document_append_mapping_pair(mapping, key, key);
+            let key = document.add_scalar(None, "key1", ScalarStyle::Any);
+            document.yaml_document_append_mapping_pair(mapping, key, key);
+            assert_eq!(document.get_mapping_value(mapping, "new"), Some(key - 1));
+            assert_eq!(document.get_mapping_value(mapping, "key1"), linear[1]);
+            assert_eq!(document.get_mapping_value(mapping, "key99"), None);
+            assert_eq!(document.get_mapping_value(1, "complex"), None);
+            assert_eq!(document.get_mapping_value(2, "key1"), None);
+
+            // Borrowing a node mutably removes the index.
+            let NodeData::Mapping { pairs, .. } = &mut document.get_node_mut(mapping).unwrap().data
+            else {
+                unreachable!()
+            };
+            pairs.swap(0, 1);
+            assert_eq!(
+                scalar(&document, document.get_mapping_value(mapping, "key0")),
+                Some("0")
+            );
+        }
+
+        // A key renamed after the index was built is found again.
+        let mut document = load("a: 1\nb: 2\n");
+        document.build_mapping_index(1);
+        let NodeData::Scalar { value, .. } = &mut document.get_node_mut(4).unwrap().data else {
+            unreachable!()
+        };
+        *value = String::from("c");
+        assert_eq!(
+            scalar(&document, document.get_mapping_value(1, "c")),
+            Some("2")
+        );
+        assert_eq!(document.get_mapping_value(1, "b"), None);
+    }
+
+    #[test]
+    fn explicit_keys() {
+        fn render(document: &Document, index: i32) -> String {
+            match &document.get_node(index).unwrap().data {
+                NodeData::Scalar { value, .. } if value.is_empty() => String::from("''"),
+                NodeData::Scalar { value, .. } => value.clone(),
+                NodeData::Sequence { items, .. } => {
+                    let items: Vec<_> = items.iter().map(|item| render(document, *item)).collect();
+                    format!("[{}]", items.join(", "))
+                }
+                NodeData::Mapping { pairs, .. } => {
+                    let pairs: Vec<_> = pairs
+                        .iter()
+                        .map(|pair| {
+                            let key = render(document, pair.key);
+                            let value = render(document, pair.value);
+                            format!("{key}: {value}")
+                        })
+                        .collect();
+                    format!("{{{}}}", pairs.join(", "))
+                }
+                NodeData::NoNode => unreachable!(),
+            }
+        }
+
+        for (input, expected) in [
+            ("? key\n: value", "{key: value}"),
+            ("?\n: value", "{'': value}"),
+            ("?\n", "{'': ''}"),
+            ("? a\n:\n  b", "{a: b}"),
+            ("?\n  a\n:\n  b", "{a: b}"),
+            ("? key\n? key2", "{key: '', key2: ''}"),
+            ("? a\n: b\n? c\n: d", "{a: b, c: d}"),
+            ("? ? a\n  : b\n: c", "{{a: b}: c}"),
+            ("- ? a\n  : b", "[{a: b}]"),
+            ("? - a\n  - b\n: - c", "{[a, b]: [c]}"),
+            ("? |\n  text\n: v", "{text\n: v}"),
+            ("{? a : b, ? : c}", "{a: b, '': c}"),
+            // Entries with an empty key.
+            ("? &a a\n: &b b\n: *a", "{a: b, '': a}"),
+            (": a\n: b", "{'': a, '': b}"),
+            ("- : a", "[{'': a}]"),
+            ("k:\n  ? a\n  : b\n  : c", "{k: {a: b, '': c}}"),
+            (
+                "plain key: in-line value\n: # Both empty\n\"quoted key\":\n- entry",
+                "{plain key: in-line value, '': '', quoted key: [entry]}",
+            ),
+        ] {
+            assert_eq!(render(&load(input), 1), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn item_and_key_marks() {
+        fn positions(document: &Document, index: i32) -> Vec<(u64, u64)> {
+            let node = document.get_node(index).unwrap();
+            let marks: Vec<Mark> = match &node.data {
+                NodeData::Sequence { .. } => node.item_marks.clone(),
+                NodeData::Mapping { pairs, .. } => pairs.iter().map(|pair| pair.key_mark).collect(),
+                _ => Vec::new(),
+            };
+            marks.iter().map(|mark| (mark.line, mark.column)).collect()
+        }
+
+        let document = load("a:\n  - x\n  -\n  - [1, 2]\n? b\n: c\nd:\n- e\n");
+        assert_eq!(positions(&document, 1), [(0, 0), (4, 0), (6, 0)]);
+        assert_eq!(positions(&document, 3), [(1, 2), (2, 2), (3, 2)]);
+        assert_eq!(positions(&document, 6), [(3, 5), (3, 8)]);
+        assert_eq!(positions(&document, 12), [(7, 0)]);
+
+        let document = load("{ a: [x, y], ? b: c, d }");
+        assert_eq!(positions(&document, 1), [(0, 2), (0, 13), (0, 21)]);
+        assert_eq!(positions(&document, 3), [(0, 6), (0, 9)]);
+
+        let document = load("[a: b]");
+        assert_eq!(positions(&document, 1), [(0, 1)]);
+        assert_eq!(positions(&document, 2), [(0, 1)]);
+
+        // The marks survive a round trip through the cache.
+        let document = load("- &x a\n- *x\n");
+        assert_eq!(positions(&document, 1), [(0, 0), (1, 0)]);
+        let mut cache = Vec::new();
+        document.write_cache(&mut cache).unwrap();
+        assert_eq!(Document::read_cache(&*cache).unwrap(), document);
+    }
+
+    #[test]
+    fn deduplicate_scalars() {
+        fn dump_deduplicated(document: Document, min_len: usize) -> String {
+            let mut output = Vec::new();
+            let mut emitter = Emitter::new();
+            emitter.set_deduplicate_scalars(min_len);
+            emitter.set_output(&mut output);
+            document.dump(&mut emitter).unwrap();
+            emitter.close().unwrap();
+            String::from_utf8(output).unwrap()
+        }
+
+        let cert = "MIIB".repeat(40);
+        let input =
+            format!("first: {cert}\nsecond: {cert}\nlist: [{cert}, short, short]\n{cert}: key\n");
+        let output = dump_deduplicated(load(&input), 16);
+        assert_eq!(output.matches(&cert).count(), 1, "{output}");
+        assert_eq!(output.matches("&id001").count(), 1, "{output}");
+        assert_eq!(output.matches("*id001").count(), 3, "{output}");
+        assert_eq!(output.matches("short").count(), 2, "{output}");
+        assert!(load(&output).deep_eq(&load(&input)));
+
+        // Off by default.
+        assert_eq!(dump(load(&input)).matches(&cert).count(), 4);
+
+        // Scalars with a different tag or style are not merged.
+        let input =
+            format!("- {cert}\n- !custom {cert}\n- '{cert}'\n- !custom {cert}\n- '{cert}'\n");
+        let output = dump_deduplicated(load(&input), 16);
+        assert_eq!(output.matches(&cert).count(), 3, "{output}");
+        assert_eq!(output.matches('*').count(), 2, "{output}");
+        assert!(load(&output).deep_eq(&load(&input)));
+
+        // Generated anchors do not collide with the anchors of the document,
+        // which are kept.
+        let input =
+            format!("a: &id001 [x]\nb: *id001\nc: {cert}\nd: {cert}\ne: &mine {cert}\nf: *mine\n");
+        let output = dump_deduplicated(load(&input), 16);
+        assert!(output.contains("a: &id001"), "{output}");
+        assert!(output.contains(&format!("c: &id002 {cert}")), "{output}");
+        assert!(output.contains("d: *id002"), "{output}");
+        assert!(output.contains(&format!("e: &mine {cert}")), "{output}");
+        assert!(output.contains("f: *mine"), "{output}");
+        assert!(load(&output).deep_eq(&load(&input)));
+    }
+
+    #[test]
+    fn directives_round_trip() {
+        for (header, content) in [
+            ("", "--- a\n"),
+            ("%YAML 1.1\n", "--- a\n"),
+            ("%TAG !e! tag:example.com,2000:\n", "--- !e!x a\n"),
+            (
+                "%YAML 1.2\n%TAG !e! tag:example.com,2000:\n",
+                "--- [!e!x a, !!str b]\n",
+            ),
+            // Directives may redefine the default handles.
+            (
+                "%TAG ! tag:example.com,2000:\n%TAG !! !local-\n",
+                "--- !x [!!y a]\n",
+            ),
+        ] {
+            let input = alloc::format!("{header}{content}");
+            let document = load(&input);
+            let output = dump(load(&input));
+            assert_eq!(output, input);
+
+            let reloaded = load(&output);
+            assert_eq!(reloaded.version_directive, document.version_directive);
+            assert_eq!(reloaded.tag_directives, document.tag_directives);
+            assert!(reloaded.deep_eq(&document));
+        }
+
+        // Directives make the start of a new document explicit.
+        let tag_directive = TagDirective {
+            handle: String::from("!e!"),
+            prefix: String::from("tag:example.com,2000:"),
+        };
+        let mut document = Document::new(
+            Some(VersionDirective { major: 1, minor: 1 }),
+            &[tag_directive],
+            true,
+            true,
+        );
+        let root = document.add_scalar(Some("tag:example.com,2000:x"), "a", ScalarStyle::Any);
+        assert_eq!(root, 1);
+        assert_eq!(
+            dump(document),
+            "%YAML 1.1\n%TAG !e! tag:example.com,2000:\n--- !e!x a\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json() {
+        let document =
+            load("%TAG !e! tag:example.com,2000:\n--- !e!map\na: &x [1, 'two']\nb: *x\n");
+        let json = serde_json::to_value(&document).unwrap();
+
+        // Enums are externally tagged with their variant names.
+        let first = &json["nodes"][0];
+        assert_eq!(first["tag"], "tag:example.com,2000:map");
+        assert_eq!(first["tag_origin"], "Explicit");
+        assert_eq!(first["data"]["Mapping"]["style"], "Block");
+        assert_eq!(first["data"]["Mapping"]["pairs"][0]["key"], 2);
+        let two = &json["nodes"][4];
+        assert_eq!(two["data"]["Scalar"]["value"], "two");
+        assert_eq!(two["data"]["Scalar"]["style"], "SingleQuoted");
+        assert_eq!(two["start_mark"]["line"], 2);
+        assert_eq!(json["tag_directives"][0]["handle"], "!e!");
+
+        let text = serde_json::to_string(&document).unwrap();
+        let decoded: Document = serde_json::from_str(&text).unwrap();
+        assert!(decoded.deep_eq(&document));
+        assert_eq!(decoded, document);
+    }
+
+    fn load_filtered(
+        input: &str,
+        filter: impl FnMut(&NodePath, &Event) -> NodeAction,
+    ) -> Result<Document> {
+        let mut parser = Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        Document::load_filtered(&mut parser, filter)
+    }
+
+    #[test]
+    fn load_filtered_paths() {
+        let input = "a: [x, {b: y}]\n? [k]\n: z\n&k c: {d: e}\n*k : f\n";
+        let mut paths = Vec::new();
+        let document = load_filtered(input, |path, _| {
+            paths.push(path.to_string());
+            NodeAction::Descend
+        })
+        .unwrap();
+        assert_eq!(
+            paths,
+            [".", ".a", ".a[0]", ".a[1]", ".a[1].b", "[key]", ".c", ".c.d", ".c"]
+        );
+        // Descending into everything composes everything.
+        assert!(document.deep_eq(&load(input)));
+    }
+
+    #[test]
+    fn load_filtered_large() {
+        let mut input = String::new();
+        for i in 0..2000 {
+            writeln!(input, "item{i}:\n  list: [1, 2, 3]\n  map: {{a: b, c: d}}").unwrap();
+        }
+        input.push_str("config:\n  deep:\n    nested: {target: {key: value}, other: [1, 2]}\n");
+        let wanted = ["config", "deep", "nested", "target"];
+
+        let document = load_filtered(&input, |path, _| {
+            let keys = path.segments().iter().map(|segment| match segment {
+                PathSegment::Key(key) => key.as_str(),
+                _ => "",
+            });
+            if path.len() == wanted.len() && keys.clone().eq(wanted) {
+                NodeAction::Compose
+            } else if keys.zip(wanted).all(|(key, wanted)| key == wanted) {
+                NodeAction::Descend
+            } else {
+                NodeAction::Skip
+            }
+        })
+        .unwrap();
+        let full = load(&input);
+        assert!(full.nodes.len() > 25_000);
+        assert_eq!(document.nodes.len(), 11);
+
+        let mut node = 1;
+        for key in wanted {
+            node = document.get_mapping_value(node, key).unwrap();
+        }
+        let value = document.get_mapping_value(node, "key").unwrap();
+        assert_eq!(document.scalar_value(value), Some("value"));
+        assert_eq!(
+            document.nodes[node as usize - 1].start_mark,
+            full.nodes[full.nodes.len() - 7].start_mark
+        );
+    }
+
+    #[test]
+    fn load_filtered_anchors() {
+        let skip_b = |path: &NodePath, _: &Event| match path.segments() {
+            [PathSegment::Key(key), ..] if key == "b" => NodeAction::Skip,
+            _ => NodeAction::Descend,
+        };
+
+        // Aliases within the skipped node, or of composed nodes, are fine.
+        let document = load_filtered("a: &a x\nb: [&b y, *b, *a]\nc: *a\n", skip_b).unwrap();
+        assert_eq!(dump(document), "a: &a x\nc: *a\n");
+
+        let err = load_filtered("b: [&b y]\nc: *b\n", skip_b).unwrap_err();
+        assert_eq!(err.problem(), "found alias of a skipped node");
+        assert_eq!(err.problem_mark().unwrap().line, 1);
+        let err = load_filtered("b: [*b]\n", skip_b).unwrap_err();
+        assert_eq!(err.problem(), "found undefined alias");
+
+        // Anchors of the keys of skipped values are skipped too.
+        let err = load_filtered("&k b: y\nc: *k\n", skip_b).unwrap_err();
+        assert_eq!(err.problem(), "found alias of a skipped node");
+        let document = load_filtered("&k b: y\nc: z\n", skip_b).unwrap();
+        assert!(document.anchors.is_empty());
+        assert_eq!(dump(document), "c: z\n");
+    }
+
+    #[test]
+    fn load_filtered_root() {
+        for (input, output) in [
+            ("&a [1, *a]", "[]\n"),
+            ("!t {a: b}", "!t {}\n"),
+            ("--- text\n", "--- ''\n"),
+        ] {
+            let document = load_filtered(input, |_, _| NodeAction::Skip).unwrap();
+            assert!(document.anchors.is_empty(), "{input}");
+            assert_eq!(dump(document), output, "{input}");
+        }
+
+        let mut parser = Parser::new();
+        let mut input = "[a, [b, c], d]\n--- next\n".as_bytes();
+        parser.set_input_string(&mut input);
+        let document = Document::load_filtered(&mut parser, |path, _| {
+            if path.segments() == [PathSegment::Index(1)] {
+                NodeAction::Abort
+            } else {
+                NodeAction::Descend
+            }
+        })
+        .unwrap();
+        assert_eq!(document.end_mark.column, 4);
+        assert_eq!(dump(document), "[a]\n...\n");
+    }
+
+    #[test]
+    fn path_of() {
+        let document = load(
+            "\
+spec:
+  containers:
+  - &c {name: a, image: x}
+  - name: b
+    image: y
+  - *c
+  ? [complex]
+  : &l [z]
+  \"a key\": *l
+",
+        );
+        let paths = (1..=document.nodes.len() as i32)
+            .map(|index| document.path_of(index).map(|path| path.to_string()))
+            .collect::<Vec<_>>();
+        let expected = [
+            Some("."),
+            None,
+            Some(".spec"),
+            None,
+            Some(".spec.containers"),
+            Some(".spec.containers[0]"),
+            None,
+            Some(".spec.containers[0].name"),
+            None,
+            Some(".spec.containers[0].image"),
+            Some(".spec.containers[1]"),
+            None,
+            Some(".spec.containers[1].name"),
+            None,
+            Some(".spec.containers[1].image"),
+            None,
+            None,
+            Some(".spec[key]"),
+            Some(".spec[key][0]"),
+            None,
+        ];
+        assert_eq!(paths, expected.map(|path| path.map(str::to_string)));
+        assert_eq!(document.path_of(0), None);
+        assert_eq!(document.path_of(21), None);
+
+        // Cycles through aliases are only followed once.
+        let mut document = load("&a [x, [y]]");
+        let NodeData::Sequence { items, .. } = &mut document.nodes[2].data else {
+            unreachable!()
+        };
+        items.push(1);
+        assert_eq!(document.path_of(4).unwrap().to_string(), "[1][0]");
+        document.nodes.push(Node::default());
+        assert_eq!(document.path_of(5), None);
+    }
+
+    #[test]
+    fn select() {
+        let document = load(
+            "\
+spec:
+  containers:
+  - &c {name: a, image: x}
+  - name: b
+    image: y
+  - *c
+  \"a key\": [z]
+",
+        );
+        let select = |path| {
+            document
+                .select(path)
+                .into_iter()
+                .map(|index| match &document.nodes[index as usize - 1].data {
+                    NodeData::Scalar { value, .. } => value.as_str(),
+                    NodeData::Sequence { .. } => "[]",
+                    _ => "{}",
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(select("."), ["{}"]);
+        assert_eq!(select(".spec.containers[1].image"), ["y"]);
+        assert_eq!(select("spec.containers[*].name"), ["a", "b", "a"]);
+        assert_eq!(select(".spec.*[0]"), ["{}", "z"]);
+        assert_eq!(select(".spec[\"a key\"][0]"), ["z"]);
+        assert_eq!(select(".*.*.*"), ["{}", "{}", "{}", "z"]);
+        assert_eq!(select(".*.*.*.*"), ["a", "x", "b", "y", "a", "x"]);
+        assert!(select(".spec.containers[3]").is_empty());
+        assert!(select(".spec.missing").is_empty());
+        assert!(select(".spec..containers").is_empty());
+        assert!(Document::new(None, &[], true, true).select(".").is_empty());
+    }
+
+    #[test]
+    fn merge() {
+        const BASE: &str = "\
+server:
+  host: localhost
+  port: 8080
+  tls:
+    enabled: false
+features: [auth, metrics]
+database:
+  url: postgres://localhost/app
+  pool: 5
+";
+        const OVERRIDE: &str = "\
+server:
+  port: 9090
+  tls:
+    enabled: true
+    cert: /etc/app/cert.pem
+features: [tracing]
+database:
+  pool: ~
+logging:
+  level: debug
+";
+        let merge = |map_conflict, seq_conflict, null_overrides| {
+            let mut document = load(BASE);
+            let strategy = MergeStrategy {
+                map_conflict,
+                seq_conflict,
+                null_overrides,
+            };
+            document.merge(&load(OVERRIDE), strategy);
+            dump(document)
+        };
+        let merged = |features: &str, pool: &str| {
+            format!(
+                "\
+server:
+  host: localhost
+  port: 9090
+  tls:
+    enabled: true
+    cert: /etc/app/cert.pem
+features: {features}
+database:
+  url: postgres://localhost/app
+  pool: {pool}
+logging:
+  level: debug
+"
+            )
+        };
+        for (seq_conflict, features) in [
+            (SeqMerge::Replace, "[tracing]"),
+            (SeqMerge::Concat, "[auth, metrics, tracing]"),
+        ] {
+            for (null_overrides, pool) in [(true, "~"), (false, "5")] {
+                assert_eq!(
+                    merge(MapMerge::DeepMerge, seq_conflict, null_overrides),
+                    merged(features, pool),
+                );
+                // The root mapping is replaced, so nothing else is merged.
+                assert_eq!(
+                    merge(MapMerge::Replace, seq_conflict, null_overrides),
+                    OVERRIDE,
+                );
+            }
+        }
+
+        // Marks are kept from the original documents.
+        let mut document = load(BASE);
+        document.merge(&load(OVERRIDE), MergeStrategy::default());
+        let server = document.get_mapping_value(1, "server").unwrap();
+        let host = document.get_mapping_value(server, "host").unwrap();
+        let port = document.get_mapping_value(server, "port").unwrap();
+        assert_eq!(document.get_node(host).unwrap().start_mark.line, 1);
+        assert_eq!(document.get_node(port).unwrap().start_mark.line, 1);
+        let logging = document.get_mapping_value(1, "logging").unwrap();
+        assert_eq!(document.get_node(logging).unwrap().start_mark.line, 9);
+
+        // The winning node keeps its tag and style.
+        let mut document = load("a: 'text'\nb: !!str 1\n");
+        document.merge(&load("a: !!int 2\nb: \"x\"\n"), MergeStrategy::default());
+        assert_eq!(dump(document), "a: !!int 2\nb: \"x\"\n");
+
+        // Merging into an empty document copies the other document, and
+        // merging an empty document does nothing.
+        let mut document = Document::new(None, &[], true, true);
+        document.merge(&load("a: &x [1]\nb: *x\n"), MergeStrategy::default());
+        document.merge(
+            &Document::new(None, &[], true, true),
+            MergeStrategy::default(),
+        );
+        assert_eq!(dump(document), "a: &x [1]\nb: *x\n");
+
+        // A root node of another kind replaces the root node.
+        let mut document = load("a: 1\n");
+        document.merge(&load("- x\n"), MergeStrategy::default());
+        assert_eq!(document.nodes[0].start_mark.line, 0);
+        assert_eq!(dump(document), "- x\n");
+    }
+
+    #[test]
+    fn merge_anchors() {
+        let mut document = load("defaults: &defaults {retries: 3}\nservice: *defaults\n");
+        document.merge(
+            &load("limits: &defaults {cpu: 2}\nworker: *defaults\nservice: {retries: 5}\n"),
+            MergeStrategy::default(),
+        );
+        let limits = document.get_mapping_value(1, "limits").unwrap();
+        assert_eq!(document.anchor("defaults2"), Some(limits));
+        let aliases: Vec<_> = document
+            .alias_uses()
+            .iter()
+            .map(|(alias, mark, _)| (alias.as_str(), mark.line))
+            .collect();
+        assert_eq!(aliases, [("defaults", 1), ("defaults2", 1)]);
+        // The shared mapping is merged, so both of its uses change.
+        assert_eq!(
+            dump(document),
+            "\
+defaults: &defaults {retries: 5}
+service: *defaults
+limits: &defaults2 {cpu: 2}
+worker: *defaults2
+",
+        );
     }
 }
@@ -1,11 +1,13 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
 
 use crate::macros::{
     is_alpha, is_ascii, is_blank, is_blankz, is_bom, is_break, is_breakz, is_printable, is_space,
 };
 use crate::{
-    Break, Encoding, Error, Event, EventData, MappingStyle, Result, ScalarStyle, SequenceStyle,
-    TagDirective, VersionDirective, OUTPUT_BUFFER_SIZE,
+    Break, Chomping, EmitterStats, Encoding, Error, Event, EventData, ExplicitKeys, FinalNewline,
+    InterruptCheck, MappingStyle, Presentation, Result, ScalarStyle, SequenceStyle, TagDirective,
+    VersionDirective, VersionDirectivePolicy, NULL_TAG, OUTPUT_BUFFER_SIZE, STR_TAG,
 };
 
 /// The emitter structure.
@@ -15,7 +17,7 @@ use crate::{
 #[non_exhaustive]
 pub struct Emitter<'w> {
     /// Write handler.
-    pub(crate) write_handler: Option<&'w mut dyn std::io::Write>,
+    pub(crate) write_handler: Option<WriteHandler<'w>>,
     /// The working buffer.
     ///
     /// This always contains valid UTF-8.
@@ -37,6 +39,17 @@ pub struct Emitter<'w> {
     pub(crate) unicode: bool,
     /// The preferred line break.
     pub(crate) line_break: Break,
+    /// The line break policy at the end of the stream.
+    pub(crate) final_newline: FinalNewline,
+    /// When to write the `%YAML` directives of documents.
+    pub(crate) version_directive_policy: VersionDirectivePolicy,
+    /// When to write mapping keys with the `? ` indicator.
+    pub(crate) explicit_keys: ExplicitKeys,
+    /// Start block sequences that are items of block sequences on the line
+    /// of the parent `-` indicator?
+    pub(crate) compact_nested_sequences: bool,
+    /// The number of spaces at the start of every line that is not empty.
+    pub(crate) base_indent: u64,
     /// The stack of states.
     pub(crate) states: Vec<EmitterState>,
     /// The current emitter state.
@@ -60,15 +73,19 @@ pub struct Emitter<'w> {
     /// Is it a simple mapping key context?
     pub(crate) simple_key_context: bool,
     /// The current line.
-    pub(crate) line: i32,
+    pub(crate) line: u64,
     /// The current column.
-    pub(crate) column: i32,
+    pub(crate) column: u64,
     /// If the last character was a whitespace?
     pub(crate) whitespace: bool,
     /// If the last character was an indentation character (' ', '-', '?', ':')?
     pub(crate) indention: bool,
     /// If an explicit document end is required?
     pub(crate) open_ended: i32,
+    /// The line and the column after the last block scalar that ends with a
+    /// line break of its value, which must not be removed at the end of the
+    /// stream.
+    pub(crate) block_scalar_end: Option<(u64, u64)>,
     /// If the stream was already opened?
     pub(crate) opened: bool,
     /// If the stream was already closed?
@@ -78,6 +95,246 @@ pub struct Emitter<'w> {
     pub(crate) anchors: Vec<Anchors>,
     /// The last assigned anchor id.
     pub(crate) last_anchor_id: i32,
+    /// The anchors defined so far in the current document, which aliases may
+    /// refer to.
+    pub(crate) defined_anchors: HashSet<String>,
+    /// The maximum line width for automatically compacted collections (0
+    /// disables compaction).
+    pub(crate) compact_width: usize,
+    /// The minimum length of scalars that are deduplicated when dumping a
+    /// document, if enabled.
+    pub(crate) deduplicate_scalars: Option<usize>,
+    /// Emit tags even if they are implicit?
+    pub(crate) explicit_tags: bool,
+    /// Indent block sequences that are mapping values?
+    pub(crate) indent_sequences: bool,
+    /// Emit multiline scalars without a style in the literal style?
+    pub(crate) prefer_block_scalars: bool,
+    /// Keep the concrete styles of scalars where they can represent the value?
+    pub(crate) respect_scalar_styles: bool,
+    /// Emit all collections in the flow style?
+    pub(crate) flow_collections: bool,
+    /// The maximum number of events waiting in `events`.
+    pub(crate) max_queued_events: usize,
+    /// The maximum length of implicit keys, in characters.
+    pub(crate) max_simple_key_length: usize,
+    /// Record the lines that end beyond the preferred width?
+    pub(crate) track_overflow: bool,
+    /// The kind of content being written.
+    pub(crate) content_kind: OverflowKind,
+    /// The kind of content that went beyond the preferred width on the
+    /// current line, if any.
+    pub(crate) overflow_kind: Option<OverflowKind>,
+    /// The lines that ended beyond the preferred width.
+    pub(crate) overflow_reports: Vec<OverflowReport>,
+    /// Is the current line being wrapped?
+    pub(crate) wrapping: bool,
+    /// The interrupt check, if any.
+    pub(crate) interrupt_check: Option<InterruptCheck>,
+    /// The output filter, if any.
+    output_filter: Option<OutputFilter>,
+    /// The role of the output being written.
+    chunk_role: OutputRole,
+    /// The start of the output of the current role in `buffer`, and the line
+    /// and the column at which it starts, once an output filter is set.
+    chunk_start: usize,
+    chunk_line: u64,
+    chunk_column: u64,
+    /// The statistics to update, if any.
+    stats: Option<Arc<EmitterStats>>,
+    /// The number of open collections, for the statistics.
+    stats_depth: u64,
+}
+
+/// The state and the settings of an [`Emitter`] without its output, which is
+/// [`Send`], for the [`AsyncEmitter`](crate::AsyncEmitter) to keep between
+/// events.
+#[cfg(feature = "tokio")]
+pub(crate) struct DetachedEmitter {
+    buffer: String,
+    raw_buffer: Vec<u8>,
+    encoding: Encoding,
+    canonical: bool,
+    best_indent: i32,
+    best_width: i32,
+    unicode: bool,
+    line_break: Break,
+    final_newline: FinalNewline,
+    version_directive_policy: VersionDirectivePolicy,
+    explicit_keys: ExplicitKeys,
+    compact_nested_sequences: bool,
+    base_indent: u64,
+    states: Vec<EmitterState>,
+    state: EmitterState,
+    events: VecDeque<Event>,
+    indents: Vec<i32>,
+    tag_directives: Vec<TagDirective>,
+    indent: i32,
+    flow_level: i32,
+    root_context: bool,
+    sequence_context: bool,
+    mapping_context: bool,
+    simple_key_context: bool,
+    line: u64,
+    column: u64,
+    whitespace: bool,
+    indention: bool,
+    open_ended: i32,
+    block_scalar_end: Option<(u64, u64)>,
+    opened: bool,
+    closed: bool,
+    anchors: Vec<Anchors>,
+    last_anchor_id: i32,
+    defined_anchors: HashSet<String>,
+    compact_width: usize,
+    deduplicate_scalars: Option<usize>,
+    explicit_tags: bool,
+    indent_sequences: bool,
+    prefer_block_scalars: bool,
+    respect_scalar_styles: bool,
+    flow_collections: bool,
+    max_queued_events: usize,
+    max_simple_key_length: usize,
+    track_overflow: bool,
+    content_kind: OverflowKind,
+    overflow_kind: Option<OverflowKind>,
+    overflow_reports: Vec<OverflowReport>,
+    wrapping: bool,
+    interrupt_check: Option<InterruptCheck>,
+    output_filter: Option<OutputFilter>,
+    chunk_role: OutputRole,
+    chunk_start: usize,
+    chunk_line: u64,
+    chunk_column: u64,
+    stats: Option<Arc<EmitterStats>>,
+    stats_depth: u64,
+}
+
+#[cfg(feature = "tokio")]
+impl DetachedEmitter {
+    /// Make an emitter that collects its output, to be taken with
+    /// [`Emitter::take_output()`].
+    pub(crate) fn attach(self) -> Emitter<'static> {
+        let DetachedEmitter {
+            buffer,
+            raw_buffer,
+            encoding,
+            canonical,
+            best_indent,
+            best_width,
+            unicode,
+            line_break,
+            final_newline,
+            version_directive_policy,
+            explicit_keys,
+            compact_nested_sequences,
+            base_indent,
+            states,
+            state,
+            events,
+            indents,
+            tag_directives,
+            indent,
+            flow_level,
+            root_context,
+            sequence_context,
+            mapping_context,
+            simple_key_context,
+            line,
+            column,
+            whitespace,
+            indention,
+            open_ended,
+            block_scalar_end,
+            opened,
+            closed,
+            anchors,
+            last_anchor_id,
+            defined_anchors,
+            compact_width,
+            deduplicate_scalars,
+            explicit_tags,
+            indent_sequences,
+            prefer_block_scalars,
+            respect_scalar_styles,
+            flow_collections,
+            max_queued_events,
+            max_simple_key_length,
+            track_overflow,
+            content_kind,
+            overflow_kind,
+            overflow_reports,
+            wrapping,
+            interrupt_check,
+            output_filter,
+            chunk_role,
+            chunk_start,
+            chunk_line,
+            chunk_column,
+            stats,
+            stats_depth,
+        } = self;
+        Emitter {
+            write_handler: Some(WriteHandler::Owned(Vec::new())),
+            buffer,
+            raw_buffer,
+            encoding,
+            canonical,
+            best_indent,
+            best_width,
+            unicode,
+            line_break,
+            final_newline,
+            version_directive_policy,
+            explicit_keys,
+            compact_nested_sequences,
+            base_indent,
+            states,
+            state,
+            events,
+            indents,
+            tag_directives,
+            indent,
+            flow_level,
+            root_context,
+            sequence_context,
+            mapping_context,
+            simple_key_context,
+            line,
+            column,
+            whitespace,
+            indention,
+            open_ended,
+            block_scalar_end,
+            opened,
+            closed,
+            anchors,
+            last_anchor_id,
+            defined_anchors,
+            compact_width,
+            deduplicate_scalars,
+            explicit_tags,
+            indent_sequences,
+            prefer_block_scalars,
+            respect_scalar_styles,
+            flow_collections,
+            max_queued_events,
+            max_simple_key_length,
+            track_overflow,
+            content_kind,
+            overflow_kind,
+            overflow_reports,
+            wrapping,
+            interrupt_check,
+            output_filter,
+            chunk_role,
+            chunk_start,
+            chunk_line,
+            chunk_column,
+            stats,
+            stats_depth,
+        }
+    }
 }
 
 impl<'a> Default for Emitter<'a> {
@@ -86,6 +343,149 @@ impl<'a> Default for Emitter<'a> {
     }
 }
 
+/// A set of presentation options for the emitter.
+///
+/// Use one of the presets and adjust individual fields as needed, then pass it
+/// to [`Emitter::apply_style()`]. The default matches the defaults of a new
+/// [`Emitter`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EmitStyle {
+    /// See [`Emitter::set_canonical()`].
+    pub canonical: bool,
+    /// See [`Emitter::set_explicit_tags()`].
+    pub explicit_tags: bool,
+    /// See [`Emitter::set_indent()`].
+    pub indent: i32,
+    /// See [`Emitter::set_width()`].
+    pub width: i32,
+    /// See [`Emitter::set_unicode()`].
+    pub unicode: bool,
+    /// See [`Emitter::set_indent_sequences()`].
+    pub indent_sequences: bool,
+    /// See [`Emitter::set_prefer_block_scalars()`].
+    pub prefer_block_scalars: bool,
+    /// See [`Emitter::set_flow_collections()`].
+    pub flow_collections: bool,
+}
+
+impl Default for EmitStyle {
+    fn default() -> Self {
+        EmitStyle {
+            canonical: false,
+            explicit_tags: false,
+            indent: 2,
+            width: 80,
+            unicode: false,
+            indent_sequences: false,
+            prefer_block_scalars: false,
+            flow_collections: false,
+        }
+    }
+}
+
+impl EmitStyle {
+    /// Readable block output: an indentation of 2 with indented sequences, a
+    /// line width of 100, unescaped non-ASCII characters, and multiline
+    /// scalars in the literal style.
+    pub fn block_pretty() -> Self {
+        EmitStyle {
+            indent: 2,
+            width: 100,
+            unicode: true,
+            indent_sequences: true,
+            prefer_block_scalars: true,
+            ..EmitStyle::default()
+        }
+    }
+
+    /// Flow collections everywhere, without folding long lines.
+    pub fn flow_compact() -> Self {
+        EmitStyle {
+            width: -1,
+            unicode: true,
+            flow_collections: true,
+            ..EmitStyle::default()
+        }
+    }
+
+    /// The canonical format of the YAML specification, with all tags
+    /// explicit.
+    pub fn canonical() -> Self {
+        EmitStyle {
+            canonical: true,
+            explicit_tags: true,
+            ..EmitStyle::default()
+        }
+    }
+}
+
+/// A line of the output that ends beyond the preferred width, because its
+/// content could not be wrapped. See [`Emitter::set_track_overflow()`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OverflowReport {
+    /// The line of the output, starting from 0.
+    pub line: u64,
+    /// The column at the end of the line.
+    pub column: u64,
+    /// The kind of content that first went beyond the preferred width.
+    pub kind: OverflowKind,
+}
+
+/// The kinds of content in an [`OverflowReport`].
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum OverflowKind {
+    /// An indicator, like `-`, `:`, or `[`.
+    Indicator,
+    /// A plain scalar.
+    PlainScalar,
+    /// A single- or double-quoted scalar.
+    QuotedScalar,
+    /// A literal or folded block scalar.
+    BlockScalar,
+    /// A tag.
+    Tag,
+    /// An anchor or alias.
+    Anchor,
+}
+
+/// A filter for the output of the emitter, set with
+/// [`Emitter::set_output_filter()`]. It returns the text to write instead of a
+/// chunk of output, or `None` to write the chunk as is.
+pub type OutputFilter = Box<dyn FnMut(OutputChunk<'_>) -> Option<String> + Send>;
+
+/// A piece of output with a single role, passed to an [`OutputFilter`].
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct OutputChunk<'a> {
+    /// What the text is.
+    pub role: OutputRole,
+    /// The text, as it would be written.
+    pub text: &'a str,
+}
+
+/// The roles of an [`OutputChunk`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum OutputRole {
+    /// An indicator, like `-`, `:`, `[`, `---`, a quote, or a directive name.
+    Indicator,
+    /// Spaces that indent a line or separate tokens.
+    Indent,
+    /// A line break.
+    Break,
+    /// The content of a scalar, with any escapes, between its indicators.
+    ScalarContent,
+    /// A tag handle or suffix, or the prefix of a `%TAG` directive.
+    TagContent,
+    /// The name of an anchor or an alias.
+    AnchorContent,
+    /// A byte order mark.
+    Bom,
+}
+
 /// The emitter states.
 #[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 #[non_exhaustive]
@@ -129,6 +529,19 @@ pub enum EmitterState {
     End = 17,
 }
 
+/// The output of the emitter.
+pub(crate) enum WriteHandler<'w> {
+    /// Encoded bytes are written to an I/O stream.
+    Io(&'w mut dyn std::io::Write),
+    /// The working buffer is appended directly to a formatter. Only UTF-8 is
+    /// supported.
+    Fmt(&'w mut dyn core::fmt::Write),
+    /// Encoded bytes are collected by the emitter, to be taken with
+    /// [`Emitter::take_output()`].
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    Owned(Vec<u8>),
+}
+
 #[derive(Copy, Clone, Default)]
 pub(crate) struct Anchors {
     /// The number of references.
@@ -169,8 +582,12 @@ struct ScalarAnalysis<'a> {
     pub single_quoted_allowed: bool,
     /// Can the scalar be expressed in the literal or folded styles?
     pub block_allowed: bool,
+    /// Can the scalar be wrapped at the line width?
+    pub wrap_allowed: bool,
     /// The output style.
     pub style: ScalarStyle,
+    /// The indicators to keep for the literal and folded styles.
+    pub presentation: Option<Presentation>,
 }
 
 impl<'w> Emitter<'w> {
@@ -186,6 +603,11 @@ impl<'w> Emitter<'w> {
             best_width: 0,
             unicode: false,
             line_break: Break::default(),
+            final_newline: FinalNewline::default(),
+            version_directive_policy: VersionDirectivePolicy::default(),
+            explicit_keys: ExplicitKeys::default(),
+            compact_nested_sequences: true,
+            base_indent: 0,
             states: Vec::with_capacity(16),
             state: EmitterState::default(),
             events: VecDeque::with_capacity(16),
@@ -202,10 +624,34 @@ impl<'w> Emitter<'w> {
             whitespace: false,
             indention: false,
             open_ended: 0,
+            block_scalar_end: None,
             opened: false,
             closed: false,
             anchors: Vec::new(),
             last_anchor_id: 0,
+            defined_anchors: HashSet::new(),
+            compact_width: 0,
+            deduplicate_scalars: None,
+            explicit_tags: false,
+            indent_sequences: false,
+            prefer_block_scalars: false,
+            respect_scalar_styles: true,
+            flow_collections: false,
+            max_queued_events: usize::MAX,
+            max_simple_key_length: 1024,
+            track_overflow: false,
+            content_kind: OverflowKind::Indicator,
+            overflow_kind: None,
+            overflow_reports: Vec::new(),
+            wrapping: false,
+            interrupt_check: None,
+            output_filter: None,
+            chunk_role: OutputRole::Indicator,
+            chunk_start: 0,
+            chunk_line: 0,
+            chunk_column: 0,
+            stats: None,
+            stats_depth: 0,
         }
     }
 
@@ -252,13 +698,167 @@ impl<'w> Emitter<'w> {
             panic!("cannot output UTF-16 to String")
         }
         output.clear();
-        self.write_handler = Some(output);
+        self.write_handler = Some(WriteHandler::Io(output));
     }
 
     /// Set a generic output handler.
     pub fn set_output(&mut self, handler: &'w mut dyn std::io::Write) {
         assert!(self.write_handler.is_none());
-        self.write_handler = Some(handler);
+        self.write_handler = Some(WriteHandler::Io(handler));
+    }
+
+    /// Drop the output handler, keeping the settings, so that the output can
+    /// be freed.
+    #[cfg(feature = "ffi")]
+    pub(crate) fn clear_output(&mut self) {
+        self.write_handler = None;
+    }
+
+    /// Set a [`core::fmt::Write`] output handler, such as a [`String`].
+    ///
+    /// The output is written without any intermediate encoding step, so the
+    /// output encoding is always UTF-8. Configuring a different encoding
+    /// results in an error when the stream is started.
+    pub fn set_output_fmt(&mut self, handler: &'w mut dyn core::fmt::Write) {
+        assert!(self.write_handler.is_none());
+        if self.encoding == Encoding::Any {
+            self.set_encoding(Encoding::Utf8);
+        }
+        self.write_handler = Some(WriteHandler::Fmt(handler));
+    }
+
+    /// Take the output flushed so far, if it is collected by the emitter.
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub(crate) fn take_output(&mut self) -> Vec<u8> {
+        match &mut self.write_handler {
+            Some(WriteHandler::Owned(output)) => core::mem::take(output),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Drop the output of the emitter, and keep the rest to be attached to a
+    /// new output with [`DetachedEmitter::attach()`].
+    #[cfg(feature = "tokio")]
+    pub(crate) fn detach(self) -> DetachedEmitter {
+        let Emitter {
+            write_handler: _,
+            buffer,
+            raw_buffer,
+            encoding,
+            canonical,
+            best_indent,
+            best_width,
+            unicode,
+            line_break,
+            final_newline,
+            version_directive_policy,
+            explicit_keys,
+            compact_nested_sequences,
+            base_indent,
+            states,
+            state,
+            events,
+            indents,
+            tag_directives,
+            indent,
+            flow_level,
+            root_context,
+            sequence_context,
+            mapping_context,
+            simple_key_context,
+            line,
+            column,
+            whitespace,
+            indention,
+            open_ended,
+            block_scalar_end,
+            opened,
+            closed,
+            anchors,
+            last_anchor_id,
+            defined_anchors,
+            compact_width,
+            deduplicate_scalars,
+            explicit_tags,
+            indent_sequences,
+            prefer_block_scalars,
+            respect_scalar_styles,
+            flow_collections,
+            max_queued_events,
+            max_simple_key_length,
+            track_overflow,
+            content_kind,
+            overflow_kind,
+            overflow_reports,
+            wrapping,
+            interrupt_check,
+            output_filter,
+            chunk_role,
+            chunk_start,
+            chunk_line,
+            chunk_column,
+            stats,
+            stats_depth,
+        } = self;
+        DetachedEmitter {
+            buffer,
+            raw_buffer,
+            encoding,
+            canonical,
+            best_indent,
+            best_width,
+            unicode,
+            line_break,
+            final_newline,
+            version_directive_policy,
+            explicit_keys,
+            compact_nested_sequences,
+            base_indent,
+            states,
+            state,
+            events,
+            indents,
+            tag_directives,
+            indent,
+            flow_level,
+            root_context,
+            sequence_context,
+            mapping_context,
+            simple_key_context,
+            line,
+            column,
+            whitespace,
+            indention,
+            open_ended,
+            block_scalar_end,
+            opened,
+            closed,
+            anchors,
+            last_anchor_id,
+            defined_anchors,
+            compact_width,
+            deduplicate_scalars,
+            explicit_tags,
+            indent_sequences,
+            prefer_block_scalars,
+            respect_scalar_styles,
+            flow_collections,
+            max_queued_events,
+            max_simple_key_length,
+            track_overflow,
+            content_kind,
+            overflow_kind,
+            overflow_reports,
+            wrapping,
+            interrupt_check,
+            output_filter,
+            chunk_role,
+            chunk_start,
+            chunk_line,
+            chunk_column,
+            stats,
+            stats_depth,
+        }
     }
 
     /// Set the output encoding.
@@ -273,7 +873,19 @@ impl<'w> Emitter<'w> {
         self.canonical = canonical;
     }
 
-    /// Set the indentation increment.
+    /// Set the indentation increment, from 2 to 9 spaces. Other values select
+    /// the default of 2.
+    ///
+    /// The root node of a document starts in column 0, and each level of
+    /// block collections below it is indented by `indent` more spaces than its
+    /// parent. The only exception is a block sequence that is the value of a
+    /// block mapping, whose items start in the column of the key, unless
+    /// [`Emitter::set_indent_sequences()`] is enabled. With it, every level is
+    /// `indent` spaces deeper than the one above.
+    ///
+    /// The content of a block sequence item starts `indent` columns after its
+    /// `-`, so that the following lines of a mapping in the item line up with
+    /// the first one.
     pub fn set_indent(&mut self, indent: i32) {
         self.best_indent = if 1 < indent && indent < 10 { indent } else { 2 };
     }
@@ -293,13 +905,238 @@ impl<'w> Emitter<'w> {
         self.line_break = line_break;
     }
 
+    /// Set the policy for the line break at the end of the stream.
+    ///
+    /// With [`FinalNewline::Never`], a trailing line break is held back in the
+    /// working buffer by [`Emitter::flush()`] until it is known whether more
+    /// output follows. A last block scalar that keeps its final line break is
+    /// followed by a document end marker so the break can be removed safely.
+    pub fn set_final_newline(&mut self, final_newline: FinalNewline) {
+        self.final_newline = final_newline;
+    }
+
+    /// Set when the `%YAML` directives of documents are written.
+    ///
+    /// The spec applies a directive to its document only, but many tools
+    /// expect at most one per stream. Directives that are not written are
+    /// also not closed off with `...` from the previous document.
+    pub fn set_version_directive_policy(&mut self, policy: VersionDirectivePolicy) {
+        self.version_directive_policy = policy;
+    }
+
+    /// Set when mapping keys are written with the explicit `? ` indicator.
+    ///
+    /// Keys that cannot be written as simple keys, like multiline scalars,
+    /// are explicit with any setting.
+    pub fn set_explicit_keys(&mut self, explicit_keys: ExplicitKeys) {
+        self.explicit_keys = explicit_keys;
+    }
+
+    /// Set whether a block sequence that is an item of a block sequence
+    /// starts on the line of the parent `-` indicator, as in `- - a`, which
+    /// is the default, or on the next line.
+    ///
+    /// A nested sequence with an anchor or a tag always starts on the next
+    /// line.
+    pub fn set_compact_nested_sequences(&mut self, compact: bool) {
+        self.compact_nested_sequences = compact;
+    }
+
+    /// Start every line that is not empty with `columns` spaces, to emit
+    /// documents as if they were nested in a larger document.
+    ///
+    /// The output can be pasted under a mapping key that is `columns - indent`
+    /// columns deep (or in column 0 for less than one indentation step), in
+    /// place of the value of the key. To keep that valid, the root node starts
+    /// in column `columns`, and the indentation hints of block scalars at the
+    /// root are relative to the column of the key. The `---` and `...`
+    /// markers and the directives of documents are indented too, and are then
+    /// no longer markers, so the documents should not need them.
+    ///
+    /// The preferred width of lines includes the base indentation. The default
+    /// is 0.
+    pub fn set_base_indent(&mut self, columns: usize) {
+        self.base_indent = columns as u64;
+    }
+
+    /// Emit small block collections in the flow style.
+    ///
+    /// When enabled, a sequence or mapping with a style other than
+    /// [`SequenceStyle::Flow`]/[`MappingStyle::Flow`] is emitted in the flow
+    /// style if it contains only scalars and its flow rendering ends before
+    /// column `max_width`. Nested collections are considered individually, so
+    /// small leaf collections are compacted even inside large block ones.
+    ///
+    /// A `max_width` of 0 disables the heuristic (the default).
+    pub fn set_compact_collections(&mut self, max_width: usize) {
+        self.compact_width = max_width;
+    }
+
+    /// Emit repeated scalars as aliases when dumping a document.
+    ///
+    /// When enabled, [`Document::dump()`](crate::Document::dump) anchors the
+    /// first occurrence of each scalar of at least `min_len` bytes that occurs
+    /// more than once with the same value, tag, and style, and emits aliases
+    /// for the other occurrences. Loading the output gives a document that is
+    /// [`deep_eq()`](crate::Document::deep_eq) to the original. Events passed
+    /// to [`Emitter::emit()`] are not affected.
+    ///
+    /// Deduplication is disabled by default.
+    pub fn set_deduplicate_scalars(&mut self, min_len: usize) {
+        self.deduplicate_scalars = Some(min_len);
+    }
+
+    /// Emit tags even if the events mark them as implicit.
+    ///
+    /// Events without a tag are not affected. Tags are always emitted in the
+    /// canonical format.
+    pub fn set_explicit_tags(&mut self, explicit_tags: bool) {
+        self.explicit_tags = explicit_tags;
+    }
+
+    /// Indent block sequences that are values of block mappings.
+    ///
+    /// By default, the items of such a sequence start in the same column as
+    /// the key, which is valid YAML but less common in hand-written files.
+    pub fn set_indent_sequences(&mut self, indent_sequences: bool) {
+        self.indent_sequences = indent_sequences;
+    }
+
+    /// Emit multiline scalars with [`ScalarStyle::Any`] in the literal style
+    /// where possible, instead of a quoted style.
+    pub fn set_prefer_block_scalars(&mut self, prefer_block_scalars: bool) {
+        self.prefer_block_scalars = prefer_block_scalars;
+    }
+
+    /// Keep the styles that scalar events request.
+    ///
+    /// When enabled (the default), a scalar with a style other than
+    /// [`ScalarStyle::Any`] is written in that style whenever it can represent
+    /// the value in its context, so that documents loaded and dumped again
+    /// keep their quoting. The style is only changed where it would be lossy or
+    /// invalid, like a plain scalar that looks like an indicator or a literal
+    /// scalar in a flow collection. Scalars that were on a single line of the
+    /// input, as told by their [`ScalarSource`](crate::ScalarSource), are not wrapped at the line
+    /// width either. When disabled, the emitter chooses the style of every
+    /// scalar as for [`ScalarStyle::Any`], and wraps any of them.
+    pub fn set_respect_scalar_styles(&mut self, respect_scalar_styles: bool) {
+        self.respect_scalar_styles = respect_scalar_styles;
+    }
+
+    /// Emit all sequences and mappings in the flow style, regardless of their
+    /// style.
+    pub fn set_flow_collections(&mut self, flow_collections: bool) {
+        self.flow_collections = flow_collections;
+    }
+
+    /// Set the maximum number of events that the emitter may hold back.
+    ///
+    /// The emitter holds back the start of a collection until it has seen the
+    /// next event, and as long as it could still be compacted (see
+    /// [`Emitter::set_compact_collections()`]). [`Emitter::emit()`] fails if
+    /// more than `max` events are waiting. There is no limit by default.
+    pub fn set_max_queued_events(&mut self, max: usize) {
+        self.max_queued_events = max;
+    }
+
+    /// Set the maximum length of implicit keys, like `key: value`, in
+    /// characters. Longer keys are emitted as explicit keys, like
+    /// `? key\n: value`.
+    ///
+    /// The length is that of the key as it is written, with its anchor, tag,
+    /// quotes, and escape sequences. The default is 1024, the limit of the
+    /// YAML specification, above which a parser rejects implicit keys. Set
+    /// 128 for the shorter implicit keys of libyaml.
+    pub fn set_max_simple_key_length(&mut self, max_length: usize) {
+        self.max_simple_key_length = max_length;
+    }
+
+    /// The number of events passed to [`Emitter::emit()`] that have not been
+    /// written yet.
+    pub fn queued_events(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Record the lines of the output that end beyond the preferred width
+    /// (see [`Emitter::set_width()`]) because their content could not be
+    /// wrapped, for example because of a long scalar without spaces. Lines
+    /// that are wrapped, which end with the word that reaches beyond the
+    /// width, are not recorded. The reports are retrieved with
+    /// [`Emitter::take_overflow_reports()`]. Tracking is disabled by default.
+    pub fn set_track_overflow(&mut self, track_overflow: bool) {
+        self.track_overflow = track_overflow;
+    }
+
+    /// Take the lines recorded since the last call, if overflow tracking is
+    /// enabled with [`Emitter::set_track_overflow()`].
+    pub fn take_overflow_reports(&mut self) -> Vec<OverflowReport> {
+        core::mem::take(&mut self.overflow_reports)
+    }
+
+    /// Set a check to be called before every event is emitted, to cancel a
+    /// long emission from another thread or on a deadline.
+    ///
+    /// When the check returns `true`, [`Emitter::emit()`] fails with an error
+    /// of kind [`ErrorKind::Interrupted`](crate::ErrorKind) at the start mark
+    /// of the event, without emitting it.
+    pub fn set_interrupt_check(&mut self, check: InterruptCheck) {
+        self.interrupt_check = Some(check);
+    }
+
+    /// Set a filter for the output, to replace chunks of it, for example to
+    /// add colors or change the spacing.
+    ///
+    /// The output is passed to the filter in chunks with a single
+    /// [`OutputRole`], before it is written. Consecutive output with the same
+    /// role may be split into several chunks. The line and the column of the
+    /// emitter are updated to match the replacement text, so that lines are
+    /// wrapped and indented as if it had been written, and the replacement
+    /// is not checked to be valid YAML.
+    pub fn set_output_filter(&mut self, filter: OutputFilter) {
+        self.output_filter = Some(filter);
+        self.chunk_start = self.buffer.len();
+        self.chunk_line = self.line;
+        self.chunk_column = self.column;
+    }
+
+    /// Set statistics to be updated for every emitted event and every write
+    /// to the output.
+    ///
+    /// The statistics can be shared with other emitters, and read at any
+    /// time. See [`EmitterStats`].
+    pub fn set_stats(&mut self, stats: Arc<EmitterStats>) {
+        self.stats = Some(stats);
+    }
+
+    /// Apply all options of a [`EmitStyle`].
+    pub fn apply_style(&mut self, style: &EmitStyle) {
+        self.set_canonical(style.canonical);
+        self.set_explicit_tags(style.explicit_tags);
+        self.set_indent(style.indent);
+        self.set_width(style.width);
+        self.set_unicode(style.unicode);
+        self.set_indent_sequences(style.indent_sequences);
+        self.set_prefer_block_scalars(style.prefer_block_scalars);
+        self.set_flow_collections(style.flow_collections);
+    }
+
     /// Emit an event.
     ///
     /// The event object may be generated using the
     /// [`Parser::parse()`](crate::Parser::parse) function. The emitter takes
     /// the responsibility for the event object and destroys its content after
     /// it is emitted. The event object is destroyed even if the function fails.
+    ///
+    /// An ALIAS event must refer to an anchor defined earlier in the same
+    /// document.
     pub fn emit(&mut self, event: Event) -> Result<()> {
+        if self.interrupt_check.as_ref().is_some_and(|check| check()) {
+            return Err(Error::interrupted(event.start_mark));
+        }
+        self.check_alias(&event)?;
+        if let Some(stats) = &self.stats {
+            stats.events().record(&event.data, &mut self.stats_depth);
+        }
         self.events.push_back(event);
         while let Some(event) = self.needs_mode_events() {
             let tag_directives = core::mem::take(&mut self.tag_directives);
@@ -313,6 +1150,120 @@ impl<'w> Emitter<'w> {
                 self.tag_directives = tag_directives;
             }
         }
+        if self.events.len() > self.max_queued_events {
+            return Err(Error::emitter(
+                "exceeded the maximum number of queued events",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that an alias refers to an anchor defined before it in the same
+    /// document, as anchors do not carry over to the next documents.
+    fn check_alias(&mut self, event: &Event) -> Result<()> {
+        match &event.data {
+            EventData::DocumentStart { .. } => self.defined_anchors.clear(),
+            EventData::Alias { anchor } if !self.defined_anchors.contains(anchor) => {
+                return Err(Error::emitter(
+                    "found an alias of an anchor that is not defined in the document",
+                ));
+            }
+            EventData::Scalar {
+                anchor: Some(anchor),
+                ..
+            }
+            | EventData::SequenceStart {
+                anchor: Some(anchor),
+                ..
+            }
+            | EventData::MappingStart {
+                anchor: Some(anchor),
+                ..
+            } => {
+                self.defined_anchors.insert(anchor.clone());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Is the current column beyond the preferred width? Never true for an
+    /// unlimited width, however long the line.
+    fn past_width(&self) -> bool {
+        self.best_width != i32::MAX && self.column > self.best_width as u64
+    }
+
+    /// Remember the kind of the content just written if it went beyond the
+    /// preferred width, to report it at the end of the line.
+    fn check_overflow(&mut self) {
+        if self.track_overflow && self.overflow_kind.is_none() && self.past_width() {
+            self.overflow_kind = Some(self.content_kind);
+        }
+    }
+
+    /// Record the current line if it ends beyond the preferred width, unless
+    /// it is being wrapped.
+    fn end_line(&mut self) {
+        if self.track_overflow && !self.wrapping && self.past_width() {
+            self.overflow_reports.push(OverflowReport {
+                line: self.line,
+                column: self.column,
+                kind: self.overflow_kind.take().unwrap_or(self.content_kind),
+            });
+        }
+        self.overflow_kind = None;
+    }
+
+    /// Start writing output with the role `role`, and return the previous
+    /// role.
+    fn set_role(&mut self, role: OutputRole) -> OutputRole {
+        if role != self.chunk_role {
+            self.filter_chunk();
+        }
+        core::mem::replace(&mut self.chunk_role, role)
+    }
+
+    /// Pass the output written since the last change of role to the output
+    /// filter, if any, and replace it.
+    fn filter_chunk(&mut self) {
+        let Some(filter) = self.output_filter.as_mut() else {
+            return;
+        };
+        let chunk = OutputChunk {
+            role: self.chunk_role,
+            text: &self.buffer[self.chunk_start..],
+        };
+        if !chunk.text.is_empty() {
+            if let Some(replacement) = filter(chunk) {
+                self.buffer.truncate(self.chunk_start);
+                self.buffer.push_str(&replacement);
+                let (mut line, mut column) = (self.chunk_line, self.chunk_column);
+                let mut chars = replacement.chars().peekable();
+                while let Some(ch) = chars.next() {
+                    if ch == '\r' && chars.peek() == Some(&'\n') {
+                        continue;
+                    }
+                    if is_break(ch) {
+                        line += 1;
+                        column = 0;
+                    } else {
+                        column += 1;
+                    }
+                }
+                self.line = line;
+                self.column = column;
+            }
+        }
+        self.chunk_start = self.buffer.len();
+        self.chunk_line = self.line;
+        self.chunk_column = self.column;
+    }
+
+    /// Write a space that separates two tokens.
+    fn put_space(&mut self) -> Result<()> {
+        let role = self.set_role(OutputRole::Indent);
+        self.put(' ')?;
+        self.set_role(role);
         Ok(())
     }
 
@@ -328,14 +1279,28 @@ impl<'w> Emitter<'w> {
     /// Equivalent of the libyaml `PUT` macro.
     fn put(&mut self, value: char) -> Result<()> {
         self.flush_if_needed()?;
+        self.put_base_indent();
         self.buffer.push(value);
         self.column += 1;
         Ok(())
     }
 
+    /// Write the base indentation if nothing was written on the line yet.
+    fn put_base_indent(&mut self) {
+        if self.column != 0 || self.base_indent == 0 {
+            return;
+        }
+        let role = self.set_role(OutputRole::Indent);
+        self.buffer
+            .extend(core::iter::repeat(' ').take(self.base_indent as usize));
+        self.column = self.base_indent;
+        self.set_role(role);
+    }
+
     /// Equivalent of the libyaml `PUT_BREAK` macro.
     fn put_break(&mut self) -> Result<()> {
         self.flush_if_needed()?;
+        let role = self.set_role(OutputRole::Break);
         if self.line_break == Break::Cr {
             self.buffer.push('\r');
         } else if self.line_break == Break::Ln {
@@ -343,8 +1308,10 @@ impl<'w> Emitter<'w> {
         } else if self.line_break == Break::CrLn {
             self.buffer.push_str("\r\n");
         };
+        self.end_line();
         self.column = 0;
         self.line += 1;
+        self.set_role(role);
         Ok(())
     }
 
@@ -359,8 +1326,11 @@ impl<'w> Emitter<'w> {
         // Note: Reserves less than what is necessary if there are UTF-8
         // characters present.
         self.buffer.reserve(string.len());
+        if !string.is_empty() {
+            self.put_base_indent();
+        }
 
-        self.column += string.chars().count() as i32;
+        self.column += string.chars().count() as u64;
 
         // Note: This may cause the buffer to become slightly larger than
         // `OUTPUT_BUFFER_SIZE`, but not by much.
@@ -372,20 +1342,30 @@ impl<'w> Emitter<'w> {
     /// Equivalent of the libyaml `WRITE` macro.
     fn write_char(&mut self, ch: char) -> Result<()> {
         self.flush_if_needed()?;
+        if !is_break(ch) {
+            self.put_base_indent();
+        }
         self.buffer.push(ch);
         self.column += 1;
         Ok(())
     }
 
     /// Equivalent of the libyaml `WRITE_BREAK` macro.
+    ///
+    /// Scalars containing `\r` or NEL are always written double-quoted, with
+    /// the break escaped, so only `\n` and the Unicode line and paragraph
+    /// separators get here. Only `\n` is replaced by the configured break.
     fn write_break(&mut self, ch: char) -> Result<()> {
         self.flush_if_needed()?;
         if ch == '\n' {
             self.put_break()?;
         } else {
+            let role = self.set_role(OutputRole::Break);
             self.write_char(ch)?;
+            self.end_line();
             self.column = 0;
             self.line += 1;
+            self.set_role(role);
         }
         Ok(())
     }
@@ -393,14 +1373,16 @@ impl<'w> Emitter<'w> {
     fn needs_mode_events(&mut self) -> Option<Event> {
         let first = self.events.front()?;
 
-        let accummulate = match &first.data {
-            EventData::DocumentStart { .. } => 1,
-            EventData::SequenceStart { .. } => 2,
-            EventData::MappingStart { .. } => 3,
+        // The start of a collection only needs the next event, to check
+        // whether the collection is empty.
+        match &first.data {
+            EventData::DocumentStart { .. }
+            | EventData::SequenceStart { .. }
+            | EventData::MappingStart { .. } => {}
             _ => return self.events.pop_front(),
-        };
+        }
 
-        if self.events.len() > accummulate {
+        if self.events.len() > 1 && !self.needs_compact_lookahead() {
             return self.events.pop_front();
         }
 
@@ -431,6 +1413,114 @@ impl<'w> Emitter<'w> {
         None
     }
 
+    /// Should the emitter keep buffering events to decide whether the
+    /// collection at the front of the queue can be compacted?
+    fn needs_compact_lookahead(&self) -> bool {
+        if self.compact_width == 0 || self.canonical || self.flow_level != 0 {
+            return false;
+        }
+        match self.events.front().map(|event| &event.data) {
+            Some(EventData::SequenceStart { style, .. }) if *style != SequenceStyle::Flow => {}
+            Some(EventData::MappingStart { style, .. }) if *style != MappingStyle::Flow => {}
+            _ => return false,
+        }
+
+        // Keep waiting as long as the collection so far consists of scalars
+        // that fit within the width. The end of the collection is handled by
+        // the caller.
+        let mut width = 2_usize;
+        for event in self.events.iter().skip(1) {
+            let EventData::Scalar { .. } = event.data else {
+                return false;
+            };
+            width = width.saturating_add(self.compact_scalar_width(event).saturating_add(2));
+            if width > self.compact_width {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// The projected width of a scalar written inside a flow collection, or
+    /// `usize::MAX` if it cannot be written on a single line.
+    fn compact_scalar_width(&self, event: &Event) -> usize {
+        let EventData::Scalar {
+            anchor,
+            tag,
+            value,
+            plain_implicit,
+            quoted_implicit,
+            ..
+        } = &event.data
+        else {
+            return usize::MAX;
+        };
+
+        let Ok(analysis) = self.analyze_scalar(value) else {
+            return usize::MAX;
+        };
+        if analysis.multiline {
+            return usize::MAX;
+        }
+        let mut width = value.chars().count();
+        if !analysis.flow_plain_allowed {
+            // Quotes, and a rough allowance for escapes.
+            width += 2 + value
+                .chars()
+                .filter(|ch| matches!(ch, '\'' | '"' | '\\'))
+                .count();
+        }
+        if let Some(anchor) = anchor {
+            width += anchor.len() + 2;
+        }
+        if let Some(tag) = tag {
+            if !*plain_implicit && !*quoted_implicit {
+                width += tag.len() + 1;
+            }
+        }
+        width
+    }
+
+    /// Check if the collection starting with `event` should be written in the
+    /// flow style because it is small enough (see
+    /// [`Emitter::set_compact_collections()`]).
+    fn check_compact_collection(&self, event: &Event) -> bool {
+        if self.compact_width == 0 || self.canonical || self.flow_level != 0 {
+            return false;
+        }
+        if !matches!(
+            event.data,
+            EventData::SequenceStart { .. } | EventData::MappingStart { .. }
+        ) {
+            return false;
+        }
+
+        let mut width = self.column as usize + 3;
+        let mut items = 0;
+        for event in &self.events {
+            match event.data {
+                EventData::SequenceEnd | EventData::MappingEnd => {
+                    return items != 0 && width <= self.compact_width;
+                }
+                EventData::Scalar { .. } => {
+                    let scalar_width = self.compact_scalar_width(event);
+                    if scalar_width == usize::MAX {
+                        return false;
+                    }
+                    // Separators: ", " between items and ": " between keys
+                    // and values.
+                    width += scalar_width + if items == 0 { 0 } else { 2 };
+                    items += 1;
+                }
+                _ => return false,
+            }
+            if width > self.compact_width {
+                return false;
+            }
+        }
+        false
+    }
+
     fn append_tag_directive(&mut self, value: TagDirective, allow_duplicates: bool) -> Result<()> {
         for tag_directive in &self.tag_directives {
             if value.handle == tag_directive.handle {
@@ -444,10 +1534,17 @@ impl<'w> Emitter<'w> {
         Ok(())
     }
 
+    /// Enter a collection. The root block collection is at column 0, and the
+    /// others one increment deeper than the parent, unless `indentless`; see
+    /// [`Emitter::set_indent()`].
     fn increase_indent(&mut self, flow: bool, indentless: bool) {
         self.indents.push(self.indent);
         if self.indent < 0 {
-            self.indent = if flow { self.best_indent } else { 0 };
+            self.indent = if flow && self.base_indent == 0 {
+                self.best_indent
+            } else {
+                0
+            };
         } else if !indentless {
             self.indent += self.best_indent;
         }
@@ -499,6 +1596,11 @@ impl<'w> Emitter<'w> {
             if self.encoding == Encoding::Any {
                 self.encoding = Encoding::Utf8;
             }
+            if self.encoding != Encoding::Utf8
+                && matches!(self.write_handler, Some(WriteHandler::Fmt(_)))
+            {
+                return Err(Error::emitter("cannot write UTF-16 to a fmt::Write output"));
+            }
             if self.best_indent < 2 || self.best_indent > 9 {
                 self.best_indent = 2;
             }
@@ -544,11 +1646,29 @@ impl<'w> Emitter<'w> {
                 },
             ];
             let mut implicit = *implicit;
+            // Check all directives before recording or writing anything, so
+            // that an invalid document start leaves no partial output.
             if let Some(version_directive) = version_directive {
                 Self::analyze_version_directive(*version_directive)?;
             }
-            for tag_directive in tag_directives {
+            for (i, tag_directive) in tag_directives.iter().enumerate() {
                 Self::analyze_tag_directive(tag_directive)?;
+                if tag_directives[..i]
+                    .iter()
+                    .any(|other| other.handle == tag_directive.handle)
+                {
+                    return Err(Error::emitter("duplicate %TAG directive"));
+                }
+            }
+            let version_directive =
+                version_directive.filter(|version_directive| match self.version_directive_policy {
+                    VersionDirectivePolicy::PerDocument => true,
+                    VersionDirectivePolicy::FirstOnly => {
+                        first && (version_directive.major, version_directive.minor) != (1, 2)
+                    }
+                    VersionDirectivePolicy::Never => false,
+                });
+            for tag_directive in tag_directives {
                 self.append_tag_directive(tag_directive.clone(), false)?;
             }
             for tag_directive in default_tag_directives {
@@ -557,7 +1677,13 @@ impl<'w> Emitter<'w> {
             if !first || self.canonical {
                 implicit = false;
             }
-            if (version_directive.is_some() || !tag_directives.is_empty()) && self.open_ended != 0 {
+            // The previous document must be closed explicitly if directives
+            // follow it, and if its trailing line breaks belong to a
+            // keep-chomped block scalar.
+            if self.open_ended == 2
+                || (version_directive.is_some() || !tag_directives.is_empty())
+                    && self.open_ended != 0
+            {
                 self.write_indicator("...", true, false, false)?;
                 self.write_indent()?;
             }
@@ -577,11 +1703,17 @@ impl<'w> Emitter<'w> {
                 for tag_directive in tag_directives {
                     self.write_indicator("%TAG", true, false, false)?;
                     self.write_tag_handle(&tag_directive.handle)?;
-                    self.write_tag_content(&tag_directive.prefix, true)?;
+                    // The `!` of a local prefix is not part of a URI.
+                    if let Some(prefix) = tag_directive.prefix.strip_prefix('!') {
+                        self.write_indicator("!", true, false, false)?;
+                        self.write_tag_content(prefix, false, true)?;
+                    } else {
+                        self.write_tag_content(&tag_directive.prefix, true, true)?;
+                    }
                     self.write_indent()?;
                 }
             }
-            if Self::check_empty_document() {
+            if self.check_empty_document() {
                 implicit = false;
             }
             if !implicit {
@@ -595,12 +1727,34 @@ impl<'w> Emitter<'w> {
             self.open_ended = 0;
             return Ok(());
         } else if let EventData::StreamEnd = &event.data {
+            // The last line break of a block scalar at the end of the stream
+            // is part of its value, so end the document explicitly instead of
+            // removing it.
+            if self.final_newline == FinalNewline::Never
+                && self.block_scalar_end == Some((self.line, self.column))
+            {
+                self.open_ended = 2;
+            }
             if self.open_ended == 2 {
                 self.write_indicator("...", true, false, false)?;
                 self.open_ended = 0;
                 self.write_indent()?;
             }
-            self.flush()?;
+            match self.final_newline {
+                FinalNewline::AsIs => {}
+                FinalNewline::Always => {
+                    if self.column != 0 {
+                        self.put_break()?;
+                    }
+                }
+                FinalNewline::Never => {
+                    self.filter_chunk();
+                    let len = self.buffer.len() - trailing_break_len(&self.buffer);
+                    self.buffer.truncate(len);
+                    self.chunk_start = len;
+                }
+            }
+            self.flush_buffer(false)?;
             self.state = EmitterState::End;
             return Ok(());
         }
@@ -658,8 +1812,8 @@ impl<'w> Emitter<'w> {
         if !first {
             self.write_indicator(",", false, false, false)?;
         }
-        if self.canonical || self.column > self.best_width {
-            self.write_indent()?;
+        if self.canonical || self.past_width() {
+            self.wrap_line()?;
         }
         self.states.push(EmitterState::FlowSequenceItem);
         self.emit_node(event, false, true, false, false, analysis)
@@ -691,10 +1845,10 @@ impl<'w> Emitter<'w> {
         if !first {
             self.write_indicator(",", false, false, false)?;
         }
-        if self.canonical || self.column > self.best_width {
-            self.write_indent()?;
+        if self.canonical || self.past_width() {
+            self.wrap_line()?;
         }
-        if !self.canonical && self.check_simple_key(event, analysis) {
+        if self.use_simple_key(event, analysis) {
             self.states.push(EmitterState::FlowMappingSimpleValue);
             self.emit_node(event, false, false, true, true, analysis)
         } else {
@@ -713,8 +1867,8 @@ impl<'w> Emitter<'w> {
         if simple {
             self.write_indicator(":", false, false, false)?;
         } else {
-            if self.canonical || self.column > self.best_width {
-                self.write_indent()?;
+            if self.canonical || self.past_width() {
+                self.wrap_line()?;
             }
             self.write_indicator(":", true, false, false)?;
         }
@@ -729,7 +1883,10 @@ impl<'w> Emitter<'w> {
         analysis: &mut Analysis,
     ) -> Result<()> {
         if first {
-            self.increase_indent(false, self.mapping_context && !self.indention);
+            self.increase_indent(
+                false,
+                self.mapping_context && !self.indention && !self.indent_sequences,
+            );
         }
         if let EventData::SequenceEnd = &event.data {
             self.indent = self.indents.pop().unwrap();
@@ -737,7 +1894,11 @@ impl<'w> Emitter<'w> {
             return Ok(());
         }
         self.write_indent()?;
-        self.write_indicator("-", true, false, true)?;
+        // A nested block sequence starts on the same line if the `-` is an
+        // indentation indicator.
+        let nested_sequence = matches!(event.data, EventData::SequenceStart { .. });
+        let is_indention = self.compact_nested_sequences || !nested_sequence;
+        self.write_indicator("-", true, false, is_indention)?;
         self.states.push(EmitterState::BlockSequenceItem);
         self.emit_node(event, false, true, false, false, analysis)
     }
@@ -757,7 +1918,7 @@ impl<'w> Emitter<'w> {
             return Ok(());
         }
         self.write_indent()?;
-        if self.check_simple_key(event, analysis) {
+        if self.use_simple_key(event, analysis) {
             self.states.push(EmitterState::BlockMappingSimpleValue);
             self.emit_node(event, false, false, true, true, analysis)
         } else {
@@ -792,6 +1953,9 @@ impl<'w> Emitter<'w> {
         simple_key: bool,
         analysis: &mut Analysis,
     ) -> Result<()> {
+        // Only a keep-chomped block scalar at the very end of a document makes
+        // it open-ended.
+        self.open_ended = 0;
         self.root_context = root;
         self.sequence_context = sequence;
         self.mapping_context = mapping;
@@ -848,8 +2012,10 @@ impl<'w> Emitter<'w> {
 
         if self.flow_level != 0
             || self.canonical
+            || self.flow_collections
             || *style == SequenceStyle::Flow
             || self.check_empty_sequence(event)
+            || self.check_compact_collection(event)
         {
             self.state = EmitterState::FlowSequenceFirstItem;
         } else {
@@ -869,8 +2035,10 @@ impl<'w> Emitter<'w> {
 
         if self.flow_level != 0
             || self.canonical
+            || self.flow_collections
             || *style == MappingStyle::Flow
             || self.check_empty_mapping(event)
+            || self.check_compact_collection(event)
         {
             self.state = EmitterState::FlowMappingFirstKey;
         } else {
@@ -879,8 +2047,21 @@ impl<'w> Emitter<'w> {
         Ok(())
     }
 
-    fn check_empty_document() -> bool {
-        false
+    /// Is the document that starts with the DOCUMENT-START event being
+    /// emitted empty, which is an empty plain scalar without an anchor or a
+    /// tag other than the null tag? Such a document is written as a bare
+    /// `---`, as it would be lost otherwise.
+    fn check_empty_document(&self) -> bool {
+        match self.events.front().map(|event| &event.data) {
+            Some(EventData::Scalar {
+                anchor: None,
+                tag,
+                value,
+                plain_implicit: true,
+                ..
+            }) => value.is_empty() && tag.as_deref().map_or(true, |tag| tag == NULL_TAG),
+            _ => false,
+        }
     }
 
     fn check_empty_sequence(&self, event: &Event) -> bool {
@@ -901,6 +2082,16 @@ impl<'w> Emitter<'w> {
         start && end
     }
 
+    /// Should the key starting with `event` be written without the `? `
+    /// indicator?
+    fn use_simple_key(&self, event: &Event, analysis: &Analysis) -> bool {
+        match self.explicit_keys {
+            ExplicitKeys::Always => false,
+            ExplicitKeys::Auto if self.canonical => false,
+            _ => self.check_simple_key(event, analysis),
+        }
+    }
+
     fn check_simple_key(&self, event: &Event, analysis: &Analysis) -> bool {
         let Analysis {
             tag,
@@ -908,14 +2099,18 @@ impl<'w> Emitter<'w> {
             scalar,
         } = analysis;
 
-        let mut length = anchor.as_ref().map_or(0, |a| a.anchor.len())
-            + tag.as_ref().map_or(0, |t| t.handle.len() + t.suffix.len());
+        // The anchor and the tag are followed by a space.
+        let mut length = anchor.as_ref().map_or(0, |a| a.anchor.chars().count() + 2)
+            + tag.as_ref().map_or(0, tag_width);
 
-        match event.data {
+        match &event.data {
             EventData::Alias { .. } => {
-                length = analysis.anchor.as_ref().map_or(0, |a| a.anchor.len());
+                // An alias is followed by a space before the ':'.
+                length = anchor.as_ref().map_or(0, |a| a.anchor.chars().count() + 2);
             }
-            EventData::Scalar { .. } => {
+            EventData::Scalar {
+                quoted_implicit, ..
+            } => {
                 let Some(scalar) = scalar else {
                     panic!("no analysis for scalar")
                 };
@@ -923,26 +2118,73 @@ impl<'w> Emitter<'w> {
                 if scalar.multiline {
                     return false;
                 }
-                length += scalar.value.len();
+                // Measure the key in the style it will be written in.
+                let style = self.choose_scalar_style(event, scalar, tag.is_none(), true);
+                length += match style {
+                    ScalarStyle::Plain => scalar.value.chars().count(),
+                    ScalarStyle::SingleQuoted => {
+                        scalar
+                            .value
+                            .chars()
+                            .map(|ch| 1 + usize::from(ch == '\''))
+                            .sum::<usize>()
+                            + 2
+                    }
+                    _ => {
+                        scalar
+                            .value
+                            .chars()
+                            .map(|ch| self.double_quoted_width(ch))
+                            .sum::<usize>()
+                            + 2
+                    }
+                };
+                if style != ScalarStyle::Plain && tag.is_none() && !*quoted_implicit {
+                    // The non-specific tag `! `.
+                    length += 2;
+                }
             }
             EventData::SequenceStart { .. } => {
                 if !self.check_empty_sequence(event) {
                     return false;
                 }
+                length += 2;
             }
             EventData::MappingStart { .. } => {
                 if !self.check_empty_mapping(event) {
                     return false;
                 }
+                length += 2;
             }
             _ => return false,
         }
 
-        if length > 128 {
-            return false;
-        }
+        length <= self.max_simple_key_length
+    }
 
-        true
+    /// The number of characters written for `ch` in a double-quoted scalar.
+    fn double_quoted_width(&self, ch: char) -> usize {
+        if is_printable(ch)
+            && (self.unicode || is_ascii(ch))
+            && !is_bom(ch)
+            && !is_break(ch)
+            && ch != '"'
+            && ch != '\\'
+        {
+            1
+        } else if matches!(
+            ch,
+            '\0' | '\x07'
+                ..='\x0D' | '\x1B' | '"' | '\\' | '\u{85}' | '\u{A0}' | '\u{2028}' | '\u{2029}'
+        ) {
+            2
+        } else if ch <= '\u{ff}' {
+            4
+        } else if ch <= '\u{ffff}' {
+            6
+        } else {
+            10
+        }
     }
 
     fn select_scalar_style(
@@ -954,27 +2196,67 @@ impl<'w> Emitter<'w> {
         let EventData::Scalar {
             plain_implicit,
             quoted_implicit,
-            style,
             ..
         } = &event.data
         else {
             unreachable!()
         };
 
-        let mut style: ScalarStyle = *style;
         let no_tag = tag_analysis.is_none();
         if no_tag && !*plain_implicit && !*quoted_implicit {
             return Err(Error::emitter(
                 "neither tag nor implicit flags are specified",
             ));
         }
-        if style == ScalarStyle::Any {
-            style = ScalarStyle::Plain;
+        let style =
+            self.choose_scalar_style(event, scalar_analysis, no_tag, self.simple_key_context);
+        if no_tag && !*quoted_implicit && style != ScalarStyle::Plain {
+            *tag_analysis = Some(TagAnalysis {
+                handle: "!",
+                suffix: "",
+            });
+        }
+        scalar_analysis.style = style;
+        Ok(())
+    }
+
+    /// The style a scalar is written in, given the analysis of its value,
+    /// whether it is written without a tag, and whether it is written as an
+    /// implicit key.
+    fn choose_scalar_style(
+        &self,
+        event: &Event,
+        scalar_analysis: &ScalarAnalysis,
+        no_tag: bool,
+        simple_key_context: bool,
+    ) -> ScalarStyle {
+        let EventData::Scalar {
+            tag,
+            plain_implicit,
+            style,
+            ..
+        } = &event.data
+        else {
+            unreachable!()
+        };
+
+        let mut style: ScalarStyle = *style;
+        if style == ScalarStyle::Any || !self.respect_scalar_styles {
+            style = if self.prefer_block_scalars
+                && scalar_analysis.multiline
+                && scalar_analysis.block_allowed
+                && self.flow_level == 0
+                && !simple_key_context
+            {
+                ScalarStyle::Literal
+            } else {
+                ScalarStyle::Plain
+            };
         }
         if self.canonical {
             style = ScalarStyle::DoubleQuoted;
         }
-        if self.simple_key_context && scalar_analysis.multiline {
+        if simple_key_context && scalar_analysis.multiline {
             style = ScalarStyle::DoubleQuoted;
         }
         if style == ScalarStyle::Plain {
@@ -983,7 +2265,10 @@ impl<'w> Emitter<'w> {
             {
                 style = ScalarStyle::SingleQuoted;
             }
-            if scalar_analysis.value.is_empty() && (self.flow_level != 0 || self.simple_key_context)
+            // An empty plain scalar would be read back as a null by schemas
+            // that resolve tags, so empty strings are always quoted.
+            if scalar_analysis.value.is_empty()
+                && (self.flow_level != 0 || simple_key_context || tag.as_deref() == Some(STR_TAG))
             {
                 style = ScalarStyle::SingleQuoted;
             }
@@ -995,18 +2280,11 @@ impl<'w> Emitter<'w> {
             style = ScalarStyle::DoubleQuoted;
         }
         if (style == ScalarStyle::Literal || style == ScalarStyle::Folded)
-            && (!scalar_analysis.block_allowed || self.flow_level != 0 || self.simple_key_context)
+            && (!scalar_analysis.block_allowed || self.flow_level != 0 || simple_key_context)
         {
             style = ScalarStyle::DoubleQuoted;
         }
-        if no_tag && !*quoted_implicit && style != ScalarStyle::Plain {
-            *tag_analysis = Some(TagAnalysis {
-                handle: "!",
-                suffix: "",
-            });
-        }
-        scalar_analysis.style = style;
-        Ok(())
+        style
     }
 
     fn process_anchor(&mut self, analysis: &Option<AnchorAnalysis>) -> Result<()> {
@@ -1027,28 +2305,31 @@ impl<'w> Emitter<'w> {
         }
         if analysis.handle.is_empty() {
             self.write_indicator("!<", true, false, false)?;
-            self.write_tag_content(analysis.suffix, false)?;
+            self.write_tag_content(analysis.suffix, false, true)?;
             self.write_indicator(">", false, false, false)?;
         } else {
             self.write_tag_handle(analysis.handle)?;
             if !analysis.suffix.is_empty() {
-                self.write_tag_content(analysis.suffix, false)?;
+                self.write_tag_content(analysis.suffix, false, false)?;
             }
         }
         Ok(())
     }
 
     fn process_scalar(&mut self, analysis: &ScalarAnalysis) -> Result<()> {
+        let allow_breaks = !self.simple_key_context && analysis.wrap_allowed;
         match analysis.style {
-            ScalarStyle::Plain => self.write_plain_scalar(analysis.value, !self.simple_key_context),
+            ScalarStyle::Plain => self.write_plain_scalar(analysis.value, allow_breaks),
             ScalarStyle::SingleQuoted => {
-                self.write_single_quoted_scalar(analysis.value, !self.simple_key_context)
+                self.write_single_quoted_scalar(analysis.value, allow_breaks)
             }
             ScalarStyle::DoubleQuoted => {
-                self.write_double_quoted_scalar(analysis.value, !self.simple_key_context)
+                self.write_double_quoted_scalar(analysis.value, allow_breaks)
             }
-            ScalarStyle::Literal => self.write_literal_scalar(analysis.value),
-            ScalarStyle::Folded => self.write_folded_scalar(analysis.value),
+            ScalarStyle::Literal => {
+                self.write_literal_scalar(analysis.value, analysis.presentation)
+            }
+            ScalarStyle::Folded => self.write_folded_scalar(analysis.value, analysis.presentation),
             ScalarStyle::Any => unreachable!("No scalar style chosen"),
         }
     }
@@ -1072,20 +2353,32 @@ impl<'w> Emitter<'w> {
         if !tag_directive.handle.ends_with('!') {
             return Err(Error::emitter("tag handle must end with '!'"));
         }
-        if tag_directive.handle.len() > 2 {
-            let tag_content = &tag_directive.handle[1..tag_directive.handle.len() - 1];
-            for ch in tag_content.chars() {
-                if !is_alpha(ch) {
-                    return Err(Error::emitter(
-                        "tag handle must contain alphanumerical characters only",
-                    ));
-                }
-            }
+        // The handle is `!`, `!!` or a named handle like `!e!`, so every
+        // character between the `!` delimiters must be a word character.
+        let handle = &tag_directive.handle;
+        let content = if handle.len() > 1 {
+            &handle[1..handle.len() - 1]
+        } else {
+            ""
+        };
+        if !content.chars().all(is_alpha) {
+            return Err(Error::emitter(
+                "tag handle must contain alphanumerical characters only",
+            ));
         }
 
         if tag_directive.prefix.is_empty() {
             return Err(Error::emitter("tag prefix must not be empty"));
         }
+        if tag_directive
+            .prefix
+            .chars()
+            .any(|ch| is_blankz(ch) || !is_printable(ch))
+        {
+            return Err(Error::emitter(
+                "tag prefix must not contain whitespace or control characters",
+            ));
+        }
 
         Ok(())
     }
@@ -1119,6 +2412,19 @@ impl<'w> Emitter<'w> {
         if tag.is_empty() {
             return Err(Error::emitter("tag value must not be empty"));
         }
+        if tag.chars().any(|ch| is_blankz(ch) || !is_printable(ch)) {
+            return Err(Error::emitter(
+                "tag value must not contain whitespace or control characters",
+            ));
+        }
+        if tag == "!" {
+            // The non-specific tag is written as is, rather than as a
+            // verbatim tag.
+            return Ok(TagAnalysis {
+                handle: tag,
+                suffix: "",
+            });
+        }
 
         let mut handle = "";
         let mut suffix = tag;
@@ -1135,7 +2441,7 @@ impl<'w> Emitter<'w> {
         Ok(TagAnalysis { handle, suffix })
     }
 
-    fn analyze_scalar<'a>(&mut self, value: &'a str) -> Result<ScalarAnalysis<'a>> {
+    fn analyze_scalar<'a>(&self, value: &'a str) -> Result<ScalarAnalysis<'a>> {
         let mut block_indicators = false;
         let mut flow_indicators = false;
         let mut line_breaks = false;
@@ -1158,7 +2464,9 @@ impl<'w> Emitter<'w> {
                 block_plain_allowed: true,
                 single_quoted_allowed: true,
                 block_allowed: false,
+                wrap_allowed: true,
                 style: ScalarStyle::Any,
+                presentation: None,
             });
         }
 
@@ -1259,7 +2567,9 @@ impl<'w> Emitter<'w> {
             block_plain_allowed: true,
             single_quoted_allowed: true,
             block_allowed: true,
+            wrap_allowed: true,
             style: ScalarStyle::Any,
+            presentation: None,
         };
 
         analysis.multiline = line_breaks;
@@ -1274,6 +2584,8 @@ impl<'w> Emitter<'w> {
         if trailing_space {
             analysis.block_allowed = false;
         }
+        // Spaces are only a problem for the single-quoted style next to line
+        // breaks, where folding would discard them.
         if break_space {
             analysis.flow_plain_allowed = false;
             analysis.block_plain_allowed = false;
@@ -1315,17 +2627,24 @@ impl<'w> Emitter<'w> {
                 value,
                 plain_implicit,
                 quoted_implicit,
+                source,
                 ..
             } => {
                 let (plain_implicit, quoted_implicit) = (*plain_implicit, *quoted_implicit);
                 if let Some(anchor) = anchor {
                     analysis.anchor = Some(Self::analyze_anchor(anchor, false)?);
                 }
-                if tag.is_some() && (self.canonical || !plain_implicit && !quoted_implicit) {
+                if tag.is_some()
+                    && (self.canonical || self.explicit_tags || !plain_implicit && !quoted_implicit)
+                {
                     analysis.tag =
                         Some(Self::analyze_tag(tag.as_deref().unwrap(), tag_directives)?);
                 }
-                analysis.scalar = Some(self.analyze_scalar(value)?);
+                let mut scalar = self.analyze_scalar(value)?;
+                // Keep a scalar that the input had on a single line unwrapped.
+                scalar.wrap_allowed = !self.respect_scalar_styles || source.lines != 1;
+                scalar.presentation = source.presentation;
+                analysis.scalar = Some(scalar);
             }
             EventData::SequenceStart {
                 anchor,
@@ -1336,7 +2655,7 @@ impl<'w> Emitter<'w> {
                 if let Some(anchor) = anchor {
                     analysis.anchor = Some(Self::analyze_anchor(anchor, false)?);
                 }
-                if tag.is_some() && (self.canonical || !*implicit) {
+                if tag.is_some() && (self.canonical || self.explicit_tags || !*implicit) {
                     analysis.tag =
                         Some(Self::analyze_tag(tag.as_deref().unwrap(), tag_directives)?);
                 }
@@ -1350,7 +2669,7 @@ impl<'w> Emitter<'w> {
                 if let Some(anchor) = anchor {
                     analysis.anchor = Some(Self::analyze_anchor(anchor, false)?);
                 }
-                if tag.is_some() && (self.canonical || !*implicit) {
+                if tag.is_some() && (self.canonical || self.explicit_tags || !*implicit) {
                     analysis.tag =
                         Some(Self::analyze_tag(tag.as_deref().unwrap(), tag_directives)?);
                 }
@@ -1363,18 +2682,40 @@ impl<'w> Emitter<'w> {
 
     fn write_bom(&mut self) -> Result<()> {
         self.flush_if_needed()?;
+        self.set_role(OutputRole::Bom);
         self.buffer.push('\u{feff}');
         Ok(())
     }
 
+    /// Break a line at a point where it may be wrapped. Like in libyaml, lines
+    /// are only wrapped once they reach beyond the preferred width, so such
+    /// lines are not reported as overflowing.
+    fn wrap_line(&mut self) -> Result<()> {
+        self.wrapping = true;
+        let result = self.write_indent();
+        self.wrapping = false;
+        result
+    }
+
     fn write_indent(&mut self) -> Result<()> {
-        let indent = if self.indent >= 0 { self.indent } else { 0 };
+        // Outside of the root node, the base indentation is only written
+        // before the next character, in case the line stays empty.
+        let indent = if self.indent >= 0 {
+            self.base_indent + self.indent as u64
+        } else {
+            0
+        };
+        let role = self.set_role(OutputRole::Indent);
         if !self.indention || self.column > indent || self.column == indent && !self.whitespace {
             self.put_break()?;
         }
+        if self.indent >= 0 {
+            self.put_base_indent();
+        }
         while self.column < indent {
             self.put(' ')?;
         }
+        self.set_role(role);
         self.whitespace = true;
         self.indention = true;
         Ok(())
@@ -1387,36 +2728,58 @@ impl<'w> Emitter<'w> {
         is_whitespace: bool,
         is_indention: bool,
     ) -> Result<()> {
+        self.content_kind = OverflowKind::Indicator;
         if need_whitespace && !self.whitespace {
-            self.put(' ')?;
+            self.put_space()?;
         }
+        // Every indicator is a chunk of its own.
+        self.filter_chunk();
+        self.set_role(OutputRole::Indicator);
         self.write_str(indicator)?;
         self.whitespace = is_whitespace;
         self.indention = self.indention && is_indention;
+        self.check_overflow();
         Ok(())
     }
 
     fn write_anchor(&mut self, value: &str) -> Result<()> {
+        self.content_kind = OverflowKind::Anchor;
+        self.set_role(OutputRole::AnchorContent);
         self.write_str(value)?;
         self.whitespace = false;
         self.indention = false;
+        self.check_overflow();
         Ok(())
     }
 
     fn write_tag_handle(&mut self, value: &str) -> Result<()> {
+        self.content_kind = OverflowKind::Tag;
         if !self.whitespace {
-            self.put(' ')?;
+            self.put_space()?;
         }
+        self.set_role(OutputRole::TagContent);
         self.write_str(value)?;
         self.whitespace = false;
         self.indention = false;
+        self.check_overflow();
         Ok(())
     }
 
-    fn write_tag_content(&mut self, value: &str, need_whitespace: bool) -> Result<()> {
+    /// Write a tag URI, escaping the characters that cannot appear in it.
+    /// `!` and flow indicators are only written as is if `uri_char` is true,
+    /// that is, in verbatim tags and `%TAG` prefixes, and escaped in tag
+    /// shorthands.
+    fn write_tag_content(
+        &mut self,
+        value: &str,
+        need_whitespace: bool,
+        uri_char: bool,
+    ) -> Result<()> {
+        self.content_kind = OverflowKind::Tag;
         if need_whitespace && !self.whitespace {
-            self.put(' ')?;
+            self.put_space()?;
         }
+        self.set_role(OutputRole::TagContent);
 
         for ch in value.chars() {
             if is_alpha(ch) {
@@ -1425,8 +2788,12 @@ impl<'w> Emitter<'w> {
             }
 
             match ch {
-                ';' | '/' | '?' | ':' | '@' | '&' | '=' | '+' | '$' | ',' | '_' | '.' | '~'
-                | '*' | '\'' | '(' | ')' | '[' | ']' => {
+                ';' | '/' | '?' | ':' | '@' | '&' | '=' | '+' | '$' | '_' | '.' | '~' | '*'
+                | '\'' | '(' | ')' | '#' => {
+                    self.write_char(ch)?;
+                    continue;
+                }
+                '!' | ',' | '[' | ']' if uri_char => {
                     self.write_char(ch)?;
                     continue;
                 }
@@ -1451,23 +2818,26 @@ impl<'w> Emitter<'w> {
 
         self.whitespace = false;
         self.indention = false;
+        self.check_overflow();
         Ok(())
     }
 
     fn write_plain_scalar(&mut self, value: &str, allow_breaks: bool) -> Result<()> {
+        self.content_kind = OverflowKind::PlainScalar;
         let mut spaces = false;
         let mut breaks = false;
         if !self.whitespace && (!value.is_empty() || self.flow_level != 0) {
-            self.put(' ')?;
+            self.put_space()?;
         }
+        self.set_role(OutputRole::ScalarContent);
 
         let mut chars = value.chars();
 
         while let Some(ch) = chars.next() {
             let next = chars.clone().next();
             if is_space(ch) {
-                if allow_breaks && !spaces && self.column > self.best_width && !is_space(next) {
-                    self.write_indent()?;
+                if allow_breaks && !spaces && self.past_width() && !is_space(next) {
+                    self.wrap_line()?;
                 } else {
                     self.write_char(ch)?;
                 }
@@ -1491,13 +2861,16 @@ impl<'w> Emitter<'w> {
         }
         self.whitespace = false;
         self.indention = false;
+        self.check_overflow();
         Ok(())
     }
 
     fn write_single_quoted_scalar(&mut self, value: &str, allow_breaks: bool) -> Result<()> {
+        self.content_kind = OverflowKind::QuotedScalar;
         let mut spaces = false;
         let mut breaks = false;
         self.write_indicator("'", true, false, false)?;
+        self.set_role(OutputRole::ScalarContent);
         let mut chars = value.chars();
         let mut is_first = true;
         while let Some(ch) = chars.next() {
@@ -1507,12 +2880,12 @@ impl<'w> Emitter<'w> {
             if is_space(ch) {
                 if allow_breaks
                     && !spaces
-                    && self.column > self.best_width
+                    && self.past_width()
                     && !is_first
                     && !is_last
                     && !is_space(next)
                 {
-                    self.write_indent()?;
+                    self.wrap_line()?;
                 } else {
                     self.write_char(ch)?;
                 }
@@ -1545,12 +2918,15 @@ impl<'w> Emitter<'w> {
         self.write_indicator("'", false, false, false)?;
         self.whitespace = false;
         self.indention = false;
+        self.check_overflow();
         Ok(())
     }
 
     fn write_double_quoted_scalar(&mut self, value: &str, allow_breaks: bool) -> Result<()> {
+        self.content_kind = OverflowKind::QuotedScalar;
         let mut spaces = false;
         self.write_indicator("\"", true, false, false)?;
+        self.set_role(OutputRole::ScalarContent);
         let mut chars = value.chars();
         let mut first = true;
         while let Some(ch) = chars.next() {
@@ -1636,11 +3012,11 @@ impl<'w> Emitter<'w> {
             } else if is_space(ch) {
                 if allow_breaks
                     && !spaces
-                    && self.column > self.best_width
+                    && self.past_width()
                     && !first
                     && chars.clone().next().is_some()
                 {
-                    self.write_indent()?;
+                    self.wrap_line()?;
                     if is_space(chars.clone().next()) {
                         self.put('\\')?;
                     }
@@ -1658,23 +3034,39 @@ impl<'w> Emitter<'w> {
         self.write_indicator("\"", false, false, false)?;
         self.whitespace = false;
         self.indention = false;
+        self.check_overflow();
         Ok(())
     }
 
-    fn write_block_scalar_hints(&mut self, string: &str) -> Result<()> {
-        let mut chomp_hint: Option<&str> = None;
-
+    fn write_block_scalar_hints(
+        &mut self,
+        string: &str,
+        presentation: Option<Presentation>,
+    ) -> Result<()> {
+        let presentation = presentation.unwrap_or_default();
+        let parent = self.indents.last().copied().unwrap_or(-1);
+        let mut indent_hint = None;
         let first = string.chars().next();
-        if is_space(first) || is_break(first) {
-            let Some(indent_hint) = char::from_digit(self.best_indent as u32, 10) else {
-                unreachable!("self.best_indent out of range")
-            };
-            let mut indent_hint_buffer = [0u8; 1];
-            let indent_hint = indent_hint.encode_utf8(&mut indent_hint_buffer);
-            self.write_indicator(indent_hint, false, false, false)?;
+        // An indentation indicator from the input is kept, and the content
+        // indented by it, except for a root scalar with a base indentation.
+        if let Some(digit) = presentation
+            .indent_indicator
+            .filter(|digit| (1..=9).contains(digit) && (parent >= 0 || self.base_indent == 0))
+        {
+            self.indent = parent.max(0) + i32::from(digit);
+            indent_hint = Some(u32::from(digit));
+        } else if is_space(first) || is_break(first) {
+            // The content of a root scalar starts in the base column, which
+            // may be less than one step right of the key it is pasted under.
+            let mut hint = self.best_indent as u32;
+            if self.base_indent != 0 && parent == -1 {
+                hint = hint.min(self.base_indent as u32);
+            }
+            indent_hint = Some(hint);
         }
         self.open_ended = 0;
 
+        let mut chomp_hint: Option<&str> = None;
         if string.is_empty() {
             chomp_hint = Some("-");
         } else {
@@ -1686,21 +3078,47 @@ impl<'w> Emitter<'w> {
                 chomp_hint = Some("-");
             } else if is_breakz(next) {
                 chomp_hint = Some("+");
-                self.open_ended = 2;
             }
         }
+        // Keeping a single final line break, or none of an empty scalar, is
+        // the same as the other indicators.
+        if presentation.chomping == Chomping::Keep && (chomp_hint.is_none() || string.is_empty()) {
+            chomp_hint = Some("+");
+        }
+        if chomp_hint == Some("+") {
+            self.open_ended = 2;
+        }
 
-        if let Some(chomp_hint) = chomp_hint {
-            self.write_indicator(chomp_hint, false, false, false)?;
+        let indent_hint = indent_hint.map(|hint| {
+            let Some(hint) = char::from_digit(hint, 10) else {
+                unreachable!("indentation hint out of range")
+            };
+            hint
+        });
+        let mut indent_hint_buffer = [0u8; 1];
+        let indent_hint = indent_hint.map(|hint| &*hint.encode_utf8(&mut indent_hint_buffer));
+        let (first_hint, second_hint) = if presentation.chomping_first {
+            (chomp_hint, indent_hint)
+        } else {
+            (indent_hint, chomp_hint)
+        };
+        for hint in [first_hint, second_hint].into_iter().flatten() {
+            self.write_indicator(hint, false, false, false)?;
         }
         Ok(())
     }
 
-    fn write_literal_scalar(&mut self, value: &str) -> Result<()> {
+    fn write_literal_scalar(
+        &mut self,
+        value: &str,
+        presentation: Option<Presentation>,
+    ) -> Result<()> {
+        self.content_kind = OverflowKind::BlockScalar;
         let mut breaks = true;
         self.write_indicator("|", true, false, false)?;
-        self.write_block_scalar_hints(value)?;
+        self.write_block_scalar_hints(value, presentation)?;
         self.put_break()?;
+        self.set_role(OutputRole::ScalarContent);
         self.indention = true;
         self.whitespace = true;
         let chars = value.chars();
@@ -1718,15 +3136,25 @@ impl<'w> Emitter<'w> {
                 breaks = false;
             }
         }
+        if breaks && !value.is_empty() {
+            self.block_scalar_end = Some((self.line, self.column));
+        }
+        self.check_overflow();
         Ok(())
     }
 
-    fn write_folded_scalar(&mut self, value: &str) -> Result<()> {
+    fn write_folded_scalar(
+        &mut self,
+        value: &str,
+        presentation: Option<Presentation>,
+    ) -> Result<()> {
+        self.content_kind = OverflowKind::BlockScalar;
         let mut breaks = true;
         let mut leading_spaces = true;
         self.write_indicator(">", true, false, false)?;
-        self.write_block_scalar_hints(value)?;
+        self.write_block_scalar_hints(value, presentation)?;
         self.put_break()?;
+        self.set_role(OutputRole::ScalarContent);
         self.indention = true;
         self.whitespace = true;
 
@@ -1735,9 +3163,8 @@ impl<'w> Emitter<'w> {
         while let Some(ch) = chars.next() {
             if is_break(ch) {
                 if !breaks && !leading_spaces && ch == '\n' {
-                    let mut skip_breaks = chars.clone();
-                    while is_break(skip_breaks.next()) {}
-                    if !is_blankz(skip_breaks.next()) {
+                    let next = chars.clone().find(|ch| !is_break(*ch));
+                    if !is_blankz(next) {
                         self.put_break()?;
                     }
                 }
@@ -1749,12 +3176,8 @@ impl<'w> Emitter<'w> {
                     self.write_indent()?;
                     leading_spaces = is_blank(ch);
                 }
-                if !breaks
-                    && is_space(ch)
-                    && !is_space(chars.clone().next())
-                    && self.column > self.best_width
-                {
-                    self.write_indent()?;
+                if !breaks && is_space(ch) && !is_space(chars.clone().next()) && self.past_width() {
+                    self.wrap_line()?;
                 } else {
                     self.write_char(ch)?;
                 }
@@ -1762,25 +3185,63 @@ impl<'w> Emitter<'w> {
                 breaks = false;
             }
         }
+        if breaks && !value.is_empty() {
+            self.block_scalar_end = Some((self.line, self.column));
+        }
+        self.check_overflow();
         Ok(())
     }
 
     /// Flush the accumulated characters to the output.
     pub fn flush(&mut self) -> Result<()> {
+        self.flush_buffer(self.final_newline == FinalNewline::Never)
+    }
+
+    /// Flush the working buffer, optionally keeping a trailing line break in
+    /// the buffer.
+    fn flush_buffer(&mut self, hold_break: bool) -> Result<()> {
+        self.filter_chunk();
+        let result = self.write_buffer(hold_break);
+        self.chunk_start = self.buffer.len();
+        result
+    }
+
+    /// Write the working buffer to the output, optionally keeping a trailing
+    /// line break in the buffer.
+    fn write_buffer(&mut self, hold_break: bool) -> Result<()> {
         assert!((self.write_handler).is_some());
         assert_ne!(self.encoding, Encoding::Any);
 
-        if self.buffer.is_empty() {
+        let held = if hold_break {
+            trailing_break_len(&self.buffer)
+        } else {
+            0
+        };
+        let len = self.buffer.len() - held;
+        if len == 0 {
             return Ok(());
         }
+        let to_emit = &self.buffer[..len];
+
+        let writer: &mut dyn std::io::Write =
+            match self.write_handler.as_mut().expect("non-null writer") {
+                WriteHandler::Io(writer) => writer,
+                WriteHandler::Owned(output) => output,
+                WriteHandler::Fmt(writer) => {
+                    if self.encoding != Encoding::Utf8 {
+                        return Err(Error::emitter("cannot write UTF-16 to a fmt::Write output"));
+                    }
+                    writer.write_str(to_emit)?;
+                    self.buffer.drain(..len);
+                    self.count_output(len);
+                    return Ok(());
+                }
+            };
 
         if self.encoding == Encoding::Utf8 {
-            let to_emit = self.buffer.as_bytes();
-            self.write_handler
-                .as_mut()
-                .expect("non-null writer")
-                .write_all(to_emit)?;
-            self.buffer.clear();
+            writer.write_all(to_emit.as_bytes())?;
+            self.buffer.drain(..len);
+            self.count_output(len);
             return Ok(());
         }
 
@@ -1792,7 +3253,7 @@ impl<'w> Emitter<'w> {
             Encoding::Utf16Be => true,
         };
 
-        for ch in self.buffer.encode_utf16() {
+        for ch in to_emit.encode_utf16() {
             let bytes = if big_endian {
                 ch.to_be_bytes()
             } else {
@@ -1801,31 +3262,1953 @@ impl<'w> Emitter<'w> {
             self.raw_buffer.extend(bytes);
         }
 
-        let to_emit = self.raw_buffer.as_slice();
-
-        self.write_handler
-            .as_mut()
-            .expect("non-null function pointer")
-            .write_all(to_emit)?;
-        self.buffer.clear();
+        writer.write_all(&self.raw_buffer)?;
+        self.buffer.drain(..len);
+        self.count_output(self.raw_buffer.len());
         self.raw_buffer.clear();
         Ok(())
     }
 
+    fn count_output(&self, bytes: usize) {
+        if let Some(stats) = &self.stats {
+            stats.add_output_bytes(bytes);
+        }
+    }
+
     pub(crate) fn reset_anchors(&mut self) {
         self.anchors.clear();
         self.last_anchor_id = 0;
     }
 
-    pub(crate) fn anchor_node_sub(&mut self, index: i32) {
-        self.anchors[index as usize - 1].references += 1;
-        if self.anchors[index as usize - 1].references == 2 {
-            self.last_anchor_id += 1;
-            self.anchors[index as usize - 1].anchor = self.last_anchor_id;
+    pub(crate) fn generate_anchor(anchor_id: i32) -> String {
+        alloc::format!("id{anchor_id:03}")
+    }
+}
+
+/// The number of characters written for a tag, with the space after it.
+fn tag_width(tag: &TagAnalysis) -> usize {
+    let width = tag.handle.chars().count() + tag.suffix.chars().count() + 1;
+    if tag.handle.is_empty() {
+        // A verbatim tag, `!<suffix>`.
+        width + 3
+    } else {
+        width
+    }
+}
+
+/// The length in bytes of the line break at the end of `buffer`, if any.
+fn trailing_break_len(buffer: &str) -> usize {
+    if buffer.ends_with("\r\n") {
+        2
+    } else {
+        usize::from(buffer.ends_with(['\n', '\r']))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Break, Document, EmitStyle, Emitter, Encoding, ErrorKind, Event, EventData, ExplicitKeys,
+        FinalNewline, MappingStyle, NodeData, OutputRole, OverflowKind, OverflowReport, Parser,
+        ScalarSource, ScalarStyle, SequenceStyle, TagDirective, VersionDirective,
+        VersionDirectivePolicy, NULL_TAG, STR_TAG,
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn load(input: &str) -> Document {
+        let mut parser = Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        Document::load(&mut parser).unwrap()
+    }
+
+    fn dump(document: Document, configure: impl FnOnce(&mut Emitter)) -> String {
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        configure(&mut emitter);
+        emitter.set_output(&mut output);
+        document.dump(&mut emitter).unwrap();
+        emitter.close().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    /// Parse `input` into a list of events, ignoring marks and presentation
+    /// details that are not significant for the content.
+    fn content_events(input: &str) -> Vec<String> {
+        let mut parser = Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        parser
+            .map(|event| {
+                let Event { data, .. } = event.unwrap();
+                match data {
+                    EventData::Scalar { value, tag, .. } => alloc::format!("={value} {tag:?}"),
+                    EventData::SequenceStart { tag, .. } => alloc::format!("+SEQ {tag:?}"),
+                    EventData::MappingStart { tag, .. } => alloc::format!("+MAP {tag:?}"),
+                    data => alloc::format!("{data:?}"),
+                }
+            })
+            .collect()
+    }
+
+    const NESTED: &str = "\
+name: demo
+point:
+- 1
+- 2
+tags:
+- a
+- b
+nested:
+  pair:
+    x: 1
+  list:
+  - one
+  - two
+  - a much longer scalar value that does not fit
+";
+
+    #[test]
+    fn compact_collections() {
+        let expected_disabled = "\
+name: demo
+point:
+- 1
+- 2
+tags:
+- a
+- b
+nested:
+  pair:
+    x: 1
+  list:
+  - one
+  - two
+  - a much longer scalar value that does not fit
+";
+        let expected_20 = "\
+name: demo
+point: [1, 2]
+tags: [a, b]
+nested:
+  pair: {x: 1}
+  list:
+  - one
+  - two
+  - a much longer scalar value that does not fit
+";
+        let expected_80 = "\
+name: demo
+point: [1, 2]
+tags: [a, b]
+nested:
+  pair: {x: 1}
+  list: [one, two, a much longer scalar value that does not fit]
+";
+
+        for (width, expected) in [(0, expected_disabled), (20, expected_20), (80, expected_80)] {
+            let output = dump(load(NESTED), |emitter| {
+                emitter.set_compact_collections(width);
+            });
+            assert_eq!(output, expected, "width {width}");
+            assert_eq!(content_events(&output), content_events(NESTED));
         }
+
+        // A multiline scalar never fits.
+        let input = "- |\n  one\n  two\n- b\n";
+        let output = dump(load(input), |emitter| {
+            emitter.set_compact_collections(80);
+        });
+        assert_eq!(output, input);
     }
 
-    pub(crate) fn generate_anchor(anchor_id: i32) -> String {
-        alloc::format!("id{anchor_id:03}")
+    #[test]
+    fn compact_collections_with_nested_collections() {
+        // A collection containing a collection is never compacted, but its
+        // children can be.
+        let input = "- [a, b]\n- [c, d]\n";
+        let output = dump(load(input), |emitter| {
+            emitter.set_compact_collections(80);
+        });
+        assert_eq!(output, "- [a, b]\n- [c, d]\n");
+        assert_eq!(content_events(&output), content_events(input));
+    }
+
+    #[test]
+    fn complex_keys_round_trip() {
+        for input in [
+            "? - a\n  - b\n: value\n",
+            "? [a, b]\n: value\n",
+            "? x: 1\n  y: 2\n: value\n",
+            "? {x: 1}\n: value\n",
+            "? |\n  line one\n  line two\n: value\n",
+            "{? [a, b]: value, ? {x: 1}: other}\n",
+            "- ? [a]\n  : [b]\n",
+            "{? \"line one\\nline two\": value}\n",
+        ] {
+            let output = dump(load(input), |_| {});
+            assert_eq!(content_events(&output), content_events(input), "{output}");
+            let again = dump(load(&output), |_| {});
+            assert_eq!(again, output);
+            let compact = dump(load(input), |emitter| {
+                emitter.set_compact_collections(80);
+            });
+            assert_eq!(content_events(&compact), content_events(input), "{compact}");
+        }
+    }
+
+    #[test]
+    fn complex_keys_from_document_api() {
+        let mut document = Document::new(None, &[], true, true);
+        let root = document.add_mapping(None, MappingStyle::Block);
+        let key = document.add_sequence(None, SequenceStyle::Block);
+        let a = document.add_scalar(None, "a", ScalarStyle::Plain);
+        let b = document.add_scalar(None, "b", ScalarStyle::Plain);
+        document.append_sequence_item(key, a);
+        document.append_sequence_item(key, b);
+        let value = document.add_scalar(None, "value", ScalarStyle::Plain);
+        document.yaml_document_append_mapping_pair(root, key, value);
+        let key = document.add_scalar(None, "line one\nline two", ScalarStyle::Plain);
+        let value = document.add_scalar(None, "other", ScalarStyle::Plain);
+        document.yaml_document_append_mapping_pair(root, key, value);
+
+        let output = dump(document, |_| {});
+        assert_eq!(
+            output,
+            "? - a\n  - b\n: value\n? 'line one\n\n  line two'\n: other\n"
+        );
+    }
+
+    #[test]
+    fn output_fmt() {
+        let document = load(NESTED);
+        let expected = dump(document.clone(), |_| {});
+
+        let mut output = String::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output_fmt(&mut output);
+        document.dump(&mut emitter).unwrap();
+        emitter.close().unwrap();
+        assert!(emitter.raw_buffer.is_empty());
+        drop(emitter);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn output_fmt_utf16() {
+        let mut output = String::new();
+        let mut emitter = Emitter::new();
+        emitter.set_encoding(Encoding::Utf16Le);
+        emitter.set_output_fmt(&mut output);
+        let err = emitter.open().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Emitter);
+    }
+
+    #[test]
+    fn output_fmt_error() {
+        struct Failing;
+        impl core::fmt::Write for Failing {
+            fn write_str(&mut self, _: &str) -> core::fmt::Result {
+                Err(core::fmt::Error)
+            }
+        }
+
+        let mut output = Failing;
+        let mut emitter = Emitter::new();
+        emitter.set_output_fmt(&mut output);
+        let err = load(NESTED).dump(&mut emitter).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Writer);
+    }
+
+    #[test]
+    fn queued_events() {
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        emitter.set_max_queued_events(1);
+        let mut max_queued = 0;
+        let mut emit = |emitter: &mut Emitter, event| {
+            emitter.emit(event).unwrap();
+            max_queued = max_queued.max(emitter.queued_events());
+        };
+        emit(&mut emitter, Event::stream_start(Encoding::Utf8));
+        emit(&mut emitter, Event::document_start(None, &[], true));
+        emit(
+            &mut emitter,
+            Event::mapping_start(None, None, true, MappingStyle::Any),
+        );
+        for i in 0..100_000 {
+            let key = alloc::format!("key{i}");
+            emit(
+                &mut emitter,
+                Event::scalar(None, None, &key, true, true, ScalarStyle::Any),
+            );
+            emit(
+                &mut emitter,
+                Event::scalar(None, None, "value", true, true, ScalarStyle::Any),
+            );
+        }
+        emit(&mut emitter, Event::mapping_end());
+        emit(&mut emitter, Event::document_end(true));
+        emit(&mut emitter, Event::stream_end());
+        assert_eq!(emitter.queued_events(), 0);
+        drop(emitter);
+        assert_eq!(max_queued, 1);
+        assert!(output.ends_with(b"key99999: value\n"));
+
+        // A collection that could still be compacted is held back.
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        emitter.set_compact_collections(usize::MAX);
+        emitter.set_max_queued_events(100);
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], true))
+            .unwrap();
+        emitter
+            .emit(Event::sequence_start(None, None, true, SequenceStyle::Any))
+            .unwrap();
+        for _ in 0..99 {
+            emitter
+                .emit(Event::scalar(None, None, "a", true, true, ScalarStyle::Any))
+                .unwrap();
+        }
+        assert_eq!(emitter.queued_events(), 100);
+        let err = emitter
+            .emit(Event::scalar(None, None, "a", true, true, ScalarStyle::Any))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Emitter);
+        assert_eq!(
+            err.problem(),
+            "exceeded the maximum number of queued events"
+        );
+    }
+
+    #[test]
+    fn invalid_tag_characters() {
+        for tag in ["!a b", "!a\tb", "!a\nb", "!a\u{7}b", "tag:x,2000:a\u{85}b"] {
+            let mut output = Vec::new();
+            let mut emitter = Emitter::new();
+            emitter.set_output_string(&mut output);
+            emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+            emitter
+                .emit(Event::document_start(None, &[], true))
+                .unwrap();
+            let err = emitter
+                .emit(Event::scalar(
+                    None,
+                    Some(tag),
+                    "x",
+                    false,
+                    false,
+                    ScalarStyle::Any,
+                ))
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::Emitter, "{tag:?}");
+            assert_eq!(
+                err.problem(),
+                "tag value must not contain whitespace or control characters"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_tag_directives() {
+        let cases = [
+            (
+                "! !",
+                "tag:x,2000:",
+                "tag handle must contain alphanumerical characters only",
+            ),
+            (
+                "!a b!",
+                "tag:x,2000:",
+                "tag handle must contain alphanumerical characters only",
+            ),
+            (
+                "!\n!",
+                "tag:x,2000:",
+                "tag handle must contain alphanumerical characters only",
+            ),
+            (
+                "!a!!",
+                "tag:x,2000:",
+                "tag handle must contain alphanumerical characters only",
+            ),
+            (
+                "!e!",
+                "tag:a b",
+                "tag prefix must not contain whitespace or control characters",
+            ),
+            (
+                "!e!",
+                "tag:a\nb",
+                "tag prefix must not contain whitespace or control characters",
+            ),
+            (
+                "!e!",
+                "x\u{7}",
+                "tag prefix must not contain whitespace or control characters",
+            ),
+        ];
+        for (handle, prefix, problem) in cases {
+            let directive = TagDirective {
+                handle: handle.to_owned(),
+                prefix: prefix.to_owned(),
+            };
+            let mut output = Vec::new();
+            let mut emitter = Emitter::new();
+            emitter.set_output_string(&mut output);
+            emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+            // The DOCUMENT-START event is queued until the next one.
+            emitter
+                .emit(Event::document_start(None, &[directive], false))
+                .unwrap();
+            let err = emitter
+                .emit(Event::scalar(None, None, "x", true, true, ScalarStyle::Any))
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::Emitter, "{handle:?} {prefix:?}");
+            assert_eq!(err.problem(), problem, "{handle:?} {prefix:?}");
+            emitter.flush().unwrap();
+            drop(emitter);
+            assert!(output.is_empty(), "{handle:?} {prefix:?}");
+        }
+    }
+
+    #[test]
+    fn overflow_reports() {
+        fn dump_tracked(input: &str, track: bool) -> (String, Vec<OverflowReport>) {
+            let mut output = Vec::new();
+            let mut emitter = Emitter::new();
+            emitter.set_output(&mut output);
+            emitter.set_width(80);
+            emitter.set_track_overflow(track);
+            load(input).dump(&mut emitter).unwrap();
+            emitter.close().unwrap();
+            let reports = emitter.take_overflow_reports();
+            assert!(emitter.take_overflow_reports().is_empty());
+            drop(emitter);
+            (String::from_utf8(output).unwrap(), reports)
+        }
+
+        let long = "x".repeat(200);
+        let input = alloc::format!("key: {long}\nnext: a b c\n");
+        let (output, reports) = dump_tracked(&input, true);
+        assert_eq!(output, input);
+        assert_eq!(
+            reports,
+            [OverflowReport {
+                line: 0,
+                column: 205,
+                kind: OverflowKind::PlainScalar,
+            }]
+        );
+        assert_eq!(dump_tracked(&input, false).1, []);
+
+        let input = alloc::format!("- short\n- !{long} a\n");
+        let (output, reports) = dump_tracked(&input, true);
+        assert_eq!(output, input);
+        assert_eq!(
+            reports,
+            [OverflowReport {
+                line: 1,
+                column: 205,
+                kind: OverflowKind::Tag,
+            }]
+        );
+
+        // Content that can be wrapped is not reported.
+        let input = "a ".repeat(100);
+        let (output, reports) = dump_tracked(&input, true);
+        assert_eq!(output.lines().count(), 3);
+        assert_eq!(reports, []);
+    }
+
+    /// Emit a flow sequence of `items` scalars, with the column of the
+    /// emitter set to `column` after the first one, as if the line was already
+    /// that long.
+    fn emit_long_line(width: i32, column: u64, items: usize) -> String {
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        emitter.set_width(width);
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], true))
+            .unwrap();
+        emitter
+            .emit(Event::sequence_start(None, None, true, SequenceStyle::Flow))
+            .unwrap();
+        for _ in 0..items {
+            emitter
+                .emit(Event::scalar(None, None, "a", true, true, ScalarStyle::Any))
+                .unwrap();
+            emitter.column = emitter.column.max(column);
+        }
+        emitter.emit(Event::sequence_end()).unwrap();
+        emitter.emit(Event::document_end(true)).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        drop(emitter);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn long_line_column() {
+        let column = u64::from(u32::MAX) * 4;
+        assert_eq!(emit_long_line(-1, column, 3), "[a, a, a]\n");
+        assert_eq!(emit_long_line(80, column, 3), "[a,\n  a,\n  a]\n");
+    }
+
+    /// Emits a line longer than `i32::MAX` characters, which takes a while.
+    #[test]
+    #[ignore = "slow"]
+    fn long_line() {
+        struct Count {
+            bytes: u64,
+            breaks: u64,
+        }
+        impl std::io::Write for Count {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.bytes += buf.len() as u64;
+                for byte in buf {
+                    self.breaks += u64::from(*byte == b'\n');
+                }
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let items = (i32::MAX as u64 / 3 + 2) as usize;
+        let mut count = Count {
+            bytes: 0,
+            breaks: 0,
+        };
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut count);
+        emitter.set_width(-1);
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], true))
+            .unwrap();
+        emitter
+            .emit(Event::sequence_start(None, None, true, SequenceStyle::Flow))
+            .unwrap();
+        for _ in 0..items {
+            emitter
+                .emit(Event::scalar(None, None, "a", true, true, ScalarStyle::Any))
+                .unwrap();
+        }
+        emitter.emit(Event::sequence_end()).unwrap();
+        emitter.emit(Event::document_end(true)).unwrap();
+        emitter.emit(Event::stream_end()).unwrap();
+        drop(emitter);
+        assert!(count.bytes > i32::MAX as u64);
+        assert_eq!(count.breaks, 1);
+    }
+
+    #[test]
+    fn final_newline() {
+        let cases = [
+            ("[a, b]", "[a, b]\n"),
+            ("a: 1\nb: 2\n", "a: 1\nb: 2\n"),
+            ("--- a\n--- [b]\n", "--- a\n--- [b]\n"),
+            ("a\n...\n", "a\n...\n"),
+            ("", ""),
+        ];
+        for (input, as_is) in cases {
+            let emit = |final_newline| {
+                let mut output = String::new();
+                let mut emitter = Emitter::new();
+                emitter.set_final_newline(final_newline);
+                emitter.set_output_fmt(&mut output);
+                let mut input = input.as_bytes();
+                let mut parser = Parser::new();
+                parser.set_input_string(&mut input);
+                for event in parser {
+                    emitter.emit(event.unwrap()).unwrap();
+                }
+                drop(emitter);
+                output
+            };
+
+            assert_eq!(emit(FinalNewline::AsIs), as_is, "{input:?}");
+            assert_eq!(emit(FinalNewline::Always), as_is, "{input:?}");
+            let never = emit(FinalNewline::Never);
+            assert_eq!(never, as_is.strip_suffix('\n').unwrap_or(""), "{input:?}");
+            assert_eq!(content_events(&never), content_events(input));
+        }
+    }
+
+    #[test]
+    fn version_directive_policy() {
+        // Documents with implicit ends, so that any `...` is written for the
+        // directives.
+        let emit = |minors: [i32; 3], policy| {
+            let mut output = String::new();
+            let mut emitter = Emitter::new();
+            emitter.set_version_directive_policy(policy);
+            emitter.set_output_fmt(&mut output);
+            emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+            for (minor, value) in minors.into_iter().zip(["a", "b", "c"]) {
+                let version_directive = VersionDirective { major: 1, minor };
+                for event in [
+                    Event::document_start(Some(version_directive), &[], true),
+                    Event::scalar(None, None, value, true, false, ScalarStyle::Plain),
+                    Event::document_end(true),
+                ] {
+                    emitter.emit(event).unwrap();
+                }
+            }
+            emitter.emit(Event::stream_end()).unwrap();
+            drop(emitter);
+            output
+        };
+
+        for (minors, policy, expected) in [
+            (
+                [2, 2, 2],
+                VersionDirectivePolicy::PerDocument,
+                "%YAML 1.2\n--- a\n...\n%YAML 1.2\n--- b\n...\n%YAML 1.2\n--- c\n",
+            ),
+            (
+                [2, 2, 2],
+                VersionDirectivePolicy::FirstOnly,
+                "a\n--- b\n--- c\n",
+            ),
+            (
+                [2, 2, 2],
+                VersionDirectivePolicy::Never,
+                "a\n--- b\n--- c\n",
+            ),
+            (
+                [1, 1, 2],
+                VersionDirectivePolicy::PerDocument,
+                "%YAML 1.1\n--- a\n...\n%YAML 1.1\n--- b\n...\n%YAML 1.2\n--- c\n",
+            ),
+            (
+                [1, 1, 2],
+                VersionDirectivePolicy::FirstOnly,
+                "%YAML 1.1\n--- a\n--- b\n--- c\n",
+            ),
+            (
+                [1, 1, 2],
+                VersionDirectivePolicy::Never,
+                "a\n--- b\n--- c\n",
+            ),
+        ] {
+            let output = emit(minors, policy);
+            assert_eq!(output, expected, "{minors:?} {policy:?}");
+            let mut scalars = content_events(&output);
+            scalars.retain(|event| event.starts_with('='));
+            assert_eq!(scalars, ["=a None", "=b None", "=c None"]);
+        }
+    }
+
+    #[test]
+    fn final_newline_edge_cases() {
+        // An empty stream stays empty.
+        let mut output = String::new();
+        let mut emitter = Emitter::new();
+        emitter.set_final_newline(FinalNewline::Always);
+        emitter.set_output_fmt(&mut output);
+        emitter.open().unwrap();
+        emitter.close().unwrap();
+        drop(emitter);
+        assert_eq!(output, "");
+
+        // Flushing explicitly does not write the final line break.
+        let mut output = String::new();
+        let mut emitter = Emitter::new();
+        emitter.set_final_newline(FinalNewline::Never);
+        emitter.set_break(crate::Break::CrLn);
+        emitter.set_output_fmt(&mut output);
+        let mut document = Document::new(None, &[], true, true);
+        _ = document.add_scalar(None, "a", ScalarStyle::Plain);
+        document.dump(&mut emitter).unwrap();
+        emitter.flush().unwrap();
+        emitter.close().unwrap();
+        drop(emitter);
+        assert_eq!(output, "a");
+    }
+
+    #[test]
+    fn final_newline_block_scalars() {
+        // The line break of a block scalar at the end of the stream is part of
+        // its value, so the document is ended with `...` instead.
+        for (input, expected) in [
+            ("a: |\n  x\n", "a: |\n  x\n..."),
+            ("--- >\n  a\n", "--- >\n  a\n..."),
+            ("- |+\n  x\n\n", "- |+\n  x\n\n..."),
+            ("a: |-\n  x\n", "a: |-\n  x"),
+            ("- |\n  x\n- y\n", "- |\n  x\n- y"),
+        ] {
+            let document = load(input);
+            let output = dump(document.clone(), |emitter| {
+                emitter.set_final_newline(FinalNewline::Never);
+            });
+            assert_eq!(output, expected, "{input:?}");
+            assert!(load(&output).deep_eq(&document), "{input:?}: {output:?}");
+        }
+    }
+
+    /// Emit a single scalar document with the requested style.
+    fn emit_scalar(value: &str, style: ScalarStyle) -> String {
+        let mut output = String::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output_fmt(&mut output);
+        emitter.open().unwrap();
+        for event in [
+            Event::document_start(None, &[], true),
+            Event::scalar(None, None, value, true, true, style),
+            Event::document_end(true),
+        ] {
+            emitter.emit(event).unwrap();
+        }
+        emitter.close().unwrap();
+        drop(emitter);
+        output
+    }
+
+    #[test]
+    fn hash_in_plain_scalars() {
+        // A `#` only needs quotes where it would start a comment.
+        for (value, expected) in [
+            ("foo#bar", "foo#bar\n"),
+            ("foo#", "foo#\n"),
+            ("foo# bar", "foo# bar\n"),
+            ("http://example.com/foo#bar", "http://example.com/foo#bar\n"),
+            ("foo #bar", "'foo #bar'\n"),
+            ("#foo", "'#foo'\n"),
+        ] {
+            let output = emit_scalar(value, ScalarStyle::Any);
+            assert_eq!(output, expected, "{value:?}");
+            assert_eq!(
+                content_events(&output),
+                content_events(&alloc::format!("\"{value}\"")),
+                "{value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn single_quoted_style() {
+        for value in [
+            "hello  world",
+            " leading",
+            "trailing ",
+            "  both  ",
+            " ",
+            "it's",
+            "# not a comment",
+            "- not a sequence",
+            // Long enough to be folded.
+            &"spaced  out  words ".repeat(10),
+        ] {
+            let output = emit_scalar(value, ScalarStyle::SingleQuoted);
+            assert!(output.starts_with('\''), "{value:?}: {output:?}");
+            assert_eq!(
+                content_events(&output),
+                content_events(&alloc::format!("\"{value}\"")),
+                "{value:?}: {output:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn single_quoted_style_fallback() {
+        // Values that cannot be represented in the single-quoted style.
+        for value in [
+            "trailing space \nbefore a break",
+            "leading space after\n a break",
+            "control \x07 character",
+            "byte order \u{feff} mark",
+        ] {
+            let output = emit_scalar(value, ScalarStyle::SingleQuoted);
+            assert!(output.starts_with('"'), "{value:?}: {output:?}");
+            let events = content_events(&output);
+            assert_eq!(events[2], alloc::format!("={value} None"), "{output:?}");
+        }
+    }
+
+    #[test]
+    fn non_printable_characters() {
+        // Characters outside of the `c-printable` production of the spec are
+        // written with escapes, while noncharacters that it allows are kept.
+        let escaped = ['\u{7f}', '\u{feff}', '\u{fffe}', '\u{ffff}']
+            .into_iter()
+            .chain('\u{80}'..='\u{9f}');
+        let kept = ['\u{fdd0}', '\u{fdef}', '\u{1fffe}', '\u{10ffff}'];
+        for (ch, is_escaped) in escaped
+            .map(|ch| (ch, true))
+            .chain(kept.map(|ch| (ch, false)))
+        {
+            for unicode in [true, false] {
+                for style in [
+                    ScalarStyle::Any,
+                    ScalarStyle::Plain,
+                    ScalarStyle::DoubleQuoted,
+                ] {
+                    let value = alloc::format!("a{ch}b");
+                    let mut output = String::new();
+                    let mut emitter = Emitter::new();
+                    emitter.set_unicode(unicode);
+                    emitter.set_output_fmt(&mut output);
+                    emitter.open().unwrap();
+                    emitter
+                        .emit(Event::document_start(None, &[], true))
+                        .unwrap();
+                    emitter
+                        .emit(Event::scalar(None, None, &value, true, true, style))
+                        .unwrap();
+                    emitter.emit(Event::document_end(true)).unwrap();
+                    emitter.close().unwrap();
+                    drop(emitter);
+                    let context = alloc::format!("{ch:?} {unicode} {style:?}: {output:?}");
+                    if is_escaped {
+                        assert!(output.starts_with('"'), "{context}");
+                    }
+                    assert_eq!(output.contains(ch), !is_escaped && unicode, "{context}");
+                    let events = crate::parse_events(&output).unwrap();
+                    assert!(
+                        matches!(&events[2].data, EventData::Scalar { value: v, .. } if *v == value),
+                        "{context}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn source_lines() {
+        let input = "\
+wrapped: the same words
+  across two lines
+single: the same words across two lines
+quoted: 'the same words
+  across two lines'
+literal: |
+  one
+  two
+empty:
+";
+        let events = crate::parse_events(input).unwrap();
+        let scalars: Vec<(&str, u32)> = events
+            .iter()
+            .filter_map(|event| match &event.data {
+                EventData::Scalar { value, source, .. } => Some((value.as_str(), source.lines)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            scalars,
+            [
+                ("wrapped", 1),
+                ("the same words across two lines", 2),
+                ("single", 1),
+                ("the same words across two lines", 1),
+                ("quoted", 1),
+                ("the same words across two lines", 2),
+                ("literal", 1),
+                ("one\ntwo\n", 3),
+                ("empty", 1),
+                ("", 1),
+            ]
+        );
+
+        let emit = |events: &[Event], respect_scalar_styles: bool| {
+            let mut output = String::new();
+            let mut emitter = Emitter::new();
+            emitter.set_width(20);
+            emitter.set_respect_scalar_styles(respect_scalar_styles);
+            emitter.set_output_fmt(&mut output);
+            for event in events.iter().cloned() {
+                emitter.emit(event).unwrap();
+            }
+            drop(emitter);
+            output
+        };
+        assert_eq!(
+            emit(&events, true),
+            "\
+wrapped: the same words
+  across two lines
+single: the same words across two lines
+quoted: 'the same words
+  across two lines'
+literal: |
+  one
+  two
+empty:
+"
+        );
+        assert_eq!(
+            emit(&events, false),
+            "\
+wrapped: the same words
+  across two lines
+single: the same words
+  across two lines
+quoted: 'the same words
+  across two lines'
+literal: 'one
+
+  two
+
+  '
+empty:
+"
+        );
+        // Events that are not from a parser are wrapped.
+        let mut unknown = events.clone();
+        for event in &mut unknown {
+            if let EventData::Scalar { source, .. } = &mut event.data {
+                *source = ScalarSource::default();
+            }
+        }
+        assert!(emit(&unknown, true).contains("single: the same words\n  across two lines\n"));
+    }
+
+    #[test]
+    fn explicit_keys() {
+        let emit = |input: &str, explicit_keys, canonical| {
+            let mut output = String::new();
+            let mut emitter = Emitter::new();
+            emitter.set_explicit_keys(explicit_keys);
+            emitter.set_canonical(canonical);
+            emitter.set_output_fmt(&mut output);
+            // One event at a time, so that the emitter has to wait for the
+            // end of empty collections.
+            for event in crate::parse_events(input).unwrap() {
+                emitter.emit(event).unwrap();
+            }
+            drop(emitter);
+            output
+        };
+        let flow = "{{}: a, []: b, [x]: c, d: e}";
+        let block = "{}: a\n[]: b\n[x]: c\nd: e\n? |\n  f\n: g\n";
+        let cases = [
+            (
+                flow,
+                ExplicitKeys::Auto,
+                false,
+                "{{}: a, []: b, ? [x] : c, d: e}\n",
+            ),
+            (
+                flow,
+                ExplicitKeys::Never,
+                false,
+                "{{}: a, []: b, ? [x] : c, d: e}\n",
+            ),
+            (
+                flow,
+                ExplicitKeys::Always,
+                false,
+                "{? {} : a, ? [] : b, ? [x] : c, ? d : e}\n",
+            ),
+            (
+                block,
+                ExplicitKeys::Auto,
+                false,
+                "{}: a\n[]: b\n? [x]\n: c\nd: e\n? |\n  f\n: g\n",
+            ),
+            (
+                block,
+                ExplicitKeys::Never,
+                false,
+                "{}: a\n[]: b\n? [x]\n: c\nd: e\n? |\n  f\n: g\n",
+            ),
+            (
+                block,
+                ExplicitKeys::Always,
+                false,
+                "? {}\n: a\n? []\n: b\n? [x]\n: c\n? d\n: e\n? |\n  f\n: g\n",
+            ),
+            (
+                "{a: b, {}: c}",
+                ExplicitKeys::Auto,
+                true,
+                "---\n{\n  ? ! \"a\"\n  : ! \"b\",\n  ? {}\n  : ! \"c\",\n}\n",
+            ),
+            (
+                "{a: b, {}: c}",
+                ExplicitKeys::Never,
+                true,
+                "---\n{\n  ! \"a\": ! \"b\",\n  {}: ! \"c\",\n}\n",
+            ),
+        ];
+        for (input, explicit_keys, canonical, expected) in cases {
+            let output = emit(input, explicit_keys, canonical);
+            assert_eq!(output, expected, "{input:?} {explicit_keys:?} {canonical}");
+            if canonical {
+                // The canonical format adds tags and a document start.
+                crate::parse_events(&output).unwrap();
+            } else {
+                assert_eq!(
+                    content_events(&output),
+                    content_events(input),
+                    "{input:?} {explicit_keys:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn empty_collections() {
+        for input in [
+            "a: {}\nb: []\n",
+            "a: {b: {}}\n",
+            "a:\n  b: {}\n  c: []\n",
+            "? {}\n: x\n? []\n: y\n",
+            "- {}\n- []\n- - []\n",
+            "{}",
+            "!custom {}\n",
+            "&id001 [] : *id001\n",
+        ] {
+            for compact in [0, 80] {
+                let output = dump(load(input), |emitter| {
+                    emitter.set_compact_collections(compact);
+                });
+                assert_eq!(content_events(&output), content_events(input), "{output:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn empty_block_collections_from_document_api() {
+        let mut document = Document::new(None, &[], true, true);
+        let root = document.add_mapping(None, MappingStyle::Block);
+        let key = document.add_scalar(None, "a", ScalarStyle::Any);
+        let value = document.add_mapping(None, MappingStyle::Block);
+        document.yaml_document_append_mapping_pair(root, key, value);
+        let key = document.add_scalar(None, "b", ScalarStyle::Any);
+        let value = document.add_sequence(None, SequenceStyle::Block);
+        document.yaml_document_append_mapping_pair(root, key, value);
+        let key = document.add_scalar(None, "c", ScalarStyle::Any);
+        let value = document.add_mapping(None, MappingStyle::Any);
+        let nested_key = document.add_scalar(None, "d", ScalarStyle::Any);
+        let nested_value = document.add_mapping(None, MappingStyle::Block);
+        document.yaml_document_append_mapping_pair(value, nested_key, nested_value);
+        document.yaml_document_append_mapping_pair(root, key, value);
+        let key = document.add_mapping(None, MappingStyle::Block);
+        let value = document.add_sequence(None, SequenceStyle::Block);
+        document.yaml_document_append_mapping_pair(root, key, value);
+
+        assert_eq!(
+            dump(document, |_| {}),
+            "a: {}\nb: []\nc:\n  d: {}\n{}: []\n"
+        );
+    }
+
+    #[test]
+    fn consecutive_documents() {
+        let roots = [
+            "plain",
+            "|+\n  keep\n\n",
+            "|-\n  strip\n",
+            "{flow: map}\n",
+            "block: map\n",
+            "block: |+\n  keep\n\n",
+        ];
+        for first in roots {
+            for second in roots {
+                let mut output = Vec::new();
+                let mut emitter = Emitter::new();
+                emitter.set_output(&mut output);
+                load(first).dump(&mut emitter).unwrap();
+                load(second).dump(&mut emitter).unwrap();
+                emitter.close().unwrap();
+                drop(emitter);
+                let output = String::from_utf8(output).unwrap();
+
+                let documents = crate::parse_documents(&output).unwrap();
+                assert_eq!(documents.len(), 2, "{output:?}");
+                assert!(documents[0].deep_eq(&load(first)), "{output:?}");
+                assert!(documents[1].deep_eq(&load(second)), "{output:?}");
+                // Only a document ending with trailing empty lines is closed
+                // explicitly.
+                assert_eq!(
+                    output.contains("\n...\n---"),
+                    first.ends_with("\n\n"),
+                    "{output:?}"
+                );
+            }
+        }
+
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        load("|+\n  keep\n\n").dump(&mut emitter).unwrap();
+        load("|+\n  keep\n\n").dump(&mut emitter).unwrap();
+        emitter.close().unwrap();
+        drop(emitter);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "|+\n  keep\n\n...\n--- |+\n  keep\n\n...\n"
+        );
+    }
+
+    #[test]
+    fn escaped_tag_characters() {
+        let cases = [
+            ("!e!a%21b x\n", "tag:example.com,2000:a!b", "!e!a%21b x\n"),
+            (
+                "!e!%5Ba%2Cb%5D x\n",
+                "tag:example.com,2000:[a,b]",
+                "!e!%5Ba%2Cb%5D x\n",
+            ),
+            (
+                "!e!%7Ba%7D x\n",
+                "tag:example.com,2000:{a}",
+                "!e!%7Ba%7D x\n",
+            ),
+            ("!e!a#b x\n", "tag:example.com,2000:a#b", "!e!a#b x\n"),
+            ("!a%21b x\n", "!a!b", "!a%21b x\n"),
+            ("!a%25b x\n", "!a%b", "!a%25b x\n"),
+            ("!%C3%A9 x\n", "!\u{e9}", "!%C3%A9 x\n"),
+            (
+                "!e!%E2%82%AC%F0%9F%98%80 x\n",
+                "tag:example.com,2000:\u{20ac}\u{1f600}",
+                "!e!%E2%82%AC%F0%9F%98%80 x\n",
+            ),
+            (
+                "!<tag:x,2000:a!b[c]> x\n",
+                "tag:x,2000:a!b[c]",
+                "!<tag:x,2000:a!b[c]> x\n",
+            ),
+            (
+                "[!e!a%2Cb, !e!c]\n",
+                "tag:example.com,2000:a,b",
+                "[!e!a%2Cb '', !e!c '']\n",
+            ),
+        ];
+        for (input, tag, expected) in cases {
+            let input = alloc::format!("%TAG !e! tag:example.com,2000:\n--- {input}");
+            let document = load(&input);
+            assert!(
+                document
+                    .nodes
+                    .iter()
+                    .any(|node| node.tag.as_deref() == Some(tag)),
+                "{input:?}"
+            );
+            let output = dump(document.clone(), |_| {});
+            assert!(output.ends_with(expected), "{output:?}");
+            assert!(load(&output).deep_eq(&document), "{output:?}");
+        }
+    }
+
+    #[test]
+    fn directives() {
+        let directives = [
+            "",
+            "%YAML 1.2\n",
+            "%TAG !e! tag:example.com,2000:\n",
+            "%YAML 1.1\n%TAG !e! tag:example.com,2000:\n",
+            "%TAG !e! tag:example.com,2000:\n%TAG !f! !foo-\n",
+        ];
+        let roots = [
+            "value",
+            "&anchor value",
+            "!e!tagged value",
+            "{a: 1}",
+            "&anchor {a: 1}",
+            "!e!tagged {a: 1}",
+            "[a, b]",
+            "a: 1\nb: [c]",
+            "- a\n- b",
+            "|\n  literal",
+        ];
+        for directives in directives {
+            for root in roots {
+                if root.contains("!e!") && !directives.contains("!e!") {
+                    continue;
+                }
+                let input =
+                    alloc::format!("{directives}---\n{root}\n...\n{directives}--- second\n");
+                for canonical in [false, true] {
+                    let mut parser = Parser::new();
+                    let mut bytes = input.as_bytes();
+                    parser.set_input_string(&mut bytes);
+                    let mut output = Vec::new();
+                    let mut emitter = Emitter::new();
+                    emitter.set_canonical(canonical);
+                    emitter.set_output(&mut output);
+                    for event in parser {
+                        let mut event = event.unwrap();
+                        // Leave the end of the first document open, so that the
+                        // emitter has to close it before the directives.
+                        if let EventData::DocumentEnd { implicit } = &mut event.data {
+                            *implicit = true;
+                        }
+                        emitter.emit(event).unwrap();
+                    }
+                    drop(emitter);
+                    let output = String::from_utf8(output).unwrap();
+
+                    for line in output.lines() {
+                        if line.contains('%') {
+                            assert!(
+                                line.starts_with("%YAML ") || line.starts_with("%TAG "),
+                                "{output}"
+                            );
+                        }
+                        if line.contains("---") {
+                            assert!(line.starts_with("---"), "{output}");
+                        }
+                    }
+                    if canonical {
+                        assert_eq!(
+                            content_events(&output).len(),
+                            content_events(&input).len(),
+                            "{output}"
+                        );
+                    } else {
+                        // Whether the first document end is implicit depends on
+                        // the directives that follow it.
+                        let events = |input| {
+                            let mut events = content_events(input);
+                            events.retain(|event| !event.starts_with("DocumentEnd"));
+                            events
+                        };
+                        assert_eq!(events(&output), events(&input), "{output}");
+                    }
+                }
+            }
+        }
+
+        // A tagged and anchored root flow mapping, dumped from a document.
+        let input = "%TAG !e! tag:example.com,2000:\n--- &a !e!map {a: *a}\n";
+        let output = dump(load(input), |_| {});
+        assert_eq!(
+            output,
+            "%TAG !e! tag:example.com,2000:\n--- &a !e!map {a: *a}\n"
+        );
+    }
+
+    #[test]
+    fn line_breaks() {
+        let values = [
+            "one\ntwo",
+            "one\ntwo\n",
+            "one\n\ntwo\n\n",
+            "one\nt",
+            "one\na b",
+            "\nleading break",
+            "folded words that are long enough to be wrapped by the emitter\nand more",
+            "carriage\rreturn",
+            "dos\r\nline\r\n",
+            "next\u{85}line",
+            "line\u{2028}separator",
+        ];
+        let styles = [
+            ScalarStyle::Plain,
+            ScalarStyle::SingleQuoted,
+            ScalarStyle::DoubleQuoted,
+            ScalarStyle::Literal,
+            ScalarStyle::Folded,
+        ];
+        for (line_break, newline) in [(Break::Cr, "\r"), (Break::Ln, "\n"), (Break::CrLn, "\r\n")] {
+            for value in values {
+                for style in styles {
+                    let mut output = String::new();
+                    let mut emitter = Emitter::new();
+                    emitter.set_break(line_break);
+                    emitter.set_width(20);
+                    emitter.set_output_fmt(&mut output);
+                    emitter.open().unwrap();
+                    for event in [
+                        Event::document_start(None, &[], true),
+                        Event::mapping_start(None, None, true, MappingStyle::Block),
+                        Event::scalar(None, None, "key", true, true, ScalarStyle::Plain),
+                        Event::scalar(None, None, value, true, true, style),
+                        Event::mapping_end(),
+                        Event::document_end(true),
+                    ] {
+                        emitter.emit(event).unwrap();
+                    }
+                    emitter.close().unwrap();
+                    drop(emitter);
+
+                    let context = alloc::format!("{line_break:?} {style:?} {value:?}: {output:?}");
+                    let stray = output.replace(newline, "");
+                    assert!(!stray.contains(['\r', '\n']), "{context}");
+                    let events = content_events(&output);
+                    assert_eq!(events[4], alloc::format!("={value} None"), "{context}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn emit_style_presets() {
+        // Reset the styles of the loaded nodes, so that the presets decide them.
+        let document = || {
+            let mut document = load(
+                "name: démo\nlist: [one, {a: 1, b: [x, y]}]\ntext: \"first line\\nsecond line\\n\"\nempty: []\n",
+            );
+            for node in &mut document.nodes {
+                match &mut node.data {
+                    NodeData::Scalar { style, .. } => *style = ScalarStyle::Any,
+                    NodeData::Sequence { style, .. } => *style = SequenceStyle::Any,
+                    NodeData::Mapping { style, .. } => *style = MappingStyle::Any,
+                    NodeData::NoNode => {}
+                }
+            }
+            document
+        };
+        let expected = [
+            (
+                EmitStyle::default(),
+                "\
+name: \"d\\xE9mo\"
+list:
+- one
+- a: 1
+  b:
+  - x
+  - y
+text: 'first line
+
+  second line
+
+  '
+empty: []
+",
+            ),
+            (
+                EmitStyle::block_pretty(),
+                "\
+name: démo
+list:
+  - one
+  - a: 1
+    b:
+      - x
+      - y
+text: |
+  first line
+  second line
+empty: []
+",
+            ),
+            (
+                EmitStyle::flow_compact(),
+                "\
+{name: démo, list: [one, {a: 1, b: [x, y]}], text: 'first line
+
+    second line
+
+    ', empty: []}
+",
+            ),
+            (
+                EmitStyle::canonical(),
+                "\
+---
+!!map {
+  ? !!str \"name\"
+  : !!str \"d\\xE9mo\",
+  ? !!str \"list\"
+  : !!seq [
+    !!str \"one\",
+    !!map {
+      ? !!str \"a\"
+      : !!str \"1\",
+      ? !!str \"b\"
+      : !!seq [
+        !!str \"x\",
+        !!str \"y\",
+      ],
+    },
+  ],
+  ? !!str \"text\"
+  : !!str \"first line\\nsecond line\\n\",
+  ? !!str \"empty\"
+  : !!seq [],
+}
+",
+            ),
+        ];
+        for (style, expected) in expected {
+            let output = dump(document(), |emitter| emitter.apply_style(&style));
+            assert_eq!(output, expected, "{style:?}");
+            assert!(load(&output).deep_eq(&document()), "{style:?}");
+        }
+
+        let output = dump(load("a: [1]\n"), |emitter| emitter.set_explicit_tags(true));
+        assert_eq!(output, "!!map\n!!str a: !!seq [!!str 1]\n");
+    }
+
+    #[test]
+    fn interrupt_check() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        let events = crate::parse_events("a: [b, c]\n").unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        let check_count = count.clone();
+        emitter.set_interrupt_check(Box::new(move || check_count.load(Ordering::Relaxed) >= 4));
+        let mut events = events.into_iter();
+        let (err, start_mark) = loop {
+            let event = events.next().unwrap();
+            let start_mark = event.start_mark;
+            if let Err(err) = emitter.emit(event) {
+                break (err, start_mark);
+            }
+            count.fetch_add(1, Ordering::Relaxed);
+        };
+        // The interrupted event is the SEQUENCE-START.
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+        assert_eq!(err.problem_mark(), Some(start_mark));
+        assert_eq!((start_mark.line, start_mark.column), (0, 3));
+    }
+
+    #[test]
+    fn alias_scope() {
+        // The parser does not check aliases, so the events are as written.
+        let emit = |input: &str| {
+            let mut output = String::new();
+            let mut emitter = Emitter::new();
+            emitter.set_output_fmt(&mut output);
+            for event in crate::parse_events(input).unwrap() {
+                emitter.emit(event).map_err(|err| err.problem())?;
+            }
+            drop(emitter);
+            Ok(output)
+        };
+        let undefined = "found an alias of an anchor that is not defined in the document";
+        assert_eq!(emit("&base {a: b}\n--- [*base]\n"), Err(undefined));
+        assert_eq!(emit("{a: *base, b: &base c}\n"), Err(undefined));
+        assert_eq!(emit("[*base]\n"), Err(undefined));
+        assert_eq!(
+            emit("&base {a: b}\n--- &base [*base]\n"),
+            Ok("&base {a: b}\n--- &base [*base]\n".to_string())
+        );
+        assert_eq!(
+            emit("{a: &base b, c: *base}\n"),
+            Ok("{a: &base b, c: *base}\n".to_string())
+        );
+    }
+
+    #[test]
+    fn empty_documents() {
+        // Streams of empty documents, mixed with others, come out as they
+        // were written.
+        for input in [
+            "---\n",
+            "---\n---\n",
+            "---\n---\n---\n",
+            "---\n--- a\n---\n",
+            "a\n---\n---\n--- b\n",
+            "---\n...\n---\n...\n",
+            "--- ''\n---\n",
+        ] {
+            let events = crate::parse_events(input).unwrap();
+            let output = crate::emit_events(events.clone()).unwrap();
+            assert_eq!(output, input);
+            assert_eq!(crate::parse_events(&output).unwrap(), events);
+        }
+
+        // An empty document is written with `---` even if its start is
+        // implicit, so that it is not lost.
+        let document = |value: &str| {
+            [
+                Event::document_start(None, &[], true),
+                Event::scalar(None, None, value, true, true, ScalarStyle::Any),
+                Event::document_end(true),
+            ]
+        };
+        let mut events = vec![Event::stream_start(Encoding::Utf8)];
+        events.extend(document(""));
+        events.extend(document("a"));
+        events.extend(document(""));
+        events.push(Event::stream_end());
+        let output = crate::emit_events(events).unwrap();
+        assert_eq!(output, "---\n--- a\n---\n");
+        assert_eq!(crate::parse_documents(&output).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn empty_strings() {
+        // Replace the scalar `x` of each template with an empty scalar.
+        fn emit_empty(template: &str, tag: Option<&str>) -> String {
+            let events = crate::parse_events(template)
+                .unwrap()
+                .into_iter()
+                .map(|event| match event.data {
+                    EventData::Scalar { ref value, .. } if value == "x" => {
+                        Event::scalar(None, tag, "", true, true, ScalarStyle::Any)
+                    }
+                    _ => event,
+                })
+                .collect();
+            crate::emit_events(events).unwrap()
+        }
+
+        for (template, string, null) in [
+            ("key: x\n", "key: ''\n", "key:\n"),
+            ("x: value\n", "'': value\n", "'': value\n"),
+            ("{key: x}\n", "{key: ''}\n", "{key: ''}\n"),
+            ("- x\n", "- ''\n", "-\n"),
+            ("x\n", "''\n", "---\n"),
+        ] {
+            assert_eq!(emit_empty(template, Some(STR_TAG)), string, "{template}");
+            assert_eq!(emit_empty(template, Some(NULL_TAG)), null, "{template}");
+            assert_eq!(emit_empty(template, None), null, "{template}");
+        }
+
+        for (input, output, value, style) in [
+            ("key: ''\n", "key: ''\n", "", ScalarStyle::SingleQuoted),
+            ("key: ~\n", "key: ~\n", "~", ScalarStyle::Plain),
+            ("key:\n", "key: ''\n", "", ScalarStyle::SingleQuoted),
+        ] {
+            let dumped = dump(load(input), |_| {});
+            assert_eq!(dumped, output, "{input}");
+            let document = load(&dumped);
+            let NodeData::Scalar {
+                value: ref loaded,
+                style: loaded_style,
+            } = document.nodes[2].data
+            else {
+                panic!("{input}");
+            };
+            assert_eq!((loaded.as_str(), loaded_style), (value, style), "{input}");
+            assert_eq!(document.nodes[2].tag.as_deref(), Some(STR_TAG), "{input}");
+        }
+    }
+
+    #[test]
+    fn simple_key_length() {
+        // Emit a mapping with a single key, and check that it is read back.
+        // A key that is not `plain_implicit` is tagged `!!str`.
+        fn emit_key(
+            key: &str,
+            plain_implicit: bool,
+            configure: impl FnOnce(&mut Emitter),
+        ) -> String {
+            let tag = (!plain_implicit).then_some(STR_TAG);
+            let events = crate::parse_events("x: value\n")
+                .unwrap()
+                .into_iter()
+                .map(|event| match event.data {
+                    EventData::Scalar { ref value, .. } if value == "x" => {
+                        Event::scalar(None, tag, key, plain_implicit, true, ScalarStyle::Any)
+                    }
+                    _ => event,
+                })
+                .collect::<Vec<_>>();
+            let mut output = Vec::new();
+            let mut emitter = Emitter::new();
+            configure(&mut emitter);
+            emitter.set_output(&mut output);
+            for event in events {
+                emitter.emit(event).unwrap();
+            }
+            drop(emitter);
+            let output = String::from_utf8(output).unwrap();
+            let loaded = crate::parse_events(&output).unwrap();
+            assert!(
+                matches!(&loaded[3].data, EventData::Scalar { value, .. } if value == key),
+                "{output}"
+            );
+            output
+        }
+
+        fn ascii(len: usize) -> String {
+            "k".repeat(len)
+        }
+        fn cjk(len: usize) -> String {
+            "\u{4e2d}".repeat(len)
+        }
+        fn quoted(len: usize) -> String {
+            alloc::format!("#{}", "k".repeat(len - 1))
+        }
+        fn unicode(emitter: &mut Emitter) {
+            emitter.set_unicode(true);
+        }
+        fn libyaml(emitter: &mut Emitter) {
+            emitter.set_unicode(true);
+            emitter.set_max_simple_key_length(128);
+        }
+        type Configure = fn(&mut Emitter);
+
+        for (key, configure, limit) in [
+            (ascii as fn(usize) -> String, (|_| {}) as Configure, 1024),
+            (cjk, unicode, 1024),
+            // Escaped as `\u4E2D`, in double quotes.
+            (cjk, |_| {}, 170),
+            // In single quotes.
+            (quoted, |_| {}, 1022),
+            (ascii, libyaml, 128),
+            (cjk, libyaml, 128),
+        ] {
+            for len in [limit - 1, limit, limit + 1] {
+                let key = key(len);
+                let output = emit_key(&key, true, configure);
+                assert_eq!(output.starts_with("? "), len > limit, "{len}: {output}");
+            }
+        }
+
+        // A key that is only implicit when quoted is measured in the single
+        // quotes it is written in.
+        for len in [1021, 1022, 1023] {
+            let output = emit_key(&ascii(len), false, |_| {});
+            assert_eq!(output.starts_with("? "), len > 1022, "{len}: {output}");
+        }
+    }
+
+    #[test]
+    fn empty_documents_one_event_at_a_time() {
+        for input in [
+            "---\n",
+            "--- \n...\n",
+            "---\n---\n",
+            "---\n...\n---\n...\n",
+            "a\n---\n",
+            "---\n--- b\n---\n",
+            "%YAML 1.1\n---\n...\n",
+        ] {
+            let events = crate::parse_events(input).unwrap();
+            let mut output = Vec::new();
+            let mut emitter = Emitter::new();
+            emitter.set_output(&mut output);
+            for event in events {
+                // Only a DOCUMENT-START waits for the next event.
+                let waits = matches!(event.data, EventData::DocumentStart { .. });
+                emitter.emit(event).unwrap();
+                assert_eq!(emitter.queued_events(), usize::from(waits), "{input:?}");
+            }
+            drop(emitter);
+            let output = String::from_utf8(output).unwrap();
+            assert_eq!(content_events(&output), content_events(input), "{output:?}");
+        }
+
+        // A document without a root node is rejected as soon as it ends.
+        let mut output = Vec::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output(&mut output);
+        emitter.emit(Event::stream_start(Encoding::Utf8)).unwrap();
+        emitter
+            .emit(Event::document_start(None, &[], false))
+            .unwrap();
+        let err = emitter.emit(Event::document_end(true)).unwrap_err();
+        assert_eq!(
+            err.problem(),
+            "expected SCALAR, SEQUENCE-START, MAPPING-START, or ALIAS"
+        );
+        assert_eq!(emitter.queued_events(), 0);
+    }
+
+    #[test]
+    fn wrapped_flow_collections_in_block_mappings() {
+        let cases = [
+            (
+                "a: [aaaa, bbbb, cccc, dddd, eeee, ffff, gggg, hhhh]\nb: c\n",
+                "a: [aaaa, bbbb, cccc,\n  dddd, eeee, ffff, gggg,\n  hhhh]\nb: c\n",
+            ),
+            (
+                "x:\n  a: [aaaa, bbbb, cccc, dddd, eeee, ffff, gggg, hhhh]\n  b: c\nz: y\n",
+                "x:\n  a: [aaaa, bbbb, cccc,\n    dddd, eeee, ffff,\n    gggg, hhhh]\n  b: c\nz: y\n",
+            ),
+            (
+                "x:\n  y:\n    a: {aaaa: bbbb, cccc: dddd, eeee: ffff, gggg: hhhh}\n    b: c\n  d: e\n",
+                "x:\n  y:\n    a: {aaaa: bbbb, cccc: dddd,\n      eeee: ffff, gggg: hhhh}\n    b: c\n  d: e\n",
+            ),
+            (
+                "- a: [aaaa, [bbbb, cccc], {dddd: eeee}, ffff, gggg, hhhh]\n  b: c\n- d\n",
+                "- a: [aaaa, [bbbb, cccc],\n    {dddd: eeee}, ffff,\n    gggg, hhhh]\n  b: c\n- d\n",
+            ),
+            (
+                "a: [aaaa, bbbb, cccc, dddd, eeee, ffff, gggg, hhhh]\n? [aaaa, bbbb, cccc, dddd, eeee, ffff, gggg, hhhh]\n: c\n",
+                "a: [aaaa, bbbb, cccc,\n  dddd, eeee, ffff, gggg,\n  hhhh]\n? [aaaa, bbbb, cccc, dddd,\n  eeee, ffff, gggg, hhhh]\n: c\n",
+            ),
+        ];
+        for (input, expected) in cases {
+            let output = dump(load(input), |emitter| emitter.set_width(20));
+            assert_eq!(output, expected, "{input:?}");
+            for width in [10, 15, 20, 30, -1] {
+                for indent in [2, 4] {
+                    let output = dump(load(input), |emitter| {
+                        emitter.set_width(width);
+                        emitter.set_indent(indent);
+                    });
+                    assert!(load(&output).deep_eq(&load(input)), "{output}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn output_filter() {
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&chunks);
+        let output = dump(load("a: &x [b, !!str c]\nd: *x\n"), |emitter| {
+            emitter.set_output_filter(Box::new(move |chunk| {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((chunk.role, String::from(chunk.text)));
+                (chunk.role == OutputRole::Indicator).then(|| chunk.text.to_uppercase())
+            }));
+        });
+        assert_eq!(output, "a: &x [b, !!str c]\nd: *x\n");
+        let chunks = chunks.lock().unwrap();
+        let chunks: Vec<_> = chunks.iter().map(|(role, text)| (*role, &**text)).collect();
+        assert_eq!(
+            chunks,
+            [
+                (OutputRole::ScalarContent, "a"),
+                (OutputRole::Indicator, ":"),
+                (OutputRole::Indent, " "),
+                (OutputRole::Indicator, "&"),
+                (OutputRole::AnchorContent, "x"),
+                (OutputRole::Indent, " "),
+                (OutputRole::Indicator, "["),
+                (OutputRole::ScalarContent, "b"),
+                (OutputRole::Indicator, ","),
+                (OutputRole::Indent, " "),
+                (OutputRole::TagContent, "!!str"),
+                (OutputRole::Indent, " "),
+                (OutputRole::ScalarContent, "c"),
+                (OutputRole::Indicator, "]"),
+                (OutputRole::Break, "\n"),
+                (OutputRole::ScalarContent, "d"),
+                (OutputRole::Indicator, ":"),
+                (OutputRole::Indent, " "),
+                (OutputRole::Indicator, "*"),
+                (OutputRole::AnchorContent, "x"),
+                (OutputRole::Break, "\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn output_filter_colors() {
+        let input = "\
+server:
+  host: localhost
+  ports: [80, 443]
+  banner: 'Hello, world'
+  motd: |
+    Welcome
+    back
+";
+        let output = dump(load(input), |emitter| {
+            emitter.set_width(-1);
+            emitter.set_output_filter(Box::new(|chunk| {
+                (chunk.role == OutputRole::ScalarContent)
+                    .then(|| alloc::format!("\x1b[32m{}\x1b[0m", chunk.text))
+            }));
+        });
+        assert_eq!(
+            output,
+            "\
+\x1b[32mserver\x1b[0m:
+  \x1b[32mhost\x1b[0m: \x1b[32mlocalhost\x1b[0m
+  \x1b[32mports\x1b[0m: [\x1b[32m80\x1b[0m, \x1b[32m443\x1b[0m]
+  \x1b[32mbanner\x1b[0m: '\x1b[32mHello, world\x1b[0m'
+  \x1b[32mmotd\x1b[0m: |
+    \x1b[32mWelcome\x1b[0m
+    \x1b[32mback\x1b[0m
+"
+        );
+        let stripped = output.replace("\x1b[32m", "").replace("\x1b[0m", "");
+        assert_eq!(content_events(&stripped), content_events(input));
+    }
+
+    #[test]
+    fn output_filter_columns() {
+        // The lines are wrapped at the width of the replaced scalars.
+        let output = dump(load("[a, b, c, d, e]\n"), |emitter| {
+            emitter.set_width(20);
+            emitter.set_output_filter(Box::new(|chunk| {
+                (chunk.role == OutputRole::ScalarContent).then(|| chunk.text.repeat(5))
+            }));
+        });
+        assert_eq!(output, "[aaaaa, bbbbb, ccccc,\n  ddddd, eeeee]\n");
+    }
+
+    #[test]
+    fn compact_nested_sequences() {
+        let input = "\
+- - 1
+  - 2
+  - 3
+- - 4
+  - 5
+  - 6
+- - 7
+  - - 8
+    - 9
+  - a: b
+";
+        let document = load(input);
+        let compact = dump(document.clone(), |_| {});
+        assert_eq!(compact, input);
+        let expanded = dump(document, |emitter| {
+            emitter.set_compact_nested_sequences(false);
+        });
+        assert_eq!(
+            expanded,
+            "\
+-
+  - 1
+  - 2
+  - 3
+-
+  - 4
+  - 5
+  - 6
+-
+  - 7
+  -
+    - 8
+    - 9
+  - a: b
+"
+        );
+        for output in [&compact, &expanded] {
+            assert_eq!(content_events(output), content_events(input), "{output}");
+        }
+
+        // Empty and flow sequences stay on the line of the `-`.
+        let output = dump(load("- []\n- [a]\n"), |emitter| {
+            emitter.set_compact_nested_sequences(false);
+        });
+        assert_eq!(output, "- []\n- [a]\n");
+    }
+
+    #[test]
+    fn indentation_levels() {
+        let input = "\
+root:
+  list:
+  - name: a
+    tags:
+    - x
+    - y
+  map:
+    k: v
+";
+        let golden = [
+            (
+                2,
+                "\
+root:
+  list:
+    - name: a
+      tags:
+        - x
+        - y
+  map:
+    k: v
+",
+            ),
+            (
+                4,
+                "\
+root:
+    list:
+        -   name: a
+            tags:
+                - x
+                - y
+    map:
+        k: v
+",
+            ),
+            (
+                8,
+                "\
+root:
+        list:
+                -       name: a
+                        tags:
+                                - x
+                                - y
+        map:
+                k: v
+",
+            ),
+        ];
+        for (indent, expected) in golden {
+            let output = dump(load(input), |emitter| {
+                emitter.set_indent(indent);
+                emitter.set_indent_sequences(true);
+            });
+            assert_eq!(output, expected, "indent {indent}");
+            assert_eq!(content_events(&output), content_events(input));
+        }
+
+        // Without sequence indentation, only the sequence under `list` keeps
+        // the column of its key.
+        let output = dump(load(input), |emitter| emitter.set_indent(4));
+        assert_eq!(
+            output,
+            "\
+root:
+    list:
+    -   name: a
+        tags:
+        - x
+        - y
+    map:
+        k: v
+"
+        );
+    }
+
+    #[test]
+    fn base_indent() {
+        let input = "\
+name: example
+list:
+- a
+- nested:
+    deep: value
+text: |2
+   leading space
+  second
+long: one two three four five six seven eight nine ten eleven twelve
+flow: [x, y]
+";
+        // The events of the nodes in a stream of one document, without the
+        // wrapping mappings of the first `depth` keys.
+        let nodes = |input: &str, depth: usize| {
+            let events = content_events(input);
+            events[2 + 2 * depth..events.len() - 2 - depth].to_vec()
+        };
+        for (base, wrapper) in [(0, ""), (2, "outer:\n"), (6, "a:\n  b:\n    c:\n")] {
+            let output = dump(load(input), |emitter| {
+                emitter.set_base_indent(base);
+                emitter.set_width(40);
+            });
+            for line in output.lines().filter(|line| !line.is_empty()) {
+                assert!(line.starts_with(&" ".repeat(base)), "{line:?}");
+                // Lines are wrapped at the first space beyond the width.
+                let last_space = line.trim_end().rfind(' ').unwrap_or(0);
+                assert!(last_space <= 40, "{line:?}");
+            }
+            let pasted = alloc::format!("{wrapper}{output}");
+            assert_eq!(nodes(&pasted, base / 2), nodes(input, 0), "{pasted}");
+
+            // The indentation hint of a root block scalar is relative to the
+            // key.
+            let scalar = "--- |2\n   leading space\n  second\n";
+            let output = load(scalar).to_string_indented(base).unwrap();
+            let pasted = alloc::format!("{wrapper}{output}");
+            assert_eq!(nodes(&pasted, base / 2), nodes(scalar, 0), "{pasted}");
+        }
+        assert_eq!(
+            dump(load(input), |emitter| emitter.set_base_indent(2))
+                .lines()
+                .nth(5),
+            Some("  text: |2")
+        );
     }
 }
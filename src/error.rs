@@ -1,7 +1,11 @@
 pub type Result<T, E = Error> = core::result::Result<T, E>;
 
 /// The pointer position.
-#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+///
+/// Marks are ordered by their index, so `min()` and `max()` of [`Ord`] give
+/// the earlier and the later of two marks in the same input.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub struct Mark {
     /// The position index.
@@ -12,9 +16,79 @@ pub struct Mark {
     pub column: u64,
 }
 
+impl Mark {
+    /// Is this the zero mark, which is also used for values that do not come
+    /// from an input, like nodes added to a document?
+    pub fn is_zero(&self) -> bool {
+        *self == Mark::default()
+    }
+
+    /// The mark moved by `base`, for a mark in a slice of an input that
+    /// starts at `base`.
+    pub(crate) fn offset_by(self, base: Mark) -> Mark {
+        Mark {
+            index: self.index + base.index,
+            line: self.line + base.line,
+            column: if self.line == 0 {
+                self.column + base.column
+            } else {
+                self.column
+            },
+        }
+    }
+}
+
+/// Displays the mark as `line:column`, counting both from 1.
 impl std::fmt::Display for Mark {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "line {} column {}", self.line, self.column)
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+/// A range of the input, from `start` up to `end`.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    /// The beginning of the range.
+    pub start: Mark,
+    /// The end of the range, which is not part of it.
+    pub end: Mark,
+}
+
+impl Span {
+    /// Create a span from `start` up to `end`.
+    pub fn new(start: Mark, end: Mark) -> Span {
+        Span { start, end }
+    }
+
+    /// The smallest span that contains both spans.
+    #[must_use]
+    pub fn union(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    /// Is `mark` in the span? The end of the span is not.
+    pub fn contains(&self, mark: Mark) -> bool {
+        self.start.index <= mark.index && mark.index < self.end.index
+    }
+}
+
+/// Displays the span as `line:column-line:column`, counting from 1.
+impl std::fmt::Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// Displays a mark in error messages, as `line 0 column 3`.
+struct ErrorMark(Mark);
+
+impl std::fmt::Display for ErrorMark {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {} column {}", self.0.line, self.0.column)
     }
 }
 
@@ -25,6 +99,9 @@ struct Problem {
     pub problem_mark: Mark,
     pub context: &'static str,
     pub context_mark: Mark,
+    pub detail: Option<String>,
+    pub suggestion: Option<String>,
+    pub document: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -39,6 +116,20 @@ enum ErrorImpl {
     Composer(Problem),
     Emitter(&'static str),
     Io(std::io::Error),
+    Writer(core::fmt::Error),
+    Sink {
+        index: usize,
+        error: Error,
+    },
+    Builder {
+        problem: &'static str,
+        detail: Option<String>,
+    },
+    EventStream {
+        problem: &'static str,
+        index: usize,
+    },
+    Interrupted(Mark),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -49,6 +140,11 @@ pub enum ErrorKind {
     Composer,
     Emitter,
     Io,
+    Writer,
+    Sink,
+    Builder,
+    EventStream,
+    Interrupted,
 }
 
 #[derive(Debug)]
@@ -60,6 +156,12 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<core::fmt::Error> for Error {
+    fn from(value: core::fmt::Error) -> Self {
+        Self(Box::new(ErrorImpl::Writer(value)))
+    }
+}
+
 impl Error {
     pub(crate) fn reader(problem: &'static str, offset: usize, value: i32) -> Self {
         Self(Box::new(ErrorImpl::Reader {
@@ -80,6 +182,9 @@ impl Error {
             problem_mark,
             context,
             context_mark,
+            detail: None,
+            suggestion: None,
+            document: None,
         })))
     }
 
@@ -94,6 +199,9 @@ impl Error {
             problem_mark,
             context,
             context_mark,
+            detail: None,
+            suggestion: None,
+            document: None,
         })))
     }
 
@@ -108,13 +216,86 @@ impl Error {
             problem_mark,
             context,
             context_mark,
+            detail: None,
+            suggestion: None,
+            document: None,
         })))
     }
 
+    /// Add details about what was being processed when the error occurred,
+    /// like the properties of the node being parsed.
+    pub(crate) fn with_detail(mut self, detail: String) -> Self {
+        if let ErrorImpl::Scanner(ref mut p)
+        | ErrorImpl::Parser(ref mut p)
+        | ErrorImpl::Composer(ref mut p) = *self.0
+        {
+            p.detail = Some(detail);
+        }
+        self
+    }
+
+    /// Add a suggestion for what was meant, which the context introduces.
+    pub(crate) fn with_suggestion(mut self, suggestion: String) -> Self {
+        if let ErrorImpl::Scanner(ref mut p)
+        | ErrorImpl::Parser(ref mut p)
+        | ErrorImpl::Composer(ref mut p) = *self.0
+        {
+            p.suggestion = Some(suggestion);
+        }
+        self
+    }
+
+    /// Move the marks of an error in a slice of an input that starts at
+    /// `base`, and record the index of the document that was loaded from the
+    /// slice.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn in_document(self, index: usize, base: Mark) -> Self {
+        let mut error = self.map_marks(|mark| mark.offset_by(base));
+        match *error.0 {
+            ErrorImpl::Scanner(ref mut p)
+            | ErrorImpl::Parser(ref mut p)
+            | ErrorImpl::Composer(ref mut p) => p.document = Some(index),
+            ErrorImpl::Reader { ref mut offset, .. } => *offset += base.index as usize,
+            _ => {}
+        }
+        error
+    }
+
+    /// Replace the marks of the error with `f(mark)`.
+    pub(crate) fn map_marks(mut self, f: impl Fn(Mark) -> Mark) -> Self {
+        match *self.0 {
+            ErrorImpl::Scanner(ref mut p)
+            | ErrorImpl::Parser(ref mut p)
+            | ErrorImpl::Composer(ref mut p) => {
+                p.problem_mark = f(p.problem_mark);
+                p.context_mark = f(p.context_mark);
+            }
+            ErrorImpl::Interrupted(ref mut mark) => *mark = f(*mark),
+            _ => {}
+        }
+        self
+    }
+
     pub(crate) fn emitter(problem: &'static str) -> Self {
         Self(Box::new(ErrorImpl::Emitter(problem)))
     }
 
+    pub(crate) fn sink(index: usize, error: Error) -> Self {
+        Self(Box::new(ErrorImpl::Sink { index, error }))
+    }
+
+    pub(crate) fn builder(problem: &'static str, detail: Option<String>) -> Self {
+        Self(Box::new(ErrorImpl::Builder { problem, detail }))
+    }
+
+    pub(crate) fn event_stream(problem: &'static str, index: usize) -> Self {
+        Self(Box::new(ErrorImpl::EventStream { problem, index }))
+    }
+
+    pub(crate) fn interrupted(mark: Mark) -> Self {
+        Self(Box::new(ErrorImpl::Interrupted(mark)))
+    }
+
     pub fn kind(&self) -> ErrorKind {
         match &*self.0 {
             ErrorImpl::Reader { .. } => ErrorKind::Reader,
@@ -123,21 +304,40 @@ impl Error {
             ErrorImpl::Composer(_) => ErrorKind::Composer,
             ErrorImpl::Emitter(_) => ErrorKind::Emitter,
             ErrorImpl::Io(_) => ErrorKind::Io,
+            ErrorImpl::Writer(_) => ErrorKind::Writer,
+            ErrorImpl::Sink { .. } => ErrorKind::Sink,
+            ErrorImpl::Builder { .. } => ErrorKind::Builder,
+            ErrorImpl::EventStream { .. } => ErrorKind::EventStream,
+            ErrorImpl::Interrupted(_) => ErrorKind::Interrupted,
         }
     }
 
     pub fn problem_mark(&self) -> Option<Mark> {
         match &*self.0 {
-            ErrorImpl::Reader { .. } | ErrorImpl::Emitter(_) | ErrorImpl::Io(_) => None,
+            ErrorImpl::Reader { .. }
+            | ErrorImpl::Emitter(_)
+            | ErrorImpl::Io(_)
+            | ErrorImpl::Writer(_)
+            | ErrorImpl::Sink { .. }
+            | ErrorImpl::Builder { .. }
+            | ErrorImpl::EventStream { .. } => None,
             ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
                 Some(p.problem_mark)
             }
+            ErrorImpl::Interrupted(mark) => Some(*mark),
         }
     }
 
     pub fn context_mark(&self) -> Option<Mark> {
         match &*self.0 {
-            ErrorImpl::Reader { .. } | ErrorImpl::Emitter(..) | ErrorImpl::Io(_) => None,
+            ErrorImpl::Reader { .. }
+            | ErrorImpl::Emitter(..)
+            | ErrorImpl::Io(_)
+            | ErrorImpl::Writer(_)
+            | ErrorImpl::Sink { .. }
+            | ErrorImpl::Builder { .. }
+            | ErrorImpl::EventStream { .. }
+            | ErrorImpl::Interrupted(_) => None,
             ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
                 if p.context.is_empty() {
                     None
@@ -150,17 +350,86 @@ impl Error {
 
     pub fn problem(&self) -> &'static str {
         match &*self.0 {
-            ErrorImpl::Reader { problem, .. } | ErrorImpl::Emitter(problem) => problem,
+            ErrorImpl::Reader { problem, .. }
+            | ErrorImpl::Emitter(problem)
+            | ErrorImpl::Builder { problem, .. }
+            | ErrorImpl::EventStream { problem, .. } => problem,
             ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
                 p.problem
             }
             ErrorImpl::Io(_) => "I/O error",
+            ErrorImpl::Writer(_) => "write error",
+            ErrorImpl::Sink { .. } => "sink error",
+            ErrorImpl::Interrupted(_) => "interrupted",
+        }
+    }
+
+    /// Details about what was being processed when the error occurred, like
+    /// the anchor and tag of the node being parsed (for example
+    /// `"anchor &a and tag !e!x"`).
+    pub fn detail(&self) -> Option<&str> {
+        match &*self.0 {
+            ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
+                p.detail.as_deref()
+            }
+            ErrorImpl::Builder { ref detail, .. } => detail.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// A suggestion for what was meant, like the name of a similar anchor
+    /// for an undefined alias (for example `"&defaults"`), at
+    /// [`Error::context_mark()`].
+    pub fn suggestion(&self) -> Option<&str> {
+        match &*self.0 {
+            ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
+                p.suggestion.as_deref()
+            }
+            _ => None,
+        }
+    }
+
+    /// The index of the sink that failed, for errors returned by
+    /// [`tee_events()`](crate::tee_events). The error returned by the sink is
+    /// available through [`std::error::Error::source()`].
+    pub fn sink_index(&self) -> Option<usize> {
+        match &*self.0 {
+            ErrorImpl::Sink { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// The index of the offending event, for errors returned by
+    /// [`validate_event_stream()`](crate::validate_event_stream). It is the
+    /// length of the stream if the stream ends too early.
+    pub fn event_index(&self) -> Option<usize> {
+        match &*self.0 {
+            ErrorImpl::EventStream { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// The index of the document that failed to load, for errors returned by
+    /// `load_all_parallel()` with the `rayon` feature, counting from 0.
+    pub fn document_index(&self) -> Option<usize> {
+        match &*self.0 {
+            ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
+                p.document
+            }
+            _ => None,
         }
     }
 
     pub fn context(&self) -> Option<&'static str> {
         match &*self.0 {
-            ErrorImpl::Reader { .. } | ErrorImpl::Emitter(..) | ErrorImpl::Io(_) => None,
+            ErrorImpl::Reader { .. }
+            | ErrorImpl::Emitter(..)
+            | ErrorImpl::Io(_)
+            | ErrorImpl::Writer(_)
+            | ErrorImpl::Sink { .. }
+            | ErrorImpl::Builder { .. }
+            | ErrorImpl::EventStream { .. }
+            | ErrorImpl::Interrupted(_) => None,
             ErrorImpl::Scanner(ref p) | ErrorImpl::Parser(ref p) | ErrorImpl::Composer(ref p) => {
                 if p.context.is_empty() {
                     None
@@ -174,10 +443,11 @@ impl Error {
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        if let ErrorImpl::Io(ref err) = &*self.0 {
-            Some(err)
-        } else {
-            None
+        match &*self.0 {
+            ErrorImpl::Io(ref err) => Some(err),
+            ErrorImpl::Writer(ref err) => Some(err),
+            ErrorImpl::Sink { ref error, .. } => Some(error),
+            _ => None,
         }
     }
 }
@@ -207,6 +477,11 @@ impl core::fmt::Display for ErrorKind {
             ErrorKind::Composer => "Composer",
             ErrorKind::Emitter => "Emitter",
             ErrorKind::Io => "I/O",
+            ErrorKind::Writer => "Writer",
+            ErrorKind::Sink => "Sink",
+            ErrorKind::Builder => "Builder",
+            ErrorKind::EventStream => "Event stream",
+            ErrorKind::Interrupted => "Interrupted",
         })
     }
 }
@@ -218,13 +493,27 @@ impl core::fmt::Display for Problem {
             problem_mark,
             context,
             context_mark,
+            detail,
+            suggestion,
+            document,
         } = self;
 
-        if self.context.is_empty() {
-            write!(f, "{problem_mark}: {problem}")
-        } else {
-            write!(f, "{problem_mark}: {problem} {context} ({context_mark})")
+        write!(f, "{}: {problem}", ErrorMark(*problem_mark))?;
+        match suggestion {
+            Some(suggestion) => write!(f, "; {context} `{suggestion}`")?,
+            None if !context.is_empty() => write!(f, " {context}")?,
+            None => {}
+        }
+        if let Some(detail) = detail {
+            write!(f, " with {detail}")?;
+        }
+        if !context.is_empty() {
+            write!(f, " ({})", ErrorMark(*context_mark))?;
         }
+        if let Some(document) = document {
+            write!(f, " in document {document}")?;
+        }
+        Ok(())
     }
 }
 
@@ -242,6 +531,70 @@ impl core::fmt::Display for Error {
             }
             ErrorImpl::Emitter(problem) => write!(f, "{problem}"),
             ErrorImpl::Io(ref err) => write!(f, "{err}"),
+            ErrorImpl::Writer(ref err) => write!(f, "{err}"),
+            ErrorImpl::Sink { index, ref error } => write!(f, "sink {index} failed: {error}"),
+            ErrorImpl::Builder {
+                problem,
+                ref detail,
+            } => {
+                write!(f, "{problem}")?;
+                if let Some(detail) = detail {
+                    write!(f, " with {detail}")?;
+                }
+                Ok(())
+            }
+            ErrorImpl::EventStream { problem, index } => write!(f, "{problem} at event {index}"),
+            ErrorImpl::Interrupted(mark) => write!(f, "{}: interrupted", ErrorMark(mark)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mark, Span};
+
+    fn mark(index: u64, line: u64, column: u64) -> Mark {
+        Mark {
+            index,
+            line,
+            column,
         }
     }
+
+    #[test]
+    fn marks() {
+        assert!(Mark::default().is_zero());
+        assert!(!mark(1, 0, 1).is_zero());
+        assert_eq!(mark(0, 0, 0).to_string(), "1:1");
+        // The line break ends line 0, and the next character starts line 1.
+        assert_eq!(mark(4, 0, 4).to_string(), "1:5");
+        assert_eq!(mark(5, 1, 0).to_string(), "2:1");
+
+        let (a, b) = (mark(4, 0, 4), mark(5, 1, 0));
+        assert!(a < b);
+        assert_eq!(a.min(b), a);
+        assert_eq!(b.max(a), b);
+        let mut marks = [b, Mark::default(), a];
+        marks.sort();
+        assert_eq!(marks, [Mark::default(), a, b]);
+    }
+
+    #[test]
+    fn spans() {
+        let first = Span::new(mark(0, 0, 0), mark(3, 0, 3));
+        let second = Span::new(mark(6, 1, 1), mark(9, 1, 4));
+        let union = Span::new(mark(0, 0, 0), mark(9, 1, 4));
+        assert_eq!(first.union(second), union);
+        assert_eq!(second.union(first), union);
+        assert_eq!(first.union(first), first);
+        assert!(first.contains(mark(0, 0, 0)));
+        assert!(first.contains(mark(2, 0, 2)));
+        assert!(!first.contains(mark(3, 0, 3)));
+        assert!(union.contains(mark(4, 0, 4)));
+        assert_eq!(union.to_string(), "1:1-2:5");
+
+        let events = crate::parse_events("a: [b]\n").unwrap();
+        let span = events[4].span().union(events[6].span());
+        assert_eq!(span.to_string(), "1:4-1:7");
+    }
 }
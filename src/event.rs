@@ -1,9 +1,11 @@
+use crate::schema::{format_float, is_ambiguous_plain};
 use crate::{
-    Encoding, MappingStyle, Mark, ScalarStyle, SequenceStyle, TagDirective, VersionDirective,
+    Encoding, MappingStyle, Mark, Presentation, ScalarStyle, SequenceStyle, Span, TagDirective,
+    VersionDirective, BOOL_TAG, FLOAT_TAG, INT_TAG, NULL_TAG, STR_TAG,
 };
 
 /// The event structure.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[non_exhaustive]
 pub struct Event {
     /// The event data.
@@ -14,7 +16,7 @@ pub struct Event {
     pub end_mark: Mark,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum EventData {
     /// The stream parameters (for YAML_STREAM_START_EVENT).
     StreamStart {
@@ -44,7 +46,8 @@ pub enum EventData {
     Scalar {
         /// The anchor.
         anchor: Option<String>,
-        /// The tag.
+        /// The tag, with any `%` escapes decoded, like `!my!tag` for
+        /// `!my%21tag`. The emitter escapes the characters that need it.
         tag: Option<String>,
         /// The scalar value.
         value: String,
@@ -54,14 +57,19 @@ pub enum EventData {
         quoted_implicit: bool,
         /// The scalar style.
         style: ScalarStyle,
+        /// How the scalar was written in the input.
+        source: ScalarSource,
     },
     /// The sequence parameters (for YAML_SEQUENCE_START_EVENT).
     SequenceStart {
         /// The anchor.
         anchor: Option<String>,
-        /// The tag.
+        /// The tag, with any `%` escapes decoded, like `!my!tag` for
+        /// `!my%21tag`. The emitter escapes the characters that need it.
         tag: Option<String>,
-        /// Is the tag optional?
+        /// Is the tag optional? This is false for any tag, including the
+        /// non-specific tag `!`, which is kept as the tag `!` and resolved to
+        /// `tag:yaml.org,2002:seq` by [`Document::load()`](crate::Document::load).
         implicit: bool,
         /// The sequence style.
         style: SequenceStyle,
@@ -71,9 +79,12 @@ pub enum EventData {
     MappingStart {
         /// The anchor.
         anchor: Option<String>,
-        /// The tag.
+        /// The tag, with any `%` escapes decoded, like `!my!tag` for
+        /// `!my%21tag`. The emitter escapes the characters that need it.
         tag: Option<String>,
-        /// Is the tag optional?
+        /// Is the tag optional? This is false for any tag, including the
+        /// non-specific tag `!`, which is kept as the tag `!` and resolved to
+        /// `tag:yaml.org,2002:map` by [`Document::load()`](crate::Document::load).
         implicit: bool,
         /// The mapping style.
         style: MappingStyle,
@@ -81,7 +92,31 @@ pub enum EventData {
     MappingEnd,
 }
 
+/// How a scalar event was written in the input, for the emitter to write it
+/// the same way. This is the default for events that were not produced by a
+/// parser.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub struct ScalarSource {
+    /// The number of input lines that the scalar spans, from its first to its
+    /// last character, or 0 if unknown. With
+    /// [`Emitter::set_respect_scalar_styles()`], a scalar that was on a single
+    /// line is not wrapped.
+    ///
+    /// [`Emitter::set_respect_scalar_styles()`]: crate::Emitter::set_respect_scalar_styles
+    pub lines: u32,
+    /// The indicators of a block scalar, captured with
+    /// [`Parser::set_capture_presentation()`](crate::Parser::set_capture_presentation).
+    /// The emitter writes them where the value allows it.
+    pub presentation: Option<Presentation>,
+}
+
 impl Event {
+    /// The range of the input of the event.
+    pub fn span(&self) -> Span {
+        Span::new(self.start_mark, self.end_mark)
+    }
+
     /// Make an event from its data, setting both marks to zero.
     pub(crate) fn new(data: EventData) -> Self {
         Self {
@@ -166,9 +201,55 @@ impl Event {
             plain_implicit,
             quoted_implicit,
             style,
+            source: ScalarSource::default(),
         })
     }
 
+    /// Create a SCALAR event for an integer, with the `int` tag.
+    pub fn scalar_int(value: i64) -> Self {
+        Self::typed_scalar(INT_TAG, &value.to_string())
+    }
+
+    /// Create a SCALAR event for a float, with the `float` tag.
+    ///
+    /// The value is written in the shortest form that reads back as the same
+    /// float, like `0.1` or `1.0e300`, or as `.inf`, `-.inf`, or `.nan`. The
+    /// sign of `-0.0` is kept.
+    pub fn scalar_float(value: f64) -> Self {
+        Self::typed_scalar(FLOAT_TAG, &format_float(value))
+    }
+
+    /// Create a SCALAR event for a boolean, with the `bool` tag.
+    pub fn scalar_bool(value: bool) -> Self {
+        Self::typed_scalar(BOOL_TAG, if value { "true" } else { "false" })
+    }
+
+    /// Create a SCALAR event for `null`, with the `null` tag.
+    pub fn scalar_null() -> Self {
+        Self::typed_scalar(NULL_TAG, "null")
+    }
+
+    /// Create a SCALAR event for a string, with the `str` tag.
+    ///
+    /// The string is quoted if it would otherwise be read as another type,
+    /// like `1.5` or `null`, or as a YAML 1.1 boolean, like `no`.
+    pub fn scalar_str(value: &str) -> Self {
+        let plain_implicit = !is_ambiguous_plain(value);
+        Self::scalar(
+            None,
+            Some(STR_TAG),
+            value,
+            plain_implicit,
+            true,
+            ScalarStyle::Any,
+        )
+    }
+
+    /// A plain scalar that the core schema resolves to `tag`.
+    fn typed_scalar(tag: &str, value: &str) -> Self {
+        Self::scalar(None, Some(tag), value, true, false, ScalarStyle::Plain)
+    }
+
     /// Create a SEQUENCE-START event.
     ///
     /// The `style` argument may be ignored by the emitter.
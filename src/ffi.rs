@@ -0,0 +1,1538 @@
+//! A C API with the names of the libyaml functions, with the `ffi` feature.
+//!
+//! Parsers, emitters, and events are opaque handles allocated by the
+//! `*_initialize()` functions, instead of structures allocated by the caller,
+//! and are freed by the matching `*_delete()` functions. Like in libyaml,
+//! functions return 1 on success and 0 on failure, and the error of a parser
+//! or an emitter can be retrieved after a failure.
+//!
+//! The C header `include/libyaml_safer.h` is generated with cbindgen:
+//!
+//! ```sh
+//! cbindgen --config cbindgen.toml --output include/libyaml_safer.h
+//! ```
+//!
+//! To link the functions into a C program, build a static library with
+//! `cargo rustc --lib --release --features ffi --crate-type staticlib`.
+
+// Only this module uses unsafe code, to dereference the pointers passed by C
+// callers.
+#![allow(unsafe_code)]
+#![allow(non_camel_case_types)]
+
+use core::ffi::{c_char, c_int, CStr};
+use core::ptr;
+use std::ffi::CString;
+use std::io::Write;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::{
+    Emitter, Encoding, Error, ErrorKind, Event, EventData, MappingStyle, Mark, Parser,
+    ScalarSource, ScalarStyle, SequenceStyle, TagDirective, VersionDirective,
+};
+
+/// The character type of strings, which are UTF-8.
+pub type yaml_char_t = u8;
+
+/// The error types, as in libyaml.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum yaml_error_type_t {
+    YAML_NO_ERROR = 0,
+    YAML_MEMORY_ERROR = 1,
+    YAML_READER_ERROR = 2,
+    YAML_SCANNER_ERROR = 3,
+    YAML_PARSER_ERROR = 4,
+    YAML_COMPOSER_ERROR = 5,
+    YAML_WRITER_ERROR = 6,
+    YAML_EMITTER_ERROR = 7,
+}
+
+/// The event types, as in libyaml.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum yaml_event_type_t {
+    YAML_NO_EVENT = 0,
+    YAML_STREAM_START_EVENT = 1,
+    YAML_STREAM_END_EVENT = 2,
+    YAML_DOCUMENT_START_EVENT = 3,
+    YAML_DOCUMENT_END_EVENT = 4,
+    YAML_ALIAS_EVENT = 5,
+    YAML_SCALAR_EVENT = 6,
+    YAML_SEQUENCE_START_EVENT = 7,
+    YAML_SEQUENCE_END_EVENT = 8,
+    YAML_MAPPING_START_EVENT = 9,
+    YAML_MAPPING_END_EVENT = 10,
+}
+
+/// The stream encodings, as in libyaml.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum yaml_encoding_t {
+    YAML_ANY_ENCODING = 0,
+    YAML_UTF8_ENCODING = 1,
+    YAML_UTF16LE_ENCODING = 2,
+    YAML_UTF16BE_ENCODING = 3,
+}
+
+/// The scalar styles, as in libyaml.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum yaml_scalar_style_t {
+    YAML_ANY_SCALAR_STYLE = 0,
+    YAML_PLAIN_SCALAR_STYLE = 1,
+    YAML_SINGLE_QUOTED_SCALAR_STYLE = 2,
+    YAML_DOUBLE_QUOTED_SCALAR_STYLE = 3,
+    YAML_LITERAL_SCALAR_STYLE = 4,
+    YAML_FOLDED_SCALAR_STYLE = 5,
+}
+
+/// The sequence styles, as in libyaml.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum yaml_sequence_style_t {
+    YAML_ANY_SEQUENCE_STYLE = 0,
+    YAML_BLOCK_SEQUENCE_STYLE = 1,
+    YAML_FLOW_SEQUENCE_STYLE = 2,
+}
+
+/// The mapping styles, as in libyaml.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum yaml_mapping_style_t {
+    YAML_ANY_MAPPING_STYLE = 0,
+    YAML_BLOCK_MAPPING_STYLE = 1,
+    YAML_FLOW_MAPPING_STYLE = 2,
+}
+
+/// A position in the input, as in libyaml.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct yaml_mark_t {
+    /// The position index, in bytes for UTF-8 input.
+    pub index: usize,
+    /// The position line, starting from 0.
+    pub line: usize,
+    /// The position column, starting from 0.
+    pub column: usize,
+}
+
+/// The version directive data, as in libyaml.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct yaml_version_directive_t {
+    /// The major version number.
+    pub major: c_int,
+    /// The minor version number.
+    pub minor: c_int,
+}
+
+/// The tag directive data, as in libyaml.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct yaml_tag_directive_t {
+    /// The tag handle, NUL-terminated.
+    pub handle: *const yaml_char_t,
+    /// The tag prefix, NUL-terminated.
+    pub prefix: *const yaml_char_t,
+}
+
+/// An event, created by [`yaml_parser_parse()`] or by one of the
+/// `yaml_*_event_initialize()` functions.
+pub struct yaml_event_t {
+    event: Event,
+}
+
+/// The last error of a parser or an emitter, with its strings for C callers.
+#[derive(Default)]
+struct LastError {
+    error: Option<Error>,
+    problem: Option<CString>,
+    context: Option<CString>,
+}
+
+impl LastError {
+    fn set(&mut self, error: Error) {
+        self.problem = CString::new(error.problem()).ok();
+        self.context = error
+            .context()
+            .and_then(|context| CString::new(context).ok());
+        self.error = Some(error);
+    }
+
+    fn problem(&self) -> *const c_char {
+        self.problem
+            .as_ref()
+            .map_or(ptr::null(), |problem| problem.as_ptr())
+    }
+
+    fn context(&self) -> *const c_char {
+        self.context
+            .as_ref()
+            .map_or(ptr::null(), |context| context.as_ptr())
+    }
+}
+
+/// A parser, created by [`yaml_parser_initialize()`].
+pub struct yaml_parser_t {
+    parser: Parser<'static>,
+    /// The input and the slice the parser reads from, owned by the parser and
+    /// freed after it.
+    input: Option<(*mut [u8], *mut &'static [u8])>,
+    error: LastError,
+}
+
+impl yaml_parser_t {
+    fn free_input(&mut self) {
+        // The parser refers to the input, so it goes first. Its settings are
+        // kept.
+        self.parser.clear_input();
+        if let Some((bytes, slice)) = self.input.take() {
+            // SAFETY: Both were leaked from boxes by
+            // `yaml_parser_set_input_string()`, and nothing refers to them
+            // anymore.
+            unsafe {
+                drop(Box::from_raw(slice));
+                drop(Box::from_raw(bytes));
+            }
+        }
+    }
+}
+
+impl Drop for yaml_parser_t {
+    fn drop(&mut self) {
+        self.free_input();
+    }
+}
+
+/// The output of an emitter, in a buffer of the caller.
+struct OutputString {
+    output: *mut u8,
+    size: usize,
+    size_written: *mut usize,
+}
+
+impl Write for OutputString {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // SAFETY: `yaml_emitter_set_output_string()` requires the pointers to
+        // stay valid while the emitter is in use.
+        unsafe {
+            let written = *self.size_written;
+            if buf.len() > self.size - written {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
+            ptr::copy_nonoverlapping(buf.as_ptr(), self.output.add(written), buf.len());
+            *self.size_written = written + buf.len();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An emitter, created by [`yaml_emitter_initialize()`].
+pub struct yaml_emitter_t {
+    emitter: Emitter<'static>,
+    /// The output, owned by the emitter and freed after it.
+    output: Option<*mut OutputString>,
+    error: LastError,
+}
+
+impl yaml_emitter_t {
+    fn free_output(&mut self) {
+        // The emitter refers to the output, so it goes first. Its settings
+        // are kept.
+        self.emitter.clear_output();
+        if let Some(output) = self.output.take() {
+            // SAFETY: It was leaked from a box by
+            // `yaml_emitter_set_output_string()`, and nothing refers to it
+            // anymore.
+            unsafe { drop(Box::from_raw(output)) };
+        }
+    }
+}
+
+impl Drop for yaml_emitter_t {
+    fn drop(&mut self) {
+        self.free_output();
+    }
+}
+
+/// Run `f`, and return 0 if it panics, as panics must not unwind into C.
+fn guard(f: impl FnOnce() -> c_int) -> c_int {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(0)
+}
+
+fn mark(mark: Mark) -> yaml_mark_t {
+    yaml_mark_t {
+        index: usize::try_from(mark.index).unwrap_or(usize::MAX),
+        line: usize::try_from(mark.line).unwrap_or(usize::MAX),
+        column: usize::try_from(mark.column).unwrap_or(usize::MAX),
+    }
+}
+
+// The enums are taken as integers, because C may pass any value, and a value
+// that is not a variant would be undefined behavior in a Rust enum.
+
+fn convert_encoding(encoding: c_int) -> Option<Encoding> {
+    [
+        (yaml_encoding_t::YAML_ANY_ENCODING, Encoding::Any),
+        (yaml_encoding_t::YAML_UTF8_ENCODING, Encoding::Utf8),
+        (yaml_encoding_t::YAML_UTF16LE_ENCODING, Encoding::Utf16Le),
+        (yaml_encoding_t::YAML_UTF16BE_ENCODING, Encoding::Utf16Be),
+    ]
+    .into_iter()
+    .find_map(|(value, converted)| (value as c_int == encoding).then_some(converted))
+}
+
+fn convert_scalar_style(style: c_int) -> Option<ScalarStyle> {
+    [
+        (yaml_scalar_style_t::YAML_ANY_SCALAR_STYLE, ScalarStyle::Any),
+        (
+            yaml_scalar_style_t::YAML_PLAIN_SCALAR_STYLE,
+            ScalarStyle::Plain,
+        ),
+        (
+            yaml_scalar_style_t::YAML_SINGLE_QUOTED_SCALAR_STYLE,
+            ScalarStyle::SingleQuoted,
+        ),
+        (
+            yaml_scalar_style_t::YAML_DOUBLE_QUOTED_SCALAR_STYLE,
+            ScalarStyle::DoubleQuoted,
+        ),
+        (
+            yaml_scalar_style_t::YAML_LITERAL_SCALAR_STYLE,
+            ScalarStyle::Literal,
+        ),
+        (
+            yaml_scalar_style_t::YAML_FOLDED_SCALAR_STYLE,
+            ScalarStyle::Folded,
+        ),
+    ]
+    .into_iter()
+    .find_map(|(value, converted)| (value as c_int == style).then_some(converted))
+}
+
+fn convert_sequence_style(style: c_int) -> Option<SequenceStyle> {
+    [
+        (
+            yaml_sequence_style_t::YAML_ANY_SEQUENCE_STYLE,
+            SequenceStyle::Any,
+        ),
+        (
+            yaml_sequence_style_t::YAML_BLOCK_SEQUENCE_STYLE,
+            SequenceStyle::Block,
+        ),
+        (
+            yaml_sequence_style_t::YAML_FLOW_SEQUENCE_STYLE,
+            SequenceStyle::Flow,
+        ),
+    ]
+    .into_iter()
+    .find_map(|(value, converted)| (value as c_int == style).then_some(converted))
+}
+
+fn convert_mapping_style(style: c_int) -> Option<MappingStyle> {
+    [
+        (
+            yaml_mapping_style_t::YAML_ANY_MAPPING_STYLE,
+            MappingStyle::Any,
+        ),
+        (
+            yaml_mapping_style_t::YAML_BLOCK_MAPPING_STYLE,
+            MappingStyle::Block,
+        ),
+        (
+            yaml_mapping_style_t::YAML_FLOW_MAPPING_STYLE,
+            MappingStyle::Flow,
+        ),
+    ]
+    .into_iter()
+    .find_map(|(value, converted)| (value as c_int == style).then_some(converted))
+}
+
+/// Read an optional NUL-terminated UTF-8 string.
+///
+/// # Safety
+///
+/// `string` must be null or point to a NUL-terminated string.
+unsafe fn optional_string(string: *const yaml_char_t) -> Result<Option<String>, ()> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    let string = CStr::from_ptr(string.cast());
+    string
+        .to_str()
+        .map(|string| Some(string.to_owned()))
+        .map_err(drop)
+}
+
+/// Store a new event in `*event`.
+///
+/// # Safety
+///
+/// `event` must be null or valid for writes.
+unsafe fn new_event(event: *mut *mut yaml_event_t, data: EventData) -> c_int {
+    if event.is_null() {
+        return 0;
+    }
+    *event = Box::into_raw(Box::new(yaml_event_t {
+        event: Event::new(data),
+    }));
+    1
+}
+
+/// Create a parser in `*parser`, to be freed with [`yaml_parser_delete()`].
+///
+/// # Safety
+///
+/// `parser` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_initialize(parser: *mut *mut yaml_parser_t) -> c_int {
+    if parser.is_null() {
+        return 0;
+    }
+    *parser = Box::into_raw(Box::new(yaml_parser_t {
+        parser: Parser::new(),
+        input: None,
+        error: LastError::default(),
+    }));
+    1
+}
+
+/// Free a parser, and the input it holds.
+///
+/// # Safety
+///
+/// `parser` must be null or a parser from [`yaml_parser_initialize()`] that
+/// was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_delete(parser: *mut yaml_parser_t) {
+    if !parser.is_null() {
+        drop(Box::from_raw(parser));
+    }
+}
+
+/// Set a string input of `size` bytes. Unlike in libyaml, the input is copied,
+/// so it does not need to outlive the parser.
+///
+/// # Safety
+///
+/// `parser` must be a parser from [`yaml_parser_initialize()`], and `input`
+/// must be valid for reads of `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_set_input_string(
+    parser: *mut yaml_parser_t,
+    input: *const u8,
+    size: usize,
+) {
+    let Some(parser) = parser.as_mut() else {
+        return;
+    };
+    if input.is_null() && size != 0 {
+        return;
+    }
+    let bytes: Box<[u8]> = if size == 0 {
+        Box::new([])
+    } else {
+        core::slice::from_raw_parts(input, size).into()
+    };
+    parser.free_input();
+    let bytes = Box::into_raw(bytes);
+    let slice = Box::into_raw(Box::new(&*bytes));
+    parser.input = Some((bytes, slice));
+    parser.parser.set_input_string(&mut *slice);
+}
+
+/// Set the encoding of the input, a `yaml_encoding_t`. Returns 0 if the
+/// parser is null or the encoding is unknown.
+///
+/// # Safety
+///
+/// `parser` must be a parser from [`yaml_parser_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_set_encoding(
+    parser: *mut yaml_parser_t,
+    encoding: c_int,
+) -> c_int {
+    let (Some(parser), Some(encoding)) = (parser.as_mut(), convert_encoding(encoding)) else {
+        return 0;
+    };
+    parser.parser.set_encoding(encoding);
+    1
+}
+
+/// Parse the next event into `*event`, to be freed with
+/// [`yaml_event_delete()`] or passed to [`yaml_emitter_emit()`].
+///
+/// # Safety
+///
+/// `parser` must be a parser from [`yaml_parser_initialize()`], and `event`
+/// must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_parse(
+    parser: *mut yaml_parser_t,
+    event: *mut *mut yaml_event_t,
+) -> c_int {
+    let Some(parser) = parser.as_mut() else {
+        return 0;
+    };
+    if event.is_null() || parser.input.is_none() {
+        return 0;
+    }
+    guard(|| match parser.parser.parse() {
+        Ok(parsed) => {
+            *event = Box::into_raw(Box::new(yaml_event_t { event: parsed }));
+            1
+        }
+        Err(err) => {
+            parser.error.set(err);
+            0
+        }
+    })
+}
+
+/// The type of the last error of a parser.
+///
+/// # Safety
+///
+/// `parser` must be a parser from [`yaml_parser_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_error(parser: *const yaml_parser_t) -> yaml_error_type_t {
+    let Some(error) = parser
+        .as_ref()
+        .and_then(|parser| parser.error.error.as_ref())
+    else {
+        return yaml_error_type_t::YAML_NO_ERROR;
+    };
+    match error.kind() {
+        ErrorKind::Reader | ErrorKind::Io => yaml_error_type_t::YAML_READER_ERROR,
+        ErrorKind::Scanner => yaml_error_type_t::YAML_SCANNER_ERROR,
+        ErrorKind::Composer => yaml_error_type_t::YAML_COMPOSER_ERROR,
+        _ => yaml_error_type_t::YAML_PARSER_ERROR,
+    }
+}
+
+/// The problem of the last error of a parser, NUL-terminated, or null if there
+/// was no error. The string lives as long as the parser.
+///
+/// # Safety
+///
+/// `parser` must be a parser from [`yaml_parser_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_error_problem(parser: *const yaml_parser_t) -> *const c_char {
+    parser
+        .as_ref()
+        .map_or(ptr::null(), |parser| parser.error.problem())
+}
+
+/// The position of the problem of the last error of a parser, or a zero mark.
+///
+/// # Safety
+///
+/// `parser` must be a parser from [`yaml_parser_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_error_problem_mark(
+    parser: *const yaml_parser_t,
+) -> yaml_mark_t {
+    parser
+        .as_ref()
+        .and_then(|parser| parser.error.error.as_ref()?.problem_mark())
+        .map_or_else(yaml_mark_t::default, mark)
+}
+
+/// The context of the last error of a parser, NUL-terminated, or null if
+/// there is none. The string lives as long as the parser.
+///
+/// # Safety
+///
+/// `parser` must be a parser from [`yaml_parser_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_error_context(parser: *const yaml_parser_t) -> *const c_char {
+    parser
+        .as_ref()
+        .map_or(ptr::null(), |parser| parser.error.context())
+}
+
+/// The position of the context of the last error of a parser, or a zero
+/// mark.
+///
+/// # Safety
+///
+/// `parser` must be a parser from [`yaml_parser_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_parser_error_context_mark(
+    parser: *const yaml_parser_t,
+) -> yaml_mark_t {
+    parser
+        .as_ref()
+        .and_then(|parser| parser.error.error.as_ref()?.context_mark())
+        .map_or_else(yaml_mark_t::default, mark)
+}
+
+/// Create a STREAM-START event. `encoding` is a `yaml_encoding_t`; an
+/// unknown encoding is an error.
+///
+/// # Safety
+///
+/// `event` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_stream_start_event_initialize(
+    event: *mut *mut yaml_event_t,
+    encoding: c_int,
+) -> c_int {
+    let Some(encoding) = convert_encoding(encoding) else {
+        return 0;
+    };
+    new_event(event, EventData::StreamStart { encoding })
+}
+
+/// Create a STREAM-END event.
+///
+/// # Safety
+///
+/// `event` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_stream_end_event_initialize(event: *mut *mut yaml_event_t) -> c_int {
+    new_event(event, EventData::StreamEnd)
+}
+
+/// Create a DOCUMENT-START event, with the tag directives from
+/// `tag_directives_start` up to `tag_directives_end`.
+///
+/// # Safety
+///
+/// `event` must be valid for writes, `version_directive` must be null or
+/// valid for reads, and the tag directives must be a valid range of
+/// directives with NUL-terminated strings, or both null.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_document_start_event_initialize(
+    event: *mut *mut yaml_event_t,
+    version_directive: *const yaml_version_directive_t,
+    tag_directives_start: *const yaml_tag_directive_t,
+    tag_directives_end: *const yaml_tag_directive_t,
+    implicit: c_int,
+) -> c_int {
+    let version_directive = version_directive
+        .as_ref()
+        .map(|directive| VersionDirective {
+            major: directive.major,
+            minor: directive.minor,
+        });
+    let mut tag_directives = Vec::new();
+    if !tag_directives_start.is_null() {
+        let Ok(len) = usize::try_from(tag_directives_end.offset_from(tag_directives_start)) else {
+            return 0;
+        };
+        for directive in core::slice::from_raw_parts(tag_directives_start, len) {
+            let (Ok(Some(handle)), Ok(Some(prefix))) = (
+                optional_string(directive.handle),
+                optional_string(directive.prefix),
+            ) else {
+                return 0;
+            };
+            tag_directives.push(TagDirective { handle, prefix });
+        }
+    }
+    new_event(
+        event,
+        EventData::DocumentStart {
+            version_directive,
+            tag_directives,
+            implicit: implicit != 0,
+        },
+    )
+}
+
+/// Create a DOCUMENT-END event.
+///
+/// # Safety
+///
+/// `event` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_document_end_event_initialize(
+    event: *mut *mut yaml_event_t,
+    implicit: c_int,
+) -> c_int {
+    new_event(
+        event,
+        EventData::DocumentEnd {
+            implicit: implicit != 0,
+        },
+    )
+}
+
+/// Create an ALIAS event.
+///
+/// # Safety
+///
+/// `event` must be valid for writes, and `anchor` must be a NUL-terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_alias_event_initialize(
+    event: *mut *mut yaml_event_t,
+    anchor: *const yaml_char_t,
+) -> c_int {
+    let Ok(Some(anchor)) = optional_string(anchor) else {
+        return 0;
+    };
+    new_event(event, EventData::Alias { anchor })
+}
+
+/// Create a SCALAR event. `length` is the length of `value` in bytes, or -1 if
+/// `value` is NUL-terminated. `style` is a `yaml_scalar_style_t`; an unknown
+/// style is an error.
+///
+/// # Safety
+///
+/// `event` must be valid for writes, `anchor` and `tag` must be null or
+/// NUL-terminated strings, and `value` must be valid for reads of `length`
+/// bytes, or NUL-terminated.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn yaml_scalar_event_initialize(
+    event: *mut *mut yaml_event_t,
+    anchor: *const yaml_char_t,
+    tag: *const yaml_char_t,
+    value: *const yaml_char_t,
+    length: c_int,
+    plain_implicit: c_int,
+    quoted_implicit: c_int,
+    style: c_int,
+) -> c_int {
+    let (Ok(anchor), Ok(tag)) = (optional_string(anchor), optional_string(tag)) else {
+        return 0;
+    };
+    if value.is_null() {
+        return 0;
+    }
+    let value = match usize::try_from(length) {
+        Ok(length) => core::slice::from_raw_parts(value, length),
+        Err(_) => CStr::from_ptr(value.cast()).to_bytes(),
+    };
+    let Ok(value) = core::str::from_utf8(value) else {
+        return 0;
+    };
+    let Some(style) = convert_scalar_style(style) else {
+        return 0;
+    };
+    new_event(
+        event,
+        EventData::Scalar {
+            anchor,
+            tag,
+            value: value.to_owned(),
+            plain_implicit: plain_implicit != 0,
+            quoted_implicit: quoted_implicit != 0,
+            style,
+            source: ScalarSource::default(),
+        },
+    )
+}
+
+/// Create a SEQUENCE-START event. `style` is a `yaml_sequence_style_t`; an
+/// unknown style is an error.
+///
+/// # Safety
+///
+/// `event` must be valid for writes, and `anchor` and `tag` must be null or
+/// NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_sequence_start_event_initialize(
+    event: *mut *mut yaml_event_t,
+    anchor: *const yaml_char_t,
+    tag: *const yaml_char_t,
+    implicit: c_int,
+    style: c_int,
+) -> c_int {
+    let (Ok(anchor), Ok(tag), Some(style)) = (
+        optional_string(anchor),
+        optional_string(tag),
+        convert_sequence_style(style),
+    ) else {
+        return 0;
+    };
+    new_event(
+        event,
+        EventData::SequenceStart {
+            anchor,
+            tag,
+            implicit: implicit != 0,
+            style,
+        },
+    )
+}
+
+/// Create a SEQUENCE-END event.
+///
+/// # Safety
+///
+/// `event` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_sequence_end_event_initialize(
+    event: *mut *mut yaml_event_t,
+) -> c_int {
+    new_event(event, EventData::SequenceEnd)
+}
+
+/// Create a MAPPING-START event. `style` is a `yaml_mapping_style_t`; an
+/// unknown style is an error.
+///
+/// # Safety
+///
+/// `event` must be valid for writes, and `anchor` and `tag` must be null or
+/// NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_mapping_start_event_initialize(
+    event: *mut *mut yaml_event_t,
+    anchor: *const yaml_char_t,
+    tag: *const yaml_char_t,
+    implicit: c_int,
+    style: c_int,
+) -> c_int {
+    let (Ok(anchor), Ok(tag), Some(style)) = (
+        optional_string(anchor),
+        optional_string(tag),
+        convert_mapping_style(style),
+    ) else {
+        return 0;
+    };
+    new_event(
+        event,
+        EventData::MappingStart {
+            anchor,
+            tag,
+            implicit: implicit != 0,
+            style,
+        },
+    )
+}
+
+/// Create a MAPPING-END event.
+///
+/// # Safety
+///
+/// `event` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_mapping_end_event_initialize(event: *mut *mut yaml_event_t) -> c_int {
+    new_event(event, EventData::MappingEnd)
+}
+
+/// Free an event.
+///
+/// # Safety
+///
+/// `event` must be null or an event that was not freed or emitted yet.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_event_delete(event: *mut yaml_event_t) {
+    if !event.is_null() {
+        drop(Box::from_raw(event));
+    }
+}
+
+/// The type of an event.
+///
+/// # Safety
+///
+/// `event` must be null or a valid event.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_event_get_type(event: *const yaml_event_t) -> yaml_event_type_t {
+    let Some(event) = event.as_ref() else {
+        return yaml_event_type_t::YAML_NO_EVENT;
+    };
+    match event.event.data {
+        EventData::StreamStart { .. } => yaml_event_type_t::YAML_STREAM_START_EVENT,
+        EventData::StreamEnd => yaml_event_type_t::YAML_STREAM_END_EVENT,
+        EventData::DocumentStart { .. } => yaml_event_type_t::YAML_DOCUMENT_START_EVENT,
+        EventData::DocumentEnd { .. } => yaml_event_type_t::YAML_DOCUMENT_END_EVENT,
+        EventData::Alias { .. } => yaml_event_type_t::YAML_ALIAS_EVENT,
+        EventData::Scalar { .. } => yaml_event_type_t::YAML_SCALAR_EVENT,
+        EventData::SequenceStart { .. } => yaml_event_type_t::YAML_SEQUENCE_START_EVENT,
+        EventData::SequenceEnd => yaml_event_type_t::YAML_SEQUENCE_END_EVENT,
+        EventData::MappingStart { .. } => yaml_event_type_t::YAML_MAPPING_START_EVENT,
+        EventData::MappingEnd => yaml_event_type_t::YAML_MAPPING_END_EVENT,
+    }
+}
+
+/// The start of an event in the input.
+///
+/// # Safety
+///
+/// `event` must be null or a valid event.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_event_get_start_mark(event: *const yaml_event_t) -> yaml_mark_t {
+    event
+        .as_ref()
+        .map_or_else(yaml_mark_t::default, |event| mark(event.event.start_mark))
+}
+
+/// The end of an event in the input.
+///
+/// # Safety
+///
+/// `event` must be null or a valid event.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_event_get_end_mark(event: *const yaml_event_t) -> yaml_mark_t {
+    event
+        .as_ref()
+        .map_or_else(yaml_mark_t::default, |event| mark(event.event.end_mark))
+}
+
+/// Point `*string` and `*length` to `value`, or to null and 0.
+///
+/// # Safety
+///
+/// `string` and `length` must be valid for writes.
+unsafe fn get_string(
+    value: Option<&str>,
+    string: *mut *const yaml_char_t,
+    length: *mut usize,
+) -> c_int {
+    if string.is_null() || length.is_null() {
+        return 0;
+    }
+    *string = value.map_or(ptr::null(), str::as_ptr);
+    *length = value.map_or(0, str::len);
+    c_int::from(value.is_some())
+}
+
+/// Get the anchor of an event, which is not NUL-terminated, in `*anchor` and
+/// `*length`. Returns 0 if the event has no anchor.
+///
+/// The anchor lives as long as the event.
+///
+/// # Safety
+///
+/// `event` must be a valid event, and `anchor` and `length` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_event_get_anchor(
+    event: *const yaml_event_t,
+    anchor: *mut *const yaml_char_t,
+    length: *mut usize,
+) -> c_int {
+    let value = event.as_ref().and_then(|event| match &event.event.data {
+        EventData::Alias { anchor } => Some(anchor.as_str()),
+        EventData::Scalar { anchor, .. }
+        | EventData::SequenceStart { anchor, .. }
+        | EventData::MappingStart { anchor, .. } => anchor.as_deref(),
+        _ => None,
+    });
+    get_string(value, anchor, length)
+}
+
+/// Get the tag of an event, which is not NUL-terminated, in `*tag` and
+/// `*length`. Returns 0 if the event has no tag.
+///
+/// The tag lives as long as the event.
+///
+/// # Safety
+///
+/// `event` must be a valid event, and `tag` and `length` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_event_get_tag(
+    event: *const yaml_event_t,
+    tag: *mut *const yaml_char_t,
+    length: *mut usize,
+) -> c_int {
+    let value = event.as_ref().and_then(|event| match &event.event.data {
+        EventData::Scalar { tag, .. }
+        | EventData::SequenceStart { tag, .. }
+        | EventData::MappingStart { tag, .. } => tag.as_deref(),
+        _ => None,
+    });
+    get_string(value, tag, length)
+}
+
+/// Get the value of a SCALAR event, which is not NUL-terminated, in `*value`
+/// and `*length`. Returns 0 if the event is not a SCALAR event.
+///
+/// The value lives as long as the event.
+///
+/// # Safety
+///
+/// `event` must be a valid event, and `value` and `length` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_event_get_scalar_value(
+    event: *const yaml_event_t,
+    value: *mut *const yaml_char_t,
+    length: *mut usize,
+) -> c_int {
+    let scalar = event.as_ref().and_then(|event| match &event.event.data {
+        EventData::Scalar { value, .. } => Some(value.as_str()),
+        _ => None,
+    });
+    get_string(scalar, value, length)
+}
+
+/// Create an emitter in `*emitter`, to be freed with
+/// [`yaml_emitter_delete()`].
+///
+/// # Safety
+///
+/// `emitter` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_initialize(emitter: *mut *mut yaml_emitter_t) -> c_int {
+    if emitter.is_null() {
+        return 0;
+    }
+    *emitter = Box::into_raw(Box::new(yaml_emitter_t {
+        emitter: Emitter::new(),
+        output: None,
+        error: LastError::default(),
+    }));
+    1
+}
+
+/// Free an emitter.
+///
+/// # Safety
+///
+/// `emitter` must be null or an emitter from [`yaml_emitter_initialize()`]
+/// that was not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_delete(emitter: *mut yaml_emitter_t) {
+    if !emitter.is_null() {
+        drop(Box::from_raw(emitter));
+    }
+}
+
+/// Write the output to the buffer `output` of `size` bytes, and the number of
+/// bytes written to `*size_written`. Writing beyond `size` bytes is a writer
+/// error.
+///
+/// The other settings of the emitter are kept, so they may be made before or
+/// after this. As in libyaml, the output should be set once, before emitting.
+///
+/// # Safety
+///
+/// `emitter` must be an emitter from [`yaml_emitter_initialize()`], `output`
+/// must be valid for writes of `size` bytes and `size_written` valid for
+/// writes, as long as the emitter is in use.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_set_output_string(
+    emitter: *mut yaml_emitter_t,
+    output: *mut u8,
+    size: usize,
+    size_written: *mut usize,
+) {
+    let Some(emitter) = emitter.as_mut() else {
+        return;
+    };
+    if output.is_null() || size_written.is_null() {
+        return;
+    }
+    emitter.free_output();
+    *size_written = 0;
+    let output = Box::into_raw(Box::new(OutputString {
+        output,
+        size,
+        size_written,
+    }));
+    emitter.output = Some(output);
+    emitter.emitter.set_output(&mut *output);
+}
+
+/// Emit all scalars in the canonical format of the YAML specification.
+///
+/// # Safety
+///
+/// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_set_canonical(
+    emitter: *mut yaml_emitter_t,
+    canonical: c_int,
+) {
+    if let Some(emitter) = emitter.as_mut() {
+        emitter.emitter.set_canonical(canonical != 0);
+    }
+}
+
+/// Set the indentation increment.
+///
+/// # Safety
+///
+/// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_set_indent(emitter: *mut yaml_emitter_t, indent: c_int) {
+    if let Some(emitter) = emitter.as_mut() {
+        emitter.emitter.set_indent(indent);
+    }
+}
+
+/// Set the preferred line width, or -1 for unlimited.
+///
+/// # Safety
+///
+/// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_set_width(emitter: *mut yaml_emitter_t, width: c_int) {
+    if let Some(emitter) = emitter.as_mut() {
+        emitter.emitter.set_width(width);
+    }
+}
+
+/// Allow unescaped non-ASCII characters.
+///
+/// # Safety
+///
+/// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_set_unicode(emitter: *mut yaml_emitter_t, unicode: c_int) {
+    if let Some(emitter) = emitter.as_mut() {
+        emitter.emitter.set_unicode(unicode != 0);
+    }
+}
+
+/// Emit an event. The emitter takes the event, which must not be used
+/// afterwards, even if this fails.
+///
+/// # Safety
+///
+/// `emitter` must be an emitter from [`yaml_emitter_initialize()`] with an
+/// output, and `event` must be an event that was not freed or emitted yet.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_emit(
+    emitter: *mut yaml_emitter_t,
+    event: *mut yaml_event_t,
+) -> c_int {
+    if event.is_null() {
+        return 0;
+    }
+    let event = Box::from_raw(event).event;
+    let Some(emitter) = emitter.as_mut() else {
+        return 0;
+    };
+    if emitter.output.is_none() {
+        return 0;
+    }
+    guard(|| match emitter.emitter.emit(event) {
+        Ok(()) => 1,
+        Err(err) => {
+            emitter.error.set(err);
+            0
+        }
+    })
+}
+
+/// Write the buffered output.
+///
+/// # Safety
+///
+/// `emitter` must be an emitter from [`yaml_emitter_initialize()`] with an
+/// output.
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_flush(emitter: *mut yaml_emitter_t) -> c_int {
+    let Some(emitter) = emitter.as_mut() else {
+        return 0;
+    };
+    if emitter.output.is_none() {
+        return 0;
+    }
+    guard(|| match emitter.emitter.flush() {
+        Ok(()) => 1,
+        Err(err) => {
+            emitter.error.set(err);
+            0
+        }
+    })
+}
+
+/// The type of the last error of an emitter.
+///
+/// # Safety
+///
+/// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_error(emitter: *const yaml_emitter_t) -> yaml_error_type_t {
+    let Some(error) = emitter
+        .as_ref()
+        .and_then(|emitter| emitter.error.error.as_ref())
+    else {
+        return yaml_error_type_t::YAML_NO_ERROR;
+    };
+    match error.kind() {
+        ErrorKind::Io | ErrorKind::Writer => yaml_error_type_t::YAML_WRITER_ERROR,
+        _ => yaml_error_type_t::YAML_EMITTER_ERROR,
+    }
+}
+
+/// The problem of the last error of an emitter, NUL-terminated, or null if
+/// there was no error. The string lives as long as the emitter.
+///
+/// # Safety
+///
+/// `emitter` must be an emitter from [`yaml_emitter_initialize()`].
+#[no_mangle]
+pub unsafe extern "C" fn yaml_emitter_error_problem(
+    emitter: *const yaml_emitter_t,
+) -> *const c_char {
+    emitter
+        .as_ref()
+        .map_or(ptr::null(), |emitter| emitter.error.problem())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Getter =
+        unsafe extern "C" fn(*const yaml_event_t, *mut *const yaml_char_t, *mut usize) -> c_int;
+
+    /// Parse `input` and emit its events through the C API, and return the
+    /// output.
+    fn round_trip(input: &str) -> String {
+        let mut output = vec![0; 1024];
+        let mut size_written = 0;
+        unsafe {
+            let mut parser = ptr::null_mut();
+            let mut emitter = ptr::null_mut();
+            assert_eq!(yaml_parser_initialize(&mut parser), 1);
+            assert_eq!(yaml_emitter_initialize(&mut emitter), 1);
+            yaml_parser_set_input_string(parser, input.as_ptr(), input.len());
+            yaml_emitter_set_output_string(
+                emitter,
+                output.as_mut_ptr(),
+                output.len(),
+                &mut size_written,
+            );
+            loop {
+                let mut event = ptr::null_mut();
+                assert_eq!(yaml_parser_parse(parser, &mut event), 1);
+                let done = yaml_event_get_type(event) == yaml_event_type_t::YAML_STREAM_END_EVENT;
+                assert_eq!(yaml_emitter_emit(emitter, event), 1);
+                if done {
+                    break;
+                }
+            }
+            yaml_emitter_delete(emitter);
+            yaml_parser_delete(parser);
+        }
+        output.truncate(size_written);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn parse_and_emit() {
+        for input in [
+            "",
+            "a: b\n",
+            "--- &a [x, *a, 'y', \"z\"]\n...\n--- |\n  text\n",
+            "%TAG !e! tag:example.com,2000:\n--- !e!x {? a : b}\n",
+        ] {
+            let expected = crate::emit_events(crate::parse_events(input).unwrap()).unwrap();
+            assert_eq!(round_trip(input), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn settings_before_io() {
+        unsafe {
+            let mut parser = ptr::null_mut();
+            assert_eq!(yaml_parser_initialize(&mut parser), 1);
+            yaml_parser_set_encoding(parser, yaml_encoding_t::YAML_UTF16LE_ENCODING as c_int);
+            let input: Vec<u8> = "a: 1\n".encode_utf16().flat_map(u16::to_le_bytes).collect();
+            yaml_parser_set_input_string(parser, input.as_ptr(), input.len());
+            let mut event = ptr::null_mut();
+            for _ in 0..4 {
+                yaml_event_delete(event);
+                assert_eq!(yaml_parser_parse(parser, &mut event), 1);
+            }
+            let mut string = ptr::null();
+            let mut length = 0;
+            assert_eq!(
+                yaml_event_get_scalar_value(event, &mut string, &mut length),
+                1
+            );
+            assert_eq!(core::slice::from_raw_parts(string, length), b"a");
+            yaml_event_delete(event);
+            yaml_parser_delete(parser);
+        }
+
+        let mut output = vec![0; 128];
+        let mut size_written = 0;
+        unsafe {
+            let mut emitter = ptr::null_mut();
+            assert_eq!(yaml_emitter_initialize(&mut emitter), 1);
+            yaml_emitter_set_canonical(emitter, 1);
+            yaml_emitter_set_output_string(
+                emitter,
+                output.as_mut_ptr(),
+                output.len(),
+                &mut size_written,
+            );
+            for event in crate::parse_events("a: 1\n").unwrap() {
+                let event = Box::into_raw(Box::new(yaml_event_t { event }));
+                assert_eq!(yaml_emitter_emit(emitter, event), 1);
+            }
+            yaml_emitter_delete(emitter);
+        }
+        output.truncate(size_written);
+        let canonical = crate::parse_events("a: 1\n").unwrap();
+        let mut emitter = crate::Emitter::new();
+        let mut expected = Vec::new();
+        emitter.set_canonical(true);
+        emitter.set_output(&mut expected);
+        for event in canonical {
+            emitter.emit(event).unwrap();
+        }
+        drop(emitter);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("---\n{\n"), "{output}");
+        assert_eq!(output, String::from_utf8(expected).unwrap());
+    }
+
+    #[test]
+    fn events() {
+        unsafe {
+            let mut parser = ptr::null_mut();
+            assert_eq!(yaml_parser_initialize(&mut parser), 1);
+            let input = "- &a !t x\n";
+            yaml_parser_set_input_string(parser, input.as_ptr(), input.len());
+            let mut event = ptr::null_mut();
+            for _ in 0..4 {
+                yaml_event_delete(event);
+                assert_eq!(yaml_parser_parse(parser, &mut event), 1);
+            }
+            assert_eq!(
+                yaml_event_get_type(event),
+                yaml_event_type_t::YAML_SCALAR_EVENT
+            );
+            let mut string = ptr::null();
+            let mut length = 0;
+            let mut get = |get: Getter| {
+                if get(event, &mut string, &mut length) == 0 {
+                    return None;
+                }
+                Some(core::slice::from_raw_parts(string, length).to_vec())
+            };
+            assert_eq!(get(yaml_event_get_anchor), Some(b"a".to_vec()));
+            assert_eq!(get(yaml_event_get_tag), Some(b"!t".to_vec()));
+            assert_eq!(get(yaml_event_get_scalar_value), Some(b"x".to_vec()));
+            let start = yaml_event_get_start_mark(event);
+            let end = yaml_event_get_end_mark(event);
+            assert_eq!((start.line, start.column, end.column), (0, 2, 9));
+            yaml_event_delete(event);
+            yaml_parser_delete(parser);
+        }
+    }
+
+    #[test]
+    fn create_and_emit() {
+        let mut output = vec![0; 128];
+        let mut size_written = 0;
+        unsafe {
+            let mut emitter = ptr::null_mut();
+            assert_eq!(yaml_emitter_initialize(&mut emitter), 1);
+            yaml_emitter_set_output_string(
+                emitter,
+                output.as_mut_ptr(),
+                output.len(),
+                &mut size_written,
+            );
+            let version = yaml_version_directive_t { major: 1, minor: 2 };
+            let tag_directives = [yaml_tag_directive_t {
+                handle: b"!e!\0".as_ptr(),
+                prefix: b"tag:example.com,2000:\0".as_ptr(),
+            }];
+            let mut events = [ptr::null_mut(); 8];
+            assert_eq!(
+                yaml_stream_start_event_initialize(
+                    &mut events[0],
+                    yaml_encoding_t::YAML_UTF8_ENCODING as c_int
+                ),
+                1
+            );
+            assert_eq!(
+                yaml_document_start_event_initialize(
+                    &mut events[1],
+                    &version,
+                    tag_directives.as_ptr(),
+                    tag_directives.as_ptr().add(1),
+                    0,
+                ),
+                1
+            );
+            assert_eq!(
+                yaml_mapping_start_event_initialize(
+                    &mut events[2],
+                    b"m\0".as_ptr(),
+                    b"tag:example.com,2000:map\0".as_ptr(),
+                    0,
+                    yaml_mapping_style_t::YAML_FLOW_MAPPING_STYLE as c_int,
+                ),
+                1
+            );
+            assert_eq!(
+                yaml_scalar_event_initialize(
+                    &mut events[3],
+                    ptr::null(),
+                    ptr::null(),
+                    b"key\0".as_ptr(),
+                    -1,
+                    1,
+                    1,
+                    yaml_scalar_style_t::YAML_ANY_SCALAR_STYLE as c_int,
+                ),
+                1
+            );
+            assert_eq!(
+                yaml_alias_event_initialize(&mut events[4], b"m\0".as_ptr()),
+                1
+            );
+            assert_eq!(yaml_mapping_end_event_initialize(&mut events[5]), 1);
+            assert_eq!(yaml_document_end_event_initialize(&mut events[6], 1), 1);
+            assert_eq!(yaml_stream_end_event_initialize(&mut events[7]), 1);
+            for event in events {
+                assert_eq!(yaml_emitter_emit(emitter, event), 1);
+            }
+            yaml_emitter_delete(emitter);
+        }
+        output.truncate(size_written);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "%YAML 1.2\n%TAG !e! tag:example.com,2000:\n--- &m !e!map {key: *m}\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        unsafe {
+            let mut parser = ptr::null_mut();
+            assert_eq!(yaml_parser_initialize(&mut parser), 1);
+            assert_eq!(yaml_parser_error(parser), yaml_error_type_t::YAML_NO_ERROR);
+            assert!(yaml_parser_error_problem(parser).is_null());
+            let input = "a: [b\nc: d";
+            yaml_parser_set_input_string(parser, input.as_ptr(), input.len());
+            let mut event = ptr::null_mut();
+            while yaml_parser_parse(parser, &mut event) == 1 {
+                yaml_event_delete(event);
+            }
+            assert_eq!(
+                yaml_parser_error(parser),
+                yaml_error_type_t::YAML_PARSER_ERROR
+            );
+            let problem = CStr::from_ptr(yaml_parser_error_problem(parser));
+            assert_eq!(problem.to_str(), Ok("did not find expected ',' or ']'"));
+            let context = CStr::from_ptr(yaml_parser_error_context(parser));
+            assert_eq!(context.to_str(), Ok("while parsing a flow sequence"));
+            assert_eq!(yaml_parser_error_context_mark(parser).column, 3);
+            assert_eq!(yaml_parser_error_problem_mark(parser).line, 1);
+            yaml_parser_delete(parser);
+
+            // The output buffer is too small.
+            let mut output = vec![0; 4];
+            let mut size_written = 0;
+            let mut emitter = ptr::null_mut();
+            assert_eq!(yaml_emitter_initialize(&mut emitter), 1);
+            yaml_emitter_set_output_string(
+                emitter,
+                output.as_mut_ptr(),
+                output.len(),
+                &mut size_written,
+            );
+            let mut result = 1;
+            for event in crate::parse_events("long scalar").unwrap() {
+                let event = Box::into_raw(Box::new(yaml_event_t { event }));
+                result = yaml_emitter_emit(emitter, event);
+                if result == 0 {
+                    break;
+                }
+            }
+            assert_eq!(result, 0);
+            assert_eq!(
+                yaml_emitter_error(emitter),
+                yaml_error_type_t::YAML_WRITER_ERROR
+            );
+            assert!(!yaml_emitter_error_problem(emitter).is_null());
+
+            // An event in the wrong place.
+            let mut event = ptr::null_mut();
+            assert_eq!(yaml_stream_end_event_initialize(&mut event), 1);
+            let mut emitter = ptr::null_mut();
+            assert_eq!(yaml_emitter_initialize(&mut emitter), 1);
+            yaml_emitter_set_output_string(
+                emitter,
+                output.as_mut_ptr(),
+                output.len(),
+                &mut size_written,
+            );
+            assert_eq!(yaml_emitter_emit(emitter, event), 0);
+            assert_eq!(
+                yaml_emitter_error(emitter),
+                yaml_error_type_t::YAML_EMITTER_ERROR
+            );
+            let problem = CStr::from_ptr(yaml_emitter_error_problem(emitter));
+            assert_eq!(problem.to_str(), Ok("expected STREAM-START"));
+            yaml_emitter_delete(emitter);
+        }
+    }
+
+    #[test]
+    fn unknown_enum_values() {
+        unsafe {
+            let mut parser = ptr::null_mut();
+            assert_eq!(yaml_parser_initialize(&mut parser), 1);
+            assert_eq!(yaml_parser_set_encoding(parser, 4), 0);
+            assert_eq!(yaml_parser_set_encoding(parser, -1), 0);
+            assert_eq!(
+                yaml_parser_set_encoding(parser, yaml_encoding_t::YAML_UTF8_ENCODING as c_int),
+                1
+            );
+            yaml_parser_delete(parser);
+            assert_eq!(yaml_parser_set_encoding(ptr::null_mut(), 0), 0);
+
+            let mut event = ptr::null_mut();
+            assert_eq!(yaml_stream_start_event_initialize(&mut event, 7), 0);
+            let value = "a";
+            assert_eq!(
+                yaml_scalar_event_initialize(
+                    &mut event,
+                    ptr::null(),
+                    ptr::null(),
+                    value.as_ptr(),
+                    1,
+                    1,
+                    1,
+                    6,
+                ),
+                0
+            );
+            assert_eq!(
+                yaml_sequence_start_event_initialize(&mut event, ptr::null(), ptr::null(), 1, 3),
+                0
+            );
+            assert_eq!(
+                yaml_mapping_start_event_initialize(
+                    &mut event,
+                    ptr::null(),
+                    ptr::null(),
+                    1,
+                    c_int::MAX
+                ),
+                0
+            );
+            assert!(event.is_null());
+
+            assert_eq!(
+                yaml_mapping_start_event_initialize(
+                    &mut event,
+                    ptr::null(),
+                    ptr::null(),
+                    1,
+                    yaml_mapping_style_t::YAML_BLOCK_MAPPING_STYLE as c_int
+                ),
+                1
+            );
+            assert_eq!(
+                yaml_event_get_type(event),
+                yaml_event_type_t::YAML_MAPPING_START_EVENT
+            );
+            yaml_event_delete(event);
+        }
+    }
+}
@@ -26,27 +26,61 @@ extern crate alloc;
 #[macro_use]
 mod macros;
 
+#[cfg(feature = "arbitrary")]
+mod arbitrary;
+#[cfg(feature = "tokio")]
+mod async_io;
+mod buffer;
+mod builder;
+mod cache;
 mod document;
 mod emitter;
 mod error;
 mod event;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "rayon")]
+mod parallel;
 mod parser;
+mod path;
 mod reader;
 mod scanner;
+mod schema;
+mod stats;
 mod token;
+mod trace;
+mod validate;
 
+#[cfg(feature = "arbitrary")]
+pub use crate::arbitrary::*;
+#[cfg(feature = "tokio")]
+pub use crate::async_io::*;
+pub use crate::builder::*;
 pub use crate::document::*;
 pub use crate::emitter::*;
 pub use crate::error::*;
 pub use crate::event::*;
+#[cfg(feature = "rayon")]
+pub use crate::parallel::*;
 pub use crate::parser::*;
+pub use crate::path::*;
 pub use crate::scanner::*;
+pub use crate::schema::*;
+pub use crate::stats::*;
 pub use crate::token::*;
+pub use crate::trace::*;
+pub use crate::validate::*;
 
-pub(crate) const INPUT_RAW_BUFFER_SIZE: usize = 16384;
-pub(crate) const INPUT_BUFFER_SIZE: usize = INPUT_RAW_BUFFER_SIZE;
 pub(crate) const OUTPUT_BUFFER_SIZE: usize = 16384;
 
+/// The default capacity of the input buffer of a scanner, in characters.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 16384;
+
+/// The default maximum length of anchor and alias names, in characters.
+pub const DEFAULT_MAX_ANCHOR_LENGTH: usize = 1024;
+/// The default maximum length of tag handles and suffixes, in characters.
+pub const DEFAULT_MAX_TAG_LENGTH: usize = 4096;
+
 /// The tag `!!null` with the only possible value: `null`.
 pub const NULL_TAG: &str = "tag:yaml.org,2002:null";
 /// The tag `!!bool` with the values: `true` and `false`.
@@ -72,8 +106,340 @@ pub const DEFAULT_SEQUENCE_TAG: &str = SEQ_TAG;
 /// The default mapping tag is `!!map`.
 pub const DEFAULT_MAPPING_TAG: &str = MAP_TAG;
 
+/// Parse a YAML stream into a list of events.
+///
+/// This is a shortcut for running a [`Parser`] with the default settings over
+/// the whole input. The events include the STREAM-START and STREAM-END events.
+///
+/// ```
+/// use libyaml_safer::{parse_events, EventData};
+///
+/// let events = parse_events("greeting: hello")?;
+/// let scalars: Vec<&str> = events
+///     .iter()
+///     .filter_map(|event| match &event.data {
+///         EventData::Scalar { value, .. } => Some(value.as_str()),
+///         _ => None,
+///     })
+///     .collect();
+/// assert_eq!(scalars, ["greeting", "hello"]);
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn parse_events(input: &str) -> Result<Vec<Event>> {
+    let mut parser = Parser::new();
+    let mut input = input.as_bytes();
+    parser.set_input_string(&mut input);
+    parser.collect()
+}
+
+/// Load all documents in a YAML stream.
+///
+/// This is a shortcut for calling [`Document::load()`] with a [`Parser`]
+/// using the default settings until the end of the stream. An empty stream
+/// has no documents.
+///
+/// ```
+/// use libyaml_safer::{parse_documents, NodeData};
+///
+/// let documents = parse_documents("--- first\n--- [second, third]\n")?;
+/// assert_eq!(documents.len(), 2);
+/// let root = documents[1].get_node(1).unwrap();
+/// assert!(matches!(&root.data, NodeData::Sequence { items, .. } if items.len() == 2));
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn parse_documents(input: &str) -> Result<Vec<Document>> {
+    let mut parser = Parser::new();
+    let mut input = input.as_bytes();
+    parser.set_input_string(&mut input);
+    let mut documents = Vec::new();
+    loop {
+        let document = Document::load(&mut parser)?;
+        if document.nodes.is_empty() {
+            return Ok(documents);
+        }
+        documents.push(document);
+    }
+}
+
+/// Emit a list of events as a YAML stream.
+///
+/// This is a shortcut for running an [`Emitter`] with the default settings
+/// over the events. The STREAM-START and STREAM-END events may be left out, in
+/// which case they are added: a UTF-8 STREAM-START event before the first
+/// event, and a STREAM-END event after the last one. It is an error for either
+/// of them to appear anywhere else.
+///
+/// ```
+/// use libyaml_safer::{emit_events, Event, MappingStyle, ScalarStyle};
+///
+/// let output = emit_events(vec![
+///     Event::document_start(None, &[], true),
+///     Event::mapping_start(None, None, true, MappingStyle::Any),
+///     Event::scalar(None, None, "greeting", true, true, ScalarStyle::Any),
+///     Event::scalar(None, None, "hello", true, true, ScalarStyle::Any),
+///     Event::mapping_end(),
+///     Event::document_end(true),
+/// ])?;
+/// assert_eq!(output, "greeting: hello\n");
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+///
+/// Parsing and emitting round-trips the content of a stream:
+///
+/// ```
+/// use libyaml_safer::{emit_events, parse_events};
+///
+/// let output = emit_events(parse_events("{a: [1, 2]}")?)?;
+/// assert_eq!(output, "{a: [1, 2]}\n");
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn emit_events(events: Vec<Event>) -> Result<String> {
+    let has_stream_start = matches!(
+        events.first(),
+        Some(Event {
+            data: EventData::StreamStart { .. },
+            ..
+        })
+    );
+    let has_stream_end = events.len() > usize::from(has_stream_start)
+        && matches!(
+            events.last(),
+            Some(Event {
+                data: EventData::StreamEnd,
+                ..
+            })
+        );
+    let inner = &events[usize::from(has_stream_start)..events.len() - usize::from(has_stream_end)];
+    for event in inner {
+        match event.data {
+            EventData::StreamStart { .. } => {
+                return Err(Error::emitter(
+                    "STREAM-START is only allowed at the start of the stream",
+                ));
+            }
+            EventData::StreamEnd => {
+                return Err(Error::emitter(
+                    "STREAM-END is only allowed at the end of the stream",
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    let mut output = String::new();
+    let mut emitter = Emitter::new();
+    emitter.set_output_fmt(&mut output);
+    if !has_stream_start {
+        emitter.emit(Event::stream_start(Encoding::Utf8))?;
+    }
+    for event in events {
+        emitter.emit(event)?;
+    }
+    if !has_stream_end {
+        emitter.emit(Event::stream_end())?;
+    }
+    drop(emitter);
+    Ok(output)
+}
+
+/// Parse a stream of tokens, instead of scanning an input.
+///
+/// This is a shortcut for [`Parser::set_input_tokens()`], for tools that work
+/// with tokens and want the events for them without reading the input again,
+/// and for checking the parser independently of the scanner. The tokens must
+/// start with a STREAM-START token and end with a STREAM-END token.
+///
+/// ```
+/// use libyaml_safer::{events_from_tokens, parse_events, Scanner};
+///
+/// let mut input = "a: [b, c]".as_bytes();
+/// let mut scanner = Scanner::new();
+/// scanner.set_input_string(&mut input);
+/// let tokens = scanner.collect::<Result<Vec<_>, _>>()?;
+/// let events = events_from_tokens(tokens).collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(events, parse_events("a: [b, c]")?);
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn events_from_tokens(
+    tokens: impl IntoIterator<Item = Token>,
+) -> impl Iterator<Item = Result<Event>> {
+    let mut parser = Parser::new();
+    parser.set_input_tokens(tokens);
+    parser
+}
+
+/// The tokens of the YAML stream written for `events`.
+///
+/// This is a best effort for testing the emitter: the events are emitted as
+/// by [`emit_events()`], and the output is scanned again, so the tokens have
+/// the marks of that output.
+///
+/// ```
+/// use libyaml_safer::{parse_events, tokens_from_events, TokenData};
+///
+/// let tokens = tokens_from_events(parse_events("[a]")?)?;
+/// assert!(matches!(tokens[1].data, TokenData::FlowSequenceStart));
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn tokens_from_events(events: Vec<Event>) -> Result<Vec<Token>> {
+    let output = emit_events(events)?;
+    let mut input = output.as_bytes();
+    let mut scanner = Scanner::new();
+    scanner.set_input_string(&mut input);
+    scanner.collect()
+}
+
+/// A consumer of events, called by [`tee_events()`].
+pub type EventSink<'a> = dyn FnMut(&Event) -> Result<()> + 'a;
+
+/// Parse a YAML stream once and pass each event to several consumers.
+///
+/// Every event, from STREAM-START to STREAM-END, is passed to each of the
+/// `sinks` in order. The sinks share a reference to the event, so fanning
+/// out costs no copies; a sink that needs to keep an event clones it. Parsing
+/// stops at the first error. An error returned by a sink is wrapped in an error of kind [`ErrorKind::Sink`], with the index of
+/// the sink in [`Error::sink_index()`]; errors of the parser are returned as
+/// they are.
+///
+/// ```
+/// use libyaml_safer::{tee_events, Emitter, Event, Parser};
+///
+/// let mut input = "{a: [1, 2]}".as_bytes();
+/// let mut parser = Parser::new();
+/// parser.set_input_string(&mut input);
+///
+/// let mut events = Vec::new();
+/// let mut output = String::new();
+/// let mut emitter = Emitter::new();
+/// emitter.set_output_fmt(&mut output);
+/// tee_events(
+///     &mut parser,
+///     &mut [
+///         &mut |event: &Event| {
+///             events.push(event.clone());
+///             Ok(())
+///         },
+///         &mut |event: &Event| emitter.emit(event.clone()),
+///     ],
+/// )?;
+/// drop(emitter);
+/// assert_eq!(events.len(), 11);
+/// assert_eq!(output, "{a: [1, 2]}\n");
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn tee_events(parser: &mut Parser, sinks: &mut [&mut EventSink<'_>]) -> Result<()> {
+    loop {
+        let event = parser.parse()?;
+        for (index, sink) in sinks.iter_mut().enumerate() {
+            sink(&event).map_err(|error| Error::sink(index, error))?;
+        }
+        if let EventData::StreamEnd = event.data {
+            return Ok(());
+        }
+    }
+}
+
+/// The location of a document in a YAML stream, found by
+/// [`split_documents()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DocumentSpan {
+    /// The start of the first token of the document, which is a directive,
+    /// the `---` indicator, or the start of the content.
+    pub start: Mark,
+    /// The end of the last token of the document, which is the `...`
+    /// indicator or the end of the content.
+    pub end: Mark,
+    /// Does the document have `%YAML` or `%TAG` directives?
+    pub has_directives: bool,
+    /// Does the document start with a `---` indicator?
+    pub explicit_start: bool,
+    /// Does the document end with a `...` indicator?
+    pub explicit_end: bool,
+}
+
+/// Find the documents in a YAML stream, without parsing them.
+///
+/// This only runs the [`Scanner`], so document indicators in scalars are
+/// not mistaken for the boundaries of documents, but errors that only the
+/// [`Parser`] detects are not reported. The [`Mark::index`] of the marks is a
+/// byte offset in `input`, so the text of a document can be extracted with
+/// `&input[span.start.index as usize..span.end.index as usize]`. Comments and
+/// blank lines between documents are not part of any document.
+///
+/// ```
+/// use libyaml_safer::split_documents;
+///
+/// let input = "first: |\n  ---\n--- second\n...\n";
+/// let spans = split_documents(input)?;
+/// let documents: Vec<&str> = spans
+///     .iter()
+///     .map(|span| &input[span.start.index as usize..span.end.index as usize])
+///     .collect();
+/// assert_eq!(documents, ["first: |\n  ---\n", "--- second\n..."]);
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn split_documents(input: &str) -> Result<Vec<DocumentSpan>> {
+    let mut scanner = Scanner::new();
+    let mut bytes = input.as_bytes();
+    scanner.set_input_string(&mut bytes);
+
+    let mut spans = Vec::new();
+    // The document being scanned, and whether it has any content or a `---`
+    // indicator yet.
+    let mut current: Option<(DocumentSpan, bool)> = None;
+    for token in scanner {
+        let token = token?;
+        let (directive, document_start) = match token.data {
+            TokenData::StreamStart { .. } | TokenData::BlockEnd => continue,
+            TokenData::StreamEnd => {
+                spans.extend(current.take().map(|(span, _)| span));
+                continue;
+            }
+            TokenData::DocumentEnd => {
+                if let Some((mut span, _)) = current.take() {
+                    span.end = token.end_mark;
+                    span.explicit_end = true;
+                    spans.push(span);
+                }
+                continue;
+            }
+            TokenData::VersionDirective { .. } | TokenData::TagDirective { .. } => (true, false),
+            TokenData::DocumentStart => (false, true),
+            _ => (false, false),
+        };
+        // Directives and `---` start a new document, unless they follow the
+        // directives of the current one.
+        let new_document = match current {
+            None => true,
+            Some((_, started)) => started && (directive || document_start),
+        };
+        if new_document {
+            spans.extend(current.take().map(|(span, _)| span));
+            current = Some((
+                DocumentSpan {
+                    start: token.start_mark,
+                    end: token.end_mark,
+                    has_directives: false,
+                    explicit_start: false,
+                    explicit_end: false,
+                },
+                false,
+            ));
+        }
+        if let Some((span, started)) = &mut current {
+            span.end = token.end_mark;
+            span.has_directives |= directive;
+            span.explicit_start |= document_start;
+            *started |= !directive;
+        }
+    }
+    Ok(spans)
+}
+
 /// The version directive data.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub struct VersionDirective {
     /// The major version number.
@@ -84,6 +450,7 @@ pub struct VersionDirective {
 
 /// The tag directive data.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub struct TagDirective {
     /// The tag handle.
@@ -122,8 +489,54 @@ pub enum Break {
     CrLn = 3,
 }
 
+/// The policy for the line break at the end of an emitted stream.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum FinalNewline {
+    /// Leave the end of the stream as the emitter produced it.
+    #[default]
+    AsIs,
+    /// End a non-empty stream with exactly one line break.
+    Always,
+    /// Remove a single line break from the end of the stream.
+    ///
+    /// When the stream ends with a block scalar whose value keeps its final
+    /// line break, the last document is ended with `...` instead.
+    Never,
+}
+
+/// When the emitter writes the `%YAML` directives of documents.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum VersionDirectivePolicy {
+    /// Write the directive of every document that has one, like libyaml.
+    #[default]
+    PerDocument,
+    /// Write the directive of the first document of the stream only, and only
+    /// if it is not for YAML 1.2.
+    FirstOnly,
+    /// Write no directives, so that every document is implicitly YAML 1.2.
+    Never,
+}
+
+/// When the emitter writes mapping keys with the explicit `? ` indicator.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
+pub enum ExplicitKeys {
+    /// Write explicit keys where they are needed, and for every key of a
+    /// flow mapping in the canonical format, like libyaml.
+    #[default]
+    Auto,
+    /// Write every key explicitly.
+    Always,
+    /// Write explicit keys only where they are needed, even in the canonical
+    /// format.
+    Never,
+}
+
 /// Scalar styles.
 #[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub enum ScalarStyle {
     /// Let the emitter choose the style.
@@ -143,6 +556,7 @@ pub enum ScalarStyle {
 
 /// Sequence styles.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub enum SequenceStyle {
     /// Let the emitter choose the style.
@@ -155,6 +569,7 @@ pub enum SequenceStyle {
 
 /// Mapping styles.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub enum MappingStyle {
     /// Let the emitter choose the style.
@@ -165,6 +580,40 @@ pub enum MappingStyle {
     Flow = 2,
 }
 
+/// The chomping indicators of block scalars.
+#[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Chomping {
+    /// No indicator: keep the final line break, but not trailing empty lines.
+    #[default]
+    Clip,
+    /// The `-` indicator: remove the final line break.
+    Strip,
+    /// The `+` indicator: keep the final line break and trailing empty lines.
+    Keep,
+}
+
+/// How a node was written in the input, beyond its style, captured with
+/// [`Parser::set_capture_presentation()`].
+///
+/// The emitter writes nodes the same way where that does not change their
+/// content, so that a reformatter can keep these details.
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Presentation {
+    /// The chomping indicator of a block scalar.
+    pub chomping: Chomping,
+    /// The indentation indicator of a block scalar, from 1 to 9.
+    pub indent_indicator: Option<u8>,
+    /// Was the chomping indicator of a block scalar written before the
+    /// indentation indicator, as in `|+2`?
+    pub chomping_first: bool,
+    /// Was the collection written empty in the flow style, as `[]` or `{}`?
+    pub empty_inline: bool,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -394,4 +843,255 @@ c: true";
             .zip(b)
             .take_while(|(a, b)| a.is_some() || b.is_some())
     }
+
+    #[test]
+    fn one_shot_functions() {
+        let input = "a: [1, 2]\n--- b\n";
+        let events = parse_events(input).unwrap();
+        assert!(matches!(events[0].data, EventData::StreamStart { .. }));
+        assert!(matches!(events.last().unwrap().data, EventData::StreamEnd));
+        assert_eq!(parse_documents(input).unwrap().len(), 2);
+        assert!(parse_documents("").unwrap().is_empty());
+
+        let err = parse_events("[mismatched}").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Parser);
+        let err = parse_documents("a: *unknown").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Composer);
+    }
+
+    #[test]
+    fn tee_to_collector_and_emitter() {
+        let input = "%TAG !e! tag:example.com,2000:\n--- &a !e!x {a: [1, *a]}\n--- |\n  text\n";
+        let reference_events = parse_events(input).unwrap();
+        let reference_output = emit_events(parse_events(input).unwrap()).unwrap();
+
+        let mut parser = Parser::new();
+        let mut bytes = input.as_bytes();
+        parser.set_input_string(&mut bytes);
+        let mut events = Vec::new();
+        let mut output = String::new();
+        let mut emitter = Emitter::new();
+        emitter.set_output_fmt(&mut output);
+        tee_events(
+            &mut parser,
+            &mut [
+                &mut |event: &Event| {
+                    events.push(event.clone());
+                    Ok(())
+                },
+                &mut |event: &Event| emitter.emit(event.clone()),
+            ],
+        )
+        .unwrap();
+        drop(emitter);
+        assert_eq!(events, reference_events);
+        assert_eq!(output, reference_output);
+
+        // A failing sink stops parsing, and later sinks do not see the event.
+        let mut parser = Parser::new();
+        let mut bytes = input.as_bytes();
+        parser.set_input_string(&mut bytes);
+        let mut seen = 0;
+        let err = tee_events(
+            &mut parser,
+            &mut [
+                &mut |event: &Event| match event.data {
+                    EventData::MappingStart { .. } => Err(Error::emitter("no mappings")),
+                    _ => Ok(()),
+                },
+                &mut |_: &Event| {
+                    seen += 1;
+                    Ok(())
+                },
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(seen, 2);
+        assert_eq!(err.kind(), ErrorKind::Sink);
+        assert_eq!(err.sink_index(), Some(0));
+        assert_eq!(
+            err.to_string(),
+            "Sink error: sink 0 failed: Emitter error: no mappings"
+        );
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), "Emitter error: no mappings");
+
+        // Parser errors are not wrapped.
+        let mut bytes = "[mismatched}".as_bytes();
+        let mut parser = Parser::new();
+        parser.set_input_string(&mut bytes);
+        let err = tee_events(&mut parser, &mut [&mut |_: &Event| Ok(())]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Parser);
+        assert_eq!(err.sink_index(), None);
+    }
+
+    #[test]
+    fn emit_events_stream_boundaries() {
+        let full = || parse_events("a: [1, 2]\n--- b\n").unwrap();
+        let expected = emit_events(full()).unwrap();
+        assert_eq!(expected, "a: [1, 2]\n--- b\n");
+
+        let mut without_start = full();
+        without_start.remove(0);
+        let mut without_end = full();
+        without_end.pop();
+        let mut without_both = full();
+        without_both.remove(0);
+        without_both.pop();
+        for events in [without_start, without_end, without_both] {
+            assert_eq!(emit_events(events).unwrap(), expected);
+        }
+
+        assert_eq!(emit_events(Vec::new()).unwrap(), "");
+        assert_eq!(emit_events(vec![Event::stream_end()]).unwrap(), "");
+
+        let mut misplaced_start = full();
+        misplaced_start.insert(1, Event::stream_start(Encoding::Utf8));
+        let mut misplaced_end = full();
+        misplaced_end.insert(1, Event::stream_end());
+        for events in [misplaced_start, misplaced_end] {
+            let err = emit_events(events).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::Emitter);
+        }
+    }
+
+    #[test]
+    fn events_from_tokens() {
+        fn scan(input: &str) -> Vec<Token> {
+            let mut bytes = input.as_bytes();
+            let mut scanner = Scanner::new();
+            scanner.set_input_string(&mut bytes);
+            scanner.collect::<Result<_>>().unwrap()
+        }
+        // Up to the first error, which is compared by its message, with its
+        // marks.
+        fn messages(events: impl Iterator<Item = Result<Event>>) -> Vec<Result<Event, String>> {
+            let mut messages = Vec::new();
+            for event in events {
+                let failed = event.is_err();
+                messages.push(event.map_err(|err| err.to_string()));
+                if failed {
+                    break;
+                }
+            }
+            messages
+        }
+        fn parse(input: &str) -> Vec<Result<Event, String>> {
+            let mut bytes = input.as_bytes();
+            let mut parser = Parser::new();
+            parser.set_input_string(&mut bytes);
+            messages(parser)
+        }
+
+        for input in [
+            "",
+            "plain",
+            "%YAML 1.2\n%TAG !e! tag:example.com,2000:\n--- !e!x &a [1, *a]\n...\n",
+            "a: {b: [c, d], ? e : f}\n? - g\n: |\n  literal\nh: >-\n  folded\n",
+            "- - a\n  - b\n- 'c'\n- \"d\"\n-\n--- x\n--- [y]\n",
+            "{a, b: , : c}",
+            "&x a: *x\n!!str b: !t c\n",
+            // Errors of the parser, not of the scanner.
+            "a: [b}",
+            "a: b\n- c\n",
+            "{a: b} c",
+        ] {
+            let via_tokens = messages(super::events_from_tokens(scan(input)));
+            assert_eq!(via_tokens, parse(input), "{input:?}");
+
+            if let Ok(events) = parse_events(input) {
+                let tokens = tokens_from_events(events.clone()).unwrap();
+                let round_trip = super::events_from_tokens(tokens)
+                    .collect::<Result<Vec<_>>>()
+                    .unwrap();
+                let content = |events: &[Event]| -> Vec<EventData> {
+                    events.iter().map(|event| event.data.clone()).collect()
+                };
+                let expected = parse_events(&emit_events(events).unwrap()).unwrap();
+                assert_eq!(content(&round_trip), content(&expected), "{input:?}");
+            }
+        }
+
+        // Tokens that end early.
+        let mut tokens = scan("[a, b]");
+        tokens.truncate(3);
+        let err = super::events_from_tokens(tokens)
+            .collect::<Result<Vec<_>>>()
+            .unwrap_err();
+        assert_eq!(err.problem(), "found unexpected end of stream");
+    }
+
+    #[test]
+    fn split_documents() {
+        let input = "\
+# leading comment
+implicit: first
+  # indented comment
+---
+literal: |
+  ---
+  ...
+quoted: \"
+  --- \"
+...
+%YAML 1.2
+%TAG !e! tag:example.com,2000:
+--- !e!x
+- a
+--- >
+  folded
+...
+...
+--- [flow,
+  --- ]
+";
+        let spans = super::split_documents(input).unwrap();
+        let texts: Vec<&str> = spans
+            .iter()
+            .map(|span| &input[span.start.index as usize..span.end.index as usize])
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                "implicit: first",
+                "---\nliteral: |\n  ---\n  ...\nquoted: \"\n  --- \"\n...",
+                "%YAML 1.2\n%TAG !e! tag:example.com,2000:\n--- !e!x\n- a",
+                "--- >\n  folded\n...",
+                "--- [flow,\n  --- ]",
+            ]
+        );
+        let flags: Vec<(bool, bool, bool)> = spans
+            .iter()
+            .map(|span| (span.has_directives, span.explicit_start, span.explicit_end))
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                (false, false, false),
+                (false, true, true),
+                (true, true, false),
+                (false, true, true),
+                (false, true, false),
+            ]
+        );
+        assert_eq!(spans[1].start.line, 3);
+        assert_eq!(spans[1].end.line, 9);
+
+        // Every span parses as a single document.
+        for text in texts {
+            assert_eq!(parse_documents(text).unwrap().len(), 1, "{text:?}");
+        }
+
+        assert_eq!(super::split_documents("").unwrap(), []);
+        assert_eq!(
+            super::split_documents("# only a comment\n...\n").unwrap(),
+            []
+        );
+        let spans = super::split_documents("---\n---\n").unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].start.index, spans[0].end.index), (0, 3));
+
+        let err = super::split_documents("a: 'unterminated\n").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Scanner);
+    }
 }
@@ -73,6 +73,8 @@ pub(crate) fn is_ascii(ch: char) -> bool {
     ch.is_ascii()
 }
 
+/// The `c-printable` characters of the spec, except for the tab, the carriage
+/// return, NEL, and the byte order mark, which the emitter writes as escapes.
 pub(crate) fn is_printable(ch: char) -> bool {
     match ch {
         '\u{feff}' | '\u{fffe}' | '\u{ffff}' => false,
@@ -108,7 +110,7 @@ macro_rules! IS_BOM {
 }
 
 pub(crate) fn is_bom(ch: char) -> bool {
-    ch == '\u{7eff}'
+    ch == '\u{feff}'
 }
 
 macro_rules! IS_SPACE_AT {
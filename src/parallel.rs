@@ -0,0 +1,115 @@
+use rayon::prelude::*;
+
+use crate::{split_documents, Document, Parser, Result};
+
+/// Load all documents in a YAML stream in parallel, with the `rayon` feature.
+///
+/// The stream is first split into documents with [`split_documents()`],
+/// which only scans it. Each document is then loaded by its own [`Parser`] on
+/// the Rayon thread pool. The documents are the same as those of
+/// [`parse_documents()`], in the same order, and their marks are relative to
+/// the start of `input`.
+///
+/// The marks of an error in a document are also relative to the start of
+/// `input`, and [`Error::document_index()`](crate::Error::document_index)
+/// is the index of the document. If several documents fail to load, the
+/// error of the first one is returned.
+///
+/// ```
+/// use libyaml_safer::load_all_parallel;
+///
+/// let documents = load_all_parallel("--- first\n--- [second, third]\n")?;
+/// assert_eq!(documents.len(), 2);
+/// assert_eq!(documents[1].start_mark.index, 10);
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn load_all_parallel(input: &str) -> Result<Vec<Document>> {
+    let spans = split_documents(input)?;
+    // A document runs to the start of the next one, so that the comments
+    // and the line breaks after it are parsed as they are in the stream.
+    let ends = spans
+        .iter()
+        .skip(1)
+        .map(|span| span.start.index as usize)
+        .chain([input.len()]);
+    let slices: Vec<_> = spans.iter().map(|span| span.start).zip(ends).collect();
+    let documents: Vec<Result<Document>> = slices
+        .into_par_iter()
+        .enumerate()
+        .map(|(index, (start, end))| {
+            let mut slice = &input.as_bytes()[start.index as usize..end];
+            let mut parser = Parser::new();
+            parser.set_input_string(&mut slice);
+            let mut document =
+                Document::load(&mut parser).map_err(|error| error.in_document(index, start))?;
+            document.offset_marks(start);
+            Ok(document)
+        })
+        .collect();
+    documents.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write as _;
+
+    use super::load_all_parallel;
+    use crate::{parse_documents, ErrorKind};
+
+    fn fixture(count: usize) -> String {
+        let mut input = String::new();
+        for i in 0..count {
+            match i % 6 {
+                0 => writeln!(input, "--- {i}"),
+                1 => writeln!(input, "---\nkey: &a{i} [{i}, *a{i}]\n..."),
+                2 => writeln!(
+                    input,
+                    "%YAML 1.1\n%TAG !e! tag:example.com,2000:\n--- !e!x\n- {i}\n- |\n  text\n"
+                ),
+                3 => writeln!(input, "--- >-\n  folded\n  {i}\n\n# comment"),
+                4 => writeln!(input, "---\n..."),
+                _ => writeln!(input, "--- {{a: {i}, b: [c, d]}}\n  # indented"),
+            }
+            .unwrap();
+        }
+        input
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        for input in [
+            format!("# a stream\n\nfirst: implicit\n{}", fixture(300)),
+            String::new(),
+            String::from("a\n"),
+            String::from("[a]\n%YAML 1.2\n--- b\n"),
+            String::from("\u{feff}# only\n--- a\n"),
+            String::from("--- 'é'\n--- \"ü\"\n"),
+        ] {
+            let parallel = load_all_parallel(&input).unwrap();
+            let sequential = parse_documents(&input).unwrap();
+            assert_eq!(parallel.len(), sequential.len());
+            for (parallel, sequential) in parallel.iter().zip(&sequential) {
+                assert_eq!(parallel.start_mark, sequential.start_mark);
+                assert_eq!(parallel.end_mark, sequential.end_mark);
+                assert_eq!(parallel.nodes, sequential.nodes);
+                assert_eq!(parallel.alias_uses(), sequential.alias_uses());
+                assert_eq!(parallel.tag_directives, sequential.tag_directives);
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_errors() {
+        let mut input = fixture(100);
+        input.push_str("--- *undefined\n");
+        input.push_str(&fixture(20));
+        input.push_str("--- *other\n");
+        let error = load_all_parallel(&input).unwrap_err();
+        let sequential = parse_documents(&input).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Composer);
+        assert_eq!(error.document_index(), Some(100));
+        assert_eq!(error.problem_mark(), sequential.problem_mark());
+        assert_eq!(error.context_mark(), sequential.context_mark());
+        assert_eq!(error.to_string(), format!("{sequential} in document 100"));
+    }
+}
@@ -1,7 +1,14 @@
+use std::num::NonZeroU8;
+use std::sync::Arc;
+
 use crate::scanner::Scanner;
+#[cfg(feature = "tokio")]
+use crate::scanner::ScannerCheckpoint;
+use crate::stats::StatsPosition;
 use crate::{
-    Encoding, Error, Event, EventData, MappingStyle, Mark, Result, ScalarStyle, SequenceStyle,
-    TagDirective, TokenData, VersionDirective,
+    Encoding, Error, Event, EventData, InterruptCheck, MappingStyle, Mark, ParserStats, Result,
+    ScalarSource, ScalarStyle, SequenceStyle, TagDirective, Token, TokenData, TraceEvent,
+    TraceHook, VersionDirective,
 };
 
 /// The parser structure.
@@ -18,6 +25,24 @@ pub struct Parser<'r> {
     pub(crate) tag_directives: Vec<TagDirective>,
     /// The alias data.
     pub(crate) aliases: Vec<AliasData>,
+    /// The event returned by [`Parser::peek_event()`], if any.
+    pub(crate) peeked_event: Option<Event>,
+    /// The mark of the last '-' or KEY token, until a node takes it.
+    pending_indicator: Option<Mark>,
+    /// The mark of the '-' or KEY token that introduced the node of the last
+    /// produced event, if any.
+    pub(crate) indicator_mark: Option<Mark>,
+    /// The version of the specification to follow.
+    pub(crate) spec_mode: SpecMode,
+    /// Shrink the allocations of loaded documents?
+    pub(crate) shrink_documents: bool,
+    /// The end of the last token other than BLOCK-END consumed, which is
+    /// where block collections end.
+    last_token_end: Mark,
+    /// The statistics to update, if any.
+    stats: Option<Arc<ParserStats>>,
+    /// The position of the parser for the statistics.
+    stats_position: StatsPosition,
 }
 
 impl<'r> Default for Parser<'r> {
@@ -26,8 +51,30 @@ impl<'r> Default for Parser<'r> {
     }
 }
 
+/// The versions of the YAML specification accepted by a [`Parser`], set with
+/// [`Parser::set_spec_mode()`].
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum SpecMode {
+    /// Accept YAML 1.1 and 1.2 documents, like libyaml.
+    #[default]
+    Compatible,
+    /// Accept only YAML 1.2 documents: `%YAML 1.1` directives are rejected,
+    /// and so are the tags of the `tag:yaml.org,2002:` namespace other than
+    /// those of the YAML 1.2 schemas (`str`, `seq`, `map`, `null`, `bool`,
+    /// `int`, and `float`), like the YAML 1.1 types `!!binary`, `!!set`, and
+    /// `!!timestamp`, and language-specific tags like `!!python/tuple`.
+    ///
+    /// The parser does not resolve the types of plain scalars, so forms that
+    /// only YAML 1.1 resolves to other types than strings, like `yes`, `on`,
+    /// `0b101`, or `1:30`, are accepted, and loaded as strings with the
+    /// default tags, in both modes. The escape sequences of double-quoted
+    /// scalars are the same in both versions.
+    Yaml12Strict,
+}
+
 /// This structure holds information about a potential simple key.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 #[non_exhaustive]
 pub struct SimpleKey {
     /// Is a simple key possible?
@@ -38,6 +85,9 @@ pub struct SimpleKey {
     pub token_number: usize,
     /// The position mark.
     pub mark: Mark,
+    /// Was the key invalidated because it is longer than an implicit key may
+    /// be?
+    pub too_long: bool,
 }
 
 /// The states of the parser.
@@ -106,16 +156,55 @@ pub struct AliasData {
     pub mark: Mark,
 }
 
+/// A snapshot of the state of a [`Parser`] between two events, to parse the
+/// rest of the input with a new parser, as the
+/// [`AsyncParser`](crate::AsyncParser) does when more input arrives.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Debug)]
+pub(crate) struct ParserCheckpoint {
+    scanner: ScannerCheckpoint,
+    /// The offset in the input at which parsing resumes, in bytes.
+    input_offset: usize,
+    states: Vec<ParserState>,
+    state: ParserState,
+    marks: Vec<Mark>,
+    tag_directives: Vec<TagDirective>,
+    pending_indicator: Option<Mark>,
+    indicator_mark: Option<Mark>,
+    last_token_end: Mark,
+    stats_position: StatsPosition,
+}
+
+#[cfg(feature = "tokio")]
+impl ParserCheckpoint {
+    /// The offset in the input at which parsing resumes, in bytes.
+    pub(crate) fn input_offset(&self) -> usize {
+        self.input_offset
+    }
+}
+
 impl<'r> Iterator for Parser<'r> {
     type Item = Result<Event>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.scanner.stream_end_produced || self.state == ParserState::End {
+        if self.peeked_event.is_none()
+            && (self.scanner.stream_end_produced || self.state == ParserState::End)
+        {
             None
         } else {
             Some(self.parse())
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.peeked_event.is_none()
+            && (self.scanner.stream_end_produced || self.state == ParserState::End)
+        {
+            (0, Some(0))
+        } else {
+            (1, None)
+        }
+    }
 }
 
 impl<'r> core::iter::FusedIterator for Parser<'r> {}
@@ -130,6 +219,14 @@ impl<'r> Parser<'r> {
             marks: Vec::with_capacity(16),
             tag_directives: Vec::with_capacity(16),
             aliases: Vec::new(),
+            peeked_event: None,
+            pending_indicator: None,
+            indicator_mark: None,
+            spec_mode: SpecMode::default(),
+            shrink_documents: false,
+            last_token_end: Mark::default(),
+            stats: None,
+            stats_position: StatsPosition::default(),
         }
     }
 
@@ -148,11 +245,113 @@ impl<'r> Parser<'r> {
         self.scanner.set_input(input);
     }
 
+    /// Drop the input handler, keeping the settings, so that the input can be
+    /// freed.
+    #[cfg(feature = "ffi")]
+    pub(crate) fn clear_input(&mut self) {
+        self.scanner.read_handler = None;
+    }
+
+    /// Parse `tokens` instead of scanning an input, like the tokens of a
+    /// [`Scanner`](crate::Scanner) or tokens made by a tool.
+    ///
+    /// The tokens must start with a STREAM-START token, and end with a
+    /// STREAM-END token. The errors of the parser point at the marks of the
+    /// tokens.
+    ///
+    /// See [`events_from_tokens()`](crate::events_from_tokens).
+    pub fn set_input_tokens(&mut self, tokens: impl IntoIterator<Item = Token>) {
+        self.scanner.set_input_tokens(tokens.into_iter().collect());
+    }
+
     /// Set the source encoding.
+    ///
+    /// See [`Scanner::set_encoding()`].
     pub fn set_encoding(&mut self, encoding: Encoding) {
         self.scanner.set_encoding(encoding);
     }
 
+    /// Set the capacity of the input buffer, in characters.
+    ///
+    /// See [`Scanner::set_buffer_capacity()`].
+    pub fn set_buffer_capacity(&mut self, capacity: usize) {
+        self.scanner.set_buffer_capacity(capacity);
+    }
+
+    /// Set if characters outside the YAML character set are allowed.
+    ///
+    /// See [`Scanner::set_allow_nonprintable()`].
+    pub fn set_allow_nonprintable(&mut self, allow_nonprintable: bool) {
+        self.scanner.set_allow_nonprintable(allow_nonprintable);
+    }
+
+    /// Set the maximum length of anchor and alias names, in characters.
+    ///
+    /// See [`Scanner::set_max_anchor_length()`].
+    pub fn set_max_anchor_length(&mut self, max_length: usize) {
+        self.scanner.set_max_anchor_length(max_length);
+    }
+
+    /// Set the maximum length of tags, in characters.
+    ///
+    /// See [`Scanner::set_max_tag_length()`].
+    pub fn set_max_tag_length(&mut self, max_length: usize) {
+        self.scanner.set_max_tag_length(max_length);
+    }
+
+    /// Set the distance between tab stops, for the columns of marks.
+    ///
+    /// See [`Scanner::set_tab_width()`].
+    pub fn set_tab_width(&mut self, tab_width: NonZeroU8) {
+        self.scanner.set_tab_width(tab_width);
+    }
+
+    /// Record how nodes were written beyond their style, like the chomping
+    /// and indentation indicators of block scalars, in the
+    /// [`Presentation`](crate::Presentation) of scalar events and of the
+    /// nodes of loaded documents. This is off by default.
+    pub fn set_capture_presentation(&mut self, capture: bool) {
+        self.scanner.capture_presentation = capture;
+    }
+
+    /// Set if [`Document::load()`](crate::Document::load) and the other
+    /// loaders call [`Document::shrink_to_fit()`](crate::Document::shrink_to_fit)
+    /// on the documents they return, for documents that are kept for long.
+    /// This is off by default.
+    pub fn set_shrink_documents(&mut self, shrink: bool) {
+        self.shrink_documents = shrink;
+    }
+
+    /// Set the versions of the YAML specification to accept. The default is
+    /// [`SpecMode::Compatible`].
+    pub fn set_spec_mode(&mut self, spec_mode: SpecMode) {
+        self.spec_mode = spec_mode;
+    }
+
+    /// Set a hook to be called for every step of the parser.
+    ///
+    /// The hook is called with the state transitions of the parser, the
+    /// tokens it fetches from the scanner, the events it produces, and any
+    /// error that occurs. This is meant for debugging; when no hook is set,
+    /// tracing has no cost beyond checking for the hook.
+    pub fn set_trace(&mut self, hook: TraceHook) {
+        self.scanner.set_trace(hook);
+    }
+
+    /// Set statistics to be updated for every produced event.
+    ///
+    /// The statistics can be shared with other parsers, and read at any time.
+    /// See [`ParserStats`].
+    pub fn set_stats(&mut self, stats: Arc<ParserStats>) {
+        self.stats = Some(stats);
+    }
+
+    /// Set a check to be called before every token is fetched from the
+    /// input. See [`Scanner::set_interrupt_check()`].
+    pub fn set_interrupt_check(&mut self, check: InterruptCheck) {
+        self.scanner.set_interrupt_check(check);
+    }
+
     /// Parse the input stream and produce the next parsing event.
     ///
     /// Call the function subsequently to produce a sequence of events
@@ -165,13 +364,90 @@ impl<'r> Parser<'r> {
     /// the calls of [`Document::load()`](crate::Document::load). Doing this
     /// will break the parser.
     pub fn parse(&mut self) -> Result<Event> {
+        if let Some(event) = self.peeked_event.take() {
+            return Ok(event);
+        }
         if self.scanner.stream_end_produced || self.state == ParserState::End {
             return Ok(Event::stream_end());
         }
-        self.state_machine()
+        if self.scanner.trace.is_none() {
+            return self.state_machine();
+        }
+
+        let state_from = self.state;
+        let result = self.state_machine();
+        self.scanner.trace(&TraceEvent::StateTransition {
+            state_from,
+            state_to: self.state,
+        });
+        match &result {
+            Ok(event) => self.scanner.trace(&TraceEvent::Event(event)),
+            Err(err) => self.scanner.trace(&TraceEvent::Error(err)),
+        }
+        result
+    }
+
+    /// An estimate of the number of events left in an input of `input_len`
+    /// bytes, to reserve space for them in a `Vec`.
+    ///
+    /// The estimate allows for an event every 4 bytes of the input that was
+    /// not scanned yet, which is more than typical documents have. See
+    /// [`Scanner::reserve_hint()`].
+    pub fn reserve_hint(&self, input_len: usize) -> usize {
+        let scanned = usize::try_from(self.scanner.mark.index).unwrap_or(usize::MAX);
+        input_len.saturating_sub(scanned) / 4 + self.scanner.tokens.len() + 16
+    }
+
+    /// Get the next parsing event without consuming it.
+    ///
+    /// The event is kept by the parser and returned by the next call to
+    /// [`Parser::parse()`]. Peeking multiple times returns the same event.
+    pub fn peek_event(&mut self) -> Result<&Event> {
+        static STREAM_END: Event = Event {
+            data: EventData::StreamEnd,
+            start_mark: Mark {
+                index: 0,
+                line: 0,
+                column: 0,
+            },
+            end_mark: Mark {
+                index: 0,
+                line: 0,
+                column: 0,
+            },
+        };
+
+        if self.peeked_event.is_none() {
+            if self.scanner.stream_end_produced || self.state == ParserState::End {
+                // Like `parse()`, keep producing STREAM-END after the end of
+                // the stream, without making the iterator yield it again.
+                return Ok(&STREAM_END);
+            }
+            let event = self.parse()?;
+            self.peeked_event = Some(event);
+        }
+        Ok(self
+            .peeked_event
+            .as_ref()
+            .expect("peeked event should be present"))
     }
 
     fn state_machine(&mut self) -> Result<Event> {
+        let event = self.state_machine_event()?;
+        self.indicator_mark = match event.data {
+            EventData::Alias { .. }
+            | EventData::Scalar { .. }
+            | EventData::SequenceStart { .. }
+            | EventData::MappingStart { .. } => self.pending_indicator.take(),
+            _ => None,
+        };
+        if let Some(stats) = &self.stats {
+            stats.record(&event, &mut self.stats_position);
+        }
+        Ok(event)
+    }
+
+    fn state_machine_event(&mut self) -> Result<Event> {
         match self.state {
             ParserState::StreamStart => self.parse_stream_start(),
             ParserState::ImplicitDocumentStart => self.parse_document_start(true),
@@ -218,7 +494,7 @@ impl<'r> Parser<'r> {
                 end_mark: token.end_mark,
             };
             self.state = ParserState::ImplicitDocumentStart;
-            self.scanner.skip_token();
+            self.skip_token();
             Ok(event)
         } else {
             let mark = token.start_mark;
@@ -236,11 +512,12 @@ impl<'r> Parser<'r> {
 
         let mut tag_directives = vec![];
         let mut token = self.scanner.peek()?;
-        if !implicit {
-            while let TokenData::DocumentEnd = &token.data {
-                self.scanner.skip_token();
-                token = self.scanner.peek()?;
-            }
+        // Skip extra document end indicators. Unlike libyaml, this is also
+        // done at the start of the stream, so `...` alone is an empty stream,
+        // as the YAML specification allows.
+        while let TokenData::DocumentEnd = &token.data {
+            self.skip_token();
+            token = self.scanner.peek()?;
         }
         if implicit
             && !matches!(
@@ -257,8 +534,10 @@ impl<'r> Parser<'r> {
                     tag_directives: vec![],
                     implicit: true,
                 },
+                // The event is empty, like in libyaml, so that it does not
+                // overlap the first node.
                 start_mark: token.start_mark,
-                end_mark: token.end_mark,
+                end_mark: token.start_mark,
             };
             self.process_directives(None, None)?;
             self.states.push(ParserState::DocumentEnd);
@@ -282,7 +561,7 @@ impl<'r> Parser<'r> {
                 };
                 self.states.push(ParserState::DocumentEnd);
                 self.state = ParserState::DocumentContent;
-                self.scanner.skip_token();
+                self.skip_token();
                 Ok(event)
             } else {
                 Err(Error::parser(
@@ -299,7 +578,7 @@ impl<'r> Parser<'r> {
                 end_mark: token.end_mark,
             };
             self.state = ParserState::End;
-            self.scanner.skip_token();
+            self.skip_token();
             Ok(event)
         }
     }
@@ -328,7 +607,7 @@ impl<'r> Parser<'r> {
         let start_mark: Mark = end_mark;
         if let TokenData::DocumentEnd = &token.data {
             end_mark = token.end_mark;
-            self.scanner.skip_token();
+            self.skip_token();
             implicit = false;
         }
         self.tag_directives.clear();
@@ -364,7 +643,7 @@ impl<'r> Parser<'r> {
                 end_mark: token.end_mark,
             };
             self.state = self.states.pop().unwrap();
-            self.scanner.skip_token();
+            self.skip_token();
             return Ok(event);
         }
 
@@ -374,14 +653,19 @@ impl<'r> Parser<'r> {
             anchor = Some(core::mem::take(value));
             start_mark = token.start_mark;
             end_mark = token.end_mark;
-            self.scanner.skip_token();
-            token = self.scanner.peek_mut()?;
+            self.skip_token();
+            token = match self.scanner.peek_mut() {
+                Ok(token) => token,
+                Err(err) => {
+                    return Err(err.with_detail(node_properties(anchor.as_deref(), None, None)));
+                }
+            };
             if let TokenData::Tag { handle, suffix } = &mut token.data {
                 tag_handle = Some(core::mem::take(handle));
                 tag_suffix = Some(core::mem::take(suffix));
                 tag_mark = token.start_mark;
                 end_mark = token.end_mark;
-                self.scanner.skip_token();
+                self.skip_token();
             }
         } else if let TokenData::Tag { handle, suffix } = &mut token.data {
             tag_handle = Some(core::mem::take(handle));
@@ -389,18 +673,25 @@ impl<'r> Parser<'r> {
             tag_mark = token.start_mark;
             start_mark = tag_mark;
             end_mark = token.end_mark;
-            self.scanner.skip_token();
-            token = self.scanner.peek_mut()?;
+            self.skip_token();
+            token = match self.scanner.peek_mut() {
+                Ok(token) => token,
+                Err(err) => {
+                    let detail =
+                        node_properties(None, tag_handle.as_deref(), tag_suffix.as_deref());
+                    return Err(err.with_detail(detail));
+                }
+            };
             if let TokenData::Anchor { value } = &mut token.data {
                 anchor = Some(core::mem::take(value));
                 end_mark = token.end_mark;
-                self.scanner.skip_token();
+                self.skip_token();
             }
         }
 
         if let Some(ref tag_handle_value) = tag_handle {
             if tag_handle_value.is_empty() {
-                tag = tag_suffix;
+                tag = tag_suffix.take();
             } else {
                 for tag_directive in &self.tag_directives {
                     if tag_directive.handle == *tag_handle_value {
@@ -410,17 +701,63 @@ impl<'r> Parser<'r> {
                     }
                 }
                 if tag.is_none() {
+                    let detail = node_properties(
+                        anchor.as_deref(),
+                        tag_handle.as_deref(),
+                        tag_suffix.as_deref(),
+                    );
                     return Err(Error::parser(
                         "while parsing a node",
                         start_mark,
                         "found undefined tag handle",
                         tag_mark,
-                    ));
+                    )
+                    .with_detail(detail));
                 }
             }
         }
+        if self.spec_mode == SpecMode::Yaml12Strict {
+            let suffix = tag
+                .as_deref()
+                .and_then(|tag| tag.strip_prefix("tag:yaml.org,2002:"));
+            if suffix.is_some_and(|suffix| {
+                !matches!(
+                    suffix,
+                    "str" | "seq" | "map" | "null" | "bool" | "int" | "float"
+                )
+            }) {
+                // The suffix of a verbatim tag has been taken as the tag.
+                let detail = node_properties(
+                    anchor.as_deref(),
+                    tag_handle.as_deref(),
+                    tag_suffix.as_deref().or(tag.as_deref()),
+                );
+                return Err(Error::parser(
+                    "while parsing a node",
+                    start_mark,
+                    "found a tag that is not in the YAML 1.2 schemas",
+                    tag_mark,
+                )
+                .with_detail(detail));
+            }
+        }
 
-        let token = self.scanner.peek_mut()?;
+        // The anchor and tag have been consumed at this point, also if an
+        // error follows, so that the token queue is always left at the token
+        // after the properties of the node.
+        let token = match self.scanner.peek_mut() {
+            Ok(token) => token,
+            Err(err) if anchor.is_some() || tag_handle.is_some() => {
+                // A verbatim tag has been moved from the suffix to `tag`.
+                let detail = node_properties(
+                    anchor.as_deref(),
+                    tag_handle.as_deref(),
+                    tag_suffix.as_deref().or(tag.as_deref()),
+                );
+                return Err(err.with_detail(detail));
+            }
+            Err(err) => return Err(err),
+        };
 
         let implicit = tag.is_none() || tag.as_deref() == Some("");
 
@@ -447,20 +784,29 @@ impl<'r> Parser<'r> {
             } else if tag.is_none() {
                 quoted_implicit = true;
             }
+            let value = core::mem::take(value);
+            let style = *style;
+            let source = ScalarSource {
+                lines: source_lines(token.start_mark, token.end_mark),
+                presentation: (self.scanner.capture_presentation
+                    && matches!(style, ScalarStyle::Literal | ScalarStyle::Folded))
+                .then(|| self.scanner.take_presentation(end_mark)),
+            };
             let event = Event {
                 data: EventData::Scalar {
                     anchor,
                     tag,
-                    value: core::mem::take(value),
+                    value,
                     plain_implicit,
                     quoted_implicit,
-                    style: *style,
+                    style,
+                    source,
                 },
                 start_mark,
                 end_mark,
             };
             self.state = self.states.pop().unwrap();
-            self.scanner.skip_token();
+            self.skip_token();
             return Ok(event);
         } else if let TokenData::FlowSequenceStart = &token.data {
             end_mark = token.end_mark;
@@ -528,12 +874,43 @@ impl<'r> Parser<'r> {
                     plain_implicit: implicit,
                     quoted_implicit: false,
                     style: ScalarStyle::Plain,
+                    source: ScalarSource {
+                        lines: 1,
+                        presentation: None,
+                    },
                 },
                 start_mark,
                 end_mark,
             };
             return Ok(event);
         } else {
+            if !block
+                && matches!(
+                    token.data,
+                    TokenData::DocumentStart | TokenData::DocumentEnd
+                )
+            {
+                // A document indicator cannot appear inside a flow collection,
+                // so report the innermost unclosed collection instead.
+                let context = if matches!(
+                    self.states.last(),
+                    Some(
+                        ParserState::FlowMappingKey
+                            | ParserState::FlowMappingValue
+                            | ParserState::FlowMappingEmptyValue
+                    )
+                ) {
+                    "while parsing a flow mapping"
+                } else {
+                    "while parsing a flow sequence"
+                };
+                return Err(Error::parser(
+                    context,
+                    self.marks.last().copied().unwrap_or(start_mark),
+                    "found unexpected document indicator",
+                    token.start_mark,
+                ));
+            }
             return Err(Error::parser(
                 if block {
                     "while parsing a block node"
@@ -552,14 +929,15 @@ impl<'r> Parser<'r> {
             let token = self.scanner.peek()?;
             let mark = token.start_mark;
             self.marks.push(mark);
-            self.scanner.skip_token();
+            self.skip_token();
         }
 
         let mut token = self.scanner.peek()?;
 
         if let TokenData::BlockEntry = &token.data {
             let mark: Mark = token.end_mark;
-            self.scanner.skip_token();
+            self.pending_indicator = Some(token.start_mark);
+            self.skip_token();
             token = self.scanner.peek()?;
             if matches!(token.data, TokenData::BlockEntry | TokenData::BlockEnd) {
                 self.state = ParserState::BlockSequenceEntry;
@@ -569,14 +947,16 @@ impl<'r> Parser<'r> {
                 self.parse_node(true, false)
             }
         } else if let TokenData::BlockEnd = token.data {
+            // The BLOCK-END token is only produced at the next token, so the
+            // sequence ends after its last token instead.
             let event = Event {
                 data: EventData::SequenceEnd,
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
+                start_mark: self.last_token_end,
+                end_mark: self.last_token_end,
             };
             self.state = self.states.pop().unwrap();
             let _ = self.marks.pop();
-            self.scanner.skip_token();
+            self.skip_token();
             Ok(event)
         } else {
             let token_mark = token.start_mark;
@@ -594,7 +974,8 @@ impl<'r> Parser<'r> {
         let mut token = self.scanner.peek()?;
         if let TokenData::BlockEntry = token.data {
             let mark: Mark = token.end_mark;
-            self.scanner.skip_token();
+            self.pending_indicator = Some(token.start_mark);
+            self.skip_token();
             token = self.scanner.peek()?;
 
             if matches!(
@@ -610,8 +991,8 @@ impl<'r> Parser<'r> {
         } else {
             let event = Event {
                 data: EventData::SequenceEnd,
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
+                start_mark: self.last_token_end,
+                end_mark: self.last_token_end,
             };
             self.state = self.states.pop().unwrap();
             Ok(event)
@@ -623,13 +1004,14 @@ impl<'r> Parser<'r> {
             let token = self.scanner.peek()?;
             let mark = token.start_mark;
             self.marks.push(mark);
-            self.scanner.skip_token();
+            self.skip_token();
         }
 
         let mut token = self.scanner.peek()?;
         if let TokenData::Key = token.data {
             let mark: Mark = token.end_mark;
-            self.scanner.skip_token();
+            self.pending_indicator = Some(token.start_mark);
+            self.skip_token();
             token = self.scanner.peek()?;
             if matches!(
                 token.data,
@@ -641,15 +1023,22 @@ impl<'r> Parser<'r> {
                 self.states.push(ParserState::BlockMappingValue);
                 self.parse_node(true, true)
             }
+        } else if let TokenData::Value = token.data {
+            // An entry with an empty key, like `: value` after an explicit
+            // key. The VALUE token is consumed in the next state.
+            let mark = token.start_mark;
+            self.state = ParserState::BlockMappingValue;
+            Self::process_empty_scalar(mark)
         } else if let TokenData::BlockEnd = token.data {
+            // Like a block sequence, the mapping ends after its last token.
             let event = Event {
                 data: EventData::MappingEnd,
-                start_mark: token.start_mark,
-                end_mark: token.end_mark,
+                start_mark: self.last_token_end,
+                end_mark: self.last_token_end,
             };
             self.state = self.states.pop().unwrap();
             _ = self.marks.pop();
-            self.scanner.skip_token();
+            self.skip_token();
             Ok(event)
         } else {
             let token_mark = token.start_mark;
@@ -667,7 +1056,7 @@ impl<'r> Parser<'r> {
         let mut token = self.scanner.peek()?;
         if let TokenData::Value = token.data {
             let mark: Mark = token.end_mark;
-            self.scanner.skip_token();
+            self.skip_token();
             token = self.scanner.peek()?;
             if matches!(
                 token.data,
@@ -691,14 +1080,14 @@ impl<'r> Parser<'r> {
             let token = self.scanner.peek()?;
             let mark = token.start_mark;
             self.marks.push(mark);
-            self.scanner.skip_token();
+            self.skip_token();
         }
 
         let mut token = self.scanner.peek()?;
         if !matches!(token.data, TokenData::FlowSequenceEnd) {
             if !first {
                 if let TokenData::FlowEntry = token.data {
-                    self.scanner.skip_token();
+                    self.skip_token();
                     token = self.scanner.peek()?;
                 } else {
                     let token_mark = token.start_mark;
@@ -723,7 +1112,21 @@ impl<'r> Parser<'r> {
                     end_mark: token.end_mark,
                 };
                 self.state = ParserState::FlowSequenceEntryMappingKey;
-                self.scanner.skip_token();
+                self.skip_token();
+                return Ok(event);
+            } else if let TokenData::Value = token.data {
+                // A single pair with an empty key, as in `[: value]`.
+                let event = Event {
+                    data: EventData::MappingStart {
+                        anchor: None,
+                        tag: None,
+                        implicit: true,
+                        style: MappingStyle::Flow,
+                    },
+                    start_mark: token.start_mark,
+                    end_mark: token.start_mark,
+                };
+                self.state = ParserState::FlowSequenceEntryMappingKey;
                 return Ok(event);
             } else if !matches!(token.data, TokenData::FlowSequenceEnd) {
                 self.states.push(ParserState::FlowSequenceEntry);
@@ -737,7 +1140,7 @@ impl<'r> Parser<'r> {
         };
         self.state = self.states.pop().unwrap();
         _ = self.marks.pop();
-        self.scanner.skip_token();
+        self.skip_token();
         Ok(event)
     }
 
@@ -747,8 +1150,9 @@ impl<'r> Parser<'r> {
             token.data,
             TokenData::Value | TokenData::FlowEntry | TokenData::FlowSequenceEnd
         ) {
-            let mark: Mark = token.end_mark;
-            self.scanner.skip_token();
+            // The token is left for the value state, which needs to see the
+            // `:`, or the `,` or `]` after a key without a value.
+            let mark: Mark = token.start_mark;
             self.state = ParserState::FlowSequenceEntryMappingValue;
             Self::process_empty_scalar(mark)
         } else {
@@ -760,7 +1164,7 @@ impl<'r> Parser<'r> {
     fn parse_flow_sequence_entry_mapping_value(&mut self) -> Result<Event> {
         let mut token = self.scanner.peek()?;
         if let TokenData::Value = token.data {
-            self.scanner.skip_token();
+            self.skip_token();
             token = self.scanner.peek()?;
             if !matches!(
                 token.data,
@@ -792,14 +1196,14 @@ impl<'r> Parser<'r> {
             let token = self.scanner.peek()?;
             let mark = token.start_mark;
             self.marks.push(mark);
-            self.scanner.skip_token();
+            self.skip_token();
         }
 
         let mut token = self.scanner.peek()?;
         if !matches!(token.data, TokenData::FlowMappingEnd) {
             if !first {
                 if let TokenData::FlowEntry = token.data {
-                    self.scanner.skip_token();
+                    self.skip_token();
                     token = self.scanner.peek()?;
                 } else {
                     let token_mark = token.start_mark;
@@ -813,7 +1217,8 @@ impl<'r> Parser<'r> {
                 }
             }
             if let TokenData::Key = token.data {
-                self.scanner.skip_token();
+                self.pending_indicator = Some(token.start_mark);
+                self.skip_token();
                 token = self.scanner.peek()?;
                 if !matches!(
                     token.data,
@@ -837,7 +1242,7 @@ impl<'r> Parser<'r> {
         };
         self.state = self.states.pop().unwrap();
         _ = self.marks.pop();
-        self.scanner.skip_token();
+        self.skip_token();
         Ok(event)
     }
 
@@ -849,7 +1254,7 @@ impl<'r> Parser<'r> {
             return Self::process_empty_scalar(mark);
         }
         if let TokenData::Value = token.data {
-            self.scanner.skip_token();
+            self.skip_token();
             token = self.scanner.peek()?;
             if !matches!(token.data, TokenData::FlowEntry | TokenData::FlowMappingEnd) {
                 self.states.push(ParserState::FlowMappingKey);
@@ -870,6 +1275,10 @@ impl<'r> Parser<'r> {
                 plain_implicit: true,
                 quoted_implicit: false,
                 style: ScalarStyle::Plain,
+                source: ScalarSource {
+                    lines: 1,
+                    presentation: None,
+                },
             },
             start_mark: mark,
             end_mark: mark,
@@ -922,6 +1331,13 @@ impl<'r> Parser<'r> {
                         "found incompatible YAML document",
                         mark,
                     ));
+                } else if *minor != 2 && self.spec_mode == SpecMode::Yaml12Strict {
+                    return Err(Error::parser(
+                        "",
+                        Mark::default(),
+                        "found a YAML 1.1 document in strict YAML 1.2 mode",
+                        mark,
+                    ));
                 }
                 version_directive = Some(VersionDirective {
                     major: *major,
@@ -938,7 +1354,7 @@ impl<'r> Parser<'r> {
                 tag_directives.push(value);
             }
 
-            self.scanner.skip_token();
+            self.skip_token();
             token = self.scanner.peek_mut()?;
         }
 
@@ -987,7 +1403,916 @@ impl<'r> Parser<'r> {
         Ok(())
     }
 
+    /// Consume the next token.
+    fn skip_token(&mut self) {
+        if let Some(token) = self.scanner.tokens.front() {
+            if !matches!(token.data, TokenData::BlockEnd) {
+                self.last_token_end = token.end_mark;
+            }
+        }
+        self.scanner.skip_token();
+    }
+
     pub(crate) fn delete_aliases(&mut self) {
         self.aliases.clear();
     }
+
+    /// Take a snapshot of the parser state, between two events.
+    ///
+    /// Unlike the marks, which count the UTF-8 encoding of the characters,
+    /// the offset of the checkpoint counts the bytes of the input, so it also
+    /// works for UTF-16 input.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn checkpoint(&self) -> ParserCheckpoint {
+        debug_assert!(self.peeked_event.is_none());
+        let utf16 = matches!(self.scanner.encoding, Encoding::Utf16Le | Encoding::Utf16Be);
+        let buffered: usize = self
+            .scanner
+            .buffer
+            .iter()
+            .map(|ch| {
+                if utf16 {
+                    ch.len_utf16() * 2
+                } else {
+                    ch.len_utf8()
+                }
+            })
+            .sum();
+        ParserCheckpoint {
+            scanner: self.scanner.checkpoint(),
+            input_offset: self.scanner.offset - buffered,
+            states: self.states.clone(),
+            state: self.state,
+            marks: self.marks.clone(),
+            tag_directives: self.tag_directives.clone(),
+            pending_indicator: self.pending_indicator,
+            indicator_mark: self.indicator_mark,
+            last_token_end: self.last_token_end,
+            stats_position: self.stats_position,
+        }
+    }
+
+    /// Resume parsing from a checkpoint, with an input that starts at the
+    /// offset of the checkpoint. The settings of the parser are kept.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn restore(
+        &mut self,
+        checkpoint: ParserCheckpoint,
+        new_input: &'r mut dyn std::io::BufRead,
+    ) {
+        let ParserCheckpoint {
+            scanner,
+            input_offset,
+            states,
+            state,
+            marks,
+            tag_directives,
+            pending_indicator,
+            indicator_mark,
+            last_token_end,
+            stats_position,
+        } = checkpoint;
+        self.scanner.restore(scanner, new_input);
+        self.scanner.offset = input_offset;
+        self.states = states;
+        self.state = state;
+        self.marks = marks;
+        self.tag_directives = tag_directives;
+        self.peeked_event = None;
+        self.pending_indicator = pending_indicator;
+        self.indicator_mark = indicator_mark;
+        self.last_token_end = last_token_end;
+        self.stats_position = stats_position;
+    }
+}
+
+/// Describe the anchor and tag of a node for an error message, as written in
+/// the input.
+fn node_properties(
+    anchor: Option<&str>,
+    tag_handle: Option<&str>,
+    tag_suffix: Option<&str>,
+) -> String {
+    let tag = tag_handle.map(|handle| {
+        let suffix = tag_suffix.unwrap_or("");
+        if !handle.is_empty() || suffix == "!" {
+            alloc::format!("{handle}{suffix}")
+        } else {
+            alloc::format!("!<{suffix}>")
+        }
+    });
+    match (anchor, tag) {
+        (Some(anchor), Some(tag)) => alloc::format!("anchor &{anchor} and tag {tag}"),
+        (Some(anchor), None) => alloc::format!("anchor &{anchor}"),
+        (None, Some(tag)) => alloc::format!("tag {tag}"),
+        (None, None) => String::new(),
+    }
+}
+
+/// The number of lines of a scalar token, where an end at the start of a line
+/// follows the last line break of a block scalar.
+fn source_lines(start_mark: Mark, end_mark: Mark) -> u32 {
+    let mut lines = end_mark.line - start_mark.line + 1;
+    if end_mark.column == 0 && end_mark.line > start_mark.line {
+        lines -= 1;
+    }
+    u32::try_from(lines).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    const INPUT: &str = "\
+%YAML 1.1
+--- !!map
+a: [b, {c: d}]
+? e
+: &f |
+  g
+h: *f
+--- i
+";
+
+    fn parse_all(peek: bool) -> Vec<Event> {
+        let mut parser = Parser::new();
+        let mut input = INPUT.as_bytes();
+        parser.set_input_string(&mut input);
+        let mut events = Vec::new();
+        for event in core::iter::from_fn(|| {
+            if peek {
+                let peeked = format!("{:?}", parser.peek_event().unwrap());
+                assert_eq!(format!("{:?}", parser.peek_event().unwrap()), peeked);
+            }
+            parser.next()
+        }) {
+            events.push(event.unwrap());
+        }
+        events
+    }
+
+    #[test]
+    fn collection_tags() {
+        for (input, tag, implicit) in [
+            ("key: {}", None, true),
+            ("key: {a: b}", None, true),
+            ("key: ! {}", Some("!"), false),
+            ("key: !!map {}", Some("tag:yaml.org,2002:map"), false),
+            ("key: !e {}", Some("!e"), false),
+            ("key: !\n  a: b", Some("!"), false),
+            ("key: []", None, true),
+            ("key: ! []", Some("!"), false),
+            ("key: !!seq [a]", Some("tag:yaml.org,2002:seq"), false),
+            ("key: !e []", Some("!e"), false),
+            ("key: !\n- a", Some("!"), false),
+        ] {
+            let events = crate::parse_events(input).unwrap();
+            match &events[4].data {
+                EventData::SequenceStart {
+                    tag: event_tag,
+                    implicit: event_implicit,
+                    ..
+                }
+                | EventData::MappingStart {
+                    tag: event_tag,
+                    implicit: event_implicit,
+                    ..
+                } => {
+                    assert_eq!(event_tag.as_deref(), tag, "{input:?}");
+                    assert_eq!(*event_implicit, implicit, "{input:?}");
+                }
+                data => panic!("{input:?}: {data:?}"),
+            }
+            let output = crate::emit_events(events).unwrap();
+            assert_eq!(output.trim_end(), input);
+        }
+    }
+
+    #[test]
+    fn peek_event() {
+        let events = parse_all(false);
+        assert!(matches!(events.last().unwrap().data, EventData::StreamEnd));
+        assert_eq!(parse_all(true), events);
+    }
+
+    #[test]
+    fn peek_event_after_end() {
+        let mut parser = Parser::new();
+        let mut input = "a".as_bytes();
+        parser.set_input_string(&mut input);
+        assert_eq!(parser.by_ref().count(), 5);
+        assert_eq!(parser.peek_event().unwrap().data, EventData::StreamEnd);
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn peek_event_reset() {
+        let mut parser = Parser::new();
+        let mut input = INPUT.as_bytes();
+        parser.set_input_string(&mut input);
+        parser.parse().unwrap();
+        assert!(matches!(
+            parser.peek_event().unwrap().data,
+            EventData::DocumentStart { .. }
+        ));
+        parser.reset();
+        assert!(parser.peeked_event.is_none());
+
+        let mut input = "a".as_bytes();
+        parser.set_input_string(&mut input);
+        assert!(matches!(
+            parser.peek_event().unwrap().data,
+            EventData::StreamStart { .. }
+        ));
+    }
+
+    fn trace(input: &'static str) -> Vec<String> {
+        let records = alloc::rc::Rc::new(core::cell::RefCell::new(Vec::new()));
+        let mut parser = Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        let hook_records = records.clone();
+        parser.set_trace(Box::new(move |event| {
+            let record = match event {
+                TraceEvent::StateTransition {
+                    state_from,
+                    state_to,
+                } => format!("{state_from:?} -> {state_to:?}"),
+                TraceEvent::Token(token) => format!("  token {:?}", token.data),
+                TraceEvent::Event(event) => format!("  event {:?}", event.data),
+                TraceEvent::Error(err) => format!("  error {err}"),
+            };
+            hook_records.borrow_mut().push(record);
+        }));
+        for event in parser {
+            if event.is_err() {
+                break;
+            }
+        }
+        records.take()
+    }
+
+    #[test]
+    fn trace_hook() {
+        let records = trace("a: [b]\n");
+        let transitions: Vec<_> = records
+            .iter()
+            .filter(|record| !record.starts_with(' '))
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            transitions,
+            [
+                "StreamStart -> ImplicitDocumentStart",
+                "ImplicitDocumentStart -> BlockNode",
+                "BlockNode -> BlockMappingFirstKey",
+                "BlockMappingFirstKey -> BlockMappingValue",
+                "BlockMappingValue -> FlowSequenceFirstEntry",
+                "FlowSequenceFirstEntry -> FlowSequenceEntry",
+                "FlowSequenceEntry -> BlockMappingKey",
+                "BlockMappingKey -> DocumentEnd",
+                "DocumentEnd -> DocumentStart",
+                "DocumentStart -> End",
+            ]
+        );
+        // Each step is followed by the event it produced.
+        assert_eq!(records[1], "StreamStart -> ImplicitDocumentStart");
+        assert_eq!(records[2], "  event StreamStart { encoding: Utf8 }");
+        assert_eq!(
+            records.iter().filter(|r| r.starts_with("  token")).count(),
+            10
+        );
+        assert_eq!(records.last().unwrap(), "  event StreamEnd");
+    }
+
+    #[test]
+    fn trace_hook_error() {
+        let records = trace("a: [b\n");
+        let errors: Vec<_> = records
+            .iter()
+            .filter(|record| record.starts_with("  error"))
+            .collect();
+        assert_eq!(errors.len(), 1, "{records:#?}");
+        assert!(
+            errors[0].contains("found unexpected end of stream"),
+            "{}",
+            errors[0]
+        );
+        assert_eq!(records.last(), Some(errors[0]));
+    }
+
+    /// Parse `input`, returning a compact description of the content events
+    /// or the parser error.
+    fn flow_content(input: &str) -> core::result::Result<Vec<String>, Error> {
+        let mut parser = Parser::new();
+        let mut bytes = input.as_bytes();
+        parser.set_input_string(&mut bytes);
+        let mut content = Vec::new();
+        for event in parser {
+            match event?.data {
+                EventData::Scalar { value, .. } => content.push(value),
+                EventData::SequenceStart { .. } => content.push("[".to_string()),
+                EventData::SequenceEnd => content.push("]".to_string()),
+                EventData::MappingStart { .. } => content.push("{".to_string()),
+                EventData::MappingEnd => content.push("}".to_string()),
+                _ => {}
+            }
+        }
+        Ok(content)
+    }
+
+    #[test]
+    fn flow_trailing_comma() {
+        for (input, expected) in [
+            ("[a, b]", "[ a b ]"),
+            ("[a, b,]", "[ a b ]"),
+            ("[a, b, ]", "[ a b ]"),
+            ("[a,\n b,\n]", "[ a b ]"),
+            ("[a: 1, ]", "[ { a 1 } ]"),
+            ("[[a], ]", "[ [ a ] ]"),
+            ("[]", "[ ]"),
+            ("{a: 1, b: 2}", "{ a 1 b 2 }"),
+            ("{a: 1, b: 2,}", "{ a 1 b 2 }"),
+            ("{a: 1, b: 2, }", "{ a 1 b 2 }"),
+            ("{a: 1,\n b: 2,\n}", "{ a 1 b 2 }"),
+            ("{a, }", "{ a  }"),
+            ("{a: {b: c}, }", "{ a { b c } }"),
+            ("{}", "{ }"),
+        ] {
+            assert_eq!(
+                flow_content(input).unwrap().join(" "),
+                expected,
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn flow_empty_entry() {
+        // An empty entry is an error at the comma that follows it.
+        for (input, column) in [
+            ("[,]", 1),
+            ("[, a]", 1),
+            ("[a, , b]", 4),
+            ("[a,,]", 3),
+            ("[a, b, , ]", 7),
+            ("[a: 1, , b]", 7),
+            ("{,}", 1),
+            ("{, a: 1}", 1),
+            ("{a: 1, , b: 2}", 7),
+            ("{a: 1,,}", 6),
+            ("{a: 1, b: 2, , }", 13),
+        ] {
+            let err = flow_content(input).unwrap_err();
+            assert_eq!(
+                err.problem(),
+                "did not find expected node content",
+                "{input:?}"
+            );
+            let mark = err.problem_mark().unwrap();
+            assert_eq!((mark.line, mark.column), (0, column), "{input:?}");
+            assert_eq!(&input[column as usize..=column as usize], ",");
+        }
+    }
+
+    #[test]
+    fn unclosed_flow_collections() {
+        // Errors in unclosed flow collections point back at the innermost
+        // opening indicator.
+        for (input, context, opener, problem) in [
+            ("{a: [1, 2", "while scanning a flow sequence", 4, 9),
+            ("[a, {b: c", "while scanning a flow mapping", 4, 9),
+            ("[a, [b\n---\n", "while parsing a flow sequence", 4, 7),
+            ("[a, [b,\n---\n", "while parsing a flow sequence", 4, 8),
+            ("[a, {b: c,\n...\n", "while parsing a flow mapping", 4, 11),
+            ("{a: [b], c:\n---\n", "while parsing a flow mapping", 0, 12),
+            (
+                "[a, {b: [c]},\n--- x\n",
+                "while parsing a flow sequence",
+                0,
+                14,
+            ),
+        ] {
+            let err = flow_content(input).unwrap_err();
+            assert_eq!(err.context(), Some(context), "{input:?}");
+            assert_eq!(err.context_mark().unwrap().index, opener, "{input:?}");
+            assert_eq!(err.problem_mark().unwrap().index, problem, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn node_properties_in_errors() {
+        for (input, kind, detail) in [
+            ("&a !e!x value", ErrorKind::Parser, "anchor &a and tag !e!x"),
+            ("!e!x &a value", ErrorKind::Parser, "anchor &a and tag !e!x"),
+            ("key: !e!x value", ErrorKind::Parser, "tag !e!x"),
+            // Scanner errors only get the details if the scanner has already
+            // returned the properties, which it holds back while they may be
+            // the start of a simple key. They cannot be after `---`.
+            ("--- &a @bad", ErrorKind::Scanner, "anchor &a"),
+            ("--- !t @bad", ErrorKind::Scanner, "tag !t"),
+            ("--- !t &a @bad", ErrorKind::Scanner, "anchor &a and tag !t"),
+            ("--- &a !t @bad", ErrorKind::Scanner, "anchor &a and tag !t"),
+            ("--- !<tag:x> @bad", ErrorKind::Scanner, "tag !<tag:x>"),
+            ("--- ! @bad", ErrorKind::Scanner, "tag !"),
+        ] {
+            let err = flow_content(input).unwrap_err();
+            assert_eq!(err.kind(), kind, "{input:?}");
+            assert_eq!(err.detail(), Some(detail), "{input:?}");
+            assert!(err.to_string().contains(detail), "{err}");
+        }
+
+        let err = flow_content("&a !e!x value").unwrap_err();
+        assert_eq!(err.problem(), "found undefined tag handle");
+        assert_eq!(err.context_mark().unwrap().index, 0);
+        assert_eq!(err.problem_mark().unwrap().index, 3);
+        assert_eq!(
+            err.to_string(),
+            "Parser error: line 0 column 3: found undefined tag handle while parsing \
+             a node with anchor &a and tag !e!x (line 0 column 0)"
+        );
+
+        // The properties are consumed, and the token after them is left in
+        // the queue.
+        let mut parser = Parser::new();
+        let mut input = "key: &a !e!x value".as_bytes();
+        parser.set_input_string(&mut input);
+        let err = loop {
+            if let Err(err) = parser.parse() {
+                break err;
+            }
+        };
+        assert_eq!(err.problem(), "found undefined tag handle");
+        assert!(matches!(
+            &parser.scanner.peek().unwrap().data,
+            TokenData::Scalar { value, .. } if value == "value"
+        ));
+
+        // Errors without node properties have no details.
+        assert_eq!(flow_content("@bad").unwrap_err().detail(), None);
+        assert_eq!(flow_content("[a, , b]").unwrap_err().detail(), None);
+    }
+
+    #[test]
+    fn directives_per_document() {
+        fn directives(input: &str) -> Vec<(Option<VersionDirective>, Vec<String>, String)> {
+            crate::parse_documents(input)
+                .unwrap()
+                .into_iter()
+                .map(|mut document| {
+                    let handles = document
+                        .tag_directives
+                        .iter()
+                        .map(|tag_directive| {
+                            tag_directive.handle.clone() + tag_directive.prefix.as_str()
+                        })
+                        .collect();
+                    let root = match &document.get_root_node().unwrap().data {
+                        crate::NodeData::Scalar { value, .. } => value.clone(),
+                        _ => unreachable!(),
+                    };
+                    (document.version_directive, handles, root)
+                })
+                .collect()
+        }
+
+        let v12 = Some(VersionDirective { major: 1, minor: 2 });
+        let v11 = Some(VersionDirective { major: 1, minor: 1 });
+
+        // An empty explicit document followed by one with its own directives.
+        assert_eq!(
+            directives("%YAML 1.2\n---\n...\n%YAML 1.2\n---\nfoo\n"),
+            [
+                (v12, vec![], String::new()),
+                (v12, vec![], String::from("foo"))
+            ]
+        );
+        assert_eq!(
+            directives(
+                "%YAML 1.1\n%TAG !e! tag:a,2000:\n--- !e!x a\n...\n\
+                 %YAML 1.2\n%TAG !e! tag:b,2000:\n--- !e!x b\n...\n\
+                 --- c\n"
+            ),
+            [
+                (v11, vec![String::from("!e!tag:a,2000:")], String::from("a")),
+                (v12, vec![String::from("!e!tag:b,2000:")], String::from("b")),
+                (None, vec![], String::from("c")),
+            ]
+        );
+        // A document with directives but no content, at the end of the
+        // stream.
+        assert_eq!(
+            directives("a\n...\n%YAML 1.2\n%TAG !e! tag:a,2000:\n---\n"),
+            [
+                (None, vec![], String::from("a")),
+                (v12, vec![String::from("!e!tag:a,2000:")], String::new()),
+            ]
+        );
+
+        // The tag handles of a document are not available in the next one.
+        let err =
+            crate::parse_events("%TAG !e! tag:a,2000:\n--- a\n...\n--- !e!x b\n").unwrap_err();
+        assert_eq!(err.problem(), "found undefined tag handle");
+        // Directives must be followed by a document.
+        let err = crate::parse_events("a\n...\n%YAML 1.2\n").unwrap_err();
+        assert_eq!(err.problem(), "did not find expected <document start>");
+        // Duplicates are only reported within a document.
+        let err = crate::parse_events("%YAML 1.2\n%YAML 1.2\n---\n").unwrap_err();
+        assert_eq!(err.problem(), "found duplicate %YAML directive");
+        let err = crate::parse_events("%TAG !e! a\n%TAG !e! b\n---\n").unwrap_err();
+        assert_eq!(err.problem(), "found duplicate %TAG directive");
+    }
+
+    /// Render the events of `input` like the yaml-test-suite does, without
+    /// the stream events.
+    fn tree(input: &str) -> core::result::Result<String, String> {
+        use core::fmt::Write as _;
+
+        let mut output = Vec::new();
+        for event in crate::parse_events(input).map_err(|err| err.problem().to_string())? {
+            let properties = |anchor: Option<String>, tag: Option<String>| {
+                let mut properties = String::new();
+                if let Some(anchor) = anchor {
+                    write!(properties, " &{anchor}").unwrap();
+                }
+                if let Some(tag) = tag {
+                    write!(properties, " <{tag}>").unwrap();
+                }
+                properties
+            };
+            output.push(match event.data {
+                EventData::StreamStart { .. } | EventData::StreamEnd => continue,
+                EventData::DocumentStart { implicit, .. } => {
+                    String::from(if implicit { "+DOC" } else { "+DOC ---" })
+                }
+                EventData::DocumentEnd { implicit } => {
+                    String::from(if implicit { "-DOC" } else { "-DOC ..." })
+                }
+                EventData::Alias { anchor } => format!("=ALI *{anchor}"),
+                EventData::Scalar {
+                    anchor,
+                    tag,
+                    value,
+                    style,
+                    ..
+                } => {
+                    let indicator = match style {
+                        ScalarStyle::SingleQuoted => '\'',
+                        ScalarStyle::DoubleQuoted => '"',
+                        ScalarStyle::Literal => '|',
+                        ScalarStyle::Folded => '>',
+                        _ => ':',
+                    };
+                    format!("=VAL{} {indicator}{value}", properties(anchor, tag))
+                }
+                EventData::SequenceStart { anchor, tag, .. } => {
+                    format!("+SEQ{}", properties(anchor, tag))
+                }
+                EventData::SequenceEnd => String::from("-SEQ"),
+                EventData::MappingStart { anchor, tag, .. } => {
+                    format!("+MAP{}", properties(anchor, tag))
+                }
+                EventData::MappingEnd => String::from("-MAP"),
+            });
+        }
+        Ok(output.join(" "))
+    }
+
+    #[test]
+    fn minimal_documents() {
+        // These match libyaml, except where noted.
+        for (input, expected) in [
+            ("", ""),
+            (" ", ""),
+            ("\n", ""),
+            ("# comment", ""),
+            // A `-` followed by the end of the input is a block sequence entry,
+            // as the end of the input counts as a line break.
+            ("-", "+DOC +SEQ =VAL : -SEQ -DOC"),
+            ("- ", "+DOC +SEQ =VAL : -SEQ -DOC"),
+            ("-\n", "+DOC +SEQ =VAL : -SEQ -DOC"),
+            ("?", "+DOC +MAP =VAL : =VAL : -MAP -DOC"),
+            (":", "+DOC +MAP =VAL : =VAL : -MAP -DOC"),
+            ("---", "+DOC --- =VAL : -DOC"),
+            ("--- ...", "+DOC --- =VAL :... -DOC"),
+            ("---\n...", "+DOC --- =VAL : -DOC ..."),
+            // libyaml reports an error for a document end indicator at the
+            // start of the stream.
+            ("...", ""),
+            ("...\n...\n", ""),
+            ("...\na", "+DOC =VAL :a -DOC"),
+            ("--", "+DOC =VAL :-- -DOC"),
+            ("----", "+DOC =VAL :---- -DOC"),
+            ("-a", "+DOC =VAL :-a -DOC"),
+            ("?a", "+DOC =VAL :?a -DOC"),
+            (":a", "+DOC =VAL ::a -DOC"),
+            ("!", "+DOC =VAL <!> : -DOC"),
+            ("&a", "+DOC =VAL &a : -DOC"),
+            ("*a", "+DOC =ALI *a -DOC"),
+            ("|", "+DOC =VAL | -DOC"),
+            (">", "+DOC =VAL > -DOC"),
+            ("''", "+DOC =VAL ' -DOC"),
+            ("[]", "+DOC +SEQ -SEQ -DOC"),
+            ("{}", "+DOC +MAP -MAP -DOC"),
+        ] {
+            assert_eq!(tree(input).as_deref(), Ok(expected), "{input:?}");
+        }
+
+        for (input, problem) in [
+            ("'", "found unexpected end of stream"),
+            ("\"", "found unexpected end of stream"),
+            ("[", "found unexpected end of stream"),
+            ("]", "did not find expected node content"),
+            ("}", "did not find expected node content"),
+            ("%", "could not find expected directive name"),
+            ("%YAML 1.2", "did not find expected <document start>"),
+            (
+                "a\n...\n%YAML 1.2\n",
+                "did not find expected <document start>",
+            ),
+        ] {
+            assert_eq!(tree(input).unwrap_err(), problem, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn flow_pairs() {
+        // Spec example 7.21, Single Pair Implicit Entries.
+        assert_eq!(
+            tree("- [ YAML : separate ]\n- [ : empty key entry ]\n- [ {JSON: like}:adjacent ]\n")
+                .as_deref(),
+            Ok("+DOC +SEQ \
+                +SEQ +MAP =VAL :YAML =VAL :separate -MAP -SEQ \
+                +SEQ +MAP =VAL : =VAL :empty key entry -MAP -SEQ \
+                +SEQ +MAP +MAP =VAL :JSON =VAL :like -MAP =VAL :adjacent -MAP -SEQ \
+                -SEQ -DOC"),
+        );
+
+        // Each pair is its own mapping, whatever the spacing.
+        for input in [
+            "[a: 1, b: 2]",
+            "[a: 1,b: 2]",
+            "[ a : 1 , b : 2 ]",
+            "[a:  1 ,\n  b: 2\n]",
+            "[\"a\":1, 'b':2]",
+            "[? a : 1, b: 2]",
+            "[a: 1, b: 2,]",
+        ] {
+            let tree = tree(input).unwrap();
+            let pairs = tree.replace(['"', '\''], ":");
+            assert_eq!(
+                pairs, "+DOC +SEQ +MAP =VAL :a =VAL :1 -MAP +MAP =VAL :b =VAL :2 -MAP -SEQ -DOC",
+                "{input:?}"
+            );
+        }
+
+        // Explicit and empty keys leave the `:`, `,`, or `]` after them to the
+        // rest of the sequence.
+        for (input, expected) in [
+            ("[? : x]", "+MAP =VAL : =VAL :x -MAP"),
+            ("[?, b]", "+MAP =VAL : =VAL : -MAP =VAL :b"),
+            ("[? ]", "+MAP =VAL : =VAL : -MAP"),
+            ("[? a, b]", "+MAP =VAL :a =VAL : -MAP =VAL :b"),
+            (
+                "[a: 1, : 2]",
+                "+MAP =VAL :a =VAL :1 -MAP +MAP =VAL : =VAL :2 -MAP",
+            ),
+            ("[: ]", "+MAP =VAL : =VAL : -MAP"),
+        ] {
+            assert_eq!(
+                tree(input).as_deref(),
+                Ok(format!("+DOC +SEQ {expected} -SEQ -DOC").as_str()),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_input() {
+        let input = "\
+settings:
+  name: \"server\"
+  ports: [80, {tls: 443}]
+  notes: 'done'
+";
+        for (cut, context, context_mark) in [
+            ("\"ser", "while scanning a quoted scalar", (1, 8)),
+            ("[80,", "while scanning a flow sequence", (2, 9)),
+            ("{tls: ", "while scanning a flow mapping", (2, 14)),
+            ("{tls: 443}", "while scanning a flow sequence", (2, 9)),
+            ("'do", "while scanning a quoted scalar", (3, 9)),
+        ] {
+            let end = input.find(cut).unwrap() + cut.len();
+            let mut bytes = &input.as_bytes()[..end];
+            let mut parser = Parser::new();
+            parser.set_input_string(&mut bytes);
+            let err = parser.collect::<Result<Vec<_>>>().unwrap_err();
+            assert_eq!(err.problem(), "found unexpected end of stream", "{cut:?}");
+            assert_eq!(err.context(), Some(context), "{cut:?}");
+            let mark = err.context_mark().unwrap();
+            assert_eq!((mark.line, mark.column), context_mark, "{cut:?}");
+            assert_eq!(err.problem_mark().unwrap().index, end as u64, "{cut:?}");
+        }
+
+        // Block collections end with the input, so a cut between lines is a
+        // complete document.
+        let end = input.find("  notes").unwrap();
+        let mut bytes = &input.as_bytes()[..end];
+        let mut parser = Parser::new();
+        parser.set_input_string(&mut bytes);
+        assert!(parser.collect::<Result<Vec<_>>>().is_ok());
+    }
+
+    #[test]
+    fn interrupt_check() {
+        let interrupted = std::sync::Arc::new(core::sync::atomic::AtomicBool::new(false));
+        let mut parser = Parser::new();
+        let mut input = "a: [b, c]\nd: e\n".as_bytes();
+        parser.set_input_string(&mut input);
+        let check_interrupted = interrupted.clone();
+        parser.set_interrupt_check(Box::new(move || {
+            check_interrupted.load(core::sync::atomic::Ordering::Relaxed)
+        }));
+        let mut events = 0;
+        let err = loop {
+            match parser.parse() {
+                Ok(_) => events += 1,
+                Err(err) => break err,
+            }
+            if events == 5 {
+                interrupted.store(true, core::sync::atomic::Ordering::Relaxed);
+            }
+        };
+        assert_eq!(err.kind(), ErrorKind::Interrupted);
+        // The scanner stopped before the token for `b`.
+        assert_eq!(events, 5);
+        let mark = err.problem_mark().unwrap();
+        assert_eq!((mark.line, mark.column), (0, 4));
+    }
+
+    #[test]
+    fn spec_mode() {
+        fn parse(input: &str, spec_mode: SpecMode) -> Result<Vec<Event>> {
+            let mut parser = Parser::new();
+            let mut input = input.as_bytes();
+            parser.set_input_string(&mut input);
+            parser.set_spec_mode(spec_mode);
+            parser.collect()
+        }
+
+        for input in [
+            "%YAML 1.2\n--- a\n",
+            "a: yes\nb: on\nc: 0b101\nd: 1:30\ne: 0o17\n",
+            "- !!str a\n- !!int 1\n- !!float 1.5\n- !!bool true\n- !!null ~\n",
+            "!!map {a: !!seq []}",
+            "- !<tag:yaml.org,2002:str> a\n- !local b\n- !<tag:example.com,2000:x> c\n",
+            "\"\\x41\\u0041\\N\"",
+        ] {
+            parse(input, SpecMode::Compatible).unwrap();
+            parse(input, SpecMode::Yaml12Strict).unwrap();
+        }
+
+        for (input, problem, column, detail) in [
+            (
+                "%YAML 1.1\n--- a\n",
+                "found a YAML 1.1 document in strict YAML 1.2 mode",
+                0,
+                None,
+            ),
+            (
+                "- !!binary aGk=\n",
+                "found a tag that is not in the YAML 1.2 schemas",
+                2,
+                Some("tag !!binary"),
+            ),
+            (
+                "a: !!python/tuple [1, 2]\n",
+                "found a tag that is not in the YAML 1.2 schemas",
+                3,
+                Some("tag !!python/tuple"),
+            ),
+            (
+                "&s !<tag:yaml.org,2002:set> {a}\n",
+                "found a tag that is not in the YAML 1.2 schemas",
+                3,
+                Some("anchor &s and tag !<tag:yaml.org,2002:set>"),
+            ),
+        ] {
+            parse(input, SpecMode::Compatible).unwrap();
+            let err = parse(input, SpecMode::Yaml12Strict).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::Parser, "{input:?}");
+            assert_eq!(err.problem(), problem, "{input:?}");
+            assert_eq!(err.problem_mark().unwrap().column, column, "{input:?}");
+            assert_eq!(err.detail(), detail, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn block_collection_end_marks() {
+        for (input, ends) in [
+            ("- a: 1\n  b: 2\n- c\n", &[(1, 6), (2, 3)][..]),
+            ("- a: 1\n  b: 2 # comment\n\n- c\n", &[(1, 6), (3, 3)]),
+            ("- - x\n  - y\n- z\n", &[(1, 5), (2, 3)]),
+            ("a:\n  b: c\n  d: e\nf: g\n", &[(2, 6), (3, 4)]),
+            ("a:\n- b\n- c\nd:\n", &[(2, 3), (3, 2)]),
+            (
+                "- a:\n    - b: c\n      d:\n",
+                &[(2, 8), (2, 8), (2, 8), (2, 8)],
+            ),
+            ("a: |\n  text\n\nb: 1\n\n\n", &[(3, 4)]),
+            ("--- \n- x\n...\n", &[(1, 3)]),
+        ] {
+            let events = crate::parse_events(input).unwrap();
+            let marks: Vec<_> = events
+                .iter()
+                .filter(|event| {
+                    matches!(event.data, EventData::SequenceEnd | EventData::MappingEnd)
+                })
+                .map(|event| {
+                    assert_eq!(event.start_mark, event.end_mark);
+                    (event.end_mark.line, event.end_mark.column)
+                })
+                .collect();
+            assert_eq!(marks, ends, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn properties_only_documents() {
+        // A document with only an anchor or a tag holds an empty scalar that
+        // spans the properties, and the events around it do not overlap it.
+        for (input, marks) in [
+            ("&a", [(0, 0), (0, 0), (0, 2), (2, 2), (2, 2)]),
+            ("!foo", [(0, 0), (0, 0), (0, 4), (4, 4), (4, 4)]),
+            ("&a !foo", [(0, 0), (0, 0), (0, 7), (7, 7), (7, 7)]),
+            ("!foo &a", [(0, 0), (0, 0), (0, 7), (7, 7), (7, 7)]),
+            ("!foo &a\n", [(0, 0), (0, 0), (0, 7), (8, 8), (8, 8)]),
+            (
+                "--- &a !foo\n",
+                [(0, 0), (0, 3), (4, 11), (12, 12), (12, 12)],
+            ),
+        ] {
+            let events = crate::parse_events(input).unwrap();
+            let indexes: Vec<_> = events
+                .iter()
+                .map(|event| (event.start_mark.index, event.end_mark.index))
+                .collect();
+            assert_eq!(indexes, marks, "{input:?}");
+            let EventData::Scalar {
+                anchor, tag, value, ..
+            } = &events[2].data
+            else {
+                panic!("{input:?}");
+            };
+            assert_eq!(value, "", "{input:?}");
+            assert_eq!(anchor.is_some(), input.contains("&a"), "{input:?}");
+            assert_eq!(tag.is_some(), input.contains("!foo"), "{input:?}");
+
+            let mut parser = Parser::new();
+            let mut bytes = input.as_bytes();
+            parser.set_input_string(&mut bytes);
+            let document = crate::Document::load(&mut parser).unwrap();
+            assert_eq!(document.nodes.len(), 1, "{input:?}");
+            let node = &document.nodes[0];
+            assert!(
+                matches!(&node.data, crate::NodeData::Scalar { value, .. } if value.is_empty()),
+                "{input:?}"
+            );
+            assert_eq!(
+                (node.start_mark.index, node.end_mark.index),
+                marks[2],
+                "{input:?}"
+            );
+            if input.contains("&a") {
+                assert_eq!(document.anchor("a"), Some(1), "{input:?}");
+            }
+            if input.contains("!foo") {
+                assert_eq!(node.tag.as_deref(), Some("!foo"), "{input:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn reserve_hint() {
+        for input in [
+            "",
+            "a: b\n",
+            "a:\n b:\n  c: d\n",
+            "{a: 1, b: 2, c: [x, y]}\n",
+            "- name: example\n  version: 1.0\n  items:\n    - one\n    - two\n",
+            "key: value\nlist:\n- 1\n- 2\n- 3\nmap: {x: 1, y: 2}\n",
+            include_str!("../benches/very_large.yml"),
+        ] {
+            let mut bytes = input.as_bytes();
+            let mut parser = Parser::new();
+            parser.set_input_string(&mut bytes);
+            assert_eq!(parser.size_hint(), (1, None));
+            let mut events = Vec::with_capacity(parser.reserve_hint(input.len()));
+            let capacity = events.capacity();
+            events.extend(&mut parser);
+            assert_eq!(events.capacity(), capacity, "{input:?}");
+            assert_eq!(parser.size_hint(), (0, Some(0)));
+        }
+    }
 }
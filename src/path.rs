@@ -0,0 +1,500 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use crate::{EventData, Mark, Parser, Result};
+
+/// A step from a collection to one of its children, in a [`NodePath`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PathSegment {
+    /// The value of the pair with this scalar key in a mapping.
+    Key(String),
+    /// The value of a pair whose key is a sequence or a mapping.
+    ComplexKey,
+    /// The item at this position in a sequence.
+    Index(usize),
+}
+
+/// The position of a node in a document, as the steps from the root node.
+///
+/// Paths are displayed like `.spec.containers[2].image`. Keys that are not
+/// made of letters, digits, `_`, and `-` are quoted, as in `["a key"]`, and
+/// keys that are not scalars are shown as `[key]`. The path of the root node
+/// is displayed as `.`.
+#[derive(Clone, Default, Debug, PartialEq, Eq, Hash)]
+pub struct NodePath {
+    segments: Vec<PathSegment>,
+}
+
+impl NodePath {
+    /// The path of the root node.
+    pub fn new() -> NodePath {
+        NodePath::default()
+    }
+
+    /// The steps from the root node.
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    /// The number of steps from the root node.
+    pub fn len(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Is this the path of the root node?
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Add a step at the end of the path.
+    pub fn push(&mut self, segment: PathSegment) {
+        self.segments.push(segment);
+    }
+
+    /// Remove the last step of the path.
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.segments.pop()
+    }
+}
+
+impl From<Vec<PathSegment>> for NodePath {
+    fn from(segments: Vec<PathSegment>) -> Self {
+        NodePath { segments }
+    }
+}
+
+impl fmt::Display for NodePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.segments.is_empty() {
+            return f.write_str(".");
+        }
+        for segment in &self.segments {
+            match segment {
+                PathSegment::Key(key)
+                    if !key.is_empty()
+                        && key
+                            .chars()
+                            .all(|ch| ch.is_alphanumeric() || ch == '_' || ch == '-') =>
+                {
+                    write!(f, ".{key}")?;
+                }
+                PathSegment::Key(key) => write!(f, "[{key:?}]")?,
+                PathSegment::ComplexKey => f.write_str("[key]")?,
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A step of a path given to [`Document::select()`](crate::Document::select).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PathSelector {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+impl PathSelector {
+    /// Parse a path written like the output of [`NodePath`]'s `Display`, with
+    /// `*` for any key or index.
+    pub(crate) fn parse(path: &str) -> Option<Vec<PathSelector>> {
+        let mut selectors = Vec::new();
+        let mut chars = path.chars().peekable();
+        if path == "." {
+            return Some(selectors);
+        }
+        let mut first = true;
+        while let Some(ch) = chars.next() {
+            match ch {
+                '[' => {
+                    let selector = match chars.peek()? {
+                        '"' => {
+                            chars.next();
+                            PathSelector::Key(Self::parse_quoted(&mut chars)?)
+                        }
+                        '*' => {
+                            chars.next();
+                            PathSelector::Wildcard
+                        }
+                        _ => {
+                            let mut digits = String::new();
+                            while let Some(ch) = chars.next_if(char::is_ascii_digit) {
+                                digits.push(ch);
+                            }
+                            PathSelector::Index(digits.parse().ok()?)
+                        }
+                    };
+                    if chars.next()? != ']' {
+                        return None;
+                    }
+                    selectors.push(selector);
+                }
+                _ if ch == '.' || first => {
+                    let mut key = String::new();
+                    if ch != '.' {
+                        key.push(ch);
+                    }
+                    while let Some(ch) = chars.next_if(|ch| *ch != '.' && *ch != '[') {
+                        key.push(ch);
+                    }
+                    selectors.push(match key.as_str() {
+                        "" => return None,
+                        "*" => PathSelector::Wildcard,
+                        _ => PathSelector::Key(key),
+                    });
+                }
+                _ => return None,
+            }
+            first = false;
+        }
+        Some(selectors)
+    }
+
+    /// Parse a quoted key, after the opening quote, with the escapes of
+    /// Rust's `Debug` output for strings.
+    fn parse_quoted(chars: &mut core::iter::Peekable<core::str::Chars<'_>>) -> Option<String> {
+        let mut key = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(key),
+                '\\' => key.push(match chars.next()? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    '0' => '\0',
+                    'u' => {
+                        if chars.next()? != '{' {
+                            return None;
+                        }
+                        let mut digits = String::new();
+                        while let Some(ch) = chars.next_if(char::is_ascii_hexdigit) {
+                            digits.push(ch);
+                        }
+                        if chars.next()? != '}' {
+                            return None;
+                        }
+                        char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?
+                    }
+                    ch => ch,
+                }),
+                ch => key.push(ch),
+            }
+        }
+    }
+}
+
+/// Iterate over the scalars of the documents produced by `parser`, with their
+/// paths, without composing the documents.
+///
+/// Each item is the path of a scalar, its value, and the mark of its start.
+/// The keys of mappings are not yielded, but make up the paths of their
+/// values. A key that is an alias of a scalar is used as that scalar, while
+/// the content of a key that is a collection is not yielded, and the path of
+/// its value ends with [`PathSegment::ComplexKey`]. Aliases are not yielded.
+/// The paths start again from the root at each document.
+///
+/// The iteration stops after the first error.
+///
+/// ```
+/// use libyaml_safer::{scalars_with_paths, Parser};
+///
+/// let mut parser = Parser::new();
+/// let mut input = "image: app:latest\nports: [80, 443]\n".as_bytes();
+/// parser.set_input_string(&mut input);
+/// let scalars = scalars_with_paths(&mut parser)
+///     .map(|item| item.map(|(path, value, _)| format!("{path} = {value}")))
+///     .collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(scalars, [".image = app:latest", ".ports[0] = 80", ".ports[1] = 443"]);
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn scalars_with_paths<'p, 'r>(parser: &'p mut Parser<'r>) -> ScalarPaths<'p, 'r> {
+    ScalarPaths {
+        parser,
+        path: NodePath::new(),
+        levels: Vec::new(),
+        key_depth: 0,
+        anchors: HashMap::new(),
+        done: false,
+    }
+}
+
+/// The iterator returned by [`scalars_with_paths()`].
+pub struct ScalarPaths<'p, 'r> {
+    parser: &'p mut Parser<'r>,
+    path: NodePath,
+    levels: Vec<PathLevel>,
+    /// The number of open collections in a key that is a collection, which
+    /// is skipped.
+    key_depth: usize,
+    /// The values of the anchored scalars of the current document, for keys
+    /// that are aliases.
+    anchors: HashMap<String, String>,
+    done: bool,
+}
+
+/// A collection that contains the current node.
+enum PathLevel {
+    /// A sequence, with the index of its next item.
+    Sequence(usize),
+    /// A mapping, and whether its next node is a value rather than a key.
+    Mapping(bool),
+}
+
+impl ScalarPaths<'_, '_> {
+    /// Skip an event of a key that is a collection.
+    fn skip_key(&mut self, data: &EventData) {
+        match data {
+            EventData::SequenceStart { .. } | EventData::MappingStart { .. } => {
+                self.key_depth += 1;
+            }
+            EventData::SequenceEnd | EventData::MappingEnd => {
+                self.key_depth -= 1;
+                if self.key_depth == 0 {
+                    if let Some(PathLevel::Mapping(in_value)) = self.levels.last_mut() {
+                        *in_value = true;
+                    }
+                    self.path.push(PathSegment::ComplexKey);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle the start of a node, and return whether it is a key.
+    fn start_node(&mut self, data: &EventData) -> bool {
+        match self.levels.last_mut() {
+            Some(PathLevel::Mapping(in_value @ false)) => {
+                let segment = match data {
+                    EventData::Scalar { value, .. } => PathSegment::Key(value.clone()),
+                    EventData::Alias { anchor } => self
+                        .anchors
+                        .get(anchor)
+                        .map_or(PathSegment::ComplexKey, |value| {
+                            PathSegment::Key(value.clone())
+                        }),
+                    _ => {
+                        self.key_depth = 1;
+                        return true;
+                    }
+                };
+                *in_value = true;
+                self.path.push(segment);
+                true
+            }
+            Some(PathLevel::Sequence(index)) => {
+                self.path.push(PathSegment::Index(*index));
+                *index += 1;
+                false
+            }
+            Some(PathLevel::Mapping(true)) | None => false,
+        }
+    }
+
+    /// Leave the current node, at the end of a scalar, an alias, or a
+    /// collection.
+    fn end_node(&mut self) {
+        match self.levels.last_mut() {
+            Some(PathLevel::Mapping(in_value)) => {
+                *in_value = false;
+                self.path.pop();
+            }
+            Some(PathLevel::Sequence(_)) => {
+                self.path.pop();
+            }
+            None => {}
+        }
+    }
+}
+
+impl Iterator for ScalarPaths<'_, '_> {
+    type Item = Result<(NodePath, String, Mark)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let event = match self.parser.next()? {
+                Ok(event) => event,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            };
+            if self.key_depth != 0 {
+                self.skip_key(&event.data);
+                continue;
+            }
+            match event.data {
+                EventData::DocumentStart { .. } => self.anchors.clear(),
+                EventData::SequenceEnd | EventData::MappingEnd => {
+                    self.levels.pop();
+                    self.end_node();
+                }
+                EventData::SequenceStart { ref anchor, .. }
+                | EventData::MappingStart { ref anchor, .. } => {
+                    if let Some(anchor) = anchor {
+                        self.anchors.remove(anchor);
+                    }
+                    if !self.start_node(&event.data) {
+                        let sequence = matches!(event.data, EventData::SequenceStart { .. });
+                        self.levels.push(if sequence {
+                            PathLevel::Sequence(0)
+                        } else {
+                            PathLevel::Mapping(false)
+                        });
+                    }
+                }
+                EventData::Alias { .. } => {
+                    if !self.start_node(&event.data) {
+                        self.end_node();
+                    }
+                }
+                EventData::Scalar {
+                    ref anchor,
+                    ref value,
+                    ..
+                } => {
+                    if let Some(anchor) = anchor {
+                        self.anchors.insert(anchor.clone(), value.clone());
+                    }
+                    if !self.start_node(&event.data) {
+                        let EventData::Scalar { value, .. } = event.data else {
+                            unreachable!()
+                        };
+                        let item = (self.path.clone(), value, event.start_mark);
+                        self.end_node();
+                        return Some(Ok(item));
+                    }
+                }
+                EventData::StreamStart { .. }
+                | EventData::StreamEnd
+                | EventData::DocumentEnd { .. } => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NodePath, PathSegment, PathSelector};
+    use crate::Parser;
+
+    #[test]
+    fn display() {
+        assert_eq!(NodePath::new().to_string(), ".");
+        let path = NodePath::from(vec![
+            PathSegment::Key("spec".to_string()),
+            PathSegment::Key("containers".to_string()),
+            PathSegment::Index(2),
+            PathSegment::Key("image-name_2".to_string()),
+        ]);
+        assert_eq!(path.to_string(), ".spec.containers[2].image-name_2");
+        let path = NodePath::from(vec![
+            PathSegment::Key("a key".to_string()),
+            PathSegment::Key(String::new()),
+            PathSegment::ComplexKey,
+            PathSegment::Key("\"q\"".to_string()),
+        ]);
+        assert_eq!(path.to_string(), r#"["a key"][""][key]["\"q\""]"#);
+    }
+
+    #[test]
+    fn parse_selectors() {
+        let key = |key: &str| PathSelector::Key(key.to_string());
+        let cases = [
+            (".", Some(vec![])),
+            ("", Some(vec![])),
+            (".a", Some(vec![key("a")])),
+            ("a.b", Some(vec![key("a"), key("b")])),
+            (
+                ".spec.containers[2].image",
+                Some(vec![
+                    key("spec"),
+                    key("containers"),
+                    PathSelector::Index(2),
+                    key("image"),
+                ]),
+            ),
+            (
+                ".*[*]",
+                Some(vec![PathSelector::Wildcard, PathSelector::Wildcard]),
+            ),
+            (
+                r#"["a key"]["\"q\"\n\u{e9}"][0]"#,
+                Some(vec![
+                    key("a key"),
+                    key("\"q\"\n\u{e9}"),
+                    PathSelector::Index(0),
+                ]),
+            ),
+            ("..a", None),
+            (".a.", None),
+            ("[x]", None),
+            ("[1", None),
+            ("[\"a]", None),
+            ("a[0]x", None),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(PathSelector::parse(path), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn scalars_with_paths() {
+        let input = "\
+a: &x one
+b:
+  - two
+  - [three, {c: four}]
+  - - five
+    - six
+? [complex, key]
+: seven
+*x : eight
+d: *x
+e: {f: [nine], g: ten}
+--- eleven
+---
+- twelve
+- h:
+    i: thirteen
+  j: fourteen
+";
+        let mut parser = Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        let scalars = super::scalars_with_paths(&mut parser)
+            .map(|item| {
+                let (path, value, mark) = item.unwrap();
+                (path.to_string(), value, mark.line)
+            })
+            .collect::<Vec<_>>();
+        let expected = [
+            (".a", "one", 0),
+            (".b[0]", "two", 2),
+            (".b[1][0]", "three", 3),
+            (".b[1][1].c", "four", 3),
+            (".b[2][0]", "five", 4),
+            (".b[2][1]", "six", 5),
+            ("[key]", "seven", 7),
+            (".one", "eight", 8),
+            (".e.f[0]", "nine", 10),
+            (".e.g", "ten", 10),
+            (".", "eleven", 11),
+            ("[0]", "twelve", 13),
+            ("[1].h.i", "thirteen", 15),
+            ("[1].j", "fourteen", 16),
+        ]
+        .map(|(path, value, line)| (path.to_string(), value.to_string(), line));
+        assert_eq!(scalars, expected);
+
+        let mut parser = Parser::new();
+        let mut input = "a: [b\n".as_bytes();
+        parser.set_input_string(&mut input);
+        let mut scalars = super::scalars_with_paths(&mut parser);
+        assert_eq!(scalars.next().unwrap().unwrap().1, "b");
+        assert!(scalars.next().unwrap().is_err());
+        assert!(scalars.next().is_none());
+    }
+}
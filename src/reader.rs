@@ -1,14 +1,16 @@
 use std::io::BufRead;
 
-use alloc::collections::VecDeque;
-
+use crate::buffer::CharBuffer;
+use crate::macros::is_break;
 use crate::{scanner::Scanner, Encoding, Error, Result};
 
 const BOM_UTF8: [u8; 3] = [0xef, 0xbb, 0xbf];
 const BOM_UTF16LE: [u8; 2] = [0xff, 0xfe];
 const BOM_UTF16BE: [u8; 2] = [0xfe, 0xff];
 
-fn yaml_parser_determine_encoding(reader: &mut dyn BufRead) -> Result<Option<Encoding>> {
+/// Determine the encoding of the input from its byte order mark, and return
+/// it with the length of the byte order mark that was consumed.
+fn yaml_parser_determine_encoding(reader: &mut dyn BufRead) -> Result<Option<(Encoding, usize)>> {
     let initial_bytes = reader.fill_buf()?;
     if initial_bytes.is_empty() {
         return Ok(None);
@@ -19,7 +21,7 @@ fn yaml_parser_determine_encoding(reader: &mut dyn BufRead) -> Result<Option<Enc
             let mut bom = [0; 3];
             reader.read_exact(&mut bom)?;
             if bom == BOM_UTF8 {
-                Ok(Some(Encoding::Utf8))
+                Ok(Some((Encoding::Utf8, BOM_UTF8.len())))
             } else {
                 Err(Error::reader(
                     "invalid byte order marker",
@@ -32,9 +34,9 @@ fn yaml_parser_determine_encoding(reader: &mut dyn BufRead) -> Result<Option<Enc
             let mut bom = [0; 2];
             reader.read_exact(&mut bom)?;
             if bom == BOM_UTF16LE {
-                Ok(Some(Encoding::Utf16Le))
+                Ok(Some((Encoding::Utf16Le, BOM_UTF16LE.len())))
             } else if bom == BOM_UTF16BE {
-                Ok(Some(Encoding::Utf16Be))
+                Ok(Some((Encoding::Utf16Be, BOM_UTF16BE.len())))
             } else {
                 Err(Error::reader(
                     "invalid byte order marker",
@@ -43,18 +45,72 @@ fn yaml_parser_determine_encoding(reader: &mut dyn BufRead) -> Result<Option<Enc
                 ))
             }
         }
-        _ => Ok(Some(Encoding::Utf8)),
+        _ => Ok(Some((Encoding::Utf8, 0))),
+    }
+}
+
+/// Skip the byte order mark of an encoding set with
+/// [`Scanner::set_encoding()`], and reject the byte order marks of other
+/// encodings. Returns the length of the byte order mark that was consumed.
+fn consume_declared_bom(reader: &mut dyn BufRead, encoding: Encoding) -> Result<usize> {
+    let (bom, conflicting): (&[u8], &[[u8; 2]]) = match encoding {
+        Encoding::Any => return Ok(0),
+        Encoding::Utf8 => (&BOM_UTF8, &[BOM_UTF16LE, BOM_UTF16BE]),
+        Encoding::Utf16Le => (&BOM_UTF16LE, &[BOM_UTF16BE]),
+        Encoding::Utf16Be => (&BOM_UTF16BE, &[BOM_UTF16LE]),
+    };
+    let initial_bytes = reader.fill_buf()?;
+    if initial_bytes.starts_with(bom) {
+        reader.consume(bom.len());
+        return Ok(bom.len());
+    } else if let Some(other) = conflicting
+        .iter()
+        .find(|other| initial_bytes.starts_with(&other[..]))
+    {
+        return Err(Error::reader(
+            "byte order mark does not match the declared encoding",
+            0,
+            i32::from_be_bytes([0, 0, other[0], other[1]]),
+        ));
+    }
+    Ok(0)
+}
+
+/// The destination of decoded characters.
+struct Output<'a> {
+    buffer: &'a mut CharBuffer,
+    /// The offset of the next character in the input, in bytes.
+    offset: &'a mut usize,
+    allow_nonprintable: bool,
+    /// The first character outside the YAML character set, if any. Decoding
+    /// stops before it.
+    nonprintable: &'a mut Option<char>,
+}
+
+impl Output<'_> {
+    /// Append a character that takes `width` bytes in the input. Returns
+    /// `false` if the character is not allowed.
+    fn push(&mut self, ch: char, width: usize) -> bool {
+        if !self.allow_nonprintable && !is_printable(ch) {
+            *self.nonprintable = Some(ch);
+            return false;
+        }
+        self.buffer.push_back(ch);
+        *self.offset += width;
+        true
+    }
+
+    /// Append the characters of a UTF-8 string. Returns `false` if one of them
+    /// is not allowed.
+    fn push_str(&mut self, string: &str) -> bool {
+        string.chars().all(|ch| self.push(ch, ch.len_utf8()))
     }
 }
 
 // Allowing unsafe code because it is the only efficient way to partially decode
 // a string slice from a stream of UTF-8 bytes.
 #[allow(unsafe_code)]
-fn read_utf8_buffered(
-    reader: &mut dyn BufRead,
-    out: &mut VecDeque<char>,
-    offset: &mut usize,
-) -> Result<bool> {
+fn read_utf8_buffered(reader: &mut dyn BufRead, out: &mut Output, max_len: usize) -> Result<bool> {
     let available = loop {
         match reader.fill_buf() {
             Ok([]) => return Ok(false),
@@ -63,15 +119,16 @@ fn read_utf8_buffered(
             Err(err) => return Err(err.into()),
         }
     };
+    // Only decode up to `max_len` bytes (and therefore characters) at a time,
+    // even if the reader has more available, so that the working buffer stays
+    // bounded by the buffer capacity.
+    let available = &available[..available.len().min(max_len)];
 
     match core::str::from_utf8(available) {
         Ok(valid) => {
             let used = valid.len();
             // The entire contents of the input buffer was valid UTF-8.
-            for ch in valid.chars() {
-                push_char(out, ch, *offset)?;
-                *offset += ch.len_utf8();
-            }
+            out.push_str(valid);
             reader.consume(used);
             Ok(true)
         }
@@ -84,22 +141,23 @@ fn read_utf8_buffered(
                 // SAFETY: This is safe because of `valid_up_to()`.
                 core::str::from_utf8_unchecked(&available[..valid_bytes])
             };
-            for ch in valid.chars() {
-                push_char(out, ch, *offset)?;
-                *offset += ch.len_utf8();
+            if !out.push_str(valid) {
+                reader.consume(valid_bytes);
+                return Ok(true);
             }
 
             match err.error_len() {
                 Some(_invalid_len) => Err(Error::reader(
                     "invalid UTF-8",
-                    *offset,
+                    *out.offset,
                     available[valid_bytes] as _,
                 )),
                 None => {
                     if valid_bytes != 0 {
                         // Some valid UTF-8 characters were present, and the
-                        // tail end of the buffer was an incomplete sequence.
-                        // Leave the incomplete sequence in the buffer.
+                        // tail end of the buffer was an incomplete sequence,
+                        // or a sequence cut off by `max_len`. Leave it in the
+                        // buffer.
                         reader.consume(valid_bytes);
                         Ok(true)
                     } else {
@@ -110,7 +168,7 @@ fn read_utf8_buffered(
                         // cannot be completed. Note that `read_exact()` handles
                         // interrupt automatically.
                         let initial = available[0];
-                        read_utf8_char_unbuffered(reader, out, initial, offset)?;
+                        read_utf8_char_unbuffered(reader, out, initial)?;
                         Ok(true)
                     }
                 }
@@ -121,9 +179,8 @@ fn read_utf8_buffered(
 
 fn read_utf8_char_unbuffered(
     reader: &mut dyn BufRead,
-    out: &mut VecDeque<char>,
+    out: &mut Output,
     initial: u8,
-    offset: &mut usize,
 ) -> Result<()> {
     let width = utf8_char_width(initial);
     let mut buffer = [0; 4];
@@ -133,20 +190,19 @@ fn read_utf8_char_unbuffered(
         let Some(ch) = valid.chars().next() else {
             unreachable!()
         };
-        push_char(out, ch, *offset)?;
-        *offset += width;
+        out.push(ch, width);
         Ok(())
     } else {
         // Since we read the exact character width, the only
         // possible error here is invalid Unicode.
-        Err(Error::reader("invalid UTF-8", *offset, buffer[0] as _))
+        Err(Error::reader("invalid UTF-8", *out.offset, buffer[0] as _))
     }
 }
 
 fn read_utf16_buffered<const BIG_ENDIAN: bool>(
     reader: &mut dyn BufRead,
-    out: &mut VecDeque<char>,
-    offset: &mut usize,
+    out: &mut Output,
+    max_len: usize,
 ) -> Result<bool> {
     let available = loop {
         match reader.fill_buf() {
@@ -156,6 +212,7 @@ fn read_utf16_buffered<const BIG_ENDIAN: bool>(
             Err(err) => return Err(err.into()),
         }
     };
+    let available = &available[..available.len().min(max_len.saturating_mul(2))];
 
     let chunks = available.chunks_exact(2).map(|chunk| {
         let [a, b] = chunk else { unreachable!() };
@@ -167,12 +224,15 @@ fn read_utf16_buffered<const BIG_ENDIAN: bool>(
     });
 
     let mut used = 0;
+    let mut allowed = true;
     for ch in core::char::decode_utf16(chunks) {
         match ch {
             Ok(ch) => {
-                push_char(out, ch, *offset)?;
-                let n = ch.len_utf16();
-                *offset += n;
+                let n = ch.len_utf16() * 2;
+                allowed = out.push(ch, n);
+                if !allowed {
+                    break;
+                }
                 used += n;
             }
             Err(_) => {
@@ -187,21 +247,18 @@ fn read_utf16_buffered<const BIG_ENDIAN: bool>(
         }
     }
 
-    if used != 0 {
+    if used != 0 || !allowed {
         reader.consume(used);
-        *offset += used;
         Ok(true)
     } else {
-        debug_assert!(!available.is_empty() && available.len() < 2);
-        read_utf16_char_unbuffered::<BIG_ENDIAN>(reader, out, offset)?;
+        read_utf16_char_unbuffered::<BIG_ENDIAN>(reader, out)?;
         Ok(true)
     }
 }
 
 fn read_utf16_char_unbuffered<const BIG_ENDIAN: bool>(
     reader: &mut dyn BufRead,
-    out: &mut VecDeque<char>,
-    offset: &mut usize,
+    out: &mut Output,
 ) -> Result<()> {
     let mut buffer = [0; 2];
     reader.read_exact(&mut buffer)?;
@@ -221,13 +278,12 @@ fn read_utf16_char_unbuffered<const BIG_ENDIAN: bool>(
 
         match core::char::decode_utf16([first, second]).next() {
             Some(Ok(ch)) => {
-                push_char(out, ch, *offset)?;
-                *offset += 4;
+                out.push(ch, 4);
                 Ok(())
             }
             Some(Err(err)) => Err(Error::reader(
                 "invalid UTF-16",
-                *offset,
+                *out.offset,
                 err.unpaired_surrogate() as _,
             )),
             None => unreachable!(),
@@ -235,8 +291,7 @@ fn read_utf16_char_unbuffered<const BIG_ENDIAN: bool>(
     } else {
         match core::char::decode_utf16([first]).next() {
             Some(Ok(ch)) => {
-                push_char(out, ch, *offset)?;
-                *offset += 2;
+                out.push(ch, 2);
                 Ok(())
             }
             Some(Err(_)) | None => unreachable!(),
@@ -262,24 +317,39 @@ fn is_utf16_surrogate(value: u16) -> bool {
     matches!(value, 0xD800..=0xDFFF)
 }
 
-fn push_char(out: &mut VecDeque<char>, ch: char, offset: usize) -> Result<()> {
-    if !(ch == '\x09'
-        || ch == '\x0A'
-        || ch == '\x0D'
-        || ch >= '\x20' && ch <= '\x7E'
-        || ch == '\u{0085}'
-        || ch >= '\u{00A0}' && ch <= '\u{D7FF}'
-        || ch >= '\u{E000}' && ch <= '\u{FFFD}'
-        || ch >= '\u{10000}' && ch <= '\u{10FFFF}')
-    {
-        return Err(Error::reader(
-            "control characters are not allowed",
-            offset,
-            ch as _,
-        ));
+/// Is the character in the YAML character set (`c-printable`)?
+fn is_printable(ch: char) -> bool {
+    matches!(
+        ch,
+        '\x09'
+            | '\x0A'
+            | '\x0D'
+            | '\x20'..='\x7E'
+            | '\u{0085}'
+            | '\u{00A0}'..='\u{D7FF}'
+            | '\u{E000}'..='\u{FFFD}'
+            | '\u{10000}'..='\u{10FFFF}'
+    )
+}
+
+/// The error for a character outside the YAML character set, which follows
+/// the characters in the working buffer.
+fn nonprintable_error(parser: &Scanner, ch: char) -> Error {
+    let mut mark = parser.mark;
+    let mut chars = parser.buffer.iter().peekable();
+    while let Some(&current) = chars.next() {
+        mark.index += current.len_utf8() as u64;
+        if current == '\r' && chars.peek() == Some(&&'\n') {
+            // The line break is counted at the '\n'.
+        } else if is_break(current) {
+            mark.line += 1;
+            mark.column = 0;
+        } else {
+            mark.column += 1;
+        }
     }
-    out.push_back(ch);
-    Ok(())
+    Error::scanner("", mark, "found a non-printable character", mark)
+        .with_detail(alloc::format!("code point U+{:04X}", ch as u32))
 }
 
 pub(crate) fn yaml_parser_update_buffer(parser: &mut Scanner, length: usize) -> Result<()> {
@@ -287,29 +357,53 @@ pub(crate) fn yaml_parser_update_buffer(parser: &mut Scanner, length: usize) ->
     if parser.buffer.len() >= length {
         return Ok(());
     }
-    if parser.encoding == Encoding::Any {
-        if let Some(encoding) = yaml_parser_determine_encoding(reader)? {
-            parser.encoding = encoding;
-        } else {
+    let bom_len = if parser.encoding_declared {
+        parser.encoding_declared = false;
+        consume_declared_bom(reader, parser.encoding)?
+    } else if parser.encoding == Encoding::Any {
+        let Some((encoding, bom_len)) = yaml_parser_determine_encoding(reader)? else {
             parser.eof = true;
             return Ok(());
-        }
+        };
+        parser.encoding = encoding;
+        bom_len
+    } else {
+        0
+    };
+    // The byte order mark is not a character of the stream, but it is part of
+    // the input, so offsets count it. Mark indexes count the UTF-8 encoding of
+    // the characters, which only matches the input if it is UTF-8.
+    parser.offset += bom_len;
+    if parser.encoding == Encoding::Utf8 {
+        parser.mark.index += bom_len as u64;
     }
 
+    parser.buffer.compact();
     while parser.buffer.len() < length {
         if parser.eof {
             return Ok(());
         }
+        if let Some(ch) = parser.nonprintable {
+            return Err(nonprintable_error(parser, ch));
+        }
 
+        // Fill the working buffer up to its capacity, or at least up to the
+        // requested length.
+        let max_len = parser
+            .buffer_capacity
+            .saturating_sub(parser.buffer.len())
+            .max(length - parser.buffer.len());
+        let mut out = Output {
+            buffer: &mut parser.buffer,
+            offset: &mut parser.offset,
+            allow_nonprintable: parser.allow_nonprintable,
+            nonprintable: &mut parser.nonprintable,
+        };
         let not_eof = match parser.encoding {
             Encoding::Any => unreachable!(),
-            Encoding::Utf8 => read_utf8_buffered(reader, &mut parser.buffer, &mut parser.offset)?,
-            Encoding::Utf16Le => {
-                read_utf16_buffered::<false>(reader, &mut parser.buffer, &mut parser.offset)?
-            }
-            Encoding::Utf16Be => {
-                read_utf16_buffered::<true>(reader, &mut parser.buffer, &mut parser.offset)?
-            }
+            Encoding::Utf8 => read_utf8_buffered(reader, &mut out, max_len)?,
+            Encoding::Utf16Le => read_utf16_buffered::<false>(reader, &mut out, max_len)?,
+            Encoding::Utf16Be => read_utf16_buffered::<true>(reader, &mut out, max_len)?,
         };
         if !not_eof {
             parser.eof = true;
@@ -322,3 +416,159 @@ pub(crate) fn yaml_parser_update_buffer(parser: &mut Scanner, length: usize) ->
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufRead;
+
+    use crate::{Encoding, ErrorKind, Event, EventData, Parser, Scanner, DEFAULT_BUFFER_CAPACITY};
+
+    /// A reader that makes its whole input available in a single `fill_buf()`.
+    struct HugeBuffer(Vec<u8>, usize);
+
+    impl std::io::Read for HugeBuffer {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.fill_buf()?.read(buf)?;
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl BufRead for HugeBuffer {
+        fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+            Ok(&self.0[self.1..])
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.1 += amt;
+        }
+    }
+
+    fn events(input: &[u8], capacity: usize) -> Vec<EventData> {
+        let mut input = input;
+        let mut parser = Parser::new();
+        parser.set_buffer_capacity(capacity);
+        parser.set_input(&mut input);
+        parser
+            .map(|event| {
+                let Event { data, .. } = event.unwrap();
+                data
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bounded_buffer() {
+        let input = "- item\n".repeat(1 << 17);
+        for capacity in [1, 100, DEFAULT_BUFFER_CAPACITY] {
+            let mut reader = HugeBuffer(input.clone().into_bytes(), 0);
+            let mut scanner = Scanner::new();
+            scanner.set_buffer_capacity(capacity);
+            scanner.set_input(&mut reader);
+            let mut tokens = 0;
+            while let Some(token) = scanner.next() {
+                token.unwrap();
+                tokens += 1;
+                assert!(scanner.buffer.len() <= capacity.max(4), "{capacity}");
+            }
+            assert_eq!(tokens, 2 * (1 << 17) + 4);
+        }
+    }
+
+    #[test]
+    fn capacity_does_not_change_events() {
+        let input = "key: välue\nlist: [\"😀\", 'ü', x]\n";
+        let utf16 = |big_endian: bool| {
+            let mut bytes = if big_endian {
+                vec![0xfe, 0xff]
+            } else {
+                vec![0xff, 0xfe]
+            };
+            for unit in input.encode_utf16() {
+                bytes.extend(if big_endian {
+                    unit.to_be_bytes()
+                } else {
+                    unit.to_le_bytes()
+                });
+            }
+            bytes
+        };
+        let expected = events(input.as_bytes(), DEFAULT_BUFFER_CAPACITY);
+        for capacity in [1, 2, 3, 5, 7, DEFAULT_BUFFER_CAPACITY] {
+            for (encoding, bytes) in [
+                (Encoding::Utf8, input.as_bytes().to_vec()),
+                (Encoding::Utf16Le, utf16(false)),
+                (Encoding::Utf16Be, utf16(true)),
+            ] {
+                let mut events = events(&bytes, capacity);
+                events[0] = EventData::StreamStart {
+                    encoding: Encoding::Utf8,
+                };
+                assert_eq!(events, expected, "{encoding:?} {capacity}");
+            }
+        }
+    }
+
+    #[test]
+    fn declared_encoding() {
+        let input = "key: välue\n";
+        let encode = |encoding: Encoding, bom: bool| -> Vec<u8> {
+            let text = if bom {
+                alloc::format!("\u{feff}{input}")
+            } else {
+                input.to_string()
+            };
+            match encoding {
+                Encoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+                Encoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+                _ => text.into_bytes(),
+            }
+        };
+        let parse = |bytes: &[u8], encoding: Encoding| {
+            let mut input = bytes;
+            let mut parser = Parser::new();
+            parser.set_encoding(encoding);
+            parser.set_input(&mut input);
+            parser
+                .map(|event| event.map(|event| event.data))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let expected = events(input.as_bytes(), DEFAULT_BUFFER_CAPACITY);
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            for bom in [false, true] {
+                let mut events = parse(&encode(encoding, bom), encoding).unwrap();
+                assert_eq!(
+                    events[0],
+                    EventData::StreamStart { encoding },
+                    "{encoding:?} {bom}"
+                );
+                events[0] = expected[0].clone();
+                assert_eq!(events, expected, "{encoding:?} {bom}");
+            }
+        }
+
+        // `Encoding::Any` detects the encoding.
+        let events = parse(&encode(Encoding::Utf16Be, true), Encoding::Any).unwrap();
+        assert_eq!(
+            events[0],
+            EventData::StreamStart {
+                encoding: Encoding::Utf16Be
+            }
+        );
+
+        for (bytes, encoding) in [
+            (encode(Encoding::Utf16Le, true), Encoding::Utf8),
+            (encode(Encoding::Utf16Be, true), Encoding::Utf8),
+            (encode(Encoding::Utf16Le, true), Encoding::Utf16Be),
+            (encode(Encoding::Utf16Be, true), Encoding::Utf16Le),
+        ] {
+            let err = parse(&bytes, encoding).unwrap_err();
+            assert_eq!(
+                err.problem(),
+                "byte order mark does not match the declared encoding",
+                "{encoding:?}"
+            );
+            assert_eq!(err.kind(), ErrorKind::Reader);
+        }
+    }
+}
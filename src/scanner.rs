@@ -1,28 +1,53 @@
 use std::collections::VecDeque;
+use std::io::BufRead;
+use std::num::NonZeroU8;
 
-use crate::macros::{is_blankz, is_break};
+use crate::buffer::CharBuffer;
+use crate::macros::{is_alpha, is_blankz, is_bom, is_break, is_breakz};
 use crate::reader::yaml_parser_update_buffer;
 use crate::{
-    Encoding, Error, Mark, Result, ScalarStyle, SimpleKey, Token, TokenData, INPUT_BUFFER_SIZE,
+    Chomping, Encoding, Error, Mark, Presentation, Result, ScalarStyle, SimpleKey, Token,
+    TokenData, TraceEvent, TraceHook, DEFAULT_BUFFER_CAPACITY, DEFAULT_MAX_ANCHOR_LENGTH,
+    DEFAULT_MAX_TAG_LENGTH,
 };
 
 const MAX_NUMBER_LENGTH: u64 = 9_u64;
 
+/// A check for cancellation, called by the scanner before every token it
+/// fetches and by the emitter before every event. Returning `true` makes the
+/// operation fail with an error of kind
+/// [`ErrorKind::Interrupted`](crate::ErrorKind).
+pub type InterruptCheck = Box<dyn Fn() -> bool + Send>;
+
+/// The maximum length of an implicit key, in characters, including any
+/// whitespace before the `:` indicator.
+const MAX_SIMPLE_KEY_LENGTH: u64 = 1024;
+
 /// Given an input stream of bytes, produce a stream of [`Token`]s.
 ///
 /// This is used internally by the parser, and may also be used standalone as a
 /// replacement for the libyaml `yaml_parser_scan()` function.
 pub struct Scanner<'r> {
     /// Read handler.
-    pub(crate) read_handler: Option<&'r mut dyn std::io::BufRead>,
+    pub(crate) read_handler: Option<&'r mut dyn BufRead>,
     /// EOF flag
     pub(crate) eof: bool,
     /// The working buffer.
     ///
     /// This always contains valid UTF-8.
-    pub(crate) buffer: VecDeque<char>,
+    pub(crate) buffer: CharBuffer,
+    /// The number of characters to decode into the working buffer at a time.
+    pub(crate) buffer_capacity: usize,
+    /// Are characters outside the YAML character set allowed?
+    pub(crate) allow_nonprintable: bool,
+    /// The first character outside the YAML character set that was read, if
+    /// they are not allowed. It follows the characters in the working buffer.
+    pub(crate) nonprintable: Option<char>,
     /// The input encoding.
     pub(crate) encoding: Encoding,
+    /// Was the input encoding set with [`Scanner::set_encoding()`], and is
+    /// the byte order mark at the start of the input still to be checked?
+    pub(crate) encoding_declared: bool,
     /// The offset of the current position (in bytes).
     pub(crate) offset: usize,
     /// The mark of the current position.
@@ -33,6 +58,9 @@ pub struct Scanner<'r> {
     pub(crate) stream_end_produced: bool,
     /// The number of unclosed '[' and '{' indicators.
     pub(crate) flow_level: i32,
+    /// The marks of the unclosed '[' and '{' indicators, with the context to
+    /// report if they are still unclosed at the end of the stream.
+    pub(crate) flow_marks: Vec<(Mark, &'static str)>,
     /// The tokens queue.
     pub(crate) tokens: VecDeque<Token>,
     /// The number of tokens fetched from the queue.
@@ -47,6 +75,94 @@ pub struct Scanner<'r> {
     pub(crate) simple_key_allowed: bool,
     /// The stack of simple keys.
     pub(crate) simple_keys: Vec<SimpleKey>,
+    /// The number of simple keys at the bottom of the stack that are known
+    /// not to be possible.
+    ///
+    /// The possible keys are in the order of their positions, so keys become
+    /// stale from the bottom of the stack, and only the keys above this need
+    /// to be checked.
+    pub(crate) impossible_simple_keys: usize,
+    /// The maximum length of anchor and alias names.
+    pub(crate) max_anchor_length: usize,
+    /// The maximum length of tags.
+    pub(crate) max_tag_length: usize,
+    /// The trace hook, if any.
+    pub(crate) trace: Option<TraceHook>,
+    /// The interrupt check, if any.
+    pub(crate) interrupt_check: Option<InterruptCheck>,
+    /// The tokens to produce instead of scanning an input, if any.
+    pub(crate) token_source: Option<VecDeque<Token>>,
+    /// The distance between tab stops, for the columns of marks.
+    pub(crate) tab_width: NonZeroU8,
+    /// The lines and columns of the tabs on the lines that marks may still
+    /// refer to, if the tab width is not 1. The columns count tabs as one
+    /// column, as `mark` does.
+    pub(crate) tabs: VecDeque<(u64, u64)>,
+    /// Record the indicators of block scalars in `presentations`?
+    pub(crate) capture_presentation: bool,
+    /// The indicators of the block scalars that have been scanned but not
+    /// yet parsed, with the end index of their tokens.
+    pub(crate) presentations: VecDeque<(u64, Presentation)>,
+}
+
+/// A snapshot of the state of a [`Scanner`], taken with
+/// [`Scanner::checkpoint()`].
+///
+/// This can be used to resume scanning from the position of the checkpoint
+/// with [`Scanner::restore()`], for example to re-scan a document from the
+/// start of an edited line without scanning everything before it.
+#[derive(Clone, Debug)]
+pub struct ScannerCheckpoint {
+    pub(crate) encoding: Encoding,
+    pub(crate) mark: Mark,
+    pub(crate) stream_start_produced: bool,
+    pub(crate) stream_end_produced: bool,
+    pub(crate) flow_level: i32,
+    pub(crate) flow_marks: Vec<(Mark, &'static str)>,
+    pub(crate) tokens: VecDeque<Token>,
+    pub(crate) tokens_parsed: usize,
+    pub(crate) token_available: bool,
+    pub(crate) indents: Vec<i32>,
+    pub(crate) indent: i32,
+    pub(crate) simple_key_allowed: bool,
+    pub(crate) simple_keys: Vec<SimpleKey>,
+    pub(crate) tabs: VecDeque<(u64, u64)>,
+    pub(crate) presentations: VecDeque<(u64, Presentation)>,
+}
+
+impl ScannerCheckpoint {
+    /// The position at which scanning resumes.
+    ///
+    /// For UTF-8 input, `mark().index` is the byte offset at which the input
+    /// passed to [`Scanner::restore()`] must start.
+    pub fn mark(&self) -> Mark {
+        self.mark
+    }
+
+    /// The number of unclosed `[` and `{` indicators.
+    pub fn flow_level(&self) -> i32 {
+        self.flow_level
+    }
+
+    /// The current indentation level.
+    pub fn indent(&self) -> i32 {
+        self.indent
+    }
+
+    /// The stack of enclosing indentation levels.
+    pub fn indents(&self) -> &[i32] {
+        &self.indents
+    }
+
+    /// The stack of potential simple keys, one for each flow level.
+    pub fn simple_keys(&self) -> &[SimpleKey] {
+        &self.simple_keys
+    }
+
+    /// Tokens that have been scanned, but not yet returned by the scanner.
+    pub fn pending_tokens(&self) -> impl Iterator<Item = &Token> {
+        self.tokens.iter()
+    }
 }
 
 impl<'r> Scanner<'r> {
@@ -54,13 +170,18 @@ impl<'r> Scanner<'r> {
         Self {
             read_handler: None,
             eof: false,
-            buffer: VecDeque::with_capacity(INPUT_BUFFER_SIZE),
+            buffer: CharBuffer::with_capacity(DEFAULT_BUFFER_CAPACITY),
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            allow_nonprintable: false,
+            nonprintable: None,
             encoding: Encoding::Any,
+            encoding_declared: false,
             offset: 0,
             mark: Mark::default(),
             stream_start_produced: false,
             stream_end_produced: false,
             flow_level: 0,
+            flow_marks: Vec::new(),
             tokens: VecDeque::with_capacity(16),
             tokens_parsed: 0,
             token_available: false,
@@ -68,6 +189,16 @@ impl<'r> Scanner<'r> {
             indent: 0,
             simple_key_allowed: false,
             simple_keys: Vec::with_capacity(16),
+            impossible_simple_keys: 0,
+            max_anchor_length: DEFAULT_MAX_ANCHOR_LENGTH,
+            max_tag_length: DEFAULT_MAX_TAG_LENGTH,
+            trace: None,
+            interrupt_check: None,
+            token_source: None,
+            tab_width: NonZeroU8::MIN,
+            tabs: VecDeque::new(),
+            capture_presentation: false,
+            presentations: VecDeque::new(),
         }
     }
 
@@ -78,15 +209,85 @@ impl<'r> Scanner<'r> {
     }
 
     /// Set a generic input handler.
-    pub fn set_input(&mut self, input: &'r mut dyn std::io::BufRead) {
+    pub fn set_input(&mut self, input: &'r mut dyn BufRead) {
         assert!((self.read_handler).is_none());
         self.read_handler = Some(input);
     }
 
+    /// Produce `tokens` instead of scanning an input, for
+    /// [`Parser::set_input_tokens()`](crate::Parser::set_input_tokens).
+    pub(crate) fn set_input_tokens(&mut self, tokens: VecDeque<Token>) {
+        assert!(self.read_handler.is_none() && self.token_source.is_none());
+        self.token_source = Some(tokens);
+    }
+
+    /// Set the capacity of the input buffer, in characters.
+    ///
+    /// The scanner decodes at most this many characters from the input at a
+    /// time, even if the reader has more bytes available, so that a
+    /// [`BufRead`] with a large buffer of its own is not buffered twice. Small
+    /// capacities reduce the memory used by the scanner at the cost of more
+    /// calls to the reader. The default is [`DEFAULT_BUFFER_CAPACITY`].
+    ///
+    /// This must be called before the input is set.
+    pub fn set_buffer_capacity(&mut self, capacity: usize) {
+        assert!(self.read_handler.is_none());
+        let capacity = capacity.max(1);
+        self.buffer = CharBuffer::with_capacity(capacity);
+        self.buffer_capacity = capacity;
+    }
+
+    /// Set if characters outside the YAML character set are allowed.
+    ///
+    /// By default, control characters other than tab and line breaks, DEL,
+    /// C1 control characters other than NEL, and U+FFFE and U+FFFF produce a
+    /// scanner error at their position. They can still be written
+    /// as escape sequences in double-quoted scalars. When allowed, they are
+    /// passed through like other characters, for legacy data.
+    pub fn set_allow_nonprintable(&mut self, allow_nonprintable: bool) {
+        self.allow_nonprintable = allow_nonprintable;
+    }
+
     /// Set the source encoding.
+    ///
+    /// The declared encoding is used instead of detecting it from the byte
+    /// order mark, so UTF-16 input without a byte order mark can be read. A
+    /// byte order mark of the declared encoding at the start of the input is
+    /// skipped, and one of another encoding is a reader error.
+    /// [`Encoding::Any`] keeps the detection.
     pub fn set_encoding(&mut self, encoding: Encoding) {
         assert!(self.encoding == Encoding::Any);
         self.encoding = encoding;
+        self.encoding_declared = encoding != Encoding::Any;
+    }
+
+    /// Set the maximum length of anchor and alias names, in characters.
+    ///
+    /// Longer names produce a scanner error. The default is
+    /// [`DEFAULT_MAX_ANCHOR_LENGTH`].
+    pub fn set_max_anchor_length(&mut self, max_length: usize) {
+        self.max_anchor_length = max_length;
+    }
+
+    /// Set the maximum length of tags, in characters.
+    ///
+    /// This applies separately to the handle and the suffix of a tag, and to
+    /// the handle and the prefix of a `%TAG` directive. Longer tags produce a
+    /// scanner error. The default is [`DEFAULT_MAX_TAG_LENGTH`].
+    pub fn set_max_tag_length(&mut self, max_length: usize) {
+        self.max_tag_length = max_length;
+    }
+
+    /// Set the distance between tab stops, for the columns of marks.
+    ///
+    /// A tab advances the column of the following characters to the next
+    /// multiple of `tab_width`, as editors display it, so that the marks of
+    /// tokens and errors after a tab point to the right place. The index and
+    /// line of marks, and the columns used to check indentation, are not
+    /// affected. The default is 1, which counts a tab as one column like any
+    /// other character.
+    pub fn set_tab_width(&mut self, tab_width: NonZeroU8) {
+        self.tab_width = tab_width;
     }
 
     fn cache(&mut self, length: usize) -> Result<()> {
@@ -99,6 +300,7 @@ impl<'r> Scanner<'r> {
 
     /// Equivalent to the libyaml macro `SKIP`.
     fn skip_char(&mut self) {
+        self.note_tabs(1);
         let popped = self.buffer.pop_front().expect("unexpected end of tokens");
         let width = popped.len_utf8();
         self.mark.index += width as u64;
@@ -112,7 +314,7 @@ impl<'r> Scanner<'r> {
                 self.mark.index += 2;
                 self.mark.column = 0;
                 self.mark.line += 1;
-                self.buffer.drain(0..2);
+                self.buffer.consume(2);
             } else if is_break(front) {
                 let width = front.len_utf8();
                 self.mark.index += width as u64;
@@ -125,6 +327,7 @@ impl<'r> Scanner<'r> {
 
     /// Equivalent to the libyaml macro `READ`.
     fn read_char(&mut self, string: &mut String) {
+        self.note_tabs(1);
         if let Some(popped) = self.buffer.pop_front() {
             string.push(popped);
             self.mark.index += popped.len_utf8() as u64;
@@ -134,15 +337,160 @@ impl<'r> Scanner<'r> {
         }
     }
 
+    /// Count the leading characters of the buffer that satisfy `predicate`, up
+    /// to `limit` characters. Returns the number of characters and their width
+    /// in bytes.
+    fn count_while(&self, limit: usize, predicate: impl Fn(char) -> bool) -> (usize, usize) {
+        let mut count = 0;
+        let mut width = 0;
+        for ch in self.buffer.iter().copied() {
+            if count == limit || !predicate(ch) {
+                break;
+            }
+            debug_assert!(!is_break(ch), "line breaks must be read individually");
+            count += 1;
+            width += ch.len_utf8();
+        }
+        (count, width)
+    }
+
+    /// Read the longest run of characters satisfying `predicate`, up to
+    /// `limit` characters, into `string`. The predicate must not accept line
+    /// breaks.
+    ///
+    /// This is equivalent to calling [`Scanner::read_char()`] in a loop, but
+    /// works on runs of buffered characters at a time. Returns the number of
+    /// characters read.
+    fn read_while(
+        &mut self,
+        string: &mut String,
+        mut limit: usize,
+        predicate: impl Fn(char) -> bool,
+    ) -> Result<usize> {
+        let mut total = 0;
+        loop {
+            self.cache(1)?;
+            let (count, width) = self.count_while(limit, &predicate);
+            if count == 0 {
+                return Ok(total);
+            }
+            self.note_tabs(count);
+            string.reserve(width);
+            string.extend(self.buffer.consume(count));
+            self.mark.index += width as u64;
+            self.mark.column += count as u64;
+            total += count;
+            limit -= count;
+        }
+    }
+
+    /// Skip the longest run of characters satisfying `predicate`. The predicate
+    /// must not accept line breaks.
+    fn skip_while(&mut self, predicate: impl Fn(char) -> bool) -> Result<()> {
+        loop {
+            self.cache(1)?;
+            let (count, width) = self.count_while(usize::MAX, &predicate);
+            if count == 0 {
+                return Ok(());
+            }
+            self.note_tabs(count);
+            self.buffer.consume(count);
+            self.mark.index += width as u64;
+            self.mark.column += count as u64;
+        }
+    }
+
+    /// Record the positions of the tabs among the next `count` characters of
+    /// the buffer, which are about to be consumed, if the tab width is not 1.
+    fn note_tabs(&mut self, count: usize) {
+        if self.tab_width.get() == 1 {
+            return;
+        }
+        let Mark { line, column, .. } = self.mark;
+        for (offset, ch) in self.buffer.iter().take(count).enumerate() {
+            if *ch == '\t' {
+                self.tabs.push_back((line, column + offset as u64));
+            }
+        }
+    }
+
+    /// The mark with its column counting tabs to the next tab stop.
+    fn tab_mark(&self, mark: Mark) -> Mark {
+        let width = u64::from(self.tab_width.get());
+        let mut column = 0;
+        let mut next = 0;
+        for &(_, tab) in self.tabs.iter().filter(|(line, _)| *line == mark.line) {
+            if tab >= mark.column {
+                break;
+            }
+            column += tab - next;
+            column = (column / width + 1) * width;
+            next = tab + 1;
+        }
+        Mark {
+            column: column + mark.column - next,
+            ..mark
+        }
+    }
+
+    /// Give the marks of the next token columns that count tabs, and forget
+    /// the tabs on the lines before it that no mark refers to anymore.
+    fn set_tab_columns(&mut self) {
+        if self.tab_width.get() == 1 || self.token_source.is_some() {
+            return;
+        }
+        let Some(token) = self.tokens.front() else {
+            return;
+        };
+        let (start_mark, end_mark) = (
+            self.tab_mark(token.start_mark),
+            self.tab_mark(token.end_mark),
+        );
+        let mut first_line = start_mark.line;
+        if let Some((mark, _)) = self.flow_marks.first() {
+            first_line = first_line.min(mark.line);
+        }
+        let token = self.tokens.front_mut().unwrap();
+        token.start_mark = start_mark;
+        token.end_mark = end_mark;
+        while self
+            .tabs
+            .front()
+            .is_some_and(|(line, _)| *line < first_line)
+        {
+            self.tabs.pop_front();
+        }
+    }
+
+    /// Skip a comment, up to the line break.
+    fn skip_comment(&mut self) -> Result<()> {
+        let start_mark = self.mark;
+        self.skip_while(|ch| !is_breakz(ch) && !is_bom(ch))?;
+        self.check_bom("while scanning a comment", start_mark)
+    }
+
+    /// Fail if the next character is a byte order mark, which is only
+    /// allowed at the start of a line, and inside quoted scalars.
+    fn check_bom(&mut self, context: &'static str, context_mark: Mark) -> Result<()> {
+        if IS_BOM!(self.buffer) {
+            return self.set_scanner_error(
+                context,
+                context_mark,
+                "found a byte order mark (U+FEFF) outside of a quoted scalar",
+            );
+        }
+        Ok(())
+    }
+
     /// Equivalent to the libyaml macro `READ_LINE`.
     fn read_line_break(&mut self, string: &mut String) {
         let Some(front) = self.buffer.front().copied() else {
-            panic!("unexpected end of input");
+            return;
         };
 
-        if let Some('\r') = self.buffer.get(1).copied() {
+        if let ('\r', Some('\n')) = (front, self.buffer.get(1).copied()) {
             string.push('\n');
-            self.buffer.drain(0..2);
+            self.buffer.consume(2);
             self.mark.index += 2;
             self.mark.column = 0;
             self.mark.line += 1;
@@ -161,6 +509,105 @@ impl<'r> Scanner<'r> {
         }
     }
 
+    /// Set a hook to be called with the tokens fetched by the scanner, and
+    /// with any error that occurs.
+    ///
+    /// This is meant for debugging. Tokens are reported when they become the
+    /// next token in the queue, which may be some time after they have been
+    /// scanned if the scanner is looking ahead for a simple key.
+    pub fn set_trace(&mut self, hook: TraceHook) {
+        self.trace = Some(hook);
+    }
+
+    /// Set a check to be called before every token is fetched, to cancel a
+    /// long scan from another thread or on a deadline.
+    ///
+    /// When the check returns `true`, scanning fails with an error of kind
+    /// [`ErrorKind::Interrupted`](crate::ErrorKind) at the current position.
+    /// The scanner cannot be resumed after that.
+    pub fn set_interrupt_check(&mut self, check: InterruptCheck) {
+        self.interrupt_check = Some(check);
+    }
+
+    /// Report a step to the trace hook, if there is one.
+    pub(crate) fn trace(&mut self, event: &TraceEvent<'_>) {
+        if let Some(hook) = self.trace.as_mut() {
+            hook(event);
+        }
+    }
+
+    /// Take a snapshot of the scanner state.
+    ///
+    /// The snapshot includes any tokens that have been scanned but not yet
+    /// returned, but not the input, or settings like the trace hook.
+    pub fn checkpoint(&self) -> ScannerCheckpoint {
+        ScannerCheckpoint {
+            encoding: self.encoding,
+            mark: self.mark,
+            stream_start_produced: self.stream_start_produced,
+            stream_end_produced: self.stream_end_produced,
+            flow_level: self.flow_level,
+            flow_marks: self.flow_marks.clone(),
+            tokens: self.tokens.clone(),
+            tokens_parsed: self.tokens_parsed,
+            token_available: self.token_available,
+            indents: self.indents.clone(),
+            indent: self.indent,
+            simple_key_allowed: self.simple_key_allowed,
+            simple_keys: self.simple_keys.clone(),
+            tabs: self.tabs.clone(),
+            presentations: self.presentations.clone(),
+        }
+    }
+
+    /// Resume scanning from a checkpoint.
+    ///
+    /// `new_input` must produce the input from the position of the checkpoint
+    /// onwards, which is [`ScannerCheckpoint::mark()`] for UTF-8 input. The
+    /// tokens produced afterwards are the same as those that would have been
+    /// produced by continuing the scan at the time the checkpoint was taken.
+    pub fn restore(&mut self, checkpoint: ScannerCheckpoint, new_input: &'r mut dyn BufRead) {
+        let ScannerCheckpoint {
+            encoding,
+            mark,
+            stream_start_produced,
+            stream_end_produced,
+            flow_level,
+            flow_marks,
+            tokens,
+            tokens_parsed,
+            token_available,
+            indents,
+            indent,
+            simple_key_allowed,
+            simple_keys,
+            tabs,
+            presentations,
+        } = checkpoint;
+        self.read_handler = Some(new_input);
+        self.eof = false;
+        self.buffer.clear();
+        self.nonprintable = None;
+        self.encoding = encoding;
+        self.encoding_declared = false;
+        self.offset = mark.index as usize;
+        self.mark = mark;
+        self.stream_start_produced = stream_start_produced;
+        self.stream_end_produced = stream_end_produced;
+        self.flow_level = flow_level;
+        self.flow_marks = flow_marks;
+        self.tokens = tokens;
+        self.tokens_parsed = tokens_parsed;
+        self.token_available = token_available;
+        self.indents = indents;
+        self.indent = indent;
+        self.simple_key_allowed = simple_key_allowed;
+        self.simple_keys = simple_keys;
+        self.tabs = tabs;
+        self.presentations = presentations;
+        self.impossible_simple_keys = 0;
+    }
+
     /// Scan the input stream and produce the next token.
     ///
     /// Call the function subsequently to produce a sequence of tokens
@@ -176,7 +623,10 @@ impl<'r> Scanner<'r> {
             });
         }
         if !self.token_available {
-            self.fetch_more_tokens()?;
+            if let Err(err) = self.fetch_more_tokens() {
+                self.trace(&TraceEvent::Error(&err));
+                return Err(err);
+            }
         }
         if let Some(token) = self.tokens.pop_front() {
             self.token_available = false;
@@ -190,6 +640,81 @@ impl<'r> Scanner<'r> {
         }
     }
 
+    /// Scan the whole input stream, up to and including the
+    /// [`TokenData::StreamEnd`] token.
+    ///
+    /// If an error occurs, the tokens that were successfully scanned before
+    /// the error are returned along with the error. This includes any tokens
+    /// that were still queued in the scanner (see
+    /// [`Scanner::take_pending_tokens()`]), so the result covers as much of
+    /// the input as possible.
+    pub fn scan_all(&mut self) -> Result<Vec<Token>, (Vec<Token>, Error)> {
+        let mut tokens = Vec::new();
+        loop {
+            match self.scan() {
+                Ok(token) => {
+                    let is_end = matches!(token.data, TokenData::StreamEnd);
+                    tokens.push(token);
+                    if is_end {
+                        return Ok(tokens);
+                    }
+                }
+                Err(err) => {
+                    tokens.extend(self.take_pending_tokens());
+                    return Err((tokens, err));
+                }
+            }
+        }
+    }
+
+    /// Remove and return the tokens that have been scanned, but not yet
+    /// returned by [`Scanner::scan()`].
+    ///
+    /// The scanner holds on to tokens while it is still deciding whether they
+    /// start a simple key. After an error, this can be used to retrieve the
+    /// tokens that were produced before the error occurred.
+    pub fn take_pending_tokens(&mut self) -> Vec<Token> {
+        let mut tokens = Vec::with_capacity(self.tokens.len());
+        if !self.token_available {
+            self.set_tab_columns();
+        }
+        while let Some(token) = self.tokens.pop_front() {
+            tokens.push(token);
+            self.set_tab_columns();
+        }
+        self.token_available = false;
+        self.tokens_parsed += tokens.len();
+        tokens
+    }
+
+    /// The number of tokens that have been scanned, but not yet returned by
+    /// [`Scanner::scan()`].
+    pub fn tokens_queued(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// An estimate of the number of tokens left in an input of `input_len`
+    /// bytes, to reserve space for them in a `Vec`.
+    ///
+    /// The estimate allows for a token every 3 bytes of the input that was not
+    /// scanned yet, which is more than typical documents have. Flow
+    /// collections of very short scalars, like `[a, b, c]`, can exceed it.
+    ///
+    /// ```
+    /// use libyaml_safer::Scanner;
+    ///
+    /// let input = "key: value\nlist: [1, 2, 3]\n";
+    /// let mut bytes = input.as_bytes();
+    /// let mut scanner = Scanner::new();
+    /// scanner.set_input_string(&mut bytes);
+    /// let mut tokens = Vec::with_capacity(scanner.reserve_hint(input.len()));
+    /// tokens.extend(scanner);
+    /// ```
+    pub fn reserve_hint(&self, input_len: usize) -> usize {
+        let scanned = usize::try_from(self.mark.index).unwrap_or(usize::MAX);
+        input_len.saturating_sub(scanned) / 3 + self.tokens.len() + 16
+    }
+
     /// Equivalent of the libyaml `PEEK_TOKEN` macro, used by the parser.
     pub(crate) fn peek(&mut self) -> Result<&Token> {
         if self.token_available {
@@ -252,30 +777,67 @@ impl<'r> Scanner<'r> {
     }
 
     pub(crate) fn fetch_more_tokens(&mut self) -> Result<()> {
-        let mut need_more_tokens;
+        if self.token_source.is_some() {
+            self.fetch_source_token()?;
+        } else if let Err(err) = self.fetch_scanned_tokens() {
+            if self.tab_width.get() == 1 {
+                return Err(err);
+            }
+            return Err(err.map_marks(|mark| self.tab_mark(mark)));
+        }
+        self.set_tab_columns();
+        self.token_available = true;
+        if let (Some(hook), Some(token)) = (self.trace.as_mut(), self.tokens.front()) {
+            hook(&TraceEvent::Token(token));
+        }
+        Ok(())
+    }
+
+    /// Take the next token of the token source, if the queue is empty.
+    fn fetch_source_token(&mut self) -> Result<()> {
+        if !self.tokens.is_empty() {
+            return Ok(());
+        }
+        if self.interrupt_check.as_ref().is_some_and(|check| check()) {
+            return Err(Error::interrupted(self.mark));
+        }
+        let token = self.token_source.as_mut().and_then(VecDeque::pop_front);
+        let Some(token) = token else {
+            return self.set_scanner_error(
+                "while reading tokens",
+                self.mark,
+                "found unexpected end of stream",
+            );
+        };
+        self.mark = token.end_mark;
+        self.stream_start_produced = true;
+        self.tokens.push_back(token);
+        Ok(())
+    }
+
+    fn fetch_scanned_tokens(&mut self) -> Result<()> {
         loop {
-            need_more_tokens = false;
-            if self.tokens.is_empty() {
-                need_more_tokens = true;
+            let need_more_tokens = if self.tokens.is_empty() {
+                true
             } else {
                 self.stale_simple_keys()?;
-                for simple_key in &self.simple_keys {
-                    if simple_key.possible && simple_key.token_number == self.tokens_parsed {
-                        need_more_tokens = true;
-                        break;
-                    }
-                }
-            }
+                // The first possible key has the lowest token number.
+                self.simple_keys
+                    .get(self.impossible_simple_keys)
+                    .is_some_and(|simple_key| simple_key.token_number == self.tokens_parsed)
+            };
             if !need_more_tokens {
                 break;
             }
             self.fetch_next_token()?;
         }
-        self.token_available = true;
         Ok(())
     }
 
     fn fetch_next_token(&mut self) -> Result<()> {
+        if self.interrupt_check.as_ref().is_some_and(|check| check()) {
+            return Err(Error::interrupted(self.mark));
+        }
         self.cache(1)?;
         if !self.stream_start_produced {
             self.fetch_stream_start();
@@ -288,7 +850,7 @@ impl<'r> Scanner<'r> {
         if IS_Z!(self.buffer) {
             return self.fetch_stream_end();
         }
-        if self.mark.column == 0_u64 && self.buffer[0] == '%' {
+        if self.mark.column == 0_u64 && self.flow_level == 0 && self.buffer[0] == '%' {
             return self.fetch_directive();
         }
         if self.mark.column == 0_u64
@@ -369,7 +931,6 @@ impl<'r> Scanner<'r> {
             || CHECK!(self.buffer, '>')
             || CHECK!(self.buffer, '\'')
             || CHECK!(self.buffer, '"')
-            || CHECK!(self.buffer, '%')
             || CHECK!(self.buffer, '@')
             || CHECK!(self.buffer, '`'))
             || CHECK!(self.buffer, '-') && !IS_BLANK_AT!(self.buffer, 1)
@@ -379,19 +940,28 @@ impl<'r> Scanner<'r> {
         {
             return self.fetch_plain_scalar();
         }
-        self.set_scanner_error(
-            "while scanning for the next token",
-            self.mark,
-            "found character that cannot start any token",
-        )
+        let problem = match self.buffer.front() {
+            Some('@') => "found the reserved indicator '@', which cannot start any token",
+            Some('`') => "found the reserved indicator '`', which cannot start any token",
+            _ => "found character that cannot start any token",
+        };
+        self.set_scanner_error("while scanning for the next token", self.mark, problem)
     }
 
     fn stale_simple_keys(&mut self) -> Result<()> {
-        for simple_key in &mut self.simple_keys {
+        while let Some(simple_key) = self.simple_keys.get_mut(self.impossible_simple_keys) {
             let mark = simple_key.mark;
-            if simple_key.possible
-                && (mark.line < self.mark.line || mark.index + 1024 < self.mark.index)
-            {
+            if simple_key.possible {
+                // A simple key is restricted to a single line. On that line,
+                // columns count characters, which is what the length limit is
+                // specified in.
+                let too_long = mark.line == self.mark.line
+                    && self.mark.column - mark.column > MAX_SIMPLE_KEY_LENGTH;
+                if mark.line == self.mark.line && !too_long {
+                    // The keys above this one are after it, so they are not
+                    // stale either.
+                    break;
+                }
                 if simple_key.required {
                     return self.set_scanner_error(
                         "while scanning a simple key",
@@ -400,7 +970,9 @@ impl<'r> Scanner<'r> {
                     );
                 }
                 simple_key.possible = false;
+                simple_key.too_long = too_long;
             }
+            self.impossible_simple_keys += 1;
         }
 
         Ok(())
@@ -414,9 +986,12 @@ impl<'r> Scanner<'r> {
                 required,
                 token_number: self.tokens_parsed + self.tokens.len(),
                 mark: self.mark,
+                too_long: false,
             };
             self.remove_simple_key()?;
             *self.simple_keys.last_mut().unwrap() = simple_key;
+            self.impossible_simple_keys =
+                self.impossible_simple_keys.min(self.simple_keys.len() - 1);
         }
         Ok(())
     }
@@ -434,6 +1009,7 @@ impl<'r> Scanner<'r> {
             }
         }
         simple_key.possible = false;
+        simple_key.too_long = false;
         Ok(())
     }
 
@@ -447,6 +1023,7 @@ impl<'r> Scanner<'r> {
                 line: 0_u64,
                 column: 0_u64,
             },
+            too_long: false,
         };
         self.simple_keys.push(empty_simple_key);
         assert!(
@@ -461,6 +1038,8 @@ impl<'r> Scanner<'r> {
         if self.flow_level != 0 {
             self.flow_level -= 1;
             let _ = self.simple_keys.pop();
+            let _ = self.flow_marks.pop();
+            self.impossible_simple_keys = self.impossible_simple_keys.min(self.simple_keys.len());
         }
     }
 
@@ -512,6 +1091,7 @@ impl<'r> Scanner<'r> {
                 line: 0,
                 column: 0,
             },
+            too_long: false,
         };
         self.indent = -1;
         self.simple_keys.push(simple_key);
@@ -528,6 +1108,14 @@ impl<'r> Scanner<'r> {
     }
 
     fn fetch_stream_end(&mut self) -> Result<()> {
+        if let Some(&(context_mark, context)) = self.flow_marks.last() {
+            return Err(Error::scanner(
+                context,
+                context_mark,
+                "found unexpected end of stream",
+                self.mark,
+            ));
+        }
         if self.mark.column != 0_u64 {
             self.mark.column = 0_u64;
             self.mark.line += 1;
@@ -577,6 +1165,12 @@ impl<'r> Scanner<'r> {
         self.increase_flow_level()?;
         self.simple_key_allowed = true;
         let start_mark: Mark = self.mark;
+        let context = if let TokenData::FlowMappingStart = data {
+            "while scanning a flow mapping"
+        } else {
+            "while scanning a flow sequence"
+        };
+        self.flow_marks.push((start_mark, context));
         self.skip_char();
         let end_mark: Mark = self.mark;
         let token = Token {
@@ -703,6 +1297,17 @@ impl<'r> Scanner<'r> {
             )?;
             self.simple_key_allowed = false;
         } else {
+            if simple_key.too_long && simple_key.mark.line == self.mark.line {
+                // The ':' would have made this a simple key, had it not been
+                // too long. Say so, rather than producing a confusing error
+                // later on.
+                let mark = simple_key.mark;
+                return self.set_scanner_error(
+                    "while scanning a simple key",
+                    mark,
+                    "implicit key is longer than 1024 characters",
+                );
+            }
             if self.flow_level == 0 {
                 if !self.simple_key_allowed {
                     return self.set_scanner_error(
@@ -776,7 +1381,11 @@ impl<'r> Scanner<'r> {
         loop {
             self.cache(1)?;
             if self.mark.column == 0 && IS_BOM!(self.buffer) {
+                // As at the start of the stream, a byte order mark at the
+                // start of a line is not counted as a column, so that it does
+                // not change the indentation.
                 self.skip_char();
+                self.mark.column = 0;
             }
             self.cache(1)?;
             while CHECK!(self.buffer, ' ')
@@ -786,10 +1395,7 @@ impl<'r> Scanner<'r> {
                 self.cache(1)?;
             }
             if CHECK!(self.buffer, '#') {
-                while !IS_BREAKZ!(self.buffer) {
-                    self.skip_char();
-                    self.cache(1)?;
-                }
+                self.skip_comment()?;
             }
             if !IS_BREAK!(self.buffer) {
                 break;
@@ -800,7 +1406,8 @@ impl<'r> Scanner<'r> {
                 self.simple_key_allowed = true;
             }
         }
-        Ok(())
+        let mark = self.mark;
+        self.check_bom("while scanning for the next token", mark)
     }
 
     fn scan_directive(&mut self) -> Result<Token> {
@@ -844,13 +1451,7 @@ impl<'r> Scanner<'r> {
         }
 
         if CHECK!(self.buffer, '#') {
-            loop {
-                if IS_BREAKZ!(self.buffer) {
-                    break;
-                }
-                self.skip_char();
-                self.cache(1)?;
-            }
+            self.skip_comment()?;
         }
 
         if IS_BREAKZ!(self.buffer) {
@@ -990,20 +1591,21 @@ impl<'r> Scanner<'r> {
     }
 
     fn scan_anchor(&mut self, scan_alias_instead_of_anchor: bool) -> Result<Token> {
-        let mut length: i32 = 0;
-
         let mut string = String::new();
         let start_mark: Mark = self.mark;
         self.skip_char();
-        self.cache(1)?;
 
-        loop {
-            if !IS_ALPHA!(self.buffer) {
-                break;
-            }
-            self.read_char(&mut string);
-            self.cache(1)?;
-            length += 1;
+        let length = self.read_while(&mut string, self.max_anchor_length, is_alpha)?;
+        if length == self.max_anchor_length && IS_ALPHA!(self.buffer) {
+            return self.set_scanner_error(
+                if scan_alias_instead_of_anchor {
+                    "while scanning an alias"
+                } else {
+                    "while scanning an anchor"
+                },
+                start_mark,
+                "exceeded the maximum anchor length",
+            );
         }
         let end_mark: Mark = self.mark;
         if length == 0
@@ -1075,15 +1677,20 @@ impl<'r> Scanner<'r> {
         }
 
         self.cache(1)?;
-        if !IS_BLANKZ!(self.buffer) {
-            if self.flow_level == 0 || !CHECK!(self.buffer, ',') {
-                return self.set_scanner_error(
-                    "while scanning a tag",
-                    start_mark,
-                    "did not find expected whitespace or line break",
-                );
-            }
-            panic!("TODO: What is expected here?");
+        // In a flow collection, a tagged empty node may be directly followed by
+        // the end of the entry.
+        let entry_end = self.flow_level > 0 && matches!(self.buffer.front(), Some(',' | ']' | '}'));
+        if !IS_BLANKZ!(self.buffer) && !entry_end {
+            let problem = if handle.is_empty() {
+                "did not find expected whitespace or line break"
+            } else if CHECK!(self.buffer, '!') {
+                "found an unescaped '!' in a tag suffix"
+            } else if matches!(self.buffer.front(), Some(',' | '[' | ']' | '{' | '}')) {
+                "found an unescaped flow indicator in a tag suffix"
+            } else {
+                "did not find expected whitespace or line break"
+            };
+            return self.set_scanner_error("while scanning a tag", start_mark, problem);
         }
 
         let end_mark: Mark = self.mark;
@@ -1111,13 +1718,17 @@ impl<'r> Scanner<'r> {
         }
 
         self.read_char(&mut string);
-        self.cache(1)?;
-        loop {
-            if !IS_ALPHA!(self.buffer) {
-                break;
-            }
-            self.read_char(&mut string);
-            self.cache(1)?;
+        let length = self.read_while(&mut string, self.max_tag_length, is_alpha)?;
+        if length == self.max_tag_length && IS_ALPHA!(self.buffer) {
+            return self.set_scanner_error(
+                if directive {
+                    "while scanning a tag directive"
+                } else {
+                    "while scanning a tag"
+                },
+                start_mark,
+                "exceeded the maximum tag length",
+            );
         }
         if CHECK!(self.buffer, '!') {
             self.read_char(&mut string);
@@ -1145,38 +1756,33 @@ impl<'r> Scanner<'r> {
         if length > 1 {
             string = String::from(&head[1..]);
         }
-        self.cache(1)?;
-
-        while IS_ALPHA!(self.buffer)
-            || CHECK!(self.buffer, ';')
-            || CHECK!(self.buffer, '/')
-            || CHECK!(self.buffer, '?')
-            || CHECK!(self.buffer, ':')
-            || CHECK!(self.buffer, '@')
-            || CHECK!(self.buffer, '&')
-            || CHECK!(self.buffer, '=')
-            || CHECK!(self.buffer, '+')
-            || CHECK!(self.buffer, '$')
-            || CHECK!(self.buffer, '.')
-            || CHECK!(self.buffer, '%')
-            || CHECK!(self.buffer, '!')
-            || CHECK!(self.buffer, '~')
-            || CHECK!(self.buffer, '*')
-            || CHECK!(self.buffer, '\'')
-            || CHECK!(self.buffer, '(')
-            || CHECK!(self.buffer, ')')
-            || uri_char
-                && (CHECK!(self.buffer, ',')
-                    || CHECK!(self.buffer, '[')
-                    || CHECK!(self.buffer, ']'))
-        {
-            if CHECK!(self.buffer, '%') {
+        loop {
+            let limit = self.max_tag_length.saturating_sub(length);
+            length += self.read_while(&mut string, limit, |ch| {
+                ch != '%' && is_uri_char(ch, uri_char)
+            })?;
+            if length < self.max_tag_length && CHECK!(self.buffer, '%') {
                 self.scan_uri_escapes(directive, start_mark, &mut string)?;
+                length += 1;
             } else {
-                self.read_char(&mut string);
+                break;
             }
-            length += 1;
-            self.cache(1)?;
+        }
+        if length >= self.max_tag_length
+            && self
+                .buffer
+                .front()
+                .is_some_and(|ch| is_uri_char(*ch, uri_char))
+        {
+            return self.set_scanner_error(
+                if directive {
+                    "while parsing a %TAG directive"
+                } else {
+                    "while parsing a tag"
+                },
+                start_mark,
+                "exceeded the maximum tag length",
+            );
         }
         if length == 0 {
             self.set_scanner_error(
@@ -1199,7 +1805,14 @@ impl<'r> Scanner<'r> {
         start_mark: Mark,
         string: &mut String,
     ) -> Result<()> {
-        let mut width: i32 = 0;
+        let context = if directive {
+            "while parsing a %TAG directive"
+        } else {
+            "while parsing a tag"
+        };
+        let mut octets = [0_u8; 4];
+        let mut width = 0;
+        let mut length = 0;
         loop {
             self.cache(3)?;
             if !(CHECK!(self.buffer, '%')
@@ -1207,11 +1820,7 @@ impl<'r> Scanner<'r> {
                 && IS_HEX_AT!(self.buffer, 2))
             {
                 return self.set_scanner_error(
-                    if directive {
-                        "while parsing a %TAG directive"
-                    } else {
-                        "while parsing a tag"
-                    },
+                    context,
                     start_mark,
                     "did not find URI escaped octet",
                 );
@@ -1229,39 +1838,51 @@ impl<'r> Scanner<'r> {
                 } else {
                     0
                 };
-                // TODO: Something is fishy here, why isn't `width` being used?
                 if width == 0 {
                     return self.set_scanner_error(
-                        if directive {
-                            "while parsing a %TAG directive"
-                        } else {
-                            "while parsing a tag"
-                        },
+                        context,
                         start_mark,
                         "found an incorrect leading UTF-8 octet",
                     );
                 }
             } else if octet & 0xC0 != 0x80 {
                 return self.set_scanner_error(
-                    if directive {
-                        "while parsing a %TAG directive"
-                    } else {
-                        "while parsing a tag"
-                    },
+                    context,
                     start_mark,
                     "found an incorrect trailing UTF-8 octet",
                 );
             }
-            string.push(char::from_u32(octet as _).expect("invalid Unicode"));
+            octets[length] = octet;
+            length += 1;
             self.skip_char();
             self.skip_char();
             self.skip_char();
-            width -= 1;
-            if width == 0 {
+            if length == width {
                 break;
             }
         }
-        Ok(())
+        // The octets of an escaped character are decoded together, so that a
+        // multibyte character is not read as several Latin-1 characters.
+        match core::str::from_utf8(&octets[..length]) {
+            Ok(decoded) => {
+                string.push_str(decoded);
+                Ok(())
+            }
+            Err(_) => {
+                self.set_scanner_error(context, start_mark, "found an invalid UTF-8 sequence")
+            }
+        }
+    }
+
+    /// Take the indicators of the block scalar whose token ends at
+    /// `end_mark`, if they were recorded.
+    pub(crate) fn take_presentation(&mut self, end_mark: Mark) -> Presentation {
+        while let Some((index, presentation)) = self.presentations.pop_front() {
+            if index == end_mark.index {
+                return presentation;
+            }
+        }
+        Presentation::default()
     }
 
     fn scan_block_scalar(&mut self, literal: bool) -> Result<Token> {
@@ -1278,31 +1899,16 @@ impl<'r> Scanner<'r> {
         self.skip_char();
         self.cache(1)?;
 
-        if CHECK!(self.buffer, '+') || CHECK!(self.buffer, '-') {
+        let chomping_first = CHECK!(self.buffer, '+') || CHECK!(self.buffer, '-');
+        if chomping_first {
             chomping = if CHECK!(self.buffer, '+') { 1 } else { -1 };
             self.skip_char();
             self.cache(1)?;
             if IS_DIGIT!(self.buffer) {
-                if CHECK!(self.buffer, '0') {
-                    return self.set_scanner_error(
-                        "while scanning a block scalar",
-                        start_mark,
-                        "found an indentation indicator equal to 0",
-                    );
-                }
-                increment = AS_DIGIT!(self.buffer) as i32;
-                self.skip_char();
+                increment = self.scan_block_scalar_increment(start_mark)?;
             }
         } else if IS_DIGIT!(self.buffer) {
-            if CHECK!(self.buffer, '0') {
-                return self.set_scanner_error(
-                    "while scanning a block scalar",
-                    start_mark,
-                    "found an indentation indicator equal to 0",
-                );
-            }
-            increment = AS_DIGIT!(self.buffer) as i32;
-            self.skip_char();
+            increment = self.scan_block_scalar_increment(start_mark)?;
             self.cache(1)?;
             if CHECK!(self.buffer, '+') || CHECK!(self.buffer, '-') {
                 chomping = if CHECK!(self.buffer, '+') { 1 } else { -1 };
@@ -1311,6 +1917,13 @@ impl<'r> Scanner<'r> {
         }
 
         self.cache(1)?;
+        if CHECK!(self.buffer, '+') || CHECK!(self.buffer, '-') || IS_DIGIT!(self.buffer) {
+            return self.set_scanner_error(
+                "while scanning a block scalar header",
+                start_mark,
+                "found a repeated chomping or indentation indicator",
+            );
+        }
         loop {
             if !IS_BLANK!(self.buffer) {
                 break;
@@ -1320,18 +1933,12 @@ impl<'r> Scanner<'r> {
         }
 
         if CHECK!(self.buffer, '#') {
-            loop {
-                if IS_BREAKZ!(self.buffer) {
-                    break;
-                }
-                self.skip_char();
-                self.cache(1)?;
-            }
+            self.skip_comment()?;
         }
 
         if !IS_BREAKZ!(self.buffer) {
             return self.set_scanner_error(
-                "while scanning a block scalar",
+                "while scanning a block scalar header",
                 start_mark,
                 "did not find expected comment or line break",
             );
@@ -1380,10 +1987,8 @@ impl<'r> Scanner<'r> {
             string.push_str(&trailing_breaks);
             trailing_breaks.clear();
             leading_blank = IS_BLANK!(self.buffer) as i32;
-            while !IS_BREAKZ!(self.buffer) {
-                self.read_char(&mut string);
-                self.cache(1)?;
-            }
+            self.read_while(&mut string, usize::MAX, |ch| !is_breakz(ch) && !is_bom(ch))?;
+            self.check_bom("while scanning a block scalar", start_mark)?;
             self.cache(2)?;
             self.read_line_break(&mut leading_break);
             self.scan_block_scalar_breaks(
@@ -1394,6 +1999,22 @@ impl<'r> Scanner<'r> {
             )?;
         }
 
+        // A line that is less indented than required by the indentation
+        // indicator ends the scalar, but it cannot continue the enclosing
+        // block either.
+        if increment != 0
+            && (self.mark.column as i32) < indent
+            && self.mark.column as i32 > self.indent.max(0)
+            && !IS_BLANKZ!(self.buffer)
+            && !CHECK!(self.buffer, '#')
+        {
+            return self.set_scanner_error(
+                "while scanning a block scalar indentation",
+                start_mark,
+                "found a line less indented than the indentation indicator",
+            );
+        }
+
         if chomping != -1 {
             string.push_str(&leading_break);
         }
@@ -1402,6 +2023,20 @@ impl<'r> Scanner<'r> {
             string.push_str(&trailing_breaks);
         }
 
+        if self.capture_presentation {
+            let presentation = Presentation {
+                chomping: match chomping {
+                    -1 => Chomping::Strip,
+                    1 => Chomping::Keep,
+                    _ => Chomping::Clip,
+                },
+                indent_indicator: u8::try_from(increment).ok().filter(|digit| *digit != 0),
+                chomping_first: chomping_first && increment != 0,
+                empty_inline: false,
+            };
+            self.presentations.push_back((end_mark.index, presentation));
+        }
+
         Ok(Token {
             data: TokenData::Scalar {
                 value: string,
@@ -1416,6 +2051,20 @@ impl<'r> Scanner<'r> {
         })
     }
 
+    /// Scan the indentation indicator of a block scalar header.
+    fn scan_block_scalar_increment(&mut self, start_mark: Mark) -> Result<i32> {
+        if CHECK!(self.buffer, '0') {
+            return self.set_scanner_error(
+                "while scanning a block scalar header",
+                start_mark,
+                "found an indentation indicator equal to 0",
+            );
+        }
+        let increment = AS_DIGIT!(self.buffer) as i32;
+        self.skip_char();
+        Ok(increment)
+    }
+
     fn scan_block_scalar_breaks(
         &mut self,
         indent: &mut i32,
@@ -1436,7 +2085,7 @@ impl<'r> Scanner<'r> {
             }
             if (*indent == 0 || (self.mark.column as i32) < *indent) && IS_TAB!(self.buffer) {
                 return self.set_scanner_error(
-                    "while scanning a block scalar",
+                    "while scanning a block scalar indentation",
                     start_mark,
                     "found a tab character where an indentation space is expected",
                 );
@@ -1628,6 +2277,9 @@ impl<'r> Scanner<'r> {
                         }
                     } else {
                         self.read_char(&mut string);
+                        self.read_while(&mut string, usize::MAX, |ch| {
+                            !is_blankz(ch) && !matches!(ch, '\'' | '"' | '\\')
+                        })?;
                     }
                 }
                 self.cache(2)?;
@@ -1720,23 +2372,18 @@ impl<'r> Scanner<'r> {
                 break;
             }
             while !IS_BLANKZ!(self.buffer) {
-                if self.flow_level != 0
-                    && CHECK!(self.buffer, ':')
-                    && (CHECK_AT!(self.buffer, ',', 1)
-                        || CHECK_AT!(self.buffer, '?', 1)
-                        || CHECK_AT!(self.buffer, '[', 1)
-                        || CHECK_AT!(self.buffer, ']', 1)
-                        || CHECK_AT!(self.buffer, '{', 1)
-                        || CHECK_AT!(self.buffer, '}', 1))
-                {
-                    return self.set_scanner_error(
-                        "while scanning a plain scalar",
-                        start_mark,
-                        "found unexpected ':'",
-                    );
-                }
-
-                if CHECK!(self.buffer, ':') && IS_BLANKZ_AT!(self.buffer, 1)
+                self.check_bom("while scanning a plain scalar", start_mark)?;
+                // A `:` is only part of the scalar if it is followed by a
+                // character that could be part of it, as in `a:b`. Otherwise,
+                // it is a value indicator, as in `[a:]` or `{a:[b]}`.
+                if CHECK!(self.buffer, ':')
+                    && (IS_BLANKZ_AT!(self.buffer, 1)
+                        || self.flow_level != 0
+                            && (CHECK_AT!(self.buffer, ',', 1)
+                                || CHECK_AT!(self.buffer, '[', 1)
+                                || CHECK_AT!(self.buffer, ']', 1)
+                                || CHECK_AT!(self.buffer, '{', 1)
+                                || CHECK_AT!(self.buffer, '}', 1)))
                     || self.flow_level != 0
                         && (CHECK!(self.buffer, ',')
                             || CHECK!(self.buffer, '[')
@@ -1769,6 +2416,13 @@ impl<'r> Scanner<'r> {
                     }
                 }
                 self.read_char(&mut string);
+                let flow = self.flow_level != 0;
+                self.read_while(&mut string, usize::MAX, |ch| {
+                    !(is_blankz(ch)
+                        || is_bom(ch)
+                        || ch == ':'
+                        || flow && matches!(ch, ',' | '[' | ']' | '{' | '}'))
+                })?;
                 end_mark = self.mark;
                 self.cache(2)?;
             }
@@ -1824,6 +2478,33 @@ impl<'r> Scanner<'r> {
     }
 }
 
+/// Can `ch` be part of a tag URI? `!` and flow indicators are only allowed if
+/// `uri_char` is true, that is, in verbatim tags and `%TAG` prefixes, and must
+/// be escaped in tag shorthands.
+fn is_uri_char(ch: char, uri_char: bool) -> bool {
+    is_alpha(ch)
+        || matches!(
+            ch,
+            ';' | '/'
+                | '?'
+                | ':'
+                | '@'
+                | '&'
+                | '='
+                | '+'
+                | '$'
+                | '.'
+                | '%'
+                | '#'
+                | '~'
+                | '*'
+                | '\''
+                | '('
+                | ')'
+        )
+        || uri_char && matches!(ch, '!' | ',' | '[' | ']')
+}
+
 impl<'r> Default for Scanner<'r> {
     fn default() -> Self {
         Self::new()
@@ -1840,6 +2521,951 @@ impl<'r> Iterator for Scanner<'r> {
             Some(self.scan())
         }
     }
+
+    /// At least one item is left until the end of the stream, and at least
+    /// as many as there are queued tokens.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.stream_end_produced {
+            (0, Some(0))
+        } else {
+            (self.tokens.len().max(1), None)
+        }
+    }
 }
 
 impl<'r> core::iter::FusedIterator for Scanner<'r> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write as _;
+
+    fn scan_all(input: &str) -> Result<Vec<Token>, (Vec<Token>, Error)> {
+        let mut scanner = Scanner::new();
+        let mut input = input.as_bytes();
+        scanner.set_input_string(&mut input);
+        scanner.scan_all()
+    }
+
+    fn assert_prefix(tokens: &[Token], prefix: &str) {
+        let expected = scan_all(prefix).unwrap();
+        assert!(
+            expected.starts_with(tokens),
+            "{tokens:#?}\nis not a prefix of\n{expected:#?}"
+        );
+    }
+
+    #[test]
+    fn scan_all_valid() {
+        let tokens = scan_all("a: [b, c]\n").unwrap();
+        assert_eq!(tokens.len(), 12);
+        assert!(matches!(tokens[0].data, TokenData::StreamStart { .. }));
+        assert!(matches!(tokens[11].data, TokenData::StreamEnd));
+    }
+
+    #[test]
+    fn scan_all_error_near_end() {
+        let (tokens, err) = scan_all("a: 1\nb: [x, y]\nc: @bad\n").unwrap_err();
+        assert_eq!(err.kind(), crate::ErrorKind::Scanner);
+        assert!(matches!(tokens.last().unwrap().data, TokenData::Value));
+        assert_eq!(tokens.len(), 17);
+        assert_prefix(&tokens, "a: 1\nb: [x, y]\nc: ");
+    }
+
+    #[test]
+    fn nonprintable_characters() {
+        for (input, line, column) in [
+            ("plain \x07 scalar", 0, 6),
+            ("'single\x07'", 0, 7),
+            ("a: \"double\x1b\"", 0, 10),
+            ("- |\n  block\n  \x7f\n", 2, 2),
+            ("key: >\r\n  folded\u{80}\n", 1, 8),
+            ("x\u{fffe}", 0, 1),
+            ("# comment \x00\n", 0, 10),
+        ] {
+            let (_tokens, err) = scan_all(input).unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::Scanner, "{input:?}");
+            assert_eq!(err.problem(), "found a non-printable character");
+            let mark = err.problem_mark().unwrap();
+            assert_eq!((mark.line, mark.column), (line, column), "{input:?}");
+            let ch = input[mark.index as usize..].chars().next().unwrap();
+            assert_eq!(
+                err.detail(),
+                Some(alloc::format!("code point U+{:04X}", ch as u32).as_str())
+            );
+        }
+
+        // Escape sequences produce the same characters.
+        let tokens = scan_all("\"\\a\\x7f\\x80\"").unwrap();
+        let TokenData::Scalar { value, .. } = &tokens[1].data else {
+            panic!("{tokens:?}");
+        };
+        assert_eq!(value, "\x07\x7f\u{80}");
+
+        let mut scanner = Scanner::new();
+        scanner.set_allow_nonprintable(true);
+        let mut input = "'a\x07b'".as_bytes();
+        scanner.set_input_string(&mut input);
+        let tokens = scanner.scan_all().unwrap();
+        let TokenData::Scalar { value, .. } = &tokens[1].data else {
+            panic!("{tokens:?}");
+        };
+        assert_eq!(value, "a\x07b");
+
+        let err = scan_all("a: \x07").unwrap_err().1;
+        assert_eq!(
+            err.to_string(),
+            "Scanner error: line 0 column 3: found a non-printable character with code point U+0007"
+        );
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        // At the start of a line, a byte order mark is skipped.
+        assert_eq!(
+            events("a: b\n\u{feff}c: d\n\u{feff}--- e\n"),
+            events("a: b\nc: d\n--- e\n")
+        );
+
+        // Inside quoted scalars, it is content.
+        for (input, expected) in [
+            ("\"a\u{feff}b\"", "a\u{feff}b"),
+            ("'a\u{feff}b'", "a\u{feff}b"),
+            ("\"a\n  \u{feff}b\"", "a \u{feff}b"),
+        ] {
+            let tokens = scan_all(input).unwrap();
+            let TokenData::Scalar { value, .. } = &tokens[1].data else {
+                panic!("{tokens:?}");
+            };
+            assert_eq!(value, expected, "{input:?}");
+        }
+
+        // Anywhere else, it is an error.
+        for (input, context, (line, column)) in [
+            (
+                "a:\n  \u{feff}b: c\n",
+                "while scanning for the next token",
+                (1, 2),
+            ),
+            ("- \u{feff}a\n", "while scanning for the next token", (0, 2)),
+            (
+                "[a, \u{feff}b]",
+                "while scanning for the next token",
+                (0, 4),
+            ),
+            ("a\u{feff}b: c\n", "while scanning a plain scalar", (0, 1)),
+            ("a: b \u{feff}c\n", "while scanning a plain scalar", (0, 5)),
+            (
+                "a: b\n  \u{feff}c\n",
+                "while scanning a plain scalar",
+                (1, 2),
+            ),
+            ("a # comment\u{feff}\n", "while scanning a comment", (0, 11)),
+            (
+                "a: |\n  x\u{feff}\n",
+                "while scanning a block scalar",
+                (1, 3),
+            ),
+        ] {
+            let (_tokens, err) = scan_all(input).unwrap_err();
+            assert_eq!(err.kind(), crate::ErrorKind::Scanner, "{input:?}");
+            assert_eq!(err.context(), Some(context), "{input:?}");
+            assert_eq!(
+                err.problem(),
+                "found a byte order mark (U+FEFF) outside of a quoted scalar"
+            );
+            let mark = err.problem_mark().unwrap();
+            assert_eq!((mark.line, mark.column), (line, column), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn block_scalar_errors() {
+        for (input, context, problem, (line, column)) in [
+            (
+                "a: |+-\n  x\n",
+                "while scanning a block scalar header",
+                "found a repeated chomping or indentation indicator",
+                (0, 5),
+            ),
+            (
+                "a: |12\n  x\n",
+                "while scanning a block scalar header",
+                "found a repeated chomping or indentation indicator",
+                (0, 5),
+            ),
+            (
+                "a: >-0\n  x\n",
+                "while scanning a block scalar header",
+                "found an indentation indicator equal to 0",
+                (0, 5),
+            ),
+            (
+                "a: |0\n  x\n",
+                "while scanning a block scalar header",
+                "found an indentation indicator equal to 0",
+                (0, 4),
+            ),
+            (
+                "a: |  junk\n  x\n",
+                "while scanning a block scalar header",
+                "did not find expected comment or line break",
+                (0, 6),
+            ),
+            (
+                "a: |\n  x\n \ty\n",
+                "while scanning a block scalar indentation",
+                "found a tab character where an indentation space is expected",
+                (2, 1),
+            ),
+            (
+                "a: |3\n   x\n  y\n",
+                "while scanning a block scalar indentation",
+                "found a line less indented than the indentation indicator",
+                (2, 2),
+            ),
+        ] {
+            let (_tokens, err) = scan_all(input).unwrap_err();
+            assert_eq!(err.context(), Some(context), "{input:?}");
+            assert_eq!(err.problem(), problem, "{input:?}");
+            let context_mark = err.context_mark().unwrap();
+            assert_eq!(
+                (context_mark.line, context_mark.column),
+                (0, 3),
+                "{input:?}"
+            );
+            let mark = err.problem_mark().unwrap();
+            assert_eq!((mark.line, mark.column), (line, column), "{input:?}");
+        }
+
+        // Less indented comments and enclosing blocks may follow the scalar.
+        for input in [
+            "a: |3\n   x\n  # comment\nb: c\n",
+            "- a: |2\n    x\n  b: c\n",
+            "--- |1\n x\n--- y\n",
+        ] {
+            scan_all(input).unwrap();
+        }
+    }
+
+    #[test]
+    fn block_scalar_at_end_of_input() {
+        for (input, expected) in [
+            ("|\n  a", "a"),
+            ("|-\n  a", "a"),
+            ("- >+\n  a\n  b", "a b"),
+            ("|+\n  a\n", "a\n"),
+        ] {
+            let tokens = scan_all(input).unwrap();
+            let value = tokens.iter().find_map(|token| match &token.data {
+                TokenData::Scalar { value, .. } => Some(value.as_str()),
+                _ => None,
+            });
+            assert_eq!(value, Some(expected), "{input:?}");
+        }
+    }
+
+    #[test]
+    fn scan_all_error_with_pending_tokens() {
+        // The scalar `'y'` is held back while the scanner waits to see whether
+        // it is a simple key.
+        let (tokens, _err) = scan_all("a: 1\nb: [x, 'y' @]\n").unwrap_err();
+        let TokenData::Scalar { value, .. } = &tokens.last().unwrap().data else {
+            panic!("expected a scalar, got {tokens:#?}");
+        };
+        assert_eq!(value, "y");
+        assert_prefix(&tokens, "a: 1\nb: [x, 'y']");
+    }
+
+    #[test]
+    fn take_pending_tokens() {
+        let mut scanner = Scanner::new();
+        let mut input = "[x, 'y' @]".as_bytes();
+        scanner.set_input_string(&mut input);
+        let mut tokens = Vec::new();
+        let err = loop {
+            match Scanner::scan(&mut scanner) {
+                Ok(token) => tokens.push(token),
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err.kind(), crate::ErrorKind::Scanner);
+        // The whole flow sequence may still turn out to be a simple key.
+        let pending = scanner.take_pending_tokens();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(pending.len(), 4);
+        assert!(matches!(pending[0].data, TokenData::FlowSequenceStart));
+        assert!(scanner.take_pending_tokens().is_empty());
+    }
+
+    const LINE_ENDING_DOCUMENTS: &[&str] = &[
+        "key: value\nother: value\n",
+        "plain\n  multi-line\n\n  scalar\n",
+        "key:\n  nested: plain\n    continued\n  list:\n  - a\n  -\n    b\n",
+        "- 'single\n  quoted\n\n  scalar'\n- \"double\n  quoted \\\n  escaped\"\n",
+        "literal: |\n  line one\n\n  line two\n\n\nfolded: >-\n  line one\n  line two\n\n  line three\n",
+        "- [a,\n   b]\n- {c: d,\n   e: f}\n# comment\n- ? complex\n  : value\n",
+        "%YAML 1.1\n%TAG !e! tag:example.com,2000:\n---\n!e!foo &a bar\n...\n--- *a\n",
+    ];
+
+    /// Events of `input`, ignoring marks.
+    fn events(input: &str) -> Vec<crate::EventData> {
+        let mut parser = crate::Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        parser.map(|event| event.unwrap().data).collect()
+    }
+
+    #[test]
+    fn line_endings() {
+        for document in LINE_ENDING_DOCUMENTS {
+            let expected = events(document);
+            for line_ending in ["\r", "\r\n"] {
+                let converted = document.replace('\n', line_ending);
+                assert_eq!(events(&converted), expected, "{converted:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn line_ending_marks() {
+        for line_ending in ["\n", "\r", "\r\n"] {
+            let input = "a: 1\nb:\n  c\n".replace('\n', line_ending);
+            let lines = scan_all(&input)
+                .unwrap()
+                .iter()
+                .map(|token| token.start_mark.line)
+                .collect::<Vec<_>>();
+            assert_eq!(lines, [0, 0, 0, 0, 0, 0, 1, 1, 1, 2, 3, 3], "{input:?}");
+        }
+    }
+
+    #[test]
+    fn max_anchor_length() {
+        let input = format!("- &{0} a\n- *{0}\n", "x".repeat(DEFAULT_MAX_ANCHOR_LENGTH));
+        assert!(scan_all(&input).is_ok());
+
+        let input = format!("&{} a\n", "x".repeat(DEFAULT_MAX_ANCHOR_LENGTH + 1));
+        let (_, err) = scan_all(&input).unwrap_err();
+        assert_eq!(err.problem(), "exceeded the maximum anchor length");
+
+        let mut scanner = Scanner::new();
+        let mut input = "- &abcd a\n- *abcde\n".as_bytes();
+        scanner.set_input_string(&mut input);
+        scanner.set_max_anchor_length(4);
+        let (tokens, err) = scanner.scan_all().unwrap_err();
+        assert_eq!(err.context(), Some("while scanning an alias"));
+        assert!(tokens.iter().any(|token| token.data
+            == TokenData::Anchor {
+                value: "abcd".into()
+            }));
+    }
+
+    #[test]
+    fn max_tag_length() {
+        let suffix = "x".repeat(DEFAULT_MAX_TAG_LENGTH);
+        for input in [
+            format!("!<{suffix}> a\n"),
+            format!("!e!{suffix} a\n"),
+            format!("!e!{}%21 a\n", &suffix[1..]),
+        ] {
+            assert!(scan_all(&input).is_ok(), "{}", &input[..10]);
+        }
+        for input in [
+            format!("!<{suffix}x> a\n"),
+            format!("!e!{suffix}x a\n"),
+            format!("!e!{suffix}%21 a\n"),
+            format!("!{suffix}x!y a\n"),
+        ] {
+            let (_, err) = scan_all(&input).unwrap_err();
+            assert_eq!(
+                err.problem(),
+                "exceeded the maximum tag length",
+                "{}",
+                &input[..10]
+            );
+        }
+    }
+
+    #[test]
+    fn long_tokens() {
+        let text = "y".repeat(100_000);
+        let input = format!("- {text}\n- '{text}'\n- \"{text}\"\n- |\n  {text}\n# {text}\n");
+        let tokens = scan_all(&input).unwrap();
+        let scalars = tokens
+            .iter()
+            .filter_map(|token| match &token.data {
+                TokenData::Scalar { value, .. } => Some(value.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let literal = format!("{text}\n");
+        assert_eq!(scalars, [&*text, &*text, &*text, &*literal]);
+        let end = tokens.last().unwrap().end_mark;
+        assert_eq!(end.index, input.len() as u64);
+        assert_eq!(end.line, 6);
+    }
+
+    #[test]
+    fn trace_hook() {
+        let traced = alloc::rc::Rc::new(core::cell::RefCell::new(Vec::new()));
+        let mut scanner = Scanner::new();
+        let mut input = "a: 'b\n".as_bytes();
+        scanner.set_input_string(&mut input);
+        let hook_traced = traced.clone();
+        scanner.set_trace(Box::new(move |event| {
+            hook_traced.borrow_mut().push(match event {
+                TraceEvent::Token(token) => format!("{:?}", token.data),
+                TraceEvent::Error(err) => format!("{:?}", err.kind()),
+                TraceEvent::StateTransition { .. } | TraceEvent::Event(_) => unreachable!(),
+            });
+        }));
+        let (tokens, _) = scanner.scan_all().unwrap_err();
+        let mut expected: Vec<_> = tokens
+            .iter()
+            .map(|token| format!("{:?}", token.data))
+            .collect();
+        expected.push("Scanner".to_string());
+        assert_eq!(*traced.borrow(), expected);
+        assert_eq!(expected.len(), 6);
+    }
+
+    #[test]
+    fn simple_key_length() {
+        for ch in ["k", "\u{e9}", "\u{1f389}"] {
+            for template in [
+                "{key}: x\n",
+                "{key} : x\n",
+                "- {key}: x\n",
+                "{{{key}: x}}",
+                "[{key}: x]",
+            ] {
+                // The limit includes the whitespace before the ':'.
+                let limit = if template.contains(" :") { 1023 } else { 1024 };
+                for len in [limit - 1, limit, limit + 1] {
+                    let input = template.replace("{key}", &ch.repeat(len));
+                    let input = input.replace("{{", "{").replace("}}", "}");
+                    let result = scan_all(&input);
+                    if len <= limit {
+                        let tokens = result.unwrap();
+                        assert!(
+                            tokens.iter().any(|token| token.data == TokenData::Key),
+                            "{template} {len}"
+                        );
+                    } else {
+                        let (_, err) = result.unwrap_err();
+                        assert_eq!(
+                            err.problem(),
+                            "implicit key is longer than 1024 characters",
+                            "{template} {len}"
+                        );
+                        let key_start = input.find(ch).unwrap() as u64;
+                        assert_eq!(err.context_mark().unwrap().index, key_start);
+                    }
+                }
+            }
+        }
+
+        // A long scalar that is not a key is fine, as is a ':' on a
+        // later line.
+        let long = "k".repeat(2000);
+        scan_all(&alloc::format!("{long}\n")).unwrap();
+        scan_all(&alloc::format!("[{long}, a: b]")).unwrap();
+        scan_all(&alloc::format!("? {long}\n: x\n")).unwrap();
+    }
+
+    #[test]
+    fn checkpoint_restore() {
+        const ITEM: &str = "\
+item{i}: &a{i}
+  plain: multi
+    line {i}
+  quoted: \"esc\\taped
+    {i}\"
+  flow: [a, {b: c}, 'd',
+    e]
+  block: |
+    text {i}
+  seq:
+  - x
+  - ? complex
+    : *a{i}
+";
+        let mut input = String::from("%YAML 1.2\n---\n");
+        for i in 0..60 {
+            input.push_str(&ITEM.replace("{i}", &i.to_string()));
+        }
+        assert!(input.lines().count() > 500);
+
+        let mut scanner = Scanner::new();
+        let mut bytes = input.as_bytes();
+        scanner.set_input_string(&mut bytes);
+        let mut tokens = Vec::new();
+        let mut checkpoints = Vec::new();
+        loop {
+            if tokens.len() % 23 == 0 {
+                checkpoints.push((tokens.len(), scanner.checkpoint()));
+            }
+            let token = Scanner::scan(&mut scanner).unwrap();
+            let is_end = token.data == TokenData::StreamEnd;
+            tokens.push(token);
+            if is_end {
+                break;
+            }
+        }
+        assert!(checkpoints.len() > 100);
+
+        for (count, checkpoint) in checkpoints {
+            let offset = checkpoint.mark().index as usize;
+            let mut rest = &input.as_bytes()[offset..];
+            let mut restored = Scanner::new();
+            restored.restore(checkpoint, &mut rest);
+            let rest = restored.scan_all().unwrap();
+            assert_eq!(rest, tokens[count..], "from token {count}");
+        }
+    }
+
+    #[test]
+    fn unclosed_flow_collections() {
+        // The context mark is the innermost unclosed indicator, and the
+        // problem mark is the end of the stream.
+        for (input, context, opener) in [
+            ("[", "while scanning a flow sequence", 0),
+            ("{", "while scanning a flow mapping", 0),
+            ("{a: [1, 2", "while scanning a flow sequence", 4),
+            ("[1, {a: b", "while scanning a flow mapping", 4),
+            ("[[], [x", "while scanning a flow sequence", 5),
+            ("{a: [b, {c: [d]}]", "while scanning a flow mapping", 0),
+            (
+                "key: [a,\n  {b: [c,\n    {d: e",
+                "while scanning a flow mapping",
+                23,
+            ),
+            ("- [a, b\n", "while scanning a flow sequence", 2),
+        ] {
+            let (_, err) = scan_all(input).unwrap_err();
+            assert_eq!(err.problem(), "found unexpected end of stream", "{input:?}");
+            assert_eq!(err.context(), Some(context), "{input:?}");
+            assert_eq!(err.context_mark().unwrap().index, opener, "{input:?}");
+            assert_eq!(err.problem_mark().unwrap().index, input.len() as u64);
+        }
+
+        // Closed collections are not reported, even if mismatched.
+        assert!(scan_all("{a: [1, 2]}").is_ok());
+        assert!(scan_all("[a}").is_ok());
+    }
+
+    #[test]
+    fn tag_suffix_characters() {
+        for input in [
+            "!a#b x\n",
+            "!a%21b x\n",
+            "!e!a%5Bb%5D x\n",
+            "!<tag:a!b[c]#d> x\n",
+            "[!a, !b]\n",
+            "{!a: b, c: !d}\n",
+        ] {
+            assert!(scan_all(input).is_ok(), "{input:?}");
+        }
+        for (input, problem, column) in [
+            ("!e!a!b x\n", "found an unescaped '!' in a tag suffix", 4),
+            ("!a!b!c x\n", "found an unescaped '!' in a tag suffix", 4),
+            (
+                "!a[b] x\n",
+                "found an unescaped flow indicator in a tag suffix",
+                2,
+            ),
+            (
+                "!a,b x\n",
+                "found an unescaped flow indicator in a tag suffix",
+                2,
+            ),
+            (
+                "[!a{b}]\n",
+                "found an unescaped flow indicator in a tag suffix",
+                3,
+            ),
+            (
+                "!<a>b x\n",
+                "did not find expected whitespace or line break",
+                4,
+            ),
+            ("!%C0%80 x\n", "found an invalid UTF-8 sequence", 7),
+            ("!%C3x x\n", "did not find URI escaped octet", 4),
+        ] {
+            let (_, err) = scan_all(input).unwrap_err();
+            assert_eq!(err.problem(), problem, "{input:?}");
+            assert_eq!(err.problem_mark().unwrap().column, column, "{input:?}");
+        }
+    }
+
+    /// The content of `input` in a compact form, like `{a: [b]}`.
+    fn compose(input: &str) -> String {
+        let mut output = String::new();
+        for event in events(input) {
+            match event {
+                crate::EventData::Scalar { value, .. } => {
+                    write!(output, "{value:?} ").unwrap();
+                }
+                crate::EventData::SequenceStart { .. } => output.push_str("[ "),
+                crate::EventData::SequenceEnd => output.push_str("] "),
+                crate::EventData::MappingStart { .. } => output.push_str("{ "),
+                crate::EventData::MappingEnd => output.push_str("} "),
+                _ => {}
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn plain_scalar_indicators() {
+        for (input, expected) in [
+            // Block context.
+            (
+                "url: http://example.com:8080/path",
+                r#"{ "url" "http://example.com:8080/path" } "#,
+            ),
+            ("a:b: 1", r#"{ "a:b" "1" } "#),
+            ("a::b: c", r#"{ "a::b" "c" } "#),
+            (":a: b", r#"{ ":a" "b" } "#),
+            ("12:34: x", r#"{ "12:34" "x" } "#),
+            ("key:\n  12:34\n", r#"{ "key" "12:34" } "#),
+            ("a:\tb", r#"{ "a" "b" } "#),
+            (
+                "- a:b\n- :c\n- ::d\n- ::\n",
+                r#"[ "a:b" ":c" "::d" { ":" "" } ] "#,
+            ),
+            ("? a:b\n: c:d\n", r#"{ "a:b" "c:d" } "#),
+            ("a#b: c#d # e", r#"{ "a#b" "c#d" } "#),
+            ("a: b\n  :c\n  c:d\n", r#"{ "a" "b :c c:d" } "#),
+            ("a: b\n  #c\n", r#"{ "a" "b" } "#),
+            ("a: b\n  -c\n", r#"{ "a" "b -c" } "#),
+            // Flow context.
+            ("[a:b, c]", r#"[ "a:b" "c" ] "#),
+            ("[http://x:80/p]", r#"[ "http://x:80/p" ] "#),
+            ("{a:b: c}", r#"{ "a:b" "c" } "#),
+            ("{a: b:c}", r#"{ "a" "b:c" } "#),
+            ("[a:?]", r#"[ "a:?" ] "#),
+            ("[a:]", r#"[ { "a" "" } ] "#),
+            ("[a:b:]", r#"[ { "a:b" "" } ] "#),
+            ("{a:, b: c}", r#"{ "a" "" "b" "c" } "#),
+            ("[a:[b]]", r#"[ { "a" [ "b" ] } ] "#),
+            ("{a:{b: c}}", r#"{ "a" { "b" "c" } } "#),
+            ("{\"a\":b}", r#"{ "a" "b" } "#),
+            ("[a\n  :b]", r#"[ "a :b" ] "#),
+            ("[a#b, c #d\n]", r#"[ "a#b" "c" ] "#),
+        ] {
+            assert_eq!(compose(input), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn quoted_scalar_folding() {
+        for (input, expected) in [
+            // Examples 6.8, 7.5, 7.6, and 7.9 of the YAML 1.2 spec.
+            (
+                "\"\n  foo \n \n  \t bar\n\n  baz\n\"",
+                r#"" foo\nbar\nbaz " "#,
+            ),
+            (
+                "\"folded \nto a space,\t\n \nto a line feed, or \t\\\n \\ \tnon-content\"",
+                r#""folded to a space,\nto a line feed, or \t \tnon-content" "#,
+            ),
+            (
+                "\" 1st non-empty\n\n 2nd non-empty \n\t3rd non-empty \"",
+                r#"" 1st non-empty\n2nd non-empty 3rd non-empty " "#,
+            ),
+            (
+                "' 1st non-empty\n\n 2nd non-empty \n\t3rd non-empty '",
+                r#"" 1st non-empty\n2nd non-empty 3rd non-empty " "#,
+            ),
+            // A blank line is a line feed, even with whitespace around it.
+            ("'first\n\n  second'", r#""first\nsecond" "#),
+            ("'first \n  \n  second'", r#""first\nsecond" "#),
+            ("\"first\t\n\t\n  second\"", r#""first\nsecond" "#),
+            ("'first\r\n\r\n  second'", r#""first\nsecond" "#),
+            ("- 'a\n\n  b'\n- c", r#"[ "a\nb" "c" ] "#),
+            // Consecutive blank lines.
+            ("'a\n\n\n\n  b'", r#""a\n\n\nb" "#),
+            ("\"a \n \n\n \n b\"", r#""a\n\n\nb" "#),
+            // Blank lines before the closing quote.
+            ("'a\n'", r#""a " "#),
+            ("'a\n  \n\t\n'", r#""a\n\n" "#),
+            ("\"a\n\n\n\"", r#""a\n\n" "#),
+        ] {
+            assert_eq!(compose(input), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn comments_after_plain_scalars() {
+        for (input, expected) in [
+            // Examples 6.9, 6.12, 7.10, and 7.12 of the YAML 1.2 spec.
+            ("key:    # Comment\n  value", r#"{ "key" "value" } "#),
+            (
+                "{ first: Sammy, last: Sosa }:\n# Statistics:\n  hr:  # Home runs\n     65\n  avg: # Average\n   0.278",
+                r#"{ { "first" "Sammy" "last" "Sosa" } { "hr" "65" "avg" "0.278" } } "#,
+            ),
+            (
+                "- http://example.com/foo#bar\n- [ -123, http://example.com/foo#bar ]",
+                r#"[ "http://example.com/foo#bar" [ "-123" "http://example.com/foo#bar" ] ] "#,
+            ),
+            (
+                "1st non-empty\n\n 2nd non-empty \n\t3rd non-empty",
+                r#""1st non-empty\n2nd non-empty 3rd non-empty" "#,
+            ),
+            // A `#` after content is part of the scalar, on any line.
+            ("foo#bar", r#""foo#bar" "#),
+            ("foo#", r#""foo#" "#),
+            ("foo# bar", r#""foo# bar" "#),
+            ("foo\n  bar#baz # c", r#""foo bar#baz" "#),
+            ("[a#b, c#, d #e\n]", r#"[ "a#b" "c#" "d" ] "#),
+            // A `#` after whitespace starts a comment, also at the start of a
+            // continuation line.
+            ("foo #bar", r#""foo" "#),
+            ("foo\t#bar", r#""foo" "#),
+            ("a: foo\n  # bar\n", r#"{ "a" "foo" } "#),
+            ("a: foo\n  #bar\nbaz: c\n", r#"{ "a" "foo" "baz" "c" } "#),
+            ("foo\n#bar\n", r#""foo" "#),
+        ] {
+            assert_eq!(compose(input), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn reserved_indicators() {
+        for (input, expected) in [
+            ("=\n", r#""=" "#),
+            ("=: default\n", r#"{ "=" "default" } "#),
+            ("a: =b\n", r#"{ "a" "=b" } "#),
+            ("[=, {=: =}]\n", r#"[ "=" { "=" "=" } ] "#),
+            ("a: %foo\n", r#"{ "a" "%foo" } "#),
+            ("- %foo\n", r#"[ "%foo" ] "#),
+            ("{%foo: 1}\n", r#"{ "%foo" "1" } "#),
+            ("[\n%foo]\n", r#"[ "%foo" ] "#),
+            ("a: b@c`d\n", r#"{ "a" "b@c`d" } "#),
+        ] {
+            assert_eq!(compose(input), expected, "{input:?}");
+        }
+
+        // At the start of a line outside of flow collections, `%` starts a
+        // directive.
+        let (_, err) = scan_all("%foo\n").unwrap_err();
+        assert_eq!(err.context(), Some("while scanning a directive"));
+
+        for (input, column) in [
+            ("@a\n", 0),
+            ("a: @b\n", 3),
+            ("[a, @b]\n", 4),
+            ("`a\n", 0),
+            ("a: `b\n", 3),
+            ("{a: `b}\n", 4),
+        ] {
+            let (_, err) = scan_all(input).unwrap_err();
+            let reserved = &input[column..=column];
+            assert_eq!(
+                err.problem(),
+                format!("found the reserved indicator '{reserved}', which cannot start any token"),
+                "{input:?}"
+            );
+            assert_eq!(err.problem_mark().unwrap().column, column as u64);
+        }
+    }
+
+    #[test]
+    fn reserve_hint() {
+        for input in [
+            "",
+            "a: b\n",
+            "a:\n b:\n  c: d\n",
+            "{a: 1, b: 2, c: [x, y]}\n",
+            "- name: example\n  version: 1.0\n  items:\n    - one\n    - two\n",
+            "key: value\nlist:\n- 1\n- 2\n- 3\nmap: {x: 1, y: 2}\n",
+            include_str!("../benches/very_large.yml"),
+        ] {
+            let mut bytes = input.as_bytes();
+            let mut scanner = Scanner::new();
+            scanner.set_input_string(&mut bytes);
+            let mut tokens = Vec::with_capacity(scanner.reserve_hint(input.len()));
+            let capacity = tokens.capacity();
+            tokens.extend(&mut scanner);
+            assert_eq!(tokens.capacity(), capacity, "{input:?}");
+            assert_eq!(scanner.size_hint(), (0, Some(0)));
+        }
+
+        // Part of the input has been scanned, and some tokens are queued.
+        let input = "a: b\nc: d\n";
+        let mut bytes = input.as_bytes();
+        let mut scanner = Scanner::new();
+        scanner.set_input_string(&mut bytes);
+        assert_eq!(scanner.size_hint(), (1, None));
+        for _ in 0..2 {
+            scanner.next().unwrap().unwrap();
+        }
+        // KEY, SCALAR, and VALUE are queued, and the scanner is after the `:`.
+        assert_eq!(scanner.tokens_queued(), 3);
+        assert_eq!(scanner.size_hint(), (3, None));
+        assert_eq!(scanner.mark.index, 2);
+        assert_eq!(scanner.reserve_hint(input.len()), 8 / 3 + 3 + 16);
+    }
+
+    #[test]
+    fn marks_after_header() {
+        /// The byte offset of the line and the column of `mark` in `input`.
+        /// A byte order mark at the start is not part of the first line.
+        fn offset(input: &str, mark: Mark) -> usize {
+            let (mut line, mut column) = (0, 0);
+            for (offset, ch) in input.char_indices() {
+                if offset == 0 && ch == '\u{feff}' {
+                    continue;
+                }
+                if (line, column) == (mark.line, mark.column) {
+                    return offset;
+                }
+                if ch == '\n' {
+                    line += 1;
+                    column = 0;
+                } else if ch != '\r' {
+                    column += 1;
+                }
+            }
+            input.len()
+        }
+
+        let header = "\
+# Copyright 2024 The Example Authors.
+#
+# Licensed under the Apache License, Version 2.0 (the “License”); you may
+# not use this file except in compliance with the License.
+
+   # Überblick: indented comment
+
+%YAML 1.2
+# Tag shorthands for the application.
+%TAG !app! tag:example.com,2024:app/
+---
+# Settings
+name: !app!name café # trailing comment
+items:
+- &first é
+- *first
+...
+# The next document.
+--- |
+  text
+";
+        for input in [
+            String::from(header),
+            header.replace('\n', "\r\n"),
+            format!("\u{feff}{header}"),
+        ] {
+            let tokens = scan_all(&input).unwrap();
+            for token in &tokens {
+                for mark in [token.start_mark, token.end_mark] {
+                    let expected = offset(&input, mark) as u64;
+                    assert_eq!(mark.index, expected, "{:?} in {input:?}", token.data);
+                }
+            }
+            let mut parser = crate::Parser::new();
+            let mut bytes = input.as_bytes();
+            parser.set_input_string(&mut bytes);
+            for event in parser {
+                let event = event.unwrap();
+                for mark in [event.start_mark, event.end_mark] {
+                    let expected = offset(&input, mark) as u64;
+                    assert_eq!(mark.index, expected, "{:?} in {input:?}", event.data);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn block_scalars_before_document_start() {
+        // The trailing blank lines of a block scalar that ends a document
+        // belong to the scalar, as in libyaml, and the `---` of the next
+        // document is not affected by them.
+        for chomping in ["+", "-", ""] {
+            for blank_lines in 0..4 {
+                let breaks = "\n".repeat(blank_lines);
+                let (value, last) = match chomping {
+                    "+" => (alloc::format!("x\n{breaks}"), "y\n"),
+                    "-" => (String::from("x"), "y"),
+                    _ => (String::from("x\n"), "y\n"),
+                };
+                for (template, values) in [
+                    ("a: |C\n  x\nB---\nb\n", ["a", &value, "b"].as_slice()),
+                    ("--- |C\n  x\nB--- b\n", &[&value, "b"]),
+                    ("- >C\n  x\nB...\n--- b\n", &[&value, "b"]),
+                    ("a:\n- |C\n   x\nB--- |C\n  y\n", &["a", &value, last]),
+                ] {
+                    let input = template.replace('C', chomping).replace('B', &breaks);
+                    let mut documents = 0;
+                    let mut scalars = Vec::new();
+                    for data in events(&input) {
+                        match data {
+                            crate::EventData::DocumentStart { .. } => documents += 1,
+                            crate::EventData::Scalar { value, .. } => scalars.push(value),
+                            _ => {}
+                        }
+                    }
+                    assert_eq!(documents, 2, "{input:?}");
+                    assert_eq!(scalars, values, "{input:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tab_width() {
+        fn scan(input: &str, tab_width: u8) -> Result<Vec<Token>> {
+            let mut scanner = Scanner::new();
+            let mut input = input.as_bytes();
+            scanner.set_input_string(&mut input);
+            scanner.set_tab_width(NonZeroU8::new(tab_width).unwrap());
+            scanner.collect()
+        }
+
+        let input = "{a:\tb,\n\tc:  d,\n  \te: \"x\ty\"\t}\n";
+        for (tab_width, columns, end) in [
+            (1, [1, 4, 1, 5, 3, 6, 12], 11),
+            (4, [1, 4, 4, 8, 4, 7, 16], 14),
+            (8, [1, 8, 8, 12, 8, 11, 24], 18),
+        ] {
+            let tokens = scan(input, tab_width).unwrap();
+            let marks: Vec<Mark> = tokens
+                .iter()
+                .filter(|token| {
+                    matches!(
+                        token.data,
+                        TokenData::Scalar { .. } | TokenData::FlowMappingEnd
+                    )
+                })
+                .map(|token| token.start_mark)
+                .collect();
+            let found: Vec<u64> = marks.iter().map(|mark| mark.column).collect();
+            assert_eq!(found, columns, "tab width {tab_width}");
+            let quoted = tokens
+                .iter()
+                .find(|token| matches!(&token.data, TokenData::Scalar { value, .. } if value == "x\ty"))
+                .unwrap();
+            assert_eq!(quoted.end_mark.column, end, "tab width {tab_width}");
+            let indexes: Vec<u64> = marks.iter().map(|mark| mark.index).collect();
+            assert_eq!(indexes, [1, 4, 8, 12, 18, 21, 27]);
+        }
+
+        // Errors count tabs too, and only the columns of tokens change.
+        for (tab_width, column) in [(1, 5), (4, 8), (8, 8)] {
+            let err = scan("key:\t@x\n", tab_width).unwrap_err();
+            assert_eq!(err.problem_mark().unwrap().column, column);
+        }
+        let input = "a:\t[b,\n\tc]\nd:\te\n";
+        let data = |tab_width| -> Vec<TokenData> {
+            scan(input, tab_width)
+                .unwrap()
+                .into_iter()
+                .map(|token| token.data)
+                .collect()
+        };
+        assert_eq!(data(8), data(1));
+    }
+}
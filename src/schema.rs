@@ -0,0 +1,503 @@
+use crate::{
+    Document, Mark, Node, NodeData, NodePair, BOOL_TAG, FLOAT_TAG, INT_TAG, NULL_TAG, STR_TAG,
+};
+
+/// The kinds of [`Node`]s, as returned by [`Node::kind()`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum NodeKind {
+    /// An empty node.
+    None,
+    /// A scalar node.
+    Scalar,
+    /// A sequence node.
+    Sequence,
+    /// A mapping node.
+    Mapping,
+}
+
+/// Displays the kind with an article, as `a mapping`.
+impl core::fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            NodeKind::None => "an empty node",
+            NodeKind::Scalar => "a scalar",
+            NodeKind::Sequence => "a sequence",
+            NodeKind::Mapping => "a mapping",
+        })
+    }
+}
+
+/// A node that does not match what an application expects, returned by the
+/// `expect_*` methods of [`Document`] and [`Document::require_keys()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SchemaError {
+    /// What was expected, like `a mapping`.
+    pub expected: String,
+    /// What was found, like `a scalar`.
+    pub found: String,
+    /// The start of the node, or zero if there is no such node.
+    pub mark: Mark,
+}
+
+impl SchemaError {
+    fn new(expected: impl Into<String>, found: impl Into<String>, mark: Mark) -> SchemaError {
+        SchemaError {
+            expected: expected.into(),
+            found: found.into(),
+            mark,
+        }
+    }
+}
+
+/// Displays the error as `line:column: expected a mapping, found a scalar`,
+/// counting from 1.
+impl core::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, found {}",
+            self.mark, self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl Node {
+    /// Does the node have the tag `tag`? Untagged nodes have no tag.
+    pub fn tag_is(&self, tag: &str) -> bool {
+        self.tag.as_deref() == Some(tag)
+    }
+
+    /// The kind of the node.
+    pub fn kind(&self) -> NodeKind {
+        match self.data {
+            NodeData::NoNode => NodeKind::None,
+            NodeData::Scalar { .. } => NodeKind::Scalar,
+            NodeData::Sequence { .. } => NodeKind::Sequence,
+            NodeData::Mapping { .. } => NodeKind::Mapping,
+        }
+    }
+}
+
+impl Document {
+    /// Get the pairs of a MAPPING node, or an error describing the node.
+    pub fn expect_mapping(&self, node: i32) -> Result<&[NodePair], SchemaError> {
+        let node = self.expect_node(node, NodeKind::Mapping)?;
+        match &node.data {
+            NodeData::Mapping { pairs, .. } => Ok(pairs),
+            _ => Err(kind_error(NodeKind::Mapping, node)),
+        }
+    }
+
+    /// Get the items of a SEQUENCE node, or an error describing the node.
+    pub fn expect_sequence(&self, node: i32) -> Result<&[i32], SchemaError> {
+        let node = self.expect_node(node, NodeKind::Sequence)?;
+        match &node.data {
+            NodeData::Sequence { items, .. } => Ok(items),
+            _ => Err(kind_error(NodeKind::Sequence, node)),
+        }
+    }
+
+    /// Get the value of a SCALAR node, or an error describing the node.
+    pub fn expect_scalar(&self, node: i32) -> Result<&str, SchemaError> {
+        let node = self.expect_node(node, NodeKind::Scalar)?;
+        match &node.data {
+            NodeData::Scalar { value, .. } => Ok(value),
+            _ => Err(kind_error(NodeKind::Scalar, node)),
+        }
+    }
+
+    /// Get a node with the tag `tag`, or an error describing the tag of the
+    /// node.
+    pub fn expect_tag(&self, node: i32, tag: &str) -> Result<&Node, SchemaError> {
+        let expected = alloc::format!("a node tagged `{tag}`");
+        let Some(found) = self.get_node(node) else {
+            return Err(SchemaError::new(expected, "no node", Mark::default()));
+        };
+        if found.tag_is(tag) {
+            return Ok(found);
+        }
+        let description = match &found.tag {
+            Some(found_tag) => alloc::format!("{} tagged `{found_tag}`", found.kind()),
+            None => alloc::format!("{} without a tag", found.kind()),
+        };
+        Err(SchemaError::new(expected, description, found.start_mark))
+    }
+
+    /// Check that a MAPPING node has a pair for each of the scalar keys
+    /// `keys`.
+    ///
+    /// All missing keys are reported in a single error, at the start of the
+    /// mapping.
+    pub fn require_keys(&self, mapping: i32, keys: &[&str]) -> Result<(), SchemaError> {
+        self.expect_mapping(mapping)?;
+        let missing: Vec<&str> = keys
+            .iter()
+            .copied()
+            .filter(|key| self.get_mapping_value(mapping, key).is_none())
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        let list = |keys: &[&str]| {
+            keys.iter()
+                .map(|key| alloc::format!("`{key}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let node = &self.nodes[mapping as usize - 1];
+        Err(SchemaError::new(
+            alloc::format!("a mapping with the keys {}", list(keys)),
+            alloc::format!("a mapping without {}", list(&missing)),
+            node.start_mark,
+        ))
+    }
+
+    fn expect_node(&self, node: i32, expected: NodeKind) -> Result<&Node, SchemaError> {
+        self.get_node(node)
+            .ok_or_else(|| SchemaError::new(expected.to_string(), "no node", Mark::default()))
+    }
+}
+
+fn kind_error(expected: NodeKind, node: &Node) -> SchemaError {
+    SchemaError::new(
+        expected.to_string(),
+        node.kind().to_string(),
+        node.start_mark,
+    )
+}
+
+/// The tag that the core schema of YAML 1.2 resolves a plain scalar to.
+pub(crate) fn core_schema_tag(value: &str) -> &'static str {
+    if matches!(value, "" | "~" | "null" | "Null" | "NULL") {
+        NULL_TAG
+    } else if matches!(
+        value,
+        "true" | "True" | "TRUE" | "false" | "False" | "FALSE"
+    ) {
+        BOOL_TAG
+    } else if is_core_int(value) {
+        INT_TAG
+    } else if is_core_float(value) {
+        FLOAT_TAG
+    } else {
+        STR_TAG
+    }
+}
+
+/// Would a plain scalar be read as something other than a string, by the
+/// core schema or as a YAML 1.1 boolean like `no`?
+pub(crate) fn is_ambiguous_plain(value: &str) -> bool {
+    core_schema_tag(value) != STR_TAG
+        || matches!(
+            value.to_ascii_lowercase().as_str(),
+            "y" | "n" | "yes" | "no" | "on" | "off"
+        )
+}
+
+fn is_core_int(value: &str) -> bool {
+    let (digits, radix) = if let Some(digits) = value.strip_prefix("0o") {
+        (digits, 8)
+    } else if let Some(digits) = value.strip_prefix("0x") {
+        (digits, 16)
+    } else {
+        (value.strip_prefix(['-', '+']).unwrap_or(value), 10)
+    };
+    !digits.is_empty() && digits.chars().all(|ch| ch.is_digit(radix))
+}
+
+fn is_core_float(value: &str) -> bool {
+    let unsigned = value.strip_prefix(['-', '+']).unwrap_or(value);
+    if matches!(unsigned, ".inf" | ".Inf" | ".INF") || matches!(value, ".nan" | ".NaN" | ".NAN") {
+        return true;
+    }
+    let is_digits = |digits: &str| digits.bytes().all(|byte| byte.is_ascii_digit());
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (unsigned, None),
+    };
+    let mantissa = match mantissa.split_once('.') {
+        Some((integer, fraction)) => {
+            is_digits(integer)
+                && is_digits(fraction)
+                && !(integer.is_empty() && fraction.is_empty())
+        }
+        None => !mantissa.is_empty() && is_digits(mantissa),
+    };
+    mantissa
+        && exponent.map_or(true, |exponent| {
+            let digits = exponent.strip_prefix(['-', '+']).unwrap_or(exponent);
+            !digits.is_empty() && is_digits(digits)
+        })
+}
+
+/// The text of a float for the core schema: the shortest that reads back as
+/// the same value, with `.inf`, `-.inf`, and `.nan` for the special values.
+pub(crate) fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        String::from(".nan")
+    } else if value.is_infinite() {
+        String::from(if value > 0.0 { ".inf" } else { "-.inf" })
+    } else {
+        // `Debug` leaves out the fraction before an exponent, as in `1e300`,
+        // which some schemas read as a string.
+        let text = format!("{value:?}");
+        match text.split_once('e') {
+            Some((mantissa, exponent)) if !mantissa.contains('.') => {
+                format!("{mantissa}.0e{exponent}")
+            }
+            _ => text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::core_schema_tag;
+    use crate::{
+        emit_events, Document, Event, NodeData, NodeKind, Parser, ScalarStyle, SchemaError,
+        SequenceStyle, BOOL_TAG, FLOAT_TAG, INT_TAG, NULL_TAG, STR_TAG,
+    };
+
+    fn load(input: &str) -> Document {
+        let mut parser = Parser::new();
+        let mut input = input.as_bytes();
+        parser.set_input_string(&mut input);
+        Document::load(&mut parser).unwrap()
+    }
+
+    /// A schema for the configuration of a service: a mapping with a `name`,
+    /// a `port` that is an integer, and a list of `backends`, each a mapping
+    /// with a `host`.
+    fn validate(document: &Document) -> Result<Vec<String>, SchemaError> {
+        document.require_keys(1, &["name", "port", "backends"])?;
+        document.expect_scalar(document.get_mapping_value(1, "name").unwrap())?;
+        let port = document.get_mapping_value(1, "port").unwrap();
+        document.expect_scalar(port)?;
+        document.expect_tag(port, INT_TAG)?;
+        let backends = document.get_mapping_value(1, "backends").unwrap();
+        let mut hosts = Vec::new();
+        for backend in document.expect_sequence(backends)? {
+            document.require_keys(*backend, &["host"])?;
+            let host = document.get_mapping_value(*backend, "host").unwrap();
+            hosts.push(String::from(document.expect_scalar(host)?));
+        }
+        Ok(hosts)
+    }
+
+    #[test]
+    fn schema() {
+        let valid = "\
+name: web
+port: !!int 8080
+backends:
+- host: a.example.com
+- host: b.example.com
+  weight: 2
+";
+        assert_eq!(
+            validate(&load(valid)).unwrap(),
+            ["a.example.com", "b.example.com"]
+        );
+
+        for (input, error) in [
+            ("- name: web\n", "1:1: expected a mapping, found a sequence"),
+            (
+                "\nname: web\n",
+                "2:1: expected a mapping with the keys `name`, `port`, `backends`, \
+                 found a mapping without `port`, `backends`",
+            ),
+            (
+                "name: [web]\nport: 80\nbackends: []\n",
+                "1:7: expected a scalar, found a sequence",
+            ),
+            (
+                "name: web\nport: '80'\nbackends: []\n",
+                "2:7: expected a node tagged `tag:yaml.org,2002:int`, \
+                 found a scalar tagged `tag:yaml.org,2002:str`",
+            ),
+            (
+                "name: web\nport: !!int 80\nbackends:\n  host: a\n",
+                "4:3: expected a sequence, found a mapping",
+            ),
+            (
+                "name: web\nport: !!int 80\nbackends:\n- host: a\n- hots: b\n",
+                "5:3: expected a mapping with the keys `host`, found a mapping without `host`",
+            ),
+            (
+                "name: web\nport: !!int 80\nbackends:\n- host: [a]\n",
+                "4:9: expected a scalar, found a sequence",
+            ),
+        ] {
+            assert_eq!(
+                validate(&load(input)).unwrap_err().to_string(),
+                error,
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn node_kinds() {
+        let document = load("a: [b]\n");
+        let kinds: Vec<_> = document.nodes.iter().map(crate::Node::kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                NodeKind::Mapping,
+                NodeKind::Scalar,
+                NodeKind::Sequence,
+                NodeKind::Scalar
+            ]
+        );
+        assert!(document.nodes[0].tag_is(crate::DEFAULT_MAPPING_TAG));
+        assert!(!document.nodes[0].tag_is(crate::DEFAULT_SCALAR_TAG));
+        assert!(!crate::Node::default().tag_is(crate::DEFAULT_SCALAR_TAG));
+
+        let error = document.expect_mapping(5).unwrap_err();
+        assert_eq!(error.to_string(), "1:1: expected a mapping, found no node");
+        assert_eq!(document.expect_sequence(3).unwrap(), [4]);
+    }
+
+    /// Read a plain scalar as a float with the core schema.
+    fn parse_float(value: &str) -> f64 {
+        assert_eq!(core_schema_tag(value), FLOAT_TAG, "{value:?}");
+        match value {
+            ".nan" => f64::NAN,
+            ".inf" => f64::INFINITY,
+            "-.inf" => f64::NEG_INFINITY,
+            _ => value.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn core_schema() {
+        for (value, tag) in [
+            ("", NULL_TAG),
+            ("~", NULL_TAG),
+            ("NULL", NULL_TAG),
+            ("True", BOOL_TAG),
+            ("false", BOOL_TAG),
+            ("yes", STR_TAG),
+            ("-12", INT_TAG),
+            ("0o17", INT_TAG),
+            ("0x1F", INT_TAG),
+            ("0x", STR_TAG),
+            ("1.5", FLOAT_TAG),
+            (".5", FLOAT_TAG),
+            ("1.", FLOAT_TAG),
+            ("1e3", FLOAT_TAG),
+            ("-1.0e-3", FLOAT_TAG),
+            ("-.INF", FLOAT_TAG),
+            (".NaN", FLOAT_TAG),
+            ("-.nan", STR_TAG),
+            (".", STR_TAG),
+            ("1e", STR_TAG),
+            ("1.2.3", STR_TAG),
+            ("12:30", STR_TAG),
+        ] {
+            assert_eq!(core_schema_tag(value), tag, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn typed_scalars() {
+        let ints = [0, 1, -1, i64::MIN, i64::MAX];
+        let floats = [
+            0.0,
+            -0.0,
+            0.1,
+            1.0,
+            -2.5,
+            1e308,
+            -1e-308,
+            f64::MIN_POSITIVE,
+            5e-324,
+            f64::MAX,
+            1e16,
+            123_456_789.012_345_68,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+        ];
+        let strs = [
+            "plain", "1", "-1.5", "null", "", "~", "true", "no", "On", "Y", ".inf", "0x10",
+        ];
+        let emit = |scalars: Vec<Event>| {
+            let mut events = vec![
+                Event::document_start(None, &[], true),
+                Event::sequence_start(None, None, true, SequenceStyle::Block),
+            ];
+            events.extend(scalars);
+            events.push(Event::sequence_end());
+            events.push(Event::document_end(true));
+            emit_events(events).unwrap()
+        };
+        let mut typed: Vec<_> = ints.map(Event::scalar_int).into();
+        typed.extend(floats.map(Event::scalar_float));
+        typed.extend([true, false].map(Event::scalar_bool));
+        typed.push(Event::scalar_null());
+        let typed = emit(typed);
+        assert!(!typed.contains('!'), "{typed}");
+
+        // The nodes of a document are written the same way.
+        let mut document = Document::new(None, &[], true, true);
+        let sequence = document.add_sequence(None, SequenceStyle::Block);
+        let mut nodes: Vec<_> = ints.map(|value| document.add_int(value)).into();
+        nodes.extend(floats.map(|value| document.add_float(value)));
+        nodes.extend([true, false].map(|value| document.add_bool(value)));
+        nodes.push(document.add_null());
+        for node in nodes {
+            document.append_sequence_item(sequence, node);
+        }
+        assert_eq!(document.to_string_indented(0).unwrap(), typed);
+
+        let output = format!("{typed}{}", emit(strs.map(Event::scalar_str).into()));
+        let values = load(&output);
+        let scalars: Vec<_> = values.nodes[1..]
+            .iter()
+            .map(|node| {
+                let NodeData::Scalar { value, style } = &node.data else {
+                    unreachable!()
+                };
+                (value.as_str(), *style)
+            })
+            .collect();
+        let (ints_out, rest) = scalars.split_at(ints.len());
+        let (floats_out, rest) = rest.split_at(floats.len());
+        let (others, strs_out) = rest.split_at(3);
+        assert_eq!(strs_out.len(), strs.len());
+        for (&(value, style), int) in ints_out.iter().zip(ints) {
+            assert_eq!(style, ScalarStyle::Plain);
+            assert_eq!(core_schema_tag(value), INT_TAG, "{value:?}");
+            assert_eq!(value.parse::<i64>().unwrap(), int);
+        }
+        for (&(value, style), float) in floats_out.iter().zip(floats) {
+            assert_eq!(style, ScalarStyle::Plain);
+            let parsed = parse_float(value);
+            assert!(
+                parsed.to_bits() == float.to_bits() || parsed.is_nan() && float.is_nan(),
+                "{value:?}"
+            );
+        }
+        assert_eq!(
+            others,
+            [
+                ("true", ScalarStyle::Plain),
+                ("false", ScalarStyle::Plain),
+                ("null", ScalarStyle::Plain)
+            ]
+        );
+        for (&(value, style), expected) in strs_out.iter().zip(strs) {
+            assert_eq!(value, expected);
+            assert_eq!(
+                style == ScalarStyle::Plain,
+                expected == "plain",
+                "{value:?}"
+            );
+        }
+    }
+}
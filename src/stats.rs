@@ -0,0 +1,253 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{Event, EventData};
+
+/// Counters of the events that went through a [`Parser`](crate::Parser) or
+/// an [`Emitter`](crate::Emitter).
+///
+/// See [`ParserStats::events()`] and [`EmitterStats::events()`].
+#[derive(Debug, Default)]
+pub struct EventStats {
+    events: AtomicU64,
+    documents: AtomicU64,
+    scalars: AtomicU64,
+    sequences: AtomicU64,
+    mappings: AtomicU64,
+    aliases: AtomicU64,
+    max_depth: AtomicU64,
+    largest_scalar_len: AtomicU64,
+}
+
+impl EventStats {
+    /// The number of events, including STREAM-START and STREAM-END.
+    pub fn event_count(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// The number of DOCUMENT-START events.
+    pub fn document_count(&self) -> u64 {
+        self.documents.load(Ordering::Relaxed)
+    }
+
+    /// The number of SCALAR events.
+    pub fn scalar_count(&self) -> u64 {
+        self.scalars.load(Ordering::Relaxed)
+    }
+
+    /// The number of SEQUENCE-START events.
+    pub fn sequence_count(&self) -> u64 {
+        self.sequences.load(Ordering::Relaxed)
+    }
+
+    /// The number of MAPPING-START events.
+    pub fn mapping_count(&self) -> u64 {
+        self.mappings.load(Ordering::Relaxed)
+    }
+
+    /// The number of ALIAS events.
+    pub fn alias_count(&self) -> u64 {
+        self.aliases.load(Ordering::Relaxed)
+    }
+
+    /// The largest number of nested collections, where the content of a
+    /// document is at depth 0 and the entries of its root collection are at
+    /// depth 1.
+    pub fn max_depth(&self) -> u64 {
+        self.max_depth.load(Ordering::Relaxed)
+    }
+
+    /// The length of the largest scalar value, in bytes.
+    pub fn largest_scalar_len(&self) -> u64 {
+        self.largest_scalar_len.load(Ordering::Relaxed)
+    }
+
+    /// Count `event`, where `depth` is the number of open collections, kept
+    /// by the caller.
+    pub(crate) fn record(&self, event: &EventData, depth: &mut u64) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+        match event {
+            EventData::DocumentStart { .. } => {
+                self.documents.fetch_add(1, Ordering::Relaxed);
+            }
+            EventData::Alias { .. } => {
+                self.aliases.fetch_add(1, Ordering::Relaxed);
+            }
+            EventData::Scalar { value, .. } => {
+                self.scalars.fetch_add(1, Ordering::Relaxed);
+                self.largest_scalar_len
+                    .fetch_max(value.len() as u64, Ordering::Relaxed);
+            }
+            EventData::SequenceStart { .. } => {
+                self.sequences.fetch_add(1, Ordering::Relaxed);
+                self.open_collection(depth);
+            }
+            EventData::MappingStart { .. } => {
+                self.mappings.fetch_add(1, Ordering::Relaxed);
+                self.open_collection(depth);
+            }
+            EventData::SequenceEnd | EventData::MappingEnd => {
+                *depth = depth.saturating_sub(1);
+            }
+            EventData::StreamStart { .. }
+            | EventData::StreamEnd
+            | EventData::DocumentEnd { .. } => {}
+        }
+    }
+
+    fn open_collection(&self, depth: &mut u64) {
+        *depth += 1;
+        self.max_depth.fetch_max(*depth, Ordering::Relaxed);
+    }
+}
+
+/// The position of a parser or an emitter, to update its statistics.
+#[derive(Copy, Clone, Default, Debug)]
+pub(crate) struct StatsPosition {
+    /// The number of open collections.
+    pub depth: u64,
+    /// The index of the end of the last counted event.
+    pub end: u64,
+}
+
+/// Statistics collected by a [`Parser`](crate::Parser), set with
+/// [`Parser::set_stats()`](crate::Parser::set_stats).
+///
+/// The counters are relaxed atomics, so a `ParserStats` can be shared by the
+/// parsers of several threads, and read while they run, to aggregate metrics.
+/// They are best-effort: counts read while parsers run may be from different
+/// points in time, and the maximums are over all parsers that share them.
+#[derive(Debug, Default)]
+pub struct ParserStats {
+    events: EventStats,
+    input_bytes: AtomicU64,
+}
+
+impl ParserStats {
+    /// Create zeroed statistics.
+    pub fn new() -> ParserStats {
+        ParserStats::default()
+    }
+
+    /// The counters of the produced events.
+    pub fn events(&self) -> &EventStats {
+        &self.events
+    }
+
+    /// The number of input bytes up to the end of the last produced event.
+    pub fn input_bytes(&self) -> u64 {
+        self.input_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Count a produced event, where `position` is kept by the parser.
+    pub(crate) fn record(&self, event: &Event, position: &mut StatsPosition) {
+        self.events.record(&event.data, &mut position.depth);
+        let end = event.end_mark.index;
+        if end > position.end {
+            self.input_bytes
+                .fetch_add(end - position.end, Ordering::Relaxed);
+            position.end = end;
+        }
+    }
+}
+
+/// Statistics collected by an [`Emitter`](crate::Emitter), set with
+/// [`Emitter::set_stats()`](crate::Emitter::set_stats).
+///
+/// Like [`ParserStats`], the counters are best-effort relaxed atomics, which
+/// can be shared by several emitters.
+#[derive(Debug, Default)]
+pub struct EmitterStats {
+    events: EventStats,
+    output_bytes: AtomicU64,
+}
+
+impl EmitterStats {
+    /// Create zeroed statistics.
+    pub fn new() -> EmitterStats {
+        EmitterStats::default()
+    }
+
+    /// The counters of the emitted events.
+    pub fn events(&self) -> &EventStats {
+        &self.events
+    }
+
+    /// The number of bytes written to the output, in the output encoding.
+    pub fn output_bytes(&self) -> u64 {
+        self.output_bytes.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_output_bytes(&self, bytes: usize) {
+        self.output_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{EmitterStats, EventStats, ParserStats};
+    use crate::{Emitter, Encoding, Parser};
+
+    const INPUT: &str = "a: &x [1, 22, {b: c}]\nd: *x\n--- long string value\n";
+
+    fn counts(stats: &EventStats) -> [u64; 8] {
+        [
+            stats.event_count(),
+            stats.document_count(),
+            stats.scalar_count(),
+            stats.sequence_count(),
+            stats.mapping_count(),
+            stats.alias_count(),
+            stats.max_depth(),
+            stats.largest_scalar_len(),
+        ]
+    }
+
+    #[test]
+    fn parser_stats() {
+        let stats = Arc::new(ParserStats::new());
+        let mut parser = Parser::new();
+        parser.set_stats(stats.clone());
+        let mut input = INPUT.as_bytes();
+        parser.set_input_string(&mut input);
+
+        let event = parser.next().unwrap().unwrap();
+        assert_eq!(counts(stats.events()), [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(stats.input_bytes(), event.end_mark.index);
+
+        let events = parser.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(events.len(), 19);
+        assert_eq!(counts(stats.events()), [20, 2, 7, 1, 2, 1, 3, 17]);
+        assert_eq!(stats.input_bytes(), INPUT.len() as u64);
+
+        // Parsers that share statistics add up their counts.
+        let mut parser = Parser::new();
+        parser.set_stats(stats.clone());
+        let mut input = "[x]".as_bytes();
+        parser.set_input_string(&mut input);
+        parser.for_each(|event| drop(event.unwrap()));
+        assert_eq!(counts(stats.events()), [27, 3, 8, 2, 2, 1, 3, 17]);
+        assert_eq!(stats.input_bytes(), INPUT.len() as u64 + 3);
+    }
+
+    #[test]
+    fn emitter_stats() {
+        let events = crate::parse_events(INPUT).unwrap();
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le] {
+            let stats = Arc::new(EmitterStats::new());
+            let mut output = Vec::new();
+            let mut emitter = Emitter::new();
+            emitter.set_stats(stats.clone());
+            emitter.set_encoding(encoding);
+            emitter.set_output(&mut output);
+            for event in events.iter().cloned() {
+                emitter.emit(event).unwrap();
+            }
+            emitter.flush().unwrap();
+            drop(emitter);
+            assert_eq!(counts(stats.events()), [20, 2, 7, 1, 2, 1, 3, 17]);
+            assert_eq!(stats.output_bytes(), output.len() as u64, "{encoding:?}");
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use crate::{Encoding, Mark, ScalarStyle};
+use crate::{Encoding, Mark, ScalarStyle, Span};
 
 /// The token structure.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[non_exhaustive]
 pub struct Token {
     /// The token type.
@@ -12,7 +12,14 @@ pub struct Token {
     pub end_mark: Mark,
 }
 
-#[derive(Debug, PartialEq)]
+impl Token {
+    /// The range of the input of the token.
+    pub fn span(&self) -> Span {
+        Span::new(self.start_mark, self.end_mark)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum TokenData {
     /// A STREAM-START token.
     StreamStart {
@@ -0,0 +1,33 @@
+use crate::{Error, Event, ParserState, Token};
+
+/// A step in the internal processing of a [`Scanner`](crate::Scanner) or
+/// [`Parser`](crate::Parser), reported to a trace hook.
+///
+/// See [`Scanner::set_trace()`](crate::Scanner::set_trace) and
+/// [`Parser::set_trace()`](crate::Parser::set_trace).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TraceEvent<'a> {
+    /// The parser made a step in its state machine.
+    ///
+    /// The state may not change in a step, in which case `state_from` and
+    /// `state_to` are equal.
+    StateTransition {
+        /// The state before the step.
+        state_from: ParserState,
+        /// The state after the step.
+        state_to: ParserState,
+    },
+    /// The scanner fetched a token, which is now the next token in the queue.
+    Token(&'a Token),
+    /// The parser produced an event.
+    Event(&'a Event),
+    /// The scanner or the parser failed with an error.
+    ///
+    /// The marks of the error are available through [`Error::problem_mark()`]
+    /// and [`Error::context_mark()`].
+    Error(&'a Error),
+}
+
+/// A trace hook, called for every [`TraceEvent`].
+pub type TraceHook = Box<dyn FnMut(&TraceEvent<'_>)>;
@@ -0,0 +1,287 @@
+use crate::{Error, Event, EventData, Result};
+
+/// The position in the stream expected by [`validate_event_stream()`].
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum State {
+    StreamStart,
+    DocumentStart,
+    Content,
+    DocumentEnd,
+    End,
+}
+
+/// An open collection.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Collection {
+    Sequence,
+    /// A mapping, and whether its next node is a value.
+    Mapping(bool),
+}
+
+/// Check that a sequence of events can be passed to the emitter, without
+/// emitting it.
+///
+/// The stream must start with STREAM-START and end with STREAM-END, the
+/// documents between them must hold exactly one root node each, collections
+/// must be closed by the matching end event, mappings must have a value for
+/// every key, and every scalar must have a tag or one of the implicit flags
+/// set. Errors are of kind [`ErrorKind::EventStream`](crate::ErrorKind), with
+/// the index of the offending event in [`Error::event_index()`].
+///
+/// The content of the events, like the names of anchors and tags, is only
+/// checked by the emitter.
+///
+/// ```
+/// use libyaml_safer::{parse_events, validate_event_stream, Event};
+///
+/// let mut events = parse_events("[a, b]")?;
+/// validate_event_stream(&events)?;
+///
+/// events.remove(5);
+/// let err = validate_event_stream(&events).unwrap_err();
+/// assert_eq!(err.event_index(), Some(5));
+/// assert_eq!(
+///     err.problem(),
+///     "expected SCALAR, SEQUENCE-START, MAPPING-START, ALIAS, or SEQUENCE-END",
+/// );
+/// # Ok::<(), libyaml_safer::Error>(())
+/// ```
+pub fn validate_event_stream(events: &[Event]) -> Result<()> {
+    let mut state = State::StreamStart;
+    let mut collections = Vec::new();
+    for (index, event) in events.iter().enumerate() {
+        // The collection that the event closes, if it is an end event.
+        let closed = match event.data {
+            EventData::SequenceEnd => Some(Collection::Sequence),
+            EventData::MappingEnd => Some(Collection::Mapping(false)),
+            _ => None,
+        };
+        state = match (state, &event.data) {
+            (State::StreamStart, EventData::StreamStart { .. })
+            | (State::DocumentEnd, EventData::DocumentEnd { .. }) => State::DocumentStart,
+            (State::DocumentStart, EventData::DocumentStart { .. }) => State::Content,
+            (State::DocumentStart, EventData::StreamEnd) => State::End,
+            (State::Content, EventData::Alias { .. }) => end_node(&mut collections),
+            (
+                State::Content,
+                EventData::Scalar {
+                    tag,
+                    plain_implicit,
+                    quoted_implicit,
+                    ..
+                },
+            ) => {
+                if tag.is_none() && !*plain_implicit && !*quoted_implicit {
+                    return Err(Error::event_stream(
+                        "neither tag nor implicit flags are specified",
+                        index,
+                    ));
+                }
+                end_node(&mut collections)
+            }
+            (State::Content, EventData::SequenceStart { .. }) => {
+                collections.push(Collection::Sequence);
+                State::Content
+            }
+            (State::Content, EventData::MappingStart { .. }) => {
+                collections.push(Collection::Mapping(false));
+                State::Content
+            }
+            (State::Content, EventData::SequenceEnd | EventData::MappingEnd)
+                if collections.last() == closed.as_ref() =>
+            {
+                collections.pop();
+                end_node(&mut collections)
+            }
+            _ => return Err(Error::event_stream(expected(state, &collections), index)),
+        };
+    }
+    if state == State::End {
+        Ok(())
+    } else {
+        Err(Error::event_stream(
+            expected(state, &collections),
+            events.len(),
+        ))
+    }
+}
+
+/// Account for a complete node in the innermost collection, and return the
+/// next state.
+fn end_node(collections: &mut [Collection]) -> State {
+    match collections.last_mut() {
+        None => State::DocumentEnd,
+        Some(Collection::Sequence) => State::Content,
+        Some(Collection::Mapping(value)) => {
+            *value = !*value;
+            State::Content
+        }
+    }
+}
+
+fn expected(state: State, collections: &[Collection]) -> &'static str {
+    match state {
+        State::StreamStart => "expected STREAM-START",
+        State::DocumentStart => "expected DOCUMENT-START or STREAM-END",
+        State::DocumentEnd => "expected DOCUMENT-END",
+        State::End => "expected nothing after STREAM-END",
+        State::Content => match collections.last() {
+            None | Some(Collection::Mapping(true)) => {
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, or ALIAS"
+            }
+            Some(Collection::Sequence) => {
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, ALIAS, or SEQUENCE-END"
+            }
+            Some(Collection::Mapping(false)) => {
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, ALIAS, or MAPPING-END"
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_event_stream;
+    use crate::{
+        parse_events, Encoding, ErrorKind, Event, MappingStyle, ScalarStyle, SequenceStyle,
+    };
+
+    fn scalar(value: &str) -> Event {
+        Event::scalar(None, None, value, true, true, ScalarStyle::Any)
+    }
+
+    fn sequence_start() -> Event {
+        Event::sequence_start(None, None, true, SequenceStyle::Any)
+    }
+
+    fn mapping_start() -> Event {
+        Event::mapping_start(None, None, true, MappingStyle::Any)
+    }
+
+    /// Wrap `content` in a stream with one document.
+    fn stream(content: Vec<Event>) -> Vec<Event> {
+        let mut events = vec![
+            Event::stream_start(Encoding::Utf8),
+            Event::document_start(None, &[], true),
+        ];
+        events.extend(content);
+        events.push(Event::document_end(true));
+        events.push(Event::stream_end());
+        events
+    }
+
+    #[test]
+    fn valid_streams() {
+        for input in [
+            "",
+            "a",
+            "--- a\n--- b\n...\n",
+            "{a: [1, 2], ? [x]: {y: z}}",
+            "- &a a\n- *a\n- !t b\n- ! c\n- !!map {}\n",
+            "? \n: \n",
+            "[a: b, c]",
+        ] {
+            let events = parse_events(input).unwrap();
+            validate_event_stream(&events).unwrap();
+        }
+        validate_event_stream(&stream(vec![scalar("a")])).unwrap();
+    }
+
+    #[test]
+    fn invalid_streams() {
+        let untagged = Event::scalar(None, None, "a", false, false, ScalarStyle::Any);
+        let cases = [
+            (Vec::new(), 0, "expected STREAM-START"),
+            (vec![scalar("a")], 0, "expected STREAM-START"),
+            (
+                vec![Event::stream_start(Encoding::Utf8)],
+                1,
+                "expected DOCUMENT-START or STREAM-END",
+            ),
+            (
+                vec![Event::stream_start(Encoding::Utf8), scalar("a")],
+                1,
+                "expected DOCUMENT-START or STREAM-END",
+            ),
+            (
+                vec![
+                    Event::stream_start(Encoding::Utf8),
+                    Event::stream_end(),
+                    Event::stream_end(),
+                ],
+                2,
+                "expected nothing after STREAM-END",
+            ),
+            (
+                stream(Vec::new()),
+                2,
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, or ALIAS",
+            ),
+            (
+                stream(vec![scalar("a"), scalar("b")]),
+                3,
+                "expected DOCUMENT-END",
+            ),
+            (
+                stream(vec![sequence_start(), scalar("a")]),
+                4,
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, ALIAS, or SEQUENCE-END",
+            ),
+            (
+                stream(vec![sequence_start(), Event::mapping_end()]),
+                3,
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, ALIAS, or SEQUENCE-END",
+            ),
+            (
+                stream(vec![mapping_start(), scalar("a"), Event::mapping_end()]),
+                4,
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, or ALIAS",
+            ),
+            (
+                stream(vec![
+                    mapping_start(),
+                    sequence_start(),
+                    Event::sequence_end(),
+                    Event::sequence_end(),
+                ]),
+                5,
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, or ALIAS",
+            ),
+            (
+                stream(vec![mapping_start(), Event::sequence_end()]),
+                3,
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, ALIAS, or MAPPING-END",
+            ),
+            (
+                stream(vec![sequence_start(), untagged]),
+                3,
+                "neither tag nor implicit flags are specified",
+            ),
+            (
+                stream(vec![Event::document_start(None, &[], true), scalar("a")]),
+                2,
+                "expected SCALAR, SEQUENCE-START, MAPPING-START, or ALIAS",
+            ),
+            (
+                parse_events("a").unwrap()[..4].to_vec(),
+                4,
+                "expected DOCUMENT-START or STREAM-END",
+            ),
+        ];
+        for (events, index, problem) in cases {
+            let err = validate_event_stream(&events).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::EventStream, "{events:?}");
+            assert_eq!(err.event_index(), Some(index), "{events:?}");
+            assert_eq!(err.problem(), problem, "{events:?}");
+        }
+    }
+
+    #[test]
+    fn display() {
+        let err = validate_event_stream(&[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Event stream error: expected STREAM-START at event 0"
+        );
+    }
+}
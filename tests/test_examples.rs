@@ -0,0 +1,71 @@
+#[path = "../examples/reformat.rs"]
+#[allow(dead_code)]
+mod reformat;
+#[path = "../examples/validate.rs"]
+#[allow(dead_code)]
+mod validate;
+#[path = "../examples/yaml2json.rs"]
+#[allow(dead_code)]
+mod yaml2json;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new("tests").join("examples").join(name)
+}
+
+fn read(name: &str) -> String {
+    fs::read_to_string(fixture(name)).unwrap()
+}
+
+#[test]
+fn test_reformat() {
+    for (options, expected) in [
+        (reformat::Options::default(), "multi.reformat.yaml"),
+        (
+            reformat::Options {
+                indent: 4,
+                ..reformat::Options::default()
+            },
+            "multi.indent4.yaml",
+        ),
+    ] {
+        let mut output = Vec::new();
+        reformat::reformat(&mut read("multi.yaml").as_bytes(), &mut output, &options).unwrap();
+        pretty_assertions::assert_str_eq!(read(expected), String::from_utf8(output).unwrap());
+    }
+}
+
+#[test]
+fn test_validate() {
+    for (input, valid, expected) in [
+        ("multi.yaml", true, String::new()),
+        ("invalid.yaml", false, read("invalid.txt")),
+    ] {
+        let mut output = Vec::new();
+        assert_eq!(
+            validate::validate(input, &read(input), &mut output).unwrap(),
+            valid
+        );
+        pretty_assertions::assert_str_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
+
+#[test]
+fn test_yaml2json() {
+    let mut output = Vec::new();
+    yaml2json::yaml_to_json(&mut read("multi.yaml").as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    pretty_assertions::assert_str_eq!(read("multi.json"), output);
+    for line in output.lines() {
+        serde_json::from_str::<serde_json::Value>(line).unwrap();
+    }
+
+    let mut output = Vec::new();
+    let err = yaml2json::yaml_to_json(&mut "[a, b".as_bytes(), &mut output).unwrap_err();
+    assert!(
+        err.to_string().contains("found unexpected end of stream"),
+        "{err}"
+    );
+}
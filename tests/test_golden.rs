@@ -0,0 +1,262 @@
+//! Golden token and event streams, with marks, for the inputs in
+//! `tests/fixtures`.
+//!
+//! Each `<name>.yaml` fixture is paired with `<name>.tokens`, the tokens of
+//! the scanner, and `<name>.events`, the events of the parser. The format of
+//! the golden files is described by the comment at the top of each one. A
+//! stream that fails ends with an `ERROR` line, so fixtures can also cover
+//! error marks.
+//!
+//! To add a fixture, create the `.yaml` file and run the tests with
+//! `UPDATE_GOLDEN=1` to write its golden files, then review them:
+//!
+//! ```text
+//! UPDATE_GOLDEN=1 cargo test --test test_golden
+//! ```
+//!
+//! The same command blesses intended changes to existing golden files. The
+//! fixtures are read as bytes, so they may use CRLF line breaks or UTF-16.
+
+use libyaml_safer::{
+    Encoding, Event, EventData, MappingStyle, Mark, Parser, Result, ScalarStyle, Scanner,
+    SequenceStyle, Token, TokenData,
+};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+const HEADER: &str = "\
+# One line per item: `START..END ITEM`, with the marks as
+# `line:column@index`, all zero-based. Scalars are written as a style
+# indicator (`:` plain, `'` single, `\"` double, `|` literal, `>` folded)
+# followed by the value, with `\\`, line breaks, tabs, and other control
+# characters escaped. A failing stream ends with `ERROR <message>`.
+";
+
+fn mark(mark: Mark) -> String {
+    format!("{}:{}@{}", mark.line, mark.column, mark.index)
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for ch in value.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            ch if ch.is_control() => write!(escaped, "\\u{{{:x}}}", ch as u32).unwrap(),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn scalar(value: &str, style: ScalarStyle) -> String {
+    let indicator = match style {
+        ScalarStyle::SingleQuoted => '\'',
+        ScalarStyle::DoubleQuoted => '"',
+        ScalarStyle::Literal => '|',
+        ScalarStyle::Folded => '>',
+        _ => ':',
+    };
+    format!("{indicator}{}", escape(value))
+}
+
+fn encoding(encoding: Encoding) -> &'static str {
+    match encoding {
+        Encoding::Utf8 => "utf-8",
+        Encoding::Utf16Le => "utf-16le",
+        Encoding::Utf16Be => "utf-16be",
+        _ => "any",
+    }
+}
+
+fn properties(anchor: Option<&String>, tag: Option<&String>) -> String {
+    let mut properties = String::new();
+    if let Some(anchor) = anchor {
+        write!(properties, " &{anchor}").unwrap();
+    }
+    if let Some(tag) = tag {
+        write!(properties, " <{tag}>").unwrap();
+    }
+    properties
+}
+
+fn token(token: &Token) -> String {
+    let item = match &token.data {
+        TokenData::StreamStart { encoding: e } => format!("STREAM-START {}", encoding(*e)),
+        TokenData::StreamEnd => "STREAM-END".to_owned(),
+        TokenData::VersionDirective { major, minor } => {
+            format!("VERSION-DIRECTIVE {major}.{minor}")
+        }
+        TokenData::TagDirective { handle, prefix } => format!("TAG-DIRECTIVE {handle} {prefix}"),
+        TokenData::DocumentStart => "DOCUMENT-START".to_owned(),
+        TokenData::DocumentEnd => "DOCUMENT-END".to_owned(),
+        TokenData::BlockSequenceStart => "BLOCK-SEQUENCE-START".to_owned(),
+        TokenData::BlockMappingStart => "BLOCK-MAPPING-START".to_owned(),
+        TokenData::BlockEnd => "BLOCK-END".to_owned(),
+        TokenData::FlowSequenceStart => "FLOW-SEQUENCE-START".to_owned(),
+        TokenData::FlowSequenceEnd => "FLOW-SEQUENCE-END".to_owned(),
+        TokenData::FlowMappingStart => "FLOW-MAPPING-START".to_owned(),
+        TokenData::FlowMappingEnd => "FLOW-MAPPING-END".to_owned(),
+        TokenData::BlockEntry => "BLOCK-ENTRY".to_owned(),
+        TokenData::FlowEntry => "FLOW-ENTRY".to_owned(),
+        TokenData::Key => "KEY".to_owned(),
+        TokenData::Value => "VALUE".to_owned(),
+        TokenData::Alias { value } => format!("ALIAS {value}"),
+        TokenData::Anchor { value } => format!("ANCHOR {value}"),
+        TokenData::Tag { handle, suffix } => format!("TAG {handle} {suffix}"),
+        TokenData::Scalar { value, style } => format!("SCALAR {}", scalar(value, *style)),
+    };
+    format!(
+        "{}..{} {item}",
+        mark(token.start_mark),
+        mark(token.end_mark)
+    )
+}
+
+fn event(event: &Event) -> String {
+    let item = match &event.data {
+        EventData::StreamStart { encoding: e } => format!("+STR {}", encoding(*e)),
+        EventData::StreamEnd => "-STR".to_owned(),
+        EventData::DocumentStart {
+            version_directive,
+            tag_directives,
+            implicit,
+        } => {
+            let mut item = "+DOC".to_owned();
+            if let Some(version) = version_directive {
+                write!(item, " %YAML {}.{}", version.major, version.minor).unwrap();
+            }
+            for tag_directive in tag_directives {
+                write!(
+                    item,
+                    " %TAG {} {}",
+                    tag_directive.handle, tag_directive.prefix
+                )
+                .unwrap();
+            }
+            if !implicit {
+                item.push_str(" ---");
+            }
+            item
+        }
+        EventData::DocumentEnd { implicit } => {
+            if *implicit {
+                "-DOC".to_owned()
+            } else {
+                "-DOC ...".to_owned()
+            }
+        }
+        EventData::Alias { anchor } => format!("=ALI *{anchor}"),
+        EventData::Scalar {
+            anchor,
+            tag,
+            value,
+            style,
+            ..
+        } => format!(
+            "=VAL{} {}",
+            properties(anchor.as_ref(), tag.as_ref()),
+            scalar(value, *style)
+        ),
+        EventData::SequenceStart {
+            anchor, tag, style, ..
+        } => {
+            let flow = if *style == SequenceStyle::Flow {
+                " []"
+            } else {
+                ""
+            };
+            format!("+SEQ{flow}{}", properties(anchor.as_ref(), tag.as_ref()))
+        }
+        EventData::SequenceEnd => "-SEQ".to_owned(),
+        EventData::MappingStart {
+            anchor, tag, style, ..
+        } => {
+            let flow = if *style == MappingStyle::Flow {
+                " {}"
+            } else {
+                ""
+            };
+            format!("+MAP{flow}{}", properties(anchor.as_ref(), tag.as_ref()))
+        }
+        EventData::MappingEnd => "-MAP".to_owned(),
+    };
+    format!(
+        "{}..{} {item}",
+        mark(event.start_mark),
+        mark(event.end_mark)
+    )
+}
+
+fn render<T>(items: impl Iterator<Item = Result<T>>, line: fn(&T) -> String) -> String {
+    let mut output = HEADER.to_owned();
+    for item in items {
+        match item {
+            Ok(item) => output.push_str(&line(&item)),
+            Err(error) => {
+                writeln!(output, "ERROR {error}").unwrap();
+                break;
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+fn tokens(input: &[u8]) -> String {
+    let mut input = input;
+    let mut scanner = Scanner::new();
+    scanner.set_input(&mut input);
+    render(scanner, token)
+}
+
+fn events(input: &[u8]) -> String {
+    let mut input = input;
+    let mut parser = Parser::new();
+    parser.set_input(&mut input);
+    render(parser, event)
+}
+
+#[test]
+fn test_golden() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some_and(|value| value == "1");
+    let dir = Path::new("tests").join("fixtures");
+    let mut fixtures: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "yaml")
+        })
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty());
+
+    let mut failures = Vec::new();
+    for fixture in &fixtures {
+        let input = fs::read(fixture).unwrap();
+        for (extension, actual) in [("tokens", tokens(&input)), ("events", events(&input))] {
+            let golden = fixture.with_extension(extension);
+            if update {
+                fs::write(&golden, &actual).unwrap();
+                continue;
+            }
+            let expected = fs::read_to_string(&golden).unwrap_or_default();
+            if expected != actual {
+                eprintln!("{}:", golden.display());
+                eprintln!(
+                    "{}",
+                    pretty_assertions::StrComparison::new(&expected, &actual)
+                );
+                failures.push(golden);
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "golden files differ, run with UPDATE_GOLDEN=1 to bless: {failures:?}"
+    );
+}
@@ -5,6 +5,7 @@ mod bin;
 #[allow(dead_code)]
 mod run_parser_test_suite;
 
+use libyaml_safer::{events_from_tokens, Parser, Scanner};
 use std::fs;
 use std::path::Path;
 
@@ -27,6 +28,27 @@ fn test(id: &str) {
     let expected = fs::read_to_string(dir.join("test.event")).unwrap();
     pretty_assertions::assert_str_eq!(expected, stdout);
     assert!(output.success);
+
+    test_events_from_tokens(&fs::read(dir.join("in.yaml")).unwrap());
+}
+
+/// Parsing the tokens of the scanner produces the same events as parsing the
+/// input.
+fn test_events_from_tokens(input: &[u8]) {
+    let mut bytes = input;
+    let mut scanner = Scanner::new();
+    scanner.set_input_string(&mut bytes);
+    let tokens = scanner.collect::<Result<Vec<_>, _>>().unwrap();
+
+    let mut bytes = input;
+    let mut parser = Parser::new();
+    parser.set_input_string(&mut bytes);
+    let expected = parser.collect::<Result<Vec<_>, _>>().unwrap();
+
+    let events = events_from_tokens(tokens)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(expected, events);
 }
 
 unsafe_libyaml_test_suite::test_parser!();